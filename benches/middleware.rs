@@ -0,0 +1,164 @@
+//! Compares the cost of the middleware across its modes.
+//!
+//! Run with `cargo bench`. All validators fetch their keys from a JWKS server on localhost,
+//! so "cache miss" numbers exclude real network latency.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthMode, Issuer, OidcAuthLayer, OidcConfig, OidcValidator};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use serde::Deserialize;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+#[derive(Clone, Deserialize)]
+struct BenchClaims {
+    #[allow(dead_code)]
+    sub: String,
+}
+
+const SECOND_ISSUER: &str = "https://second.example.com";
+
+fn app(layer: OidcAuthLayer<BenchClaims>) -> Router {
+    Router::new()
+        .route("/", get(|| async { "ok" }))
+        .layer(layer)
+}
+
+fn request(token: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder().uri("/");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+fn validator(jwks_uri: &str) -> OidcValidator {
+    OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_uri.to_string(),
+    ))
+}
+
+/// Signs a token for `iss` padded with `extra` additional string claims.
+fn token(iss: &str, extra: usize) -> String {
+    let mut claims = serde_json::json!({
+        "sub": "bench",
+        "iss": iss,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    });
+    for i in 0..extra {
+        claims[format!("claim_{i}")] = serde_json::Value::from(format!("value-{i}"));
+    }
+    common::sign(&claims)
+}
+
+fn modes(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let jwks_uri = rt.block_on(common::start_jwks_server());
+    let token = token(common::ISSUER, 0);
+
+    let mut group = c.benchmark_group("mode");
+    for mode in [AuthMode::Optional, AuthMode::Strict] {
+        let app =
+            app(OidcAuthLayer::new(validator(&jwks_uri), common::validation()).with_mode(mode));
+        // Populate the JWKS cache before measuring.
+        rt.block_on(app.clone().oneshot(request(Some(&token))))
+            .unwrap();
+
+        group.bench_function(BenchmarkId::new("valid", format!("{mode:?}")), |b| {
+            b.to_async(&rt)
+                .iter(|| app.clone().oneshot(request(Some(&token))));
+        });
+        group.bench_function(BenchmarkId::new("missing", format!("{mode:?}")), |b| {
+            b.to_async(&rt).iter(|| app.clone().oneshot(request(None)));
+        });
+    }
+    group.finish();
+}
+
+fn jwks_cache(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let jwks_uri = rt.block_on(common::start_jwks_server());
+    let token = token(common::ISSUER, 0);
+
+    let mut group = c.benchmark_group("jwks_cache");
+    let warm = app(OidcAuthLayer::new(
+        validator(&jwks_uri),
+        common::validation(),
+    ));
+    rt.block_on(warm.clone().oneshot(request(Some(&token))))
+        .unwrap();
+    group.bench_function("hit", |b| {
+        b.to_async(&rt)
+            .iter(|| warm.clone().oneshot(request(Some(&token))));
+    });
+    // Each iteration uses a fresh validator, so the keys are fetched before validating.
+    group.bench_function("miss", |b| {
+        b.to_async(&rt).iter_batched(
+            || {
+                app(OidcAuthLayer::new(
+                    validator(&jwks_uri),
+                    common::validation(),
+                ))
+            },
+            |cold| cold.oneshot(request(Some(&token))),
+            BatchSize::SmallInput,
+        );
+    });
+    group.finish();
+}
+
+fn issuers(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let jwks_uri = rt.block_on(common::start_jwks_server());
+    let token = token(common::ISSUER, 0);
+
+    let single = app(OidcAuthLayer::new(
+        validator(&jwks_uri),
+        common::validation(),
+    ));
+    let multi = app(OidcAuthLayer::multi_issuer([
+        Issuer::new(common::ISSUER, validator(&jwks_uri), common::validation()),
+        Issuer::new(SECOND_ISSUER, validator(&jwks_uri), common::validation()),
+    ]));
+
+    let mut group = c.benchmark_group("issuers");
+    for (name, app) in [("single", single), ("multi", multi)] {
+        rt.block_on(app.clone().oneshot(request(Some(&token))))
+            .unwrap();
+        group.bench_function(name, |b| {
+            b.to_async(&rt)
+                .iter(|| app.clone().oneshot(request(Some(&token))));
+        });
+    }
+    group.finish();
+}
+
+fn claims_size(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let jwks_uri = rt.block_on(common::start_jwks_server());
+    let app = app(OidcAuthLayer::new(
+        validator(&jwks_uri),
+        common::validation(),
+    ));
+
+    let mut group = c.benchmark_group("claims_size");
+    for extra in [0, 16, 256] {
+        let token = token(common::ISSUER, extra);
+        rt.block_on(app.clone().oneshot(request(Some(&token))))
+            .unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(extra), &token, |b, token| {
+            b.to_async(&rt)
+                .iter(|| app.clone().oneshot(request(Some(token))));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, modes, jwks_cache, issuers, claims_size);
+criterion_main!(benches);
@@ -1,5 +1,5 @@
-use axum::{routing::get, Extension, Router};
-use axum_jwt_oidc::{OidcAuthLayer, OidcConfig, OidcValidator, Validation};
+use axum::{routing::get, Router};
+use axum_jwt_oidc::{OidcAuthLayer, OidcConfig, OidcValidator, OptionalClaims, Validation};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,8 +42,8 @@ async fn public_handler() -> &'static str {
     "This is a public endpoint"
 }
 
-async fn protected_handler(claims: Option<Extension<CustomClaims>>) -> String {
-    if let Some(Extension(claims)) = claims {
+async fn protected_handler(OptionalClaims(claims): OptionalClaims<CustomClaims>) -> String {
+    if let Some(claims) = claims {
         format!(
             "Hello {}! Your email is: {}",
             claims.name.as_deref().unwrap_or("Unknown"),
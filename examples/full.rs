@@ -0,0 +1,193 @@
+//! A complete application wiring the crate's pieces together against a local mock provider.
+//!
+//! - `/` greets visitors with or without a token (`AuthMode::Optional`).
+//! - `/api/orders` requires a bearer token (`AuthMode::Strict`) with the `read:orders`
+//!   scope, and creating an order additionally requires `write:orders`.
+//! - `/app` is a browser page reading the token from a cookie. Unauthenticated visitors are
+//!   redirected to `/login`, which signs them in and sends them back.
+//! - `/metrics` reports request counts gathered by the metering and post-response hooks.
+//!
+//! Run with `cargo run --example full --features examples-full`. The mock provider signs
+//! tokens with a fixed test key and must never be used outside local development.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+use axum_jwt_oidc::{
+    require_scopes, AuthMode, AuthOutcome, Claims, ErrorFormat, LoginRedirect, OidcAuthLayer,
+    OptionalClaims, RequireScopesLayer, ResponseEvent, StandardClaims, UsageRecord,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+const SESSION_COOKIE: &str = "session";
+
+/// Counters fed by the authentication layers.
+#[derive(Default)]
+struct Metrics {
+    authenticated: AtomicU64,
+    unauthenticated: AtomicU64,
+    rejected: AtomicU64,
+    usage: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    fn observe(&self, event: ResponseEvent) {
+        let counter = match event.outcome {
+            AuthOutcome::Authenticated => &self.authenticated,
+            AuthOutcome::Unauthenticated(_) => &self.unauthenticated,
+            AuthOutcome::Rejected(_) => &self.rejected,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn meter(&self, record: UsageRecord) {
+        let key = format!(
+            "{} {} {}",
+            record.subject.as_deref().unwrap_or("-"),
+            record.method,
+            record.route
+        );
+        *self.usage.lock().unwrap().entry(key).or_default() += 1;
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!(
+            "auth_authenticated_total {}\nauth_unauthenticated_total {}\nauth_rejected_total {}\n",
+            self.authenticated.load(Ordering::Relaxed),
+            self.unauthenticated.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+        );
+        for (key, count) in self.usage.lock().unwrap().iter() {
+            out.push_str(&format!("usage{{request=\"{key}\"}} {count}\n"));
+        }
+        out
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Stands in for the identity provider's JWKS endpoint.
+    let validator = common::validator().await;
+    let metrics = Arc::new(Metrics::default());
+
+    // Every layer shares the validator, and with it the cached signing keys.
+    let layer = |mode: AuthMode| {
+        let observer = metrics.clone();
+        let meter = metrics.clone();
+        OidcAuthLayer::<StandardClaims>::new(validator.clone(), common::validation())
+            .with_mode(mode)
+            .with_post_response(move |event| {
+                observer.observe(event);
+                async {}
+            })
+            .with_metering(move |record| meter.meter(record))
+    };
+
+    let login_redirect = LoginRedirect::new("/login").default_return("/app");
+
+    let public = Router::new()
+        .route("/", get(home))
+        .layer(layer(AuthMode::Optional));
+
+    let api = Router::new()
+        .route("/api/orders", get(list_orders).post(create_order))
+        .layer(RequireScopesLayer::new(["read:orders"]).with_error_format(ErrorFormat::ProblemJson))
+        .layer(layer(AuthMode::Strict).with_error_format(ErrorFormat::ProblemJson));
+
+    let app_pages = Router::new().route("/app", get(app_page)).layer(
+        layer(AuthMode::Strict)
+            .with_cookie(SESSION_COOKIE)
+            .with_login_redirect(login_redirect.clone()),
+    );
+
+    let app = Router::new()
+        .merge(public)
+        .merge(api)
+        .merge(app_pages)
+        .route("/login", get(login).with_state(Arc::new(login_redirect)))
+        .route("/metrics", get(render_metrics).with_state(metrics));
+
+    println!("Server starting on http://localhost:3000");
+    println!(
+        "Try: curl -H 'Authorization: Bearer {}' http://localhost:3000/api/orders",
+        mint_token("demo-user", "read:orders write:orders")
+    );
+
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
+
+/// Signs a token as the mock provider would issue it after a login.
+fn mint_token(sub: &str, scope: &str) -> String {
+    common::sign(&serde_json::json!({
+        "sub": sub,
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "scope": scope,
+    }))
+}
+
+async fn home(OptionalClaims(claims): OptionalClaims<StandardClaims>) -> String {
+    match claims.and_then(|claims| claims.sub) {
+        Some(sub) => format!("Welcome back, {sub}"),
+        None => "Welcome, guest. Visit /app to sign in.".to_string(),
+    }
+}
+
+async fn list_orders(Claims(claims): Claims<StandardClaims>) -> String {
+    format!(
+        "Orders of {}: []",
+        claims.sub.as_deref().unwrap_or("unknown")
+    )
+}
+
+#[require_scopes("write:orders")]
+async fn create_order() -> &'static str {
+    "Order created"
+}
+
+async fn app_page(Claims(claims): Claims<StandardClaims>) -> String {
+    format!(
+        "Signed in as {}",
+        claims.sub.as_deref().unwrap_or("unknown")
+    )
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    next: Option<String>,
+}
+
+/// Completes a login against the mock provider and stores the token in the session cookie.
+///
+/// A real application would redirect to the provider's authorization endpoint here and
+/// exchange the returned code for a token in its callback.
+async fn login(
+    State(login_redirect): State<Arc<LoginRedirect>>,
+    Query(query): Query<LoginQuery>,
+) -> impl IntoResponse {
+    let token = mint_token("demo-user", "read:orders");
+    let cookie = format!("{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax");
+    let target = login_redirect.return_to(query.next.as_deref());
+    ([(header::SET_COOKIE, cookie)], Redirect::to(target))
+}
+
+async fn render_metrics(State(metrics): State<Arc<Metrics>>) -> String {
+    metrics.render()
+}
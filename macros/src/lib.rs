@@ -0,0 +1,128 @@
+//! Attribute macros for `axum-jwt-oidc`, re-exported by that crate under its `macros`
+//! feature. Depend on `axum-jwt-oidc` rather than on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Error, FnArg, ItemFn, LitStr, ReturnType, Token, Type,
+};
+
+/// Rejects requests to an axum handler unless the token grants every listed scope.
+///
+/// See `axum_jwt_oidc::require_scopes` for details.
+#[proc_macro_attribute]
+pub fn require_scopes(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let scopes = parse_macro_input!(attr with Punctuated::<LitStr, Token![,]>::parse_terminated);
+    let handler = parse_macro_input!(item as ItemFn);
+
+    let scopes = scopes.iter();
+    let check = quote! {
+        ::axum_jwt_oidc::__private::require_scopes(&__granted, &[#(#scopes),*])
+    };
+    let granted = quote! { ::axum_jwt_oidc::__private::GrantedScopes };
+    expand(handler, granted, check).unwrap_or_else(|e| e.into_compile_error().into())
+}
+
+/// Rejects requests to an axum handler unless the token grants every listed Keycloak role.
+///
+/// See `axum_jwt_oidc::require_roles` for details.
+#[proc_macro_attribute]
+pub fn require_roles(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let roles = parse_macro_input!(attr as RoleArgs);
+    let handler = parse_macro_input!(item as ItemFn);
+
+    let RoleArgs { roles, client } = roles;
+    let client = match client {
+        Some(client) => quote! { ::core::option::Option::Some(#client) },
+        None => quote! { ::core::option::Option::None },
+    };
+    let check = quote! {
+        ::axum_jwt_oidc::__private::require_roles(&__granted, #client, &[#(#roles),*])
+    };
+    let granted = quote! { ::axum_jwt_oidc::KeycloakRoles };
+    expand(handler, granted, check).unwrap_or_else(|e| e.into_compile_error().into())
+}
+
+/// The arguments of `#[require_roles]`: role names, optionally followed by `client = "..."`.
+struct RoleArgs {
+    roles: Vec<LitStr>,
+    client: Option<LitStr>,
+}
+
+impl Parse for RoleArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = RoleArgs {
+            roles: Vec::new(),
+            client: None,
+        };
+        while !input.is_empty() {
+            if input.peek(LitStr) {
+                args.roles.push(input.parse()?);
+            } else {
+                let key: syn::Ident = input.parse()?;
+                if key != "client" || args.client.is_some() {
+                    return Err(Error::new(
+                        key.span(),
+                        "expected a role or `client = \"...\"`",
+                    ));
+                }
+                input.parse::<Token![=]>()?;
+                args.client = Some(input.parse()?);
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Wraps `handler` so that it first extracts `granted` claims and returns early if `check`
+/// fails, then runs the original body and converts its output into a response.
+fn expand(handler: ItemFn, granted: TokenStream2, check: TokenStream2) -> syn::Result<TokenStream> {
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = handler;
+    if sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            sig.fn_token,
+            "handlers must be `async fn`",
+        ));
+    }
+    if let Some(FnArg::Receiver(receiver)) = sig.inputs.first() {
+        return Err(Error::new_spanned(receiver, "handlers cannot take `self`"));
+    }
+
+    // An `impl Trait` return type cannot annotate a binding, so let it be inferred.
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) if !matches!(**ty, Type::ImplTrait(_)) => quote! { : #ty },
+        ReturnType::Type(..) => quote! {},
+        ReturnType::Default => quote! { : () },
+    };
+    sig.inputs.insert(
+        0,
+        syn::parse_quote! {
+            ::axum_jwt_oidc::Claims(__granted): ::axum_jwt_oidc::Claims<#granted>
+        },
+    );
+    sig.output = syn::parse_quote! { -> ::axum_jwt_oidc::__private::Response };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            if let ::core::result::Result::Err(error) = #check {
+                return ::axum_jwt_oidc::__private::IntoResponse::into_response(error);
+            }
+            let output #output = async move #block.await;
+            ::axum_jwt_oidc::__private::IntoResponse::into_response(output)
+        }
+    }
+    .into())
+}
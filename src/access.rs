@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, SystemTime};
+
+use crate::scope::GrantedScopes;
+use crate::standard::StandardClaims;
+
+/// Read access to the claims most cross-cutting features need, whatever the claims type.
+///
+/// Implement it for your claims type to let generic authorization and telemetry code, such as
+/// a [`PostResponseHook`](crate::PostResponseHook) reading the claims from the request
+/// extensions, work with it. It is implemented for [`serde_json::Value`] and
+/// [`StandardClaims`].
+///
+/// ```rust
+/// use axum_jwt_oidc::{ClaimsAccess, GrantedScopes};
+/// use serde::Deserialize;
+/// use std::time::{Duration, SystemTime};
+///
+/// #[derive(Clone, Deserialize)]
+/// struct MyClaims {
+///     sub: String,
+///     exp: u64,
+///     #[serde(flatten)]
+///     scopes: GrantedScopes,
+/// }
+///
+/// impl ClaimsAccess for MyClaims {
+///     fn subject(&self) -> Option<&str> {
+///         Some(&self.sub)
+///     }
+///
+///     fn scopes(&self) -> GrantedScopes {
+///         self.scopes.clone()
+///     }
+///
+///     fn expires_at(&self) -> Option<SystemTime> {
+///         SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(self.exp))
+///     }
+/// }
+/// ```
+pub trait ClaimsAccess {
+    /// Returns the `sub` claim, if present.
+    fn subject(&self) -> Option<&str>;
+
+    /// Returns the scopes granted by the token.
+    fn scopes(&self) -> GrantedScopes;
+
+    /// Returns when the token expires, from its `exp` claim.
+    fn expires_at(&self) -> Option<SystemTime>;
+}
+
+impl ClaimsAccess for Value {
+    fn subject(&self) -> Option<&str> {
+        self.get("sub")?.as_str()
+    }
+
+    /// Reads the `scope` and `scp` claims, like [`GrantedScopes`].
+    fn scopes(&self) -> GrantedScopes {
+        GrantedScopes::deserialize(self).unwrap_or_default()
+    }
+
+    /// Accepts integer and fractional `exp` claims.
+    fn expires_at(&self) -> Option<SystemTime> {
+        let exp = self.get("exp")?.as_f64()?;
+        SystemTime::UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(exp).ok()?)
+    }
+}
+
+impl ClaimsAccess for StandardClaims {
+    fn subject(&self) -> Option<&str> {
+        self.sub.as_deref()
+    }
+
+    fn scopes(&self) -> GrantedScopes {
+        StandardClaims::scopes(self)
+    }
+
+    fn expires_at(&self) -> Option<SystemTime> {
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(self.exp?))
+    }
+}
@@ -0,0 +1,239 @@
+use axum::{extract::Request, response::Response};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::extract::{authenticated_payload, ValidatedPayload};
+
+/// How the `aud` claim of tokens is checked, set with
+/// [`OidcAuthLayer::with_audience`](crate::OidcAuthLayer::with_audience) in place of the
+/// audience check of the [`Validation`](crate::Validation).
+///
+/// An API serving several registered clients accepts tokens issued for
+/// [any of](Self::any_of) them, while an API reached through a chain of services can
+/// require tokens issued for [all of](Self::all_of) its audiences. Tokens failing the check
+/// are rejected with [`AuthError::AudienceMismatch`].
+///
+/// ```rust
+/// use axum_jwt_oidc::AudienceCheck;
+///
+/// let audience = AudienceCheck::any_of(["web-app", "mobile-app", "cli"]);
+/// let internal = AudienceCheck::all_of(["orders-api"]).allow_missing();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudienceCheck {
+    audiences: Vec<String>,
+    require_all: bool,
+    allow_missing: bool,
+}
+
+/// Why a token failed an [`AudienceCheck`], carried by [`AuthError::AudienceMismatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AudienceMismatch {
+    /// The token has no `aud` claim.
+    Missing,
+    /// The token's audiences include none of those accepted by
+    /// [`AudienceCheck::any_of`].
+    NoneAccepted,
+    /// The token's audiences lack the listed ones required by [`AudienceCheck::all_of`].
+    MissingRequired(Vec<String>),
+}
+
+/// The `aud` claim, which is either a single audience or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audiences {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Default, Deserialize)]
+struct AudienceClaim {
+    aud: Option<Audiences>,
+}
+
+impl AudienceCheck {
+    /// Accepts tokens whose `aud` claim contains at least one of `audiences`.
+    ///
+    /// Without any `audiences`, every token is rejected;
+    /// [`OidcAuthLayer::validate`](crate::OidcAuthLayer::validate) reports this as
+    /// [`ConfigError::EmptyAudienceCheck`](crate::ConfigError::EmptyAudienceCheck).
+    pub fn any_of(audiences: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            audiences: audiences.into_iter().map(Into::into).collect(),
+            require_all: false,
+            allow_missing: false,
+        }
+    }
+
+    /// Accepts tokens whose `aud` claim contains every one of `audiences`.
+    ///
+    /// Without any `audiences`, every token with an `aud` claim is accepted;
+    /// [`OidcAuthLayer::validate`](crate::OidcAuthLayer::validate) reports this as
+    /// [`ConfigError::EmptyAudienceCheck`](crate::ConfigError::EmptyAudienceCheck).
+    pub fn all_of(audiences: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            require_all: true,
+            ..Self::any_of(audiences)
+        }
+    }
+
+    /// Also accepts tokens without an `aud` claim, such as those some identity providers
+    /// issue for internal service accounts. Tokens with an `aud` claim are still checked.
+    pub fn allow_missing(mut self) -> Self {
+        self.allow_missing = true;
+        self
+    }
+
+    /// Whether the check lists no audiences, which is a misconfiguration.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.audiences.is_empty()
+    }
+
+    /// Checks the `aud` claim of a verified token's `payload`.
+    pub(crate) fn check(&self, payload: &ValidatedPayload) -> Result<(), AuthError> {
+        let claim: AudienceClaim = payload.decode().unwrap_or_default();
+        let token_audiences = match claim.aud {
+            Some(Audiences::One(audience)) => vec![audience],
+            Some(Audiences::Many(audiences)) => audiences,
+            None if self.allow_missing => return Ok(()),
+            None => return Err(AuthError::AudienceMismatch(AudienceMismatch::Missing)),
+        };
+        let mismatch = if self.require_all {
+            let missing: Vec<_> = self
+                .audiences
+                .iter()
+                .filter(|audience| !token_audiences.contains(audience))
+                .cloned()
+                .collect();
+            (!missing.is_empty()).then_some(AudienceMismatch::MissingRequired(missing))
+        } else {
+            let accepted = self
+                .audiences
+                .iter()
+                .any(|audience| token_audiences.contains(audience));
+            (!accepted).then_some(AudienceMismatch::NoneAccepted)
+        };
+        match mismatch {
+            Some(mismatch) => Err(AuthError::AudienceMismatch(mismatch)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for AudienceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudienceMismatch::Missing => write!(f, "the token has no audience"),
+            AudienceMismatch::NoneAccepted => write!(f, "no audience of the token is accepted"),
+            AudienceMismatch::MissingRequired(audiences) => {
+                write!(f, "missing required audiences {}", audiences.join(" "))
+            }
+        }
+    }
+}
+
+/// A Tower layer that rejects requests whose token was not issued for the audiences of a
+/// route, such as `api://admin` for an admin subtree of an API also serving `api://public`.
+///
+/// The token has already been validated by the [`OidcAuthLayer`](crate::OidcAuthLayer)
+/// outside this layer, so routes with different audiences share its validator and key
+/// cache. That layer must accept the audiences of every route, e.g. with
+/// [`with_audience`](crate::OidcAuthLayer::with_audience). Requests failing the check are
+/// rejected with `401 Unauthorized` and [`AuthError::AudienceMismatch`], like tokens
+/// failing the check of the outer layer.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::{AudienceCheck, OidcAuthLayer, RequireAudienceLayer};
+///
+/// # fn layer(auth_layer: OidcAuthLayer<serde_json::Value>) {
+/// let admin = Router::new()
+///     .route("/admin/users", get(|| async { "users" }))
+///     .route_layer(RequireAudienceLayer::new(AudienceCheck::any_of(["api://admin"])));
+/// let app: Router = Router::new()
+///     .route("/catalog", get(|| async { "catalog" }))
+///     .merge(admin)
+///     .layer(auth_layer.with_audience(AudienceCheck::any_of(["api://admin", "api://public"])));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireAudienceLayer {
+    audience: Arc<AudienceCheck>,
+    error_format: ErrorFormat,
+}
+
+impl RequireAudienceLayer {
+    /// Requires tokens to pass `audience`.
+    pub fn new(audience: AudienceCheck) -> Self {
+        Self {
+            audience: Arc::new(audience),
+            error_format: ErrorFormat::default(),
+        }
+    }
+
+    /// Sets the body format of rejections. Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Fails if the request is unauthenticated or its token fails the audience check.
+    fn check(&self, req: &Request) -> Result<(), AuthError> {
+        self.audience
+            .check(authenticated_payload(req)?)
+            .inspect_err(|error| {
+                log::warn!("Rejecting token for route {}: {error}", req.uri().path());
+            })
+    }
+}
+
+impl<S> Layer<S> for RequireAudienceLayer {
+    type Service = RequireAudience<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireAudience {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`RequireAudienceLayer`].
+#[derive(Debug, Clone)]
+pub struct RequireAudience<S> {
+    inner: S,
+    layer: RequireAudienceLayer,
+}
+
+impl<S> Service<Request> for RequireAudience<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Err(error) = self.layer.check(&req) {
+            let response = error.to_response(self.layer.error_format, Some(req.uri().path()));
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
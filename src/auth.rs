@@ -1,31 +1,220 @@
 use async_oidc_jwt_validator::{OidcValidator, Validation};
-use http::HeaderMap;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    borrow::Cow,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-pub(crate) async fn validate_auth_header<T>(
-    headers: &HeaderMap,
+use crate::audience::{AudienceCheck, AudienceMismatch};
+use crate::clock::Clock;
+use crate::error::AuthError;
+use crate::extract::ValidatedPayload;
+use crate::header::TokenHeader;
+use crate::jwks::StaticJwks;
+
+/// What is known about a token validated with [`validate_token`], besides its claims.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TokenMetadata {
+    /// The JOSE header of the token.
+    pub header: TokenHeader,
+    /// The `iss` claim, if present.
+    pub issuer: Option<String>,
+    /// The `sub` claim, if present.
+    pub subject: Option<String>,
+    /// When the token expires, from its `exp` claim.
+    pub expires_at: Option<SystemTime>,
+}
+
+#[derive(Default, Deserialize)]
+struct MetadataClaims {
+    iss: Option<String>,
+    sub: Option<String>,
+    exp: Option<u64>,
+}
+
+/// Validates `token` exactly as [`OidcAuthLayer::new`](crate::OidcAuthLayer::new) does with
+/// the same validator and rules, for tokens that do not arrive in HTTP requests, such as
+/// those carried by queued messages or sent over an established WebSocket.
+///
+/// Returns the claims deserialized as `T` along with the token's [`TokenMetadata`].
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{validate_token, OidcValidator, Validation};
+///
+/// # async fn consume(validator: OidcValidator, message_token: &str) {
+/// let validation = Validation::default();
+/// match validate_token::<serde_json::Value>(&validator, &validation, message_token).await {
+///     Ok((_claims, metadata)) => println!("message from {:?}", metadata.subject),
+///     Err(error) => eprintln!("dropping message: {error}"),
+/// }
+/// # }
+/// ```
+pub async fn validate_token<T>(
     oidc_validator: &OidcValidator,
     validation: &Validation,
-) -> Option<T>
+    token: &str,
+) -> Result<(T, TokenMetadata), AuthError>
+where
+    T: DeserializeOwned + Clone,
+{
+    let result = validate_claims(
+        token,
+        SigningKeys::Remote(oidc_validator),
+        validation,
+        None,
+        &ValidationOverrides::default(),
+    )
+    .await;
+    log_result(&result);
+    let claims = result?;
+    // The token has been validated above, so its header and payload are well-formed.
+    let header = TokenHeader::from_token(token)
+        .ok_or_else(|| AuthError::MalformedHeader("undecodable header".to_string()))?;
+    let payload: MetadataClaims = ValidatedPayload::from_token(token)
+        .and_then(|payload| payload.decode().ok())
+        .unwrap_or_default();
+    let metadata = TokenMetadata {
+        header,
+        issuer: payload.iss,
+        subject: payload.sub,
+        expires_at: payload
+            .exp
+            .and_then(|exp| UNIX_EPOCH.checked_add(Duration::from_secs(exp))),
+    };
+    Ok((claims, metadata))
+}
+
+/// Layer settings replacing those of the [`Validation`] of every issuer.
+#[derive(Clone, Default)]
+pub(crate) struct ValidationOverrides {
+    /// Accepts plain `http` issuer and JWKS URLs, including those of tenants looked up at
+    /// request time.
+    pub(crate) allow_insecure_http: bool,
+    /// Checks the `aud` claim instead of the validation.
+    pub(crate) audience: Option<Arc<AudienceCheck>>,
+    /// The leeway for `exp` and `nbf` checks, in seconds.
+    pub(crate) leeway: Option<u64>,
+}
+
+impl ValidationOverrides {
+    /// Returns `validation` with the overridden settings replaced.
+    fn apply<'a>(&self, validation: &'a Validation) -> Cow<'a, Validation> {
+        if self.audience.is_none() && self.leeway.is_none() {
+            return Cow::Borrowed(validation);
+        }
+        let mut validation = validation.clone();
+        if self.audience.is_some() {
+            validation.validate_aud = false;
+        }
+        if let Some(leeway) = self.leeway {
+            validation.leeway = leeway;
+        }
+        Cow::Owned(validation)
+    }
+}
+
+/// Validates `token` with `validation`, as changed by `overrides`.
+pub(crate) async fn validate_claims<T>(
+    token: &str,
+    keys: SigningKeys<'_>,
+    validation: &Validation,
+    clock: Option<&dyn Clock>,
+    overrides: &ValidationOverrides,
+) -> Result<T, AuthError>
 where
     T: DeserializeOwned + Clone,
 {
-    let auth_header = headers.get("authorization").and_then(|h| h.to_str().ok());
-    log::debug!("Extracting claims from headers...");
-
-    if let Some(auth_header) = auth_header {
-        let token = auth_header.strip_prefix("Bearer ").unwrap_or(auth_header);
-
-        match oidc_validator.validate_custom::<T>(token, validation).await {
-            Ok(claims) => {
-                log::info!("Successfully authenticated token");
-                return Some(claims);
-            }
-            Err(e) => {
-                log::warn!("Authentication failed: {e}");
-            }
+    if let Err(e) = jsonwebtoken::decode_header(token) {
+        return Err(AuthError::MalformedHeader(e.to_string()));
+    }
+    let validation = overrides.apply(validation);
+    let claims = match clock {
+        None => keys.verify(token, &validation).await?,
+        Some(clock) => validate_at(token, keys, &validation, clock.now()).await?,
+    };
+    if let Some(audience) = &overrides.audience {
+        // The payload has been verified above.
+        let payload = ValidatedPayload::from_token(token)
+            .ok_or(AuthError::AudienceMismatch(AudienceMismatch::Missing))?;
+        audience.check(&payload)?;
+    }
+    Ok(claims)
+}
+
+/// Where the key verifying a token's signature comes from.
+#[derive(Clone, Copy)]
+pub(crate) enum SigningKeys<'a> {
+    /// The keys of an OIDC validator, fetched from its JWKS endpoint.
+    Remote(&'a OidcValidator),
+    /// Keys given up front.
+    Static(&'a StaticJwks),
+}
+
+impl SigningKeys<'_> {
+    async fn verify<T>(self, token: &str, validation: &Validation) -> Result<T, AuthError>
+    where
+        T: DeserializeOwned + Clone,
+    {
+        match self {
+            SigningKeys::Remote(oidc_validator) => oidc_validator
+                .validate_custom::<T>(token, validation)
+                .await
+                .map_err(AuthError::from_jwt),
+            SigningKeys::Static(jwks) => jwks.verify(token, validation),
         }
     }
+}
+
+/// Logs the result of validating a token.
+pub(crate) fn log_result<T>(result: &Result<T, AuthError>) {
+    match result {
+        Ok(_) => log::info!("Successfully authenticated token"),
+        Err(AuthError::JwksUnavailable(reason)) => log::error!("Authentication failed: {reason}"),
+        Err(e) => log::warn!("Authentication failed: {e}"),
+    }
+}
 
-    None
+#[derive(Default, Deserialize)]
+struct Lifetime {
+    exp: Option<u64>,
+    nbf: Option<u64>,
+}
+
+/// Validates `token` like `jsonwebtoken` does, but checks `exp` and `nbf` against `now`
+/// instead of the system time.
+async fn validate_at<T>(
+    token: &str,
+    keys: SigningKeys<'_>,
+    validation: &Validation,
+    now: SystemTime,
+) -> Result<T, AuthError>
+where
+    T: DeserializeOwned + Clone,
+{
+    let mut signature_only = validation.clone();
+    signature_only.validate_exp = false;
+    signature_only.validate_nbf = false;
+    let claims = keys.verify::<T>(token, &signature_only).await?;
+
+    // The payload has been verified above.
+    let lifetime: Lifetime = ValidatedPayload::from_token(token)
+        .and_then(|payload| payload.decode().ok())
+        .unwrap_or_default();
+    let now = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let expired = lifetime.exp.is_some_and(|exp| {
+        exp.saturating_sub(validation.reject_tokens_expiring_in_less_than)
+            < now.saturating_sub(validation.leeway)
+    });
+    if validation.validate_exp && expired {
+        return Err(AuthError::Expired);
+    }
+    let immature = lifetime
+        .nbf
+        .is_some_and(|nbf| nbf > now.saturating_add(validation.leeway));
+    if validation.validate_nbf && immature {
+        return Err(AuthError::InvalidToken("ImmatureSignature".to_string()));
+    }
+    Ok(claims)
 }
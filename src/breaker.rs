@@ -0,0 +1,155 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use crate::error::{AuthError, NO_MATCHING_KEY};
+use crate::kid::issuer_and_kid;
+use crate::readiness::KeyStatus;
+
+/// The most keys remembered as known, bounding the memory taken by issuers with many keys.
+const MAX_KNOWN_KEYS: usize = 10_000;
+
+/// When a layer stops fetching signing keys from an unavailable identity provider, set with
+/// [`with_jwks_circuit_breaker`](crate::OidcAuthLayer::with_jwks_circuit_breaker).
+///
+/// After [`failure_threshold`](Self::new) consecutive failed JWKS fetches, the breaker opens
+/// for the [`cooldown`](Self::cooldown). While it is open, tokens signed with a key the
+/// layer has validated before are still validated with the cached keys, while tokens that
+/// would need the keys to be fetched are rejected at once with
+/// [`AuthError::JwksUnavailable`] instead of waiting for another failing fetch, and keys
+/// past their [`JwksCacheTtl`](crate::JwksCacheTtl) are not refreshed. Once the cooldown
+/// has passed, the next fetch is attempted again, reopening the breaker if it fails.
+///
+/// ```rust
+/// use axum_jwt_oidc::JwksCircuitBreaker;
+/// use std::time::Duration;
+///
+/// // Stop fetching for a minute after three failures, and reject every token once the
+/// // cached keys are a day old.
+/// let breaker = JwksCircuitBreaker::new(3)
+///     .cooldown(Duration::from_secs(60))
+///     .max_staleness(Duration::from_secs(24 * 60 * 60));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JwksCircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    max_staleness: Option<Duration>,
+}
+
+impl JwksCircuitBreaker {
+    /// Opens the breaker after `failure_threshold` consecutive failed fetches.
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown: Duration::from_secs(30),
+            max_staleness: None,
+        }
+    }
+
+    /// Sets how long the breaker stays open after the last failed fetch. Defaults to 30
+    /// seconds.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Rejects every token while the breaker is open once the keys were last fetched longer
+    /// than `max_staleness` ago. Defaults to no limit.
+    ///
+    /// Keys count as fetched when [`warm_up`](crate::OidcAuthLayer::warm_up), the
+    /// [background refresh task](crate::OidcAuthLayer::jwks_refresh_task) or a refresh after
+    /// their TTL loaded them, or when a request fetched them to validate its token.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+}
+
+/// The state of a layer's [`JwksCircuitBreaker`], shared by its clones.
+pub(crate) struct Breaker {
+    config: JwksCircuitBreaker,
+    status: Arc<KeyStatus>,
+    /// The unverified issuer and `kid` pairs of tokens validated before, whose keys are cached.
+    known: Mutex<HashSet<(String, String)>>,
+}
+
+impl Breaker {
+    pub(crate) fn new(config: JwksCircuitBreaker, status: Arc<KeyStatus>) -> Self {
+        Self {
+            config,
+            status,
+            known: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns whether fetches are currently skipped.
+    pub(crate) fn is_open(&self, now: SystemTime) -> bool {
+        let (failures, failed_at) = self.status.failures();
+        failures >= self.config.failure_threshold
+            && failed_at.is_some_and(|failed_at| {
+                now.duration_since(failed_at)
+                    .is_ok_and(|elapsed| elapsed < self.config.cooldown)
+            })
+    }
+
+    /// Rejects `token` while the breaker is open, unless it can be validated with the cached
+    /// keys.
+    pub(crate) fn check(&self, token: &str, now: SystemTime) -> Result<(), AuthError> {
+        if !self.is_open(now) {
+            return Ok(());
+        }
+        if let Some(max_staleness) = self.config.max_staleness {
+            let stale = self.status.loaded_at().is_none_or(|loaded_at| {
+                now.duration_since(loaded_at).unwrap_or_default() > max_staleness
+            });
+            if stale {
+                log::warn!("Rejecting token: the cached signing keys are too stale");
+                return Err(AuthError::JwksUnavailable(
+                    "signing keys are stale and their issuer is unavailable".to_string(),
+                ));
+            }
+        }
+        match issuer_and_kid(token) {
+            Some(key) if self.lock().contains(&key) => Ok(()),
+            _ => {
+                log::debug!("Rejecting token with an unfetched key: circuit breaker is open");
+                Err(AuthError::JwksUnavailable(
+                    "circuit breaker is open after failed JWKS fetches".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Records what validating `token` revealed about fetching the keys.
+    pub(crate) fn record<T>(&self, token: &str, result: &Result<T, AuthError>, now: SystemTime) {
+        if let Err(AuthError::JwksUnavailable(reason)) = result {
+            self.status.failed(reason, now);
+            return;
+        }
+        // Tokens without a `kid` are rejected before any fetch.
+        let Some(key) = issuer_and_kid(token) else {
+            return;
+        };
+        match result {
+            Ok(_) => {
+                let mut known = self.lock();
+                // The keys were fetched to validate the first token signed with a key.
+                if known.len() < MAX_KNOWN_KEYS && known.insert(key) {
+                    self.status.loaded(now);
+                }
+            }
+            // The keys were fetched, they just hold no key for the token.
+            Err(AuthError::InvalidSignature(reason)) if reason == NO_MATCHING_KEY => {
+                self.status.loaded(now);
+            }
+            Err(_) => {}
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<(String, String)>> {
+        self.known.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, PoisonError,
+};
+
+/// The cache a [`CacheEviction`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheKind {
+    /// A [`ValidationCache`](crate::ValidationCache).
+    Validation,
+    /// The key identifiers remembered by
+    /// [`OidcAuthLayer::with_unknown_kid_ttl`](crate::OidcAuthLayer::with_unknown_kid_ttl).
+    UnknownKids,
+    /// A [`TenantDirectory`](crate::TenantDirectory), including the unknown tenants it
+    /// remembers.
+    Tenants,
+    /// A [`MemoryKeyCache`](crate::MemoryKeyCache).
+    #[cfg(feature = "jwks-fetch")]
+    Keys,
+    /// The exchanged tokens of a [`TokenExchanger`](crate::TokenExchanger).
+    #[cfg(feature = "exchange")]
+    ExchangedTokens,
+}
+
+/// An entry dropped from a cache to make room for another, reported to the hook of a
+/// [`CacheBudget`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CacheEviction {
+    /// The cache the entry was dropped from.
+    pub cache: CacheKind,
+    /// The approximate size of the entry, in bytes.
+    pub bytes: usize,
+}
+
+/// The approximate bytes an entry takes in a cache besides its key and payload.
+pub(crate) const ENTRY_OVERHEAD: usize = 64;
+
+type EvictionHook = Box<dyn Fn(&CacheEviction) + Send + Sync>;
+
+/// Hard bounds on the entries and approximate bytes held together by the caches sharing it,
+/// so that a flood of distinct tokens, key identifiers or tenants cannot exhaust memory.
+///
+/// Share one budget between a [`ValidationCache`](crate::ValidationCache), a
+/// [`TenantDirectory`](crate::TenantDirectory), the layer's own caches with
+/// [`OidcAuthLayer::with_cache_budget`](crate::OidcAuthLayer::with_cache_budget), and, with
+/// their features, a `MemoryKeyCache` and a `TokenExchanger`. Each cache also bounds its own
+/// entries, so caches without a budget cannot grow without limit either. A cache
+/// lacking room drops its own least recently used entries, reporting each to
+/// [`on_evict`](Self::on_evict), and skips caching if that is not enough; concurrent
+/// requests for the same token are then validated separately rather than coalesced.
+///
+/// Sizes are estimates: an entry counts its key and its payload, cached claims being
+/// estimated from the payload of their token, and a tenant also counts 4 KiB for the signing
+/// keys its validator caches, which cannot be measured.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{CacheBudget, ValidationCache};
+/// use std::sync::Arc;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let budget = Arc::new(CacheBudget::new(100_000, 64 << 20).on_evict(|eviction| {
+///     log::debug!("evicted {} bytes from {:?}", eviction.bytes, eviction.cache);
+/// }));
+/// let cache = ValidationCache::new(50_000).with_budget(budget.clone());
+/// let auth_layer = auth_layer
+///     .with_validation_cache(cache)
+///     .with_cache_budget(budget.clone());
+///
+/// // Later, e.g. when rendering metrics:
+/// println!("cached: {} entries, {} bytes", budget.entries(), budget.bytes());
+/// # }
+/// ```
+pub struct CacheBudget {
+    max_entries: usize,
+    max_bytes: usize,
+    usage: Mutex<Usage>,
+    evictions: AtomicU64,
+    on_evict: Option<EvictionHook>,
+}
+
+#[derive(Default)]
+struct Usage {
+    entries: usize,
+    bytes: usize,
+}
+
+impl CacheBudget {
+    /// Lets the caches sharing the budget hold up to `max_entries` entries and about
+    /// `max_bytes` bytes together.
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            max_entries,
+            max_bytes,
+            usage: Mutex::new(Usage::default()),
+            evictions: AtomicU64::new(0),
+            on_evict: None,
+        }
+    }
+
+    /// Calls `on_evict` with each entry dropped from a cache to make room for another, e.g.
+    /// to count evictions per cache in a metric. Expired entries are not reported.
+    pub fn on_evict(mut self, on_evict: impl Fn(&CacheEviction) + Send + Sync + 'static) -> Self {
+        self.on_evict = Some(Box::new(on_evict));
+        self
+    }
+
+    /// Returns the number of entries the caches hold together.
+    pub fn entries(&self) -> usize {
+        self.lock().entries
+    }
+
+    /// Returns the approximate number of bytes the caches hold together.
+    pub fn bytes(&self) -> usize {
+        self.lock().bytes
+    }
+
+    /// Returns how many entries were dropped to make room for others.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Accounts for a new entry of `bytes`, unless it would exceed a bound.
+    pub(crate) fn try_reserve(&self, bytes: usize) -> bool {
+        let mut usage = self.lock();
+        let fits =
+            usage.entries < self.max_entries && usage.bytes.saturating_add(bytes) <= self.max_bytes;
+        if fits {
+            usage.entries += 1;
+            usage.bytes += bytes;
+        }
+        fits
+    }
+
+    /// Accounts for `entries` removed entries of `bytes` in total.
+    pub(crate) fn release(&self, entries: usize, bytes: usize) {
+        let mut usage = self.lock();
+        usage.entries = usage.entries.saturating_sub(entries);
+        usage.bytes = usage.bytes.saturating_sub(bytes);
+    }
+
+    /// Reports entries of the given sizes evicted from `cache`. Call it without holding the
+    /// lock of the cache, as the hook may use it.
+    pub(crate) fn evicted(&self, cache: CacheKind, sizes: &[usize]) {
+        self.evictions
+            .fetch_add(sizes.len() as u64, Ordering::Relaxed);
+        if let Some(on_evict) = &self.on_evict {
+            for &bytes in sizes {
+                on_evict(&CacheEviction { cache, bytes });
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Usage> {
+        self.usage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Accounts for a new entry of `bytes` in `budget`, if any.
+pub(crate) fn reserve(budget: Option<&CacheBudget>, bytes: usize) -> bool {
+    budget.is_none_or(|budget| budget.try_reserve(bytes))
+}
+
+/// Accounts for `entries` removed entries of `bytes` in total in `budget`, if any.
+pub(crate) fn release(budget: Option<&CacheBudget>, entries: usize, bytes: usize) {
+    if let Some(budget) = budget {
+        budget.release(entries, bytes);
+    }
+}
@@ -0,0 +1,301 @@
+use lru::LruCache;
+use ring::digest::{digest, SHA256};
+use serde::Deserialize;
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::budget::{self, CacheBudget, CacheKind, ENTRY_OVERHEAD};
+use crate::extract::ValidatedPayload;
+
+/// A bounded cache of validation results, so a token presented again is not re-verified.
+///
+/// Entries are keyed by the SHA-256 digest of the token, so the cache holds no bearer
+/// tokens, and store the deserialized claims until the token's `exp` claim, or for at most
+/// [`max_age`](Self::max_age). When the cache is full, the least recently used entry is
+/// dropped; expired entries are dropped when looked up or once they are the least recently
+/// used. Only successful validations are cached. Entries also expire once `max_age` has
+/// passed on the monotonic clock, and when the clock has gone backwards since they were
+/// cached, so a corrected clock cannot keep them cached. A
+/// [`CacheBudget`] set with [`with_budget`](Self::with_budget) bounds the cache together
+/// with others.
+///
+/// A cached token is accepted without checking its signature again until the layer's keys
+/// are replaced by a set lacking one of them, through its
+/// [refresh](crate::OidcAuthLayer::jwks_refresh_task), fetch, file watch or discovery
+/// tasks, a [TTL](crate::JwksCacheTtl) or [`warm_up`](crate::OidcAuthLayer::warm_up), after
+/// which cached tokens are validated again. A validator does not tell which keys such a
+/// refresh removed, so every refresh of one counts; keys it replaces on its own, when a
+/// token names an unknown key, stay trusted for up to [`max_age`](Self::max_age). The cache
+/// is used by layers with a fixed set of issuers, and bypassed by those resolving
+/// tenants per request. Share one cache only between layers with the same validators and
+/// rules.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::ValidationCache;
+/// use std::sync::Arc;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let cache = Arc::new(ValidationCache::new(50_000));
+/// let auth_layer = auth_layer.with_validation_cache(cache.clone());
+///
+/// // Later, e.g. when rendering metrics:
+/// println!("validation cache hit rate: {:.2}", cache.hit_rate());
+/// # }
+/// ```
+pub struct ValidationCache {
+    max_entries: usize,
+    max_age: Duration,
+    entries: Mutex<LruCache<TokenDigest, Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    time_anomalies: AtomicU64,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+struct Entry {
+    claims: Box<dyn Any + Send + Sync>,
+    expires_at: SystemTime,
+    /// When the entry was cached, by the layer's clock and by the monotonic clock.
+    cached_at: (SystemTime, Instant),
+    /// How often the keys had been replaced when the token was validated.
+    keys: u64,
+    /// The approximate size of the entry, accounted for in the budget.
+    bytes: usize,
+}
+
+/// The SHA-256 digest of a token, identifying it without holding on to it.
+pub(crate) type TokenDigest = [u8; 32];
+
+#[derive(Default, Deserialize)]
+struct Expiry {
+    exp: Option<u64>,
+}
+
+impl ValidationCache {
+    /// Caches the results of up to `max_entries` tokens, each for at most five minutes.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries: max_entries.max(1),
+            max_age: Duration::from_secs(300),
+            entries: Mutex::new(LruCache::unbounded()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            time_anomalies: AtomicU64::new(0),
+            budget: None,
+        }
+    }
+
+    /// Counts the cached results against `budget`, shared with other caches. When the
+    /// budget is exhausted, the least recently used results are dropped to make room, and a
+    /// result is not cached if that is not enough.
+    pub fn with_budget(mut self, budget: Arc<CacheBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sets how long a result is cached at most, bounding how long a token keeps being
+    /// accepted after its signing key is revoked. Defaults to five minutes.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Returns the number of cached results.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no result is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many tokens were found in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many tokens were not found in the cache and had to be validated.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns how often the clock was found to have gone backwards since a result was
+    /// cached, e.g. after an NTP correction or a VM snapshot restore, for monitoring.
+    ///
+    /// Such results are validated again rather than kept until the clock catches up.
+    pub fn time_anomalies(&self) -> u64 {
+        self.time_anomalies.load(Ordering::Relaxed)
+    }
+
+    /// Returns the share of lookups answered from the cache, from `0.0` to `1.0`, or `0.0`
+    /// before the first lookup.
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.hits() as f64;
+        let total = hits + self.misses() as f64;
+        if total == 0.0 {
+            0.0
+        } else {
+            hits / total
+        }
+    }
+
+    /// Drops every cached result, e.g. after a signing key was revoked.
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        let bytes = entries.iter().map(|(_, entry)| entry.bytes).sum();
+        budget::release(self.budget.as_deref(), entries.len(), bytes);
+        entries.clear();
+    }
+
+    /// Returns the cached claims of `token`, if they are cached as `T`, have not expired and
+    /// were validated since the keys were last replaced, `keys` times so far.
+    pub(crate) fn get<T>(&self, token: &str, now: SystemTime, keys: u64) -> Option<T>
+    where
+        T: Clone + 'static,
+    {
+        let key = token_digest(token);
+        let mut entries = self.lock();
+        let claims = match entries.get(&key) {
+            Some(entry) if entry.is_fresh(now, self.max_age) && entry.keys == keys => {
+                entry.claims.downcast_ref::<T>().cloned()
+            }
+            Some(entry) => {
+                if entry.cached_at.0 > now {
+                    self.time_anomalies.fetch_add(1, Ordering::Relaxed);
+                    log::warn!(
+                        "Clock went backwards since a token was cached, validating it again"
+                    );
+                }
+                let expired = entries.pop(&key);
+                drop(entries);
+                self.release(expired.as_slice());
+                None
+            }
+            None => None,
+        };
+
+        let counter = match claims {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        claims
+    }
+
+    /// Caches the claims of the validated `token`. `margin` is the time before `exp` from
+    /// which the validation rules reject the token, and `keys` how often the keys had been
+    /// replaced when it was validated.
+    pub(crate) fn insert<T>(&self, token: &str, claims: &T, margin: u64, now: SystemTime, keys: u64)
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let payload = ValidatedPayload::from_token(token);
+        let expiry: Expiry = payload
+            .as_ref()
+            .and_then(|payload| payload.decode().ok())
+            .unwrap_or_default();
+        let expires_at = expiry
+            .exp
+            .and_then(|exp| UNIX_EPOCH.checked_add(Duration::from_secs(exp.saturating_sub(margin))))
+            .into_iter()
+            .chain(now.checked_add(self.max_age))
+            .min();
+        let Some(expires_at) = expires_at.filter(|expires_at| *expires_at > now) else {
+            return;
+        };
+
+        let key = token_digest(token);
+        // The claims were deserialized from the payload, so its decoded length approximates
+        // the data they hold besides their own size.
+        let claims_bytes = std::mem::size_of::<T>() + payload.map_or(0, |p| p.0.len() * 3 / 4);
+        let bytes = ENTRY_OVERHEAD + std::mem::size_of::<TokenDigest>() + claims_bytes;
+        let budget = self.budget.as_deref();
+        let mut entries = self.lock();
+        let previous = entries.pop(&key);
+        self.release(previous.as_slice());
+        let mut evicted = Vec::new();
+        if entries.len() >= self.max_entries {
+            self.remove_least_recently_used(&mut entries, now, &mut evicted);
+        }
+        let mut reserved = budget::reserve(budget, bytes);
+        while !reserved && self.remove_least_recently_used(&mut entries, now, &mut evicted) {
+            reserved = budget::reserve(budget, bytes);
+        }
+        if reserved {
+            let entry = Entry {
+                claims: Box::new(claims.clone()),
+                expires_at,
+                cached_at: (now, Instant::now()),
+                keys,
+                bytes,
+            };
+            entries.put(key, entry);
+        }
+        drop(entries);
+
+        if let Some(budget) = budget.filter(|_| !evicted.is_empty()) {
+            budget.evicted(CacheKind::Validation, &evicted);
+        }
+    }
+
+    /// Removes the least recently used entry, adding its size to `evicted` unless it had
+    /// expired anyway. Returns `false` if the cache is empty.
+    fn remove_least_recently_used(
+        &self,
+        entries: &mut LruCache<TokenDigest, Entry>,
+        now: SystemTime,
+        evicted: &mut Vec<usize>,
+    ) -> bool {
+        let Some((_, entry)) = entries.pop_lru() else {
+            return false;
+        };
+        self.release(std::slice::from_ref(&entry));
+        if entry.is_fresh(now, self.max_age) {
+            evicted.push(entry.bytes);
+        }
+        true
+    }
+
+    /// Releases the budget of removed `entries`.
+    fn release(&self, entries: &[Entry]) {
+        let bytes = entries.iter().map(|entry| entry.bytes).sum();
+        budget::release(self.budget.as_deref(), entries.len(), bytes);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<TokenDigest, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Entry {
+    /// Whether the entry has neither expired by the layer's clock nor outlived `max_age` on
+    /// the monotonic clock. Entries cached after `now` are not fresh either.
+    fn is_fresh(&self, now: SystemTime, max_age: Duration) -> bool {
+        let (cached_at, cached) = self.cached_at;
+        cached_at <= now && self.expires_at > now && cached.elapsed() < max_age
+    }
+}
+
+impl Drop for ValidationCache {
+    fn drop(&mut self) {
+        let entries = self
+            .entries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let bytes = entries.iter().map(|(_, entry)| entry.bytes).sum();
+        budget::release(self.budget.as_deref(), entries.len(), bytes);
+    }
+}
+
+pub(crate) fn token_digest(token: &str) -> TokenDigest {
+    let mut key = [0; 32];
+    key.copy_from_slice(digest(&SHA256, token.as_bytes()).as_ref());
+    key
+}
@@ -0,0 +1,90 @@
+/// The optional features this build of the crate was compiled with.
+///
+/// Lets framework code adapt to the available integrations at runtime, without `cfg`
+/// attributes on the crate's features. New fields are added as features are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// `CedarPolicy` is available (`cedar` feature).
+    pub cedar: bool,
+    /// `ClientCredentialsManager` is available (`client-credentials` feature).
+    pub client_credentials: bool,
+    /// `OidcAuthLayer::discover` and `DiscoveryRefreshTask` are available (`discovery`
+    /// feature).
+    pub discovery: bool,
+    /// `TokenExchanger` is available (`exchange` feature).
+    pub exchange: bool,
+    /// `ForwardAuthLayer` is available (`forward` feature).
+    pub forward: bool,
+    /// `#[require_scopes]` and `#[require_roles]` are available (`macros` feature).
+    pub macros: bool,
+    /// `OidcAuthLayer::with_jwks_file` and `JwksFileWatchTask` are available (`jwks-file`
+    /// feature).
+    pub jwks_file: bool,
+    /// `JwksFetcher` and `JwksFetchTask` are available (`jwks-fetch` feature).
+    pub jwks_fetch: bool,
+    /// `JwksRefreshTask` is available (`jwks-refresh` feature).
+    pub jwks_refresh: bool,
+    /// `MessageAuthenticator` is available (`messages` feature).
+    pub messages: bool,
+    /// `OpaPolicy` is available (`opa` feature).
+    pub opa: bool,
+    /// `RedisKeyCache` is available (`redis` feature).
+    pub redis: bool,
+    /// `auth_stack` is available (`stack` feature).
+    pub stack: bool,
+    /// `TypedBearerExtractor` is available (`typed-header` feature).
+    pub typed_header: bool,
+}
+
+impl Capabilities {
+    /// Returns the names of the enabled features.
+    pub fn enabled(&self) -> Vec<&'static str> {
+        [
+            ("cedar", self.cedar),
+            ("client-credentials", self.client_credentials),
+            ("discovery", self.discovery),
+            ("exchange", self.exchange),
+            ("forward", self.forward),
+            ("jwks-file", self.jwks_file),
+            ("jwks-fetch", self.jwks_fetch),
+            ("jwks-refresh", self.jwks_refresh),
+            ("macros", self.macros),
+            ("messages", self.messages),
+            ("opa", self.opa),
+            ("redis", self.redis),
+            ("stack", self.stack),
+            ("typed-header", self.typed_header),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+    }
+}
+
+/// Returns the optional features this build of the crate was compiled with.
+///
+/// ```rust
+/// let capabilities = axum_jwt_oidc::capabilities();
+/// if !capabilities.opa {
+///     println!("OPA policies need the `opa` feature");
+/// }
+/// ```
+pub const fn capabilities() -> Capabilities {
+    Capabilities {
+        cedar: cfg!(feature = "cedar"),
+        client_credentials: cfg!(feature = "client-credentials"),
+        discovery: cfg!(feature = "discovery"),
+        exchange: cfg!(feature = "exchange"),
+        forward: cfg!(feature = "forward"),
+        jwks_file: cfg!(feature = "jwks-file"),
+        jwks_fetch: cfg!(feature = "jwks-fetch"),
+        jwks_refresh: cfg!(feature = "jwks-refresh"),
+        macros: cfg!(feature = "macros"),
+        messages: cfg!(feature = "messages"),
+        opa: cfg!(feature = "opa"),
+        redis: cfg!(feature = "redis"),
+        stack: cfg!(feature = "stack"),
+        typed_header: cfg!(feature = "typed-header"),
+    }
+}
@@ -0,0 +1,135 @@
+use axum::{extract::Request, response::Response};
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::extract::authenticated_payload;
+
+/// A Tower layer that rejects requests whose token claims do not equal expected values.
+///
+/// Claims are addressed by a dot-separated path such as `address.country`, or by a JSON
+/// pointer such as `/https:~1~1example.com~1tenant` for claim names containing dots. Every
+/// required claim must be present and equal to its expected JSON value; requests failing a
+/// check are rejected with `403 Forbidden`, unauthenticated requests with
+/// `401 Unauthorized`. Like the other `Require*` layers, it must be applied inside an
+/// [`OidcAuthLayer`](crate::OidcAuthLayer).
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::RequireClaimLayer;
+/// use serde_json::json;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/reports", get(|| async { "reports" }))
+///     .layer(RequireClaimLayer::new("email_verified", true).and("tenant_id", "acme"))
+///     .layer(auth_layer);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireClaimLayer {
+    claims: Arc<[(String, Value)]>,
+    error_format: ErrorFormat,
+}
+
+impl RequireClaimLayer {
+    /// Requires the claim at `path` to equal `expected`.
+    pub fn new(path: impl Into<String>, expected: impl Into<Value>) -> Self {
+        Self {
+            claims: Arc::from([(path.into(), expected.into())]),
+            error_format: ErrorFormat::default(),
+        }
+    }
+
+    /// Also requires the claim at `path` to equal `expected`.
+    pub fn and(mut self, path: impl Into<String>, expected: impl Into<Value>) -> Self {
+        let mut claims = self.claims.to_vec();
+        claims.push((path.into(), expected.into()));
+        self.claims = claims.into();
+        self
+    }
+
+    /// Sets the body format of rejections. Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Fails if the request is unauthenticated or a claim does not have its expected value.
+    pub(crate) fn check(&self, req: &Request) -> Result<(), AuthError> {
+        let payload = authenticated_payload(req)?
+            .decode::<Value>()
+            .unwrap_or_default();
+
+        match self
+            .claims
+            .iter()
+            .find(|(path, expected)| lookup(&payload, path) != Some(expected))
+        {
+            None => Ok(()),
+            Some((path, _)) => {
+                log::warn!("Rejecting token whose `{path}` claim does not match");
+                Err(AuthError::AccessDenied(Some(format!(
+                    "claim `{path}` does not have the required value"
+                ))))
+            }
+        }
+    }
+}
+
+/// Returns the value at a dot-separated path or JSON pointer.
+pub(crate) fn lookup<'a>(payload: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.starts_with('/') {
+        return payload.pointer(path);
+    }
+    path.split('.')
+        .try_fold(payload, |value, segment| value.get(segment))
+}
+
+impl<S> Layer<S> for RequireClaimLayer {
+    type Service = RequireClaim<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireClaim {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`RequireClaimLayer`].
+#[derive(Debug, Clone)]
+pub struct RequireClaim<S> {
+    inner: S,
+    layer: RequireClaimLayer,
+}
+
+impl<S> Service<Request> for RequireClaim<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Err(error) = self.layer.check(&req) {
+            let response = error.to_response(self.layer.error_format, Some(req.uri().path()));
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+/// The source of the current time for token lifetime checks and cache expiry.
+///
+/// Layers use the system clock unless one is set with
+/// [`OidcAuthLayer::with_clock`](crate::OidcAuthLayer::with_clock). Tests can use a
+/// [`ManualClock`] to advance time deterministically. Any
+/// `Fn() -> SystemTime + Send + Sync + 'static` closure implements this trait.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> SystemTime + Send + Sync + 'static,
+{
+    fn now(&self) -> SystemTime {
+        self()
+    }
+}
+
+/// A [`Clock`] that only moves when advanced, for tests.
+///
+/// Clones share the same time, so a test can keep one to advance the clock of a layer.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at `now`.
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+
+    /// Sets the clock to `now`.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the time of `clock`, or the system time if none is configured.
+pub(crate) fn now(clock: Option<&dyn Clock>) -> SystemTime {
+    clock.map_or_else(SystemTime::now, Clock::now)
+}
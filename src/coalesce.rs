@@ -0,0 +1,160 @@
+use futures::future::{BoxFuture, FutureExt, Shared, WeakShared};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, Weak,
+    },
+};
+
+use crate::budget::{self, CacheBudget, ENTRY_OVERHEAD};
+use crate::cache::{token_digest, TokenDigest};
+use crate::error::AuthError;
+
+type Validation<T> = Shared<BoxFuture<'static, Result<T, AuthError>>>;
+
+/// The validations in flight by key, each with the identifier of its [`Slot`].
+type Validations<T, K> = HashMap<K, (u64, WeakShared<BoxFuture<'static, Result<T, AuthError>>>)>;
+
+/// The most validations in flight at once that later requests can join, whatever the budget.
+const MAX_ENTRIES: usize = 10_000;
+
+/// The validations in flight, so concurrent requests carrying the same token, or needing the
+/// same key otherwise, share one.
+pub(crate) struct InFlight<T, K = TokenDigest> {
+    pending: Arc<Pending<T, K>>,
+}
+
+struct Pending<T, K> {
+    validations: Mutex<Validations<T, K>>,
+    next_slot: AtomicU64,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+impl<T, K> InFlight<T, K> {
+    /// Coalesces up to 10 000 validations at once, while `budget`, if any, has room for them.
+    pub(crate) fn new(budget: Option<Arc<CacheBudget>>) -> Self {
+        Self {
+            pending: Arc::new(Pending {
+                validations: Mutex::new(HashMap::new()),
+                next_slot: AtomicU64::new(0),
+                budget,
+            }),
+        }
+    }
+}
+
+impl<T, K> Pending<T, K> {
+    /// The approximate size of an entry.
+    const ENTRY_BYTES: usize = ENTRY_OVERHEAD + std::mem::size_of::<K>();
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Validations<T, K>> {
+        self.validations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The entry of a validation in flight and its share of the budget, given back once the
+/// validation completes or every request awaiting it was cancelled.
+struct Slot<T, K: Eq + Hash> {
+    key: K,
+    id: u64,
+    pending: Weak<Pending<T, K>>,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+impl<T, K: Eq + Hash> Drop for Slot<T, K> {
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.upgrade() {
+            let mut validations = pending.lock();
+            // A later validation may have taken the place of a cancelled one.
+            if validations
+                .get(&self.key)
+                .is_some_and(|(id, _)| *id == self.id)
+            {
+                validations.remove(&self.key);
+            }
+        }
+        budget::release(self.budget.as_deref(), 1, Pending::<T, K>::ENTRY_BYTES);
+    }
+}
+
+impl<T> InFlight<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Returns the result of validating `token`, joining a validation already in flight for
+    /// the same token, or running `validate` otherwise. Without room for another entry,
+    /// `validate` runs on its own.
+    pub(crate) async fn validate<F>(&self, token: &str, validate: F) -> Result<T, AuthError>
+    where
+        F: Future<Output = Result<T, AuthError>> + Send + 'static,
+    {
+        self.run(token_digest(token), validate).await
+    }
+}
+
+impl<T, K> InFlight<T, K>
+where
+    T: Clone + Send + Sync + 'static,
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+{
+    /// Returns the result of `validate`, or of the one already in flight for `key`. Without
+    /// room for another entry, `validate` runs on its own.
+    pub(crate) async fn run<F>(&self, key: K, validate: F) -> Result<T, AuthError>
+    where
+        F: Future<Output = Result<T, AuthError>> + Send + 'static,
+    {
+        let budget = &self.pending.budget;
+        let validation = {
+            let mut pending = self.pending.lock();
+            match pending
+                .get(&key)
+                .and_then(|(_, validation)| validation.upgrade())
+            {
+                Some(validation) => Ok(validation),
+                None if pending.len() < MAX_ENTRIES
+                    && budget::reserve(budget.as_deref(), Pending::<T, K>::ENTRY_BYTES) =>
+                {
+                    let slot = Slot {
+                        key: key.clone(),
+                        id: self.pending.next_slot.fetch_add(1, Ordering::Relaxed),
+                        pending: Arc::downgrade(&self.pending),
+                        budget: budget.clone(),
+                    };
+                    let id = slot.id;
+                    let validation = finish(validate, slot);
+                    if let Some(weak) = validation.downgrade() {
+                        pending.insert(key, (id, weak));
+                    }
+                    Ok(validation)
+                }
+                None => Err(validate),
+            }
+        };
+        match validation {
+            Ok(validation) => validation.await,
+            Err(validate) => validate.await,
+        }
+    }
+}
+
+/// Runs `validate`, then frees its `slot`, so a token is validated again once the shared
+/// validation has completed. The slot is freed too if the validation is dropped unfinished.
+fn finish<T, K, F>(validate: F, slot: Slot<T, K>) -> Validation<T>
+where
+    T: Clone + Send + Sync + 'static,
+    K: Eq + Hash + Send + Sync + 'static,
+    F: Future<Output = Result<T, AuthError>> + Send + 'static,
+{
+    async move {
+        let result = validate.await;
+        drop(slot);
+        result
+    }
+    .boxed()
+    .shared()
+}
@@ -0,0 +1,20 @@
+use subtle::ConstantTimeEq;
+
+/// Compares two secrets in time that depends only on their lengths, not their contents.
+///
+/// The middleware uses this to check the secret of a trusted gateway, set with
+/// [`TrustedGatewayPayload::require_secret`](crate::TrustedGatewayPayload::require_secret).
+/// Use it in a custom [`TokenExtractor`](crate::TokenExtractor) or
+/// [`TenantResolver`](crate::TenantResolver) that checks API keys or other shared secrets, so
+/// that response timing does not reveal how much of a guess was right.
+///
+/// ```rust
+/// use axum_jwt_oidc::constant_time_eq;
+///
+/// assert!(constant_time_eq("s3cret", "s3cret"));
+/// assert!(!constant_time_eq("s3cret", "s3cre7"));
+/// assert!(!constant_time_eq("s3cret", "s3"));
+/// ```
+pub fn constant_time_eq(a: impl AsRef<[u8]>, b: impl AsRef<[u8]>) -> bool {
+    a.as_ref().ct_eq(b.as_ref()).into()
+}
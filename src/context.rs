@@ -0,0 +1,123 @@
+use serde::Deserialize;
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+use crate::extract::ValidatedPayload;
+use crate::scope::GrantedScopes;
+
+/// A raw bearer token, redacted from `Debug` output and zeroed in memory when dropped.
+///
+/// Inserted into the request extensions by layers configured with
+/// [`OidcAuthLayer::with_access_token`](crate::OidcAuthLayer::with_access_token), and held
+/// by [`AuthContext`].
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Extension, Router};
+/// use axum_jwt_oidc::AccessToken;
+///
+/// async fn handler(Extension(token): Extension<AccessToken>) -> String {
+///     // Forward the token to a downstream API.
+///     format!("Bearer {}", token.expose())
+/// }
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(auth_layer.with_access_token());
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessToken(Zeroizing<String>);
+
+impl AccessToken {
+    pub(crate) fn new(token: &str) -> Self {
+        Self(Zeroizing::new(token.to_string()))
+    }
+
+    /// Returns the token, e.g. to forward it in an `Authorization` header.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AccessToken(<redacted>)")
+    }
+}
+
+/// Everything known about the principal of an authenticated request.
+///
+/// Inserted into the request extensions alongside the claims by layers configured with
+/// [`OidcAuthLayer::with_auth_context`](crate::OidcAuthLayer::with_auth_context), for
+/// handlers that delegate calls on behalf of the user or cache per-identity data.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Extension, Router};
+/// use axum_jwt_oidc::AuthContext;
+///
+/// async fn handler(Extension(context): Extension<AuthContext<serde_json::Value>>) -> String {
+///     format!("{:?} until {:?}", context.issuer, context.expires_at)
+/// }
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(auth_layer.with_auth_context());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct AuthContext<T> {
+    /// The validated claims.
+    pub claims: T,
+    /// The token the claims were read from. `None` in trusted gateway mode, where the layer
+    /// never sees the token.
+    pub token: Option<AccessToken>,
+    /// The scopes granted by the token.
+    pub scopes: GrantedScopes,
+    /// When the token expires, from its `exp` claim.
+    pub expires_at: Option<Instant>,
+    /// The issuer that issued the token, from its verified `iss` claim.
+    pub issuer: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct ContextClaims {
+    iss: Option<String>,
+    exp: Option<u64>,
+}
+
+impl<T> AuthContext<T> {
+    pub(crate) fn new(
+        claims: T,
+        token: Option<AccessToken>,
+        payload: Option<&ValidatedPayload>,
+        now: SystemTime,
+    ) -> Self {
+        let context: ContextClaims = payload
+            .and_then(|payload| payload.decode().ok())
+            .unwrap_or_default();
+        let scopes = payload
+            .and_then(|payload| payload.decode().ok())
+            .unwrap_or_default();
+        // `Instant`s cannot be built from wall-clock times, so measure from the current one.
+        let expires_at = context.exp.map(|exp| {
+            let remaining = (UNIX_EPOCH + Duration::from_secs(exp))
+                .duration_since(now)
+                .unwrap_or_default();
+            Instant::now() + remaining
+        });
+
+        Self {
+            claims,
+            token,
+            scopes,
+            expires_at,
+            issuer: context.iss,
+        }
+    }
+}
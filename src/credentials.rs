@@ -0,0 +1,235 @@
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+
+use crate::context::AccessToken;
+use crate::token_endpoint::{renew_after, request_token, MIN_TOKEN_USE};
+
+/// The error type of [`ClientCredentialsManager::token`].
+pub type ClientCredentialsError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long before its expiry the token is refreshed, so it does not expire in flight. Tokens
+/// living less than twice as long are refreshed halfway through their lifetime instead.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long the refresh task waits after a failed refresh before trying again.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+type CachedToken = Option<(AccessToken, Option<Instant>)>;
+
+/// Fetches machine-to-machine tokens with the OAuth 2.0 client credentials grant, caching
+/// each until shortly before it expires. Requires the `client-credentials` feature.
+///
+/// Clones share the cached token, so one manager can be handed to every handler as state or
+/// an extension. Concurrent callers wait for a single request to the token endpoint. The
+/// [`ClientCredentialsTask`] returned by [`refresh_task`](Self::refresh_task) refreshes the
+/// token in the background ahead of its expiry, so requests never wait for it.
+///
+/// ```rust,no_run
+/// use axum::{extract::State, routing::get, Router};
+/// use axum_jwt_oidc::ClientCredentialsManager;
+///
+/// async fn handler(State(credentials): State<ClientCredentialsManager>) -> String {
+///     match credentials.token().await {
+///         Ok(_token) => "call the inventory service with it".to_string(),
+///         Err(error) => format!("no service token: {error}"),
+///     }
+/// }
+///
+/// # async fn run(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let credentials = ClientCredentialsManager::new(
+///     "https://idp.example.com/oauth2/token",
+///     "billing-service",
+///     "client-secret",
+/// )
+/// .scope("inventory:read");
+/// tokio::spawn(credentials.refresh_task());
+///
+/// let app: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(auth_layer)
+///     .with_state(credentials);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClientCredentialsManager {
+    config: Arc<Config>,
+    cached: Arc<Mutex<CachedToken>>,
+}
+
+#[derive(Clone)]
+struct Config {
+    token_endpoint: String,
+    client_id: String,
+    client_secret: Zeroizing<String>,
+    scope: Option<String>,
+    audience: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct ClientCredentialsRequest<'a> {
+    grant_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audience: Option<&'a str>,
+}
+
+impl ClientCredentialsManager {
+    /// Requests tokens at `token_endpoint` as the client `client_id`, authenticated with
+    /// `client_secret` using HTTP basic authentication, giving up after five seconds.
+    pub fn new(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self::with_client(token_endpoint, client_id, client_secret, client)
+    }
+
+    /// Requests tokens with `client`, e.g. to configure timeouts or TLS.
+    pub fn with_client(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            config: Arc::new(Config {
+                token_endpoint: token_endpoint.into(),
+                client_id: client_id.into(),
+                client_secret: Zeroizing::new(client_secret.into()),
+                scope: None,
+                audience: None,
+                client,
+            }),
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Requests `scope`, a space-separated list.
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).scope = Some(scope.into());
+        self
+    }
+
+    /// Requests tokens for `audience`, for identity providers such as Auth0 that require it.
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).audience = Some(audience.into());
+        self
+    }
+
+    /// Returns the cached token, fetching a new one if there is none or it is about to
+    /// expire. Tokens issued without `expires_in` are kept until
+    /// [`invalidate`](Self::invalidate) is called.
+    pub async fn token(&self) -> Result<AccessToken, ClientCredentialsError> {
+        let mut cached = self.cached.lock().await;
+        let fresh = cached.as_ref().filter(|(_, refresh_at)| {
+            refresh_at.is_none_or(|refresh_at| refresh_at > Instant::now())
+        });
+        if let Some((token, _)) = fresh {
+            return Ok(token.clone());
+        }
+
+        let (token, refresh_at) = self.config.fetch().await?;
+        *cached = Some((token.clone(), refresh_at));
+        Ok(token)
+    }
+
+    /// Drops the cached token, e.g. after a downstream service rejected it, so the next call
+    /// to [`token`](Self::token) fetches a new one.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    /// Returns a task refreshing the token ahead of its expiry, which must be spawned on the
+    /// runtime. Failed refreshes are logged and retried after ten seconds.
+    ///
+    /// The task completes once every clone of the manager is dropped, or if the identity
+    /// provider issues tokens without an expiry, which need no refreshing.
+    pub fn refresh_task(&self) -> ClientCredentialsTask {
+        let config = self.config.clone();
+        let cached = Arc::downgrade(&self.cached);
+        ClientCredentialsTask(Box::pin(refresh(config, cached)))
+    }
+}
+
+impl Config {
+    async fn fetch(&self) -> Result<(AccessToken, Option<Instant>), ClientCredentialsError> {
+        log::debug!("Fetching client credentials token for {}", self.client_id);
+        let request = self
+            .client
+            .post(&self.token_endpoint)
+            .basic_auth(&self.client_id, Some(self.client_secret.as_str()))
+            .form(&ClientCredentialsRequest {
+                grant_type: "client_credentials",
+                scope: self.scope.as_deref(),
+                audience: self.audience.as_deref(),
+            });
+        let (token, expires_in) = request_token(request, "client credentials grant").await?;
+        let refresh_at =
+            expires_in.map(|expires_in| Instant::now() + renew_after(expires_in, REFRESH_MARGIN));
+        Ok((token, refresh_at))
+    }
+}
+
+/// Refreshes the token cached in `cached` until the managers sharing it are dropped.
+async fn refresh(config: Arc<Config>, cached: Weak<Mutex<CachedToken>>) {
+    loop {
+        let Some(cached) = cached.upgrade() else {
+            return;
+        };
+        let manager = ClientCredentialsManager {
+            config: config.clone(),
+            cached,
+        };
+        // Fetches a token if the cached one is missing or due for a refresh.
+        let delay = match manager.token().await {
+            Ok(_) => {
+                let refresh_at = manager
+                    .cached
+                    .lock()
+                    .await
+                    .as_ref()
+                    .and_then(|(_, refresh_at)| *refresh_at);
+                match refresh_at {
+                    Some(refresh_at) => refresh_at
+                        .saturating_duration_since(Instant::now())
+                        .max(MIN_TOKEN_USE),
+                    // Without an expiry there is nothing to refresh ahead of.
+                    None => return,
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to refresh client credentials token: {e}");
+                RETRY_DELAY
+            }
+        };
+        // Do not keep the managers' cache alive while sleeping.
+        drop(manager);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// The background task of a [`ClientCredentialsManager`], refreshing its token.
+pub struct ClientCredentialsTask(BoxFuture<'static, ()>);
+
+impl Future for ClientCredentialsTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
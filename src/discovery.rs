@@ -0,0 +1,352 @@
+use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
+use futures::future::BoxFuture;
+use http::StatusCode;
+use serde::Deserialize;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, PoisonError, RwLock, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::error::ConfigError;
+use crate::hooks::{DiscoveryChange, DiscoveryHook};
+use crate::issuer::is_plain_http;
+use crate::readiness::KeyStatus;
+
+/// The fields of an OpenID Provider Configuration or OAuth 2.0 Authorization Server
+/// Metadata document used by the layer.
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    jwks_uri: String,
+}
+
+/// Why fetching one metadata document failed.
+enum FetchError {
+    /// The document does not exist at this location, so another may be tried.
+    NotFound(String),
+    Invalid(String),
+}
+
+/// The issuer whose discovery document
+/// [`OidcAuthLayer::discover_with`](crate::OidcAuthLayer::discover_with) fetches, and the
+/// HTTP client it fetches it with. Requires the `discovery` feature.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    issuer: String,
+    client: reqwest::Client,
+    allow_insecure_http: bool,
+}
+
+impl Discovery {
+    /// Discovers the JWKS URL of `issuer` with a client timing out after 5 seconds.
+    pub fn new(issuer: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self {
+            issuer: issuer.into(),
+            client,
+            allow_insecure_http: false,
+        }
+    }
+
+    /// Fetches the discovery document with `client` instead, e.g. to configure timeouts,
+    /// proxies or TLS.
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Accepts an issuer and JWKS URL using plain `http`, and makes the layer
+    /// [allow insecure `http`](crate::OidcAuthLayer::allow_insecure_http). Only for local
+    /// development.
+    pub fn allow_insecure_http(mut self) -> Self {
+        self.allow_insecure_http = true;
+        self
+    }
+
+    pub(crate) fn allows_insecure_http(&self) -> bool {
+        self.allow_insecure_http
+    }
+
+    /// Fetches the discovery document and returns the issuer's JWKS URL, as
+    /// [`OidcAuthLayer::discover`](crate::OidcAuthLayer::discover) does. Fails with
+    /// [`ConfigError::InsecureUrl`] if the issuer, checked before fetching anything, or the
+    /// JWKS URL uses plain `http` without [`allow_insecure_http`](Self::allow_insecure_http).
+    pub async fn jwks_uri(&self) -> Result<String, ConfigError> {
+        let check = |url: &str| match !self.allow_insecure_http && is_plain_http(url) {
+            true => Err(ConfigError::InsecureUrl(url.to_string())),
+            false => Ok(()),
+        };
+        check(&self.issuer)?;
+        let jwks_uri = jwks_uri(&self.client, &self.issuer).await?;
+        check(&jwks_uri)?;
+        Ok(jwks_uri)
+    }
+}
+
+/// Fetches the discovery document of `issuer` with `client` and returns its JWKS URL.
+///
+/// The OpenID Connect discovery document is tried first. If the issuer does not publish
+/// one (`404 Not Found` or `410 Gone`), its [RFC 8414] authorization server metadata is
+/// used instead. As required by both specifications, the document's `issuer` must be
+/// identical to `issuer`, so a document served for another issuer cannot redirect key
+/// lookups.
+///
+/// [RFC 8414]: https://www.rfc-editor.org/rfc/rfc8414
+async fn jwks_uri(client: &reqwest::Client, issuer: &str) -> Result<String, ConfigError> {
+    let oidc = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let oauth = oauth_metadata_url(issuer);
+    let error = match fetch(client, &oidc, issuer).await {
+        Ok(jwks_uri) => return Ok(jwks_uri),
+        Err(FetchError::NotFound(reason)) => reason,
+        Err(FetchError::Invalid(reason)) => return Err(ConfigError::Discovery(reason)),
+    };
+    match fetch(client, &oauth, issuer).await {
+        Ok(jwks_uri) => Ok(jwks_uri),
+        Err(FetchError::NotFound(reason)) => {
+            Err(ConfigError::Discovery(format!("{error}; {reason}")))
+        }
+        Err(FetchError::Invalid(reason)) => Err(ConfigError::Discovery(reason)),
+    }
+}
+
+/// Returns the RFC 8414 metadata URL of `issuer`, which inserts the well-known path
+/// between the host and the path of the issuer.
+fn oauth_metadata_url(issuer: &str) -> String {
+    const WELL_KNOWN: &str = "/.well-known/oauth-authorization-server";
+    let issuer = issuer.trim_end_matches('/');
+    let authority_start = issuer.find("://").map_or(0, |i| i + 3);
+    match issuer[authority_start..].find('/') {
+        Some(path_start) => {
+            let (origin, path) = issuer.split_at(authority_start + path_start);
+            format!("{origin}{WELL_KNOWN}{path}")
+        }
+        None => format!("{issuer}{WELL_KNOWN}"),
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str, issuer: &str) -> Result<String, FetchError> {
+    let invalid = |reason: String| FetchError::Invalid(format!("{url}: {reason}"));
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| invalid(e.to_string()))?;
+    let status = response.status();
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Err(FetchError::NotFound(format!("{url}: status {status}")));
+    }
+    // Any other failure is an outage or misconfiguration, not a missing document: falling
+    // back to another document would hide it.
+    if !status.is_success() {
+        return Err(invalid(format!("status {status}")));
+    }
+    let metadata: ProviderMetadata = response.json().await.map_err(|e| invalid(e.to_string()))?;
+    if metadata.issuer != issuer {
+        return Err(invalid(format!(
+            "document is for issuer {}, not {issuer}",
+            metadata.issuer
+        )));
+    }
+    Ok(metadata.jwks_uri)
+}
+
+/// An issuer whose JWKS URL was discovered, together with the validator for that URL.
+pub(crate) struct DiscoveredIssuer {
+    issuer: String,
+    client: reqwest::Client,
+    /// The client ID of the validators, which is not used; `validation` checks the audiences.
+    client_id: String,
+    pub(crate) validation: Arc<Validation>,
+    /// Swapped as a whole when the JWKS URL changes, together with the validator for it.
+    current: RwLock<(String, Arc<OidcValidator>)>,
+}
+
+impl DiscoveredIssuer {
+    /// Discovers the JWKS URL of the issuer of `discovery`, expecting the `issuer` of the
+    /// discovery document in the `iss` claim in place of any issuers `validation` expects.
+    pub(crate) async fn discover(
+        discovery: Discovery,
+        mut validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        let jwks_uri = discovery.jwks_uri().await?;
+        let Discovery { issuer, client, .. } = discovery;
+        let issuer = issuer.as_str();
+        // `jwks_uri` has checked that the document names `issuer`.
+        validation.set_issuer(&[issuer]);
+        let client_id = validation
+            .aud
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(",");
+        let validator = validator_for(issuer, &client_id, &jwks_uri);
+        Ok(Self {
+            issuer: issuer.to_string(),
+            client,
+            client_id,
+            validation: Arc::new(validation),
+            current: RwLock::new((jwks_uri, validator)),
+        })
+    }
+
+    /// Returns the issuer and the current JWKS URL.
+    pub(crate) fn urls(&self) -> Vec<String> {
+        let jwks_uri = self
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            .clone();
+        vec![self.issuer.clone(), jwks_uri]
+    }
+
+    /// Returns the validator for the current JWKS URL.
+    pub(crate) fn validator(&self) -> Arc<OidcValidator> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .1
+            .clone()
+    }
+}
+
+fn validator_for(issuer: &str, client_id: &str, jwks_uri: &str) -> Arc<OidcValidator> {
+    Arc::new(OidcValidator::new(OidcConfig::new(
+        issuer.to_string(),
+        client_id.to_string(),
+        jwks_uri.to_string(),
+    )))
+}
+
+/// When a [`DiscoveryRefreshTask`] fetches the discovery document again, and whom it tells
+/// about a changed JWKS URL. Requires the `discovery` feature.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{DiscoveryChange, DiscoveryRefresh};
+/// use std::time::Duration;
+///
+/// # fn run(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let refresh = DiscoveryRefresh::new(Duration::from_secs(3600)).on_change(
+///     |change: DiscoveryChange| {
+///         println!("{} moved its keys to {}", change.issuer, change.jwks_uri);
+///     },
+/// );
+/// tokio::spawn(auth_layer.discovery_refresh_task(refresh));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DiscoveryRefresh {
+    interval: Duration,
+    on_change: Option<Arc<dyn DiscoveryHook>>,
+}
+
+impl DiscoveryRefresh {
+    /// Fetches the discovery document every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            on_change: None,
+        }
+    }
+
+    /// Calls `hook` each time the layer switches to a new JWKS URL.
+    pub fn on_change(mut self, hook: impl DiscoveryHook) -> Self {
+        self.on_change = Some(Arc::new(hook));
+        self
+    }
+}
+
+/// Fetches the discovery document of `issuer` as configured by `refresh`, until `issuer`
+/// is dropped, recording a switch to new keys in `status`. JWKS URLs using plain `http` are
+/// only followed if `allow_insecure_http`.
+pub(crate) fn refresh_task(
+    issuer: Option<Weak<DiscoveredIssuer>>,
+    refresh: DiscoveryRefresh,
+    allow_insecure_http: bool,
+    status: Arc<KeyStatus>,
+) -> DiscoveryRefreshTask {
+    DiscoveryRefreshTask(Box::pin(async move {
+        if let Some(issuer) = issuer {
+            run(issuer, refresh, allow_insecure_http, status).await;
+        }
+    }))
+}
+
+async fn run(
+    issuer: Weak<DiscoveredIssuer>,
+    refresh: DiscoveryRefresh,
+    allow_insecure_http: bool,
+    status: Arc<KeyStatus>,
+) {
+    loop {
+        tokio::time::sleep(refresh.interval).await;
+        let Some(issuer) = issuer.upgrade() else {
+            return;
+        };
+        let jwks_uri = match jwks_uri(&issuer.client, &issuer.issuer).await {
+            Ok(jwks_uri) => jwks_uri,
+            Err(e) => {
+                log::warn!("Failed to refresh the discovery document: {e}");
+                continue;
+            }
+        };
+        let previous = issuer
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            .clone();
+        if jwks_uri == previous {
+            continue;
+        }
+        if !allow_insecure_http && is_plain_http(&jwks_uri) {
+            log::warn!("Keeping JWKS URL {previous}, the new URL {jwks_uri} does not use https");
+            continue;
+        }
+        // Requests keep using the old keys until the new ones are loaded.
+        let validator = validator_for(&issuer.issuer, &issuer.client_id, &jwks_uri);
+        if let Err(e) = validator.refresh_jwks_cache().await {
+            log::warn!("Keeping JWKS URL {previous}, the keys at {jwks_uri} are unavailable: {e}");
+            continue;
+        }
+        log::info!(
+            "JWKS URL of {} changed from {previous} to {jwks_uri}",
+            issuer.issuer
+        );
+        *issuer
+            .current
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = (jwks_uri.clone(), validator);
+        status.keys_replaced();
+        if let Some(hook) = &refresh.on_change {
+            hook.jwks_uri_changed(DiscoveryChange {
+                issuer: issuer.issuer.clone(),
+                previous_jwks_uri: previous,
+                jwks_uri,
+            });
+        }
+    }
+}
+
+/// The background task of
+/// [`OidcAuthLayer::discovery_refresh_task`](crate::OidcAuthLayer::discovery_refresh_task),
+/// picking up changes to the issuer's JWKS URL.
+pub struct DiscoveryRefreshTask(BoxFuture<'static, ()>);
+
+impl Future for DiscoveryRefreshTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,171 @@
+use async_oidc_jwt_validator::Validation;
+use jsonwebtoken::Algorithm;
+
+use crate::error::ConfigError;
+use crate::issuer::is_plain_http;
+use crate::layer::AuthMode;
+
+/// The issuer tokens must be issued by, the value of their `iss` claim.
+pub(crate) const ISSUER: &str = "OIDC_ISSUER";
+/// The accepted audiences, separated by commas.
+pub(crate) const AUDIENCE: &str = "OIDC_AUDIENCE";
+/// The URL of the issuer's JWKS document.
+pub(crate) const JWKS_URL: &str = "OIDC_JWKS_URL";
+/// An inline JWKS document, used instead of fetching one.
+pub(crate) const JWKS: &str = "OIDC_JWKS";
+/// The leeway for `exp` and `nbf` checks, in seconds.
+pub(crate) const LEEWAY_SECS: &str = "OIDC_LEEWAY_SECS";
+/// The accepted signing algorithms, separated by commas.
+pub(crate) const ALGORITHMS: &str = "OIDC_ALGORITHMS";
+/// `strict` or `optional`.
+pub(crate) const AUTH_MODE: &str = "OIDC_AUTH_MODE";
+/// `true` to accept an issuer or JWKS URL using plain `http`.
+pub(crate) const ALLOW_INSECURE_HTTP: &str = "OIDC_ALLOW_INSECURE_HTTP";
+
+/// Where the signing keys configured in the environment come from.
+pub(crate) enum EnvKeys {
+    Url(String),
+    Inline(String),
+}
+
+/// The layer configuration read from the environment by
+/// [`OidcAuthLayer::from_env`](crate::OidcAuthLayer::from_env).
+pub(crate) struct EnvConfig {
+    pub(crate) issuer: String,
+    pub(crate) audiences: Vec<String>,
+    pub(crate) keys: EnvKeys,
+    pub(crate) leeway: Option<u64>,
+    pub(crate) algorithms: Vec<Algorithm>,
+    pub(crate) mode: AuthMode,
+    pub(crate) allow_insecure_http: bool,
+}
+
+impl EnvConfig {
+    /// Reads the configuration through `var`, reporting every missing variable at once.
+    pub(crate) fn read(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        // Blank values are treated as unset, as empty variables often are in deployments.
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let issuer = var(ISSUER);
+        let audiences = var(AUDIENCE).map(|audience| {
+            audience
+                .split(',')
+                .map(str::trim)
+                .filter(|audience| !audience.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        });
+        let keys = match (var(JWKS_URL), var(JWKS)) {
+            (Some(_), Some(_)) => {
+                return Err(ConfigError::InvalidEnv(
+                    JWKS.to_string(),
+                    format!("cannot be combined with {JWKS_URL}"),
+                ))
+            }
+            (Some(url), None) => Some(EnvKeys::Url(url)),
+            (None, Some(jwks)) => Some(EnvKeys::Inline(jwks)),
+            (None, None) => None,
+        };
+
+        let mut missing = Vec::new();
+        if issuer.is_none() {
+            missing.push(ISSUER.to_string());
+        }
+        if audiences.as_ref().is_none_or(Vec::is_empty) {
+            missing.push(AUDIENCE.to_string());
+        }
+        if keys.is_none() {
+            missing.push(format!("{JWKS_URL} or {JWKS}"));
+        }
+        let (issuer, audiences, keys) = match (issuer, audiences, keys) {
+            (Some(issuer), Some(audiences), Some(keys)) if missing.is_empty() => {
+                (issuer, audiences, keys)
+            }
+            _ => return Err(ConfigError::MissingEnv(missing)),
+        };
+
+        let leeway = var(LEEWAY_SECS)
+            .map(|leeway| {
+                leeway.trim().parse().map_err(|_| {
+                    ConfigError::InvalidEnv(
+                        LEEWAY_SECS.to_string(),
+                        format!("`{leeway}` is not a number of seconds"),
+                    )
+                })
+            })
+            .transpose()?;
+        let algorithms = match var(ALGORITHMS) {
+            None => vec![Algorithm::RS256],
+            Some(algorithms) => algorithms
+                .split(',')
+                .map(str::trim)
+                .filter(|algorithm| !algorithm.is_empty())
+                .map(|algorithm| {
+                    algorithm.parse().map_err(|_| {
+                        ConfigError::InvalidEnv(
+                            ALGORITHMS.to_string(),
+                            format!("`{algorithm}` is not a signing algorithm"),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        let mode = match var(AUTH_MODE).map(|mode| mode.trim().to_ascii_lowercase()) {
+            None => AuthMode::default(),
+            Some(mode) if mode == "strict" => AuthMode::Strict,
+            Some(mode) if mode == "optional" => AuthMode::Optional,
+            Some(mode) => {
+                return Err(ConfigError::InvalidEnv(
+                    AUTH_MODE.to_string(),
+                    format!("`{mode}` is neither `strict` nor `optional`"),
+                ))
+            }
+        };
+        let allow_insecure_http =
+            match var(ALLOW_INSECURE_HTTP).map(|allow| allow.trim().to_ascii_lowercase()) {
+                None => false,
+                Some(allow) if allow == "true" => true,
+                Some(allow) if allow == "false" => false,
+                Some(allow) => {
+                    return Err(ConfigError::InvalidEnv(
+                        ALLOW_INSECURE_HTTP.to_string(),
+                        format!("`{allow}` is neither `true` nor `false`"),
+                    ))
+                }
+            };
+        if !allow_insecure_http {
+            let jwks_url = match &keys {
+                EnvKeys::Url(url) => Some(url),
+                EnvKeys::Inline(_) => None,
+            };
+            if let Some(url) = [Some(&issuer), jwks_url]
+                .into_iter()
+                .flatten()
+                .find(|url| is_plain_http(url))
+            {
+                return Err(ConfigError::InsecureUrl(url.clone()));
+            }
+        }
+        Ok(Self {
+            issuer,
+            audiences,
+            keys,
+            leeway,
+            algorithms,
+            mode,
+            allow_insecure_http,
+        })
+    }
+
+    /// Returns the validation rules for the configured issuer, audiences, algorithms and
+    /// leeway.
+    pub(crate) fn validation(&self) -> Validation {
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.algorithms = self.algorithms.clone();
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&self.audiences);
+        if let Some(leeway) = self.leeway {
+            validation.leeway = leeway;
+        }
+        validation
+    }
+}
@@ -0,0 +1,419 @@
+use axum::response::{IntoResponse, Response};
+use http::{header, HeaderValue, StatusCode};
+use serde::Serialize;
+use std::fmt;
+
+use crate::audience::AudienceMismatch;
+use crate::tenant::TenantId;
+
+/// The reason of [`AuthError::InvalidSignature`] for tokens whose `kid` names no key of the
+/// issuer, even after its keys were fetched again.
+pub(crate) const NO_MATCHING_KEY: &str = "no matching signing key";
+
+/// The reason of [`AuthError::ConfigUnavailable`] when a layer in offline mode would have to
+/// fetch signing keys.
+pub(crate) const OFFLINE_FETCH: &str = "offline mode forbids fetching signing keys";
+
+/// The reason a request failed authentication or authorization.
+///
+/// Converting it into a response yields the same rejection the middleware sends in
+/// [`AuthMode::Strict`](crate::AuthMode::Strict) with [`ErrorFormat::PlainText`].
+///
+/// In [`AuthMode::Optional`](crate::AuthMode::Optional), the error of a request that failed
+/// authentication is inserted into its extensions, where downstream middleware can read it
+/// and handlers can extract it with `Extension<AuthError>` or
+/// [`AuthResult`](crate::AuthResult).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuthError {
+    /// No token was found in the request.
+    MissingToken,
+    /// The token is not a well-formed JWT, or its JOSE header cannot be decoded.
+    MalformedHeader(String),
+    /// The token's `exp` claim is in the past.
+    Expired,
+    /// The token's signature does not verify against any key of the issuer.
+    InvalidSignature(String),
+    /// The token's `aud` claim does not contain an accepted audience.
+    WrongAudience,
+    /// The token's `aud` claim failed the
+    /// [`AudienceCheck`](crate::AudienceCheck) of the layer, for the given reason.
+    AudienceMismatch(AudienceMismatch),
+    /// The token's `iss` claim is not the expected issuer.
+    WrongIssuer,
+    /// The token's `iss` claim, given, differs from the `issuer` of the discovery document
+    /// the layer was built from with
+    /// [`OidcAuthLayer::discover`](crate::OidcAuthLayer::discover).
+    IssuerMismatch(String),
+    /// The issuer's signing keys could not be fetched.
+    JwksUnavailable(String),
+    /// The token is valid but its claims could not be deserialized into the claims type.
+    ClaimsDeserialization(String),
+    /// The token failed another validation check, such as `nbf` or a required claim.
+    InvalidToken(String),
+    /// The token was issued by an issuer the layer does not trust.
+    UnknownIssuer(String),
+    /// The tenant of the request could not be resolved or has no issuer configured.
+    UnknownTenant(Option<TenantId>),
+    /// The configuration needed to validate the token could not be loaded.
+    ConfigUnavailable(String),
+    /// The token is valid but lacks some of the listed scopes required by the route.
+    InsufficientScope(Vec<String>),
+    /// The token is valid but lacks some of the listed roles required by the route.
+    MissingRoles(Vec<String>),
+    /// The token is valid but an authorization check of the route denied the request, for
+    /// the given reason if any.
+    AccessDenied(Option<String>),
+    /// The authorization policy of the layer could not be evaluated.
+    PolicyUnavailable(String),
+    /// A token source of the request is malformed or ambiguous, such as a non-ASCII or
+    /// repeated `Authorization` header, and the layer is configured with
+    /// [`MalformedCredentials::Reject`](crate::MalformedCredentials::Reject).
+    MalformedCredentials(String),
+}
+
+impl AuthError {
+    /// Classifies an error returned by the OIDC validator.
+    pub(crate) fn from_jwt(error: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+
+        match error.kind() {
+            ErrorKind::ExpiredSignature => AuthError::Expired,
+            ErrorKind::InvalidAudience => AuthError::WrongAudience,
+            ErrorKind::InvalidIssuer => AuthError::WrongIssuer,
+            // The validator reports JWKS fetch failures as key errors.
+            ErrorKind::InvalidRsaKey(reason) if reason.contains("JWKS") => {
+                AuthError::JwksUnavailable(reason.clone())
+            }
+            // The validator reports a `kid` without a matching key as an invalid token.
+            ErrorKind::InvalidToken => AuthError::InvalidSignature(NO_MATCHING_KEY.to_string()),
+            ErrorKind::InvalidSignature
+            | ErrorKind::InvalidRsaKey(_)
+            | ErrorKind::InvalidEcdsaKey
+            | ErrorKind::InvalidKeyFormat
+            | ErrorKind::InvalidAlgorithm => AuthError::InvalidSignature(error.to_string()),
+            ErrorKind::Json(e) => AuthError::ClaimsDeserialization(e.to_string()),
+            _ => AuthError::InvalidToken(error.to_string()),
+        }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            AuthError::JwksUnavailable(_)
+            | AuthError::ConfigUnavailable(_)
+            | AuthError::PolicyUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AuthError::InsufficientScope(_)
+            | AuthError::MissingRoles(_)
+            | AuthError::AccessDenied(_) => StatusCode::FORBIDDEN,
+            AuthError::MalformedCredentials(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// A short, stable identifier for the failure, used in problem type URIs.
+    ///
+    /// Token validation failures share `invalid-token`; the detail tells them apart.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingToken => "missing-token",
+            AuthError::MalformedHeader(_)
+            | AuthError::Expired
+            | AuthError::InvalidSignature(_)
+            | AuthError::WrongAudience
+            | AuthError::AudienceMismatch(_)
+            | AuthError::WrongIssuer
+            | AuthError::IssuerMismatch(_)
+            | AuthError::ClaimsDeserialization(_)
+            | AuthError::InvalidToken(_) => "invalid-token",
+            AuthError::JwksUnavailable(_) => "jwks-unavailable",
+            AuthError::UnknownIssuer(_) => "unknown-issuer",
+            AuthError::UnknownTenant(_) => "unknown-tenant",
+            AuthError::ConfigUnavailable(_) => "config-unavailable",
+            AuthError::InsufficientScope(_) => "insufficient-scope",
+            AuthError::MissingRoles(_) => "missing-roles",
+            AuthError::AccessDenied(_) => "access-denied",
+            AuthError::PolicyUnavailable(_) => "policy-unavailable",
+            AuthError::MalformedCredentials(_) => "invalid-request",
+        }
+    }
+
+    /// The `WWW-Authenticate` challenge for this error, if a new token could help.
+    pub(crate) fn www_authenticate(&self) -> Option<HeaderValue> {
+        let challenge = match self {
+            AuthError::MissingToken
+            | AuthError::JwksUnavailable(_)
+            | AuthError::ConfigUnavailable(_) => HeaderValue::from_static("Bearer"),
+            AuthError::InsufficientScope(scopes) => HeaderValue::from_str(&format!(
+                "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                scopes.join(" ")
+            ))
+            .unwrap_or_else(|_| HeaderValue::from_static("Bearer error=\"insufficient_scope\"")),
+            AuthError::MissingRoles(_) => {
+                HeaderValue::from_static("Bearer error=\"insufficient_scope\"")
+            }
+            AuthError::MalformedCredentials(_) => {
+                HeaderValue::from_static("Bearer error=\"invalid_request\"")
+            }
+            AuthError::AccessDenied(_) | AuthError::PolicyUnavailable(_) => return None,
+            _ => HeaderValue::from_static("Bearer error=\"invalid_token\""),
+        };
+        Some(challenge)
+    }
+
+    /// Builds the rejection response for this error in the requested format.
+    pub(crate) fn to_response(&self, format: ErrorFormat, instance: Option<&str>) -> Response {
+        let mut response = match format {
+            ErrorFormat::PlainText => (self.status(), self.to_string()).into_response(),
+            ErrorFormat::ProblemJson => {
+                ProblemDetails::from_auth_error(self, instance).into_response()
+            }
+        };
+        if let Some(challenge) = self.www_authenticate() {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "No bearer token was provided"),
+            AuthError::MalformedHeader(reason) => {
+                write!(f, "The bearer token is malformed: {reason}")
+            }
+            AuthError::Expired => write!(f, "The bearer token has expired"),
+            AuthError::InvalidSignature(reason) => {
+                write!(f, "The bearer token signature is invalid: {reason}")
+            }
+            AuthError::WrongAudience => {
+                write!(f, "The bearer token was not issued for this audience")
+            }
+            AuthError::AudienceMismatch(mismatch) => {
+                write!(
+                    f,
+                    "The bearer token was not issued for this audience: {mismatch}"
+                )
+            }
+            AuthError::WrongIssuer => write!(f, "The bearer token has an unexpected issuer"),
+            AuthError::IssuerMismatch(iss) => write!(
+                f,
+                "The bearer token was issued by {iss}, not the discovered issuer"
+            ),
+            AuthError::ClaimsDeserialization(reason) => {
+                write!(
+                    f,
+                    "The bearer token does not carry the required claims: {reason}"
+                )
+            }
+            AuthError::InvalidToken(reason) => write!(f, "The bearer token is invalid: {reason}"),
+            AuthError::UnknownIssuer(iss) => write!(f, "Tokens from issuer {iss} are not accepted"),
+            AuthError::UnknownTenant(Some(tenant)) => write!(f, "Unknown tenant {tenant}"),
+            AuthError::UnknownTenant(None) => write!(f, "The tenant could not be determined"),
+            AuthError::JwksUnavailable(_) | AuthError::ConfigUnavailable(_) => {
+                write!(f, "Authentication is temporarily unavailable")
+            }
+            AuthError::InsufficientScope(scopes) => {
+                write!(
+                    f,
+                    "The bearer token lacks the required scopes: {}",
+                    scopes.join(" ")
+                )
+            }
+            AuthError::AccessDenied(Some(reason)) => write!(f, "Access denied: {reason}"),
+            AuthError::AccessDenied(None) => write!(f, "Access denied"),
+            AuthError::PolicyUnavailable(_) => {
+                write!(f, "Authorization is temporarily unavailable")
+            }
+            AuthError::MalformedCredentials(reason) => {
+                write!(f, "The request credentials are malformed: {reason}")
+            }
+            AuthError::MissingRoles(roles) => {
+                write!(
+                    f,
+                    "The bearer token lacks the required roles: {}",
+                    roles.join(" ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Responds with the status, plain-text message and `WWW-Authenticate` header the middleware
+/// uses for this error.
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        self.to_response(ErrorFormat::PlainText, None)
+    }
+}
+
+/// A combination of [`OidcAuthLayer`](crate::OidcAuthLayer) options that cannot take effect,
+/// reported by [`OidcAuthLayer::validate`](crate::OidcAuthLayer::validate), an invalid
+/// JWKS document passed to [`OidcAuthLayer::with_static_jwks`](crate::OidcAuthLayer::with_static_jwks),
+/// or incomplete settings read by [`OidcAuthLayer::from_env`](crate::OidcAuthLayer::from_env).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// `strip_query_param` was called without a query parameter configured first.
+    StripWithoutQueryParam,
+    /// A cookie or query parameter source was configured with an empty name.
+    EmptySourceName,
+    /// A cookie or query parameter source was configured alongside a custom extractor chain,
+    /// which replaces the built-in sources.
+    SourcesReplacedByChain,
+    /// Token sources were configured in trusted gateway mode, which does not read tokens.
+    IgnoredByTrustedGateway,
+    /// An error format, login redirect, renderer or [`KeysUnavailable`](crate::KeysUnavailable)
+    /// policy was configured in
+    /// [`AuthMode::Optional`](crate::AuthMode::Optional), which never rejects requests.
+    RejectionsWithoutStrictMode,
+    /// A static JWKS document could not be parsed, holds a key that cannot be decoded, or
+    /// holds no keys usable for verifying signatures. Encryption keys, and keys whose
+    /// `key_ops` do not include `verify`, are skipped.
+    InvalidJwks(String),
+    /// The listed environment variables required by
+    /// [`OidcAuthLayer::from_env`](crate::OidcAuthLayer::from_env) are unset or empty.
+    MissingEnv(Vec<String>),
+    /// The named environment variable read by
+    /// [`OidcAuthLayer::from_env`](crate::OidcAuthLayer::from_env) has an invalid value, for
+    /// the given reason.
+    InvalidEnv(String, String),
+    /// The layer is in [offline mode](crate::OidcAuthLayer::with_offline_mode) but its
+    /// signing keys, or the configuration of its tenants, would be fetched over the network.
+    NetworkInOfflineMode,
+    /// The discovery document of an issuer could not be fetched, could not be parsed, or
+    /// names another issuer, for the given reason.
+    Discovery(String),
+    /// The given issuer, discovery or JWKS URL uses plain `http`, without
+    /// [`OidcAuthLayer::allow_insecure_http`](crate::OidcAuthLayer::allow_insecure_http).
+    InsecureUrl(String),
+    /// An [`IssuerTemplate`](crate::IssuerTemplate) has no `{claim}` placeholder, for the
+    /// given reason.
+    InvalidIssuerTemplate(String),
+    /// An [`AudienceCheck`](crate::AudienceCheck) passed to
+    /// [`OidcAuthLayer::with_audience`](crate::OidcAuthLayer::with_audience) lists no
+    /// audiences, so it would reject every token, or accept tokens for any audience.
+    EmptyAudienceCheck,
+    /// The JWKS document of a [`JwksFetcher`](crate::JwksFetcher) could not be fetched, for
+    /// the given reason.
+    JwksFetch(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::StripWithoutQueryParam => {
+                write!(
+                    f,
+                    "strip_query_param requires with_query_param to be called first"
+                )
+            }
+            ConfigError::EmptySourceName => {
+                write!(f, "cookie and query parameter names must not be empty")
+            }
+            ConfigError::SourcesReplacedByChain => write!(
+                f,
+                "with_cookie and with_query_param have no effect with a custom extractor chain"
+            ),
+            ConfigError::IgnoredByTrustedGateway => write!(
+                f,
+                "token sources have no effect when trusting a gateway payload"
+            ),
+            ConfigError::RejectionsWithoutStrictMode => write!(
+                f,
+                "rejection options have no effect unless AuthMode::Strict is set"
+            ),
+            ConfigError::InvalidJwks(reason) => write!(f, "invalid static JWKS: {reason}"),
+            ConfigError::MissingEnv(names) => {
+                write!(f, "missing environment variables: {}", names.join(", "))
+            }
+            ConfigError::InvalidEnv(name, reason) => {
+                write!(f, "invalid environment variable {name}: {reason}")
+            }
+            ConfigError::NetworkInOfflineMode => write!(
+                f,
+                "offline mode requires keys from with_static_jwks or with_jwks_file"
+            ),
+            ConfigError::Discovery(reason) => write!(f, "OIDC discovery failed: {reason}"),
+            ConfigError::InsecureUrl(url) => write!(
+                f,
+                "{url} does not use https; call allow_insecure_http for local development"
+            ),
+            ConfigError::InvalidIssuerTemplate(reason) => {
+                write!(f, "invalid issuer template {reason}")
+            }
+            ConfigError::EmptyAudienceCheck => {
+                write!(f, "the audience check lists no audiences")
+            }
+            ConfigError::JwksFetch(reason) => write!(f, "failed to fetch JWKS: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The body format used for responses generated by the middleware itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// A `text/plain` body containing a human-readable message.
+    #[default]
+    PlainText,
+    /// An [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) `application/problem+json` body.
+    ProblemJson,
+}
+
+/// An [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) problem details object.
+///
+/// Responds with `Content-Type: application/problem+json` when converted into a response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProblemDetails {
+    /// A URI reference identifying the problem type.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// A short, human-readable summary of the problem type.
+    pub title: String,
+    /// The HTTP status code.
+    pub status: u16,
+    /// A human-readable explanation specific to this occurrence of the problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// A URI reference identifying the specific occurrence of the problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+impl ProblemDetails {
+    /// The media type of a problem details JSON document.
+    pub const CONTENT_TYPE: &'static str = "application/problem+json";
+
+    pub(crate) fn from_auth_error(error: &AuthError, instance: Option<&str>) -> Self {
+        let status = error.status();
+        Self {
+            problem_type: format!("urn:axum-jwt-oidc:error:{}", error.code()),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: Some(error.to_string()),
+            instance: instance.map(str::to_string),
+        }
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_vec(&self).unwrap_or_default();
+        (
+            status,
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(Self::CONTENT_TYPE),
+            )],
+            body,
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,457 @@
+use axum::{
+    extract::FromRequestParts,
+    response::{IntoResponse, Response},
+};
+use http::{request::Parts, StatusCode};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+use zeroize::Zeroizing;
+
+use crate::budget::{self, CacheBudget, CacheKind, ENTRY_OVERHEAD};
+use crate::context::AccessToken;
+use crate::extract::{claims_from_parts, ClaimsRejection, ValidatedPayload};
+use crate::token_endpoint::{renew_after, request_token};
+
+/// The error type of [`TokenExchanger::exchange`].
+pub type ExchangeError = Box<dyn std::error::Error + Send + Sync>;
+
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+const JWT_BEARER: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// The grant a [`TokenExchanger`] requests tokens with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grant {
+    /// RFC 8693 token exchange.
+    TokenExchange,
+    /// The Microsoft identity platform's on-behalf-of flow.
+    OnBehalfOf,
+}
+
+/// How long before their expiry cached tokens are exchanged again, so they do not expire in
+/// flight. Tokens living less than twice as long are exchanged again halfway through their
+/// lifetime instead.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Trades validated inbound tokens for tokens scoped to a downstream audience at the
+/// identity provider's token endpoint, following
+/// [RFC 8693](https://www.rfc-editor.org/rfc/rfc8693). Requires the `exchange` feature.
+///
+/// Exchanged tokens are cached per issuer, subject and audience until shortly before they
+/// expire, so each service-to-service hop does not cost a round trip to the identity
+/// provider. The issuer and subject are read from the `iss` and `sub` claims of the inbound
+/// token, so users of different issuers with the same `sub` never share a token. Tokens
+/// issued without `expires_in` are not cached. Up to 10 000 tokens are cached, the one cached
+/// longest ago being dropped to make room, and a [`CacheBudget`] set with
+/// [`with_budget`](Self::with_budget) bounds the cache together with others. With the
+/// `forward` feature, pass exchanged tokens to `forward_token` so clients wrapped in a
+/// `ForwardAuthLayer` attach them.
+///
+/// ```rust,no_run
+/// use axum::Extension;
+/// use axum_jwt_oidc::{AuthContext, TokenExchanger};
+/// use std::sync::Arc;
+///
+/// async fn handler(
+///     Extension(exchanger): Extension<Arc<TokenExchanger>>,
+///     Extension(context): Extension<AuthContext<serde_json::Value>>,
+/// ) -> String {
+///     let token = context.token.expect("the layer sees the token");
+///     match exchanger.exchange(&token, "https://orders.internal").await {
+///         Ok(_orders_token) => "call the orders service with it".to_string(),
+///         Err(error) => format!("exchange failed: {error}"),
+///     }
+/// }
+///
+/// let exchanger = TokenExchanger::new(
+///     "https://idp.example.com/oauth2/token",
+///     "billing-service",
+/// )
+/// .client_secret("secret");
+/// ```
+pub struct TokenExchanger {
+    grant: Grant,
+    token_endpoint: String,
+    client_id: String,
+    client_secret: Option<Zeroizing<String>>,
+    scope: Option<String>,
+    max_entries: usize,
+    client: reqwest::Client,
+    /// The tokens cached longest ago first, with when they are exchanged again and the
+    /// approximate size of their entry.
+    cache: RwLock<LruCache<CacheKey, (AccessToken, Instant, usize)>>,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+/// The issuer, subject and audience a token is cached for.
+type CacheKey = (String, String, String);
+
+/// The claims identifying the user a token is exchanged on behalf of.
+#[derive(Clone, Deserialize)]
+struct Identity {
+    /// Empty for tokens without an `iss` claim.
+    #[serde(default)]
+    iss: String,
+    sub: String,
+}
+
+impl Identity {
+    /// Reads the issuer and subject of the validated `token`.
+    fn of(token: &AccessToken) -> Result<Self, ExchangeError> {
+        ValidatedPayload::from_token(token.expose())
+            .ok_or_else(|| "token is malformed".to_string())?
+            .decode()
+            .map_err(|e| format!("token has no `sub` claim: {e}").into())
+    }
+
+    fn key(&self, audience: &str) -> CacheKey {
+        (self.iss.clone(), self.sub.clone(), audience.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct ExchangeRequest<'a> {
+    grant_type: &'static str,
+    subject_token: &'a str,
+    subject_token_type: &'static str,
+    audience: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct OnBehalfOfRequest<'a> {
+    grant_type: &'static str,
+    assertion: &'a str,
+    requested_token_use: &'static str,
+    scope: &'a str,
+    client_id: &'a str,
+    client_secret: &'a str,
+}
+
+impl TokenExchanger {
+    /// Exchanges tokens at `token_endpoint` as the client `client_id`, giving up after five
+    /// seconds.
+    pub fn new(token_endpoint: impl Into<String>, client_id: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self::with_client(token_endpoint, client_id, client)
+    }
+
+    /// Exchanges tokens with `client`, e.g. to configure timeouts or TLS.
+    pub fn with_client(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            grant: Grant::TokenExchange,
+            token_endpoint: token_endpoint.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            scope: None,
+            max_entries: 10_000,
+            client,
+            cache: RwLock::new(LruCache::unbounded()),
+            budget: None,
+        }
+    }
+
+    /// Obtains tokens with the Microsoft identity platform's
+    /// [on-behalf-of flow](https://learn.microsoft.com/entra/identity-platform/v2-oauth2-on-behalf-of-flow)
+    /// instead of RFC 8693, as the confidential client `client_id`.
+    ///
+    /// The token endpoint of a tenant is
+    /// `https://login.microsoftonline.com/<tenant>/oauth2/v2.0/token`. Each audience passed
+    /// to [`exchange`](Self::exchange), such as `api://other-app`, is requested with its
+    /// `.default` scope unless a [`scope`](Self::scope) is set.
+    pub fn on_behalf_of(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        let mut exchanger = Self::new(token_endpoint, client_id).client_secret(client_secret);
+        exchanger.grant = Grant::OnBehalfOf;
+        exchanger
+    }
+
+    /// Authenticates to the token endpoint with `client_secret`, using HTTP basic
+    /// authentication. Without a secret, the client is identified by its `client_id` only.
+    pub fn client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(Zeroizing::new(client_secret.into()));
+        self
+    }
+
+    /// Requests `scope`, a space-separated list, for every exchanged token.
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Sets the maximum number of cached tokens. When the cache is full, the token cached
+    /// longest ago is dropped. Zero disables the cache.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Counts the cached tokens against `budget`, shared with other caches. When the budget
+    /// is exhausted, the tokens cached longest ago are dropped to make room, and a token is
+    /// not cached if that is not enough.
+    pub fn with_budget(mut self, budget: Arc<CacheBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Drops the token cached for `audience` on behalf of the user of the validated `token`,
+    /// e.g. after the downstream service rejected it.
+    pub fn invalidate(&self, token: &AccessToken, audience: &str) {
+        if let Ok(identity) = Identity::of(token) {
+            let removed = self
+                .cache
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pop(&identity.key(audience));
+            if let Some((_, _, bytes)) = removed {
+                budget::release(self.budget.as_deref(), 1, bytes);
+            }
+        }
+    }
+
+    /// Returns a token for `audience` on behalf of the user of the validated `token`, from
+    /// the cache or by exchanging `token`. Fails if `token` has no `sub` claim.
+    pub async fn exchange(
+        &self,
+        token: &AccessToken,
+        audience: &str,
+    ) -> Result<AccessToken, ExchangeError> {
+        self.exchange_as(&Identity::of(token)?, token, audience)
+            .await
+    }
+
+    /// Returns a token for `audience` on behalf of `identity`, the user of `token`.
+    async fn exchange_as(
+        &self,
+        identity: &Identity,
+        token: &AccessToken,
+        audience: &str,
+    ) -> Result<AccessToken, ExchangeError> {
+        let key = identity.key(audience);
+        let cached = self
+            .cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .peek(&key)
+            .filter(|(_, expires_at, _)| *expires_at > Instant::now())
+            .map(|(token, _, _)| token.clone());
+        if let Some(token) = cached {
+            return Ok(token);
+        }
+
+        let request = self.client.post(&self.token_endpoint);
+        let request = match (self.grant, &self.client_secret) {
+            (Grant::OnBehalfOf, secret) => {
+                let default_scope = format!("{audience}/.default");
+                request.form(&OnBehalfOfRequest {
+                    grant_type: JWT_BEARER,
+                    assertion: token.expose(),
+                    requested_token_use: "on_behalf_of",
+                    scope: self.scope.as_deref().unwrap_or(&default_scope),
+                    client_id: &self.client_id,
+                    client_secret: secret.as_deref().map_or("", String::as_str),
+                })
+            }
+            (Grant::TokenExchange, secret) => {
+                let request = request.form(&ExchangeRequest {
+                    grant_type: GRANT_TYPE,
+                    subject_token: token.expose(),
+                    subject_token_type: ACCESS_TOKEN_TYPE,
+                    audience,
+                    scope: self.scope.as_deref(),
+                    // Public clients identify themselves in the request body.
+                    client_id: secret.is_none().then_some(self.client_id.as_str()),
+                });
+                match secret {
+                    Some(secret) => request.basic_auth(&self.client_id, Some(secret.as_str())),
+                    None => request,
+                }
+            }
+        };
+        let purpose = format!("token exchange for {audience}");
+        let (exchanged, expires_in) = request_token(request, &purpose).await?;
+
+        if let Some(expires_in) = expires_in {
+            let expires_at = Instant::now() + renew_after(expires_in, EXPIRY_MARGIN);
+            self.store(key, exchanged.clone(), expires_at);
+        }
+        Ok(exchanged)
+    }
+
+    fn store(&self, key: CacheKey, token: AccessToken, expires_at: Instant) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let bytes = ENTRY_OVERHEAD + key.0.len() + key.1.len() + key.2.len() + token.expose().len();
+        let budget = self.budget.as_deref();
+        let mut cache = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, _, previous)) = cache.pop(&key) {
+            budget::release(budget, 1, previous);
+        }
+        let mut evicted = Vec::new();
+        if cache.len() >= self.max_entries {
+            self.remove_oldest(&mut cache, now, &mut evicted);
+        }
+        let mut reserved = budget::reserve(budget, bytes);
+        while !reserved && self.remove_oldest(&mut cache, now, &mut evicted) {
+            reserved = budget::reserve(budget, bytes);
+        }
+        if reserved {
+            cache.push(key, (token, expires_at, bytes));
+        } else {
+            log::debug!("Token exchange cache is full, not caching");
+        }
+        drop(cache);
+
+        if let Some(budget) = budget.filter(|_| !evicted.is_empty()) {
+            budget.evicted(CacheKind::ExchangedTokens, &evicted);
+        }
+    }
+
+    /// Drops the token cached longest ago, adding its size to `evicted` unless it had
+    /// expired anyway. Returns `false` if the cache is empty.
+    fn remove_oldest(
+        &self,
+        cache: &mut LruCache<CacheKey, (AccessToken, Instant, usize)>,
+        now: Instant,
+        evicted: &mut Vec<usize>,
+    ) -> bool {
+        let Some((_, (_, expires_at, bytes))) = cache.pop_lru() else {
+            return false;
+        };
+        budget::release(self.budget.as_deref(), 1, bytes);
+        if expires_at > now {
+            evicted.push(bytes);
+        }
+        true
+    }
+}
+
+impl Drop for TokenExchanger {
+    fn drop(&mut self) {
+        let cache = self.cache.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let bytes = cache.iter().map(|(_, (_, _, bytes))| bytes).sum();
+        budget::release(self.budget.as_deref(), cache.len(), bytes);
+    }
+}
+
+/// Extracts tokens for downstream APIs, exchanged on behalf of the authenticated user by the
+/// [`TokenExchanger`] in the request extensions. Requires the `exchange` feature.
+///
+/// The route must be covered by an [`OidcAuthLayer`](crate::OidcAuthLayer) configured with
+/// [`with_access_token`](crate::OidcAuthLayer::with_access_token), and by an
+/// `Extension<Arc<TokenExchanger>>`. Unauthenticated requests, and tokens without a `sub`
+/// claim, are rejected like [`Claims`](crate::Claims) rejects them; routes missing either
+/// layer with `500 Internal Server Error`. Tokens are cached for the `iss` and `sub` claims the
+/// layer validated.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Extension, Router};
+/// use axum_jwt_oidc::{DownstreamTokens, TokenExchanger};
+/// use std::sync::Arc;
+///
+/// async fn handler(tokens: DownstreamTokens) -> String {
+///     match tokens.get("api://other-app").await {
+///         Ok(_token) => "call the other app with it".to_string(),
+///         Err(error) => format!("no token for the other app: {error}"),
+///     }
+/// }
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let exchanger = TokenExchanger::on_behalf_of(
+///     "https://login.microsoftonline.com/contoso.onmicrosoft.com/oauth2/v2.0/token",
+///     "11111111-2222-3333-4444-555555555555",
+///     "client-secret",
+/// );
+/// let app: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(auth_layer.with_access_token())
+///     .layer(Extension(Arc::new(exchanger)));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DownstreamTokens {
+    exchanger: Arc<TokenExchanger>,
+    identity: Identity,
+    token: AccessToken,
+}
+
+impl DownstreamTokens {
+    /// Returns a token for `audience` on behalf of the authenticated user.
+    pub async fn get(&self, audience: &str) -> Result<AccessToken, ExchangeError> {
+        self.exchanger
+            .exchange_as(&self.identity, &self.token, audience)
+            .await
+    }
+}
+
+/// Rejection returned by the [`DownstreamTokens`] extractor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DownstreamTokensRejection {
+    /// The request was not authenticated, or its token has no `sub` claim.
+    Claims(ClaimsRejection),
+    /// The route has no `Extension<Arc<TokenExchanger>>`, or its
+    /// [`OidcAuthLayer`](crate::OidcAuthLayer) does not insert the
+    /// [`AccessToken`](crate::AccessToken).
+    NotConfigured,
+}
+
+impl IntoResponse for DownstreamTokensRejection {
+    fn into_response(self) -> Response {
+        match self {
+            DownstreamTokensRejection::Claims(rejection) => rejection.into_response(),
+            DownstreamTokensRejection::NotConfigured => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Downstream tokens were requested on a route without a token exchanger",
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for DownstreamTokens
+where
+    S: Send + Sync,
+{
+    type Rejection = DownstreamTokensRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let identity: Identity = claims_from_parts(parts, "DownstreamTokens")
+            .map_err(DownstreamTokensRejection::Claims)?;
+        let exchanger = parts.extensions.get::<Arc<TokenExchanger>>().cloned();
+        let token = parts.extensions.get::<AccessToken>().cloned();
+        match (exchanger, token) {
+            (Some(exchanger), Some(token)) => Ok(Self {
+                exchanger,
+                identity,
+                token,
+            }),
+            _ => {
+                log::error!(
+                    "DownstreamTokens extracted without a TokenExchanger extension or \
+                     OidcAuthLayer::with_access_token"
+                );
+                Err(DownstreamTokensRejection::NotConfigured)
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use axum::extract::{MatchedPath, Request};
+use futures::{
+    channel::mpsc,
+    future::BoxFuture,
+    stream::{BoxStream, StreamExt},
+};
+use http::Method;
+use serde::Deserialize;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use crate::extract::ValidatedPayload;
+
+/// Who called what: a summary of one authenticated request, exported by a
+/// [`ClaimsExporter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClaimsSummary {
+    /// The `sub` claim of the token, if present.
+    pub subject: Option<String>,
+    /// The `iss` claim of the token, if present.
+    pub issuer: Option<String>,
+    /// The `client_id` claim of the token, falling back to `azp`.
+    pub client_id: Option<String>,
+    /// The matched route pattern (e.g. `/orders/{id}`), or the request path if unavailable.
+    pub route: String,
+    /// The request method.
+    pub method: Method,
+    /// When the request was authenticated.
+    pub timestamp: SystemTime,
+}
+
+/// The error type of [`ClaimsSink`] writes.
+pub type ClaimsSinkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Persists batches of [`ClaimsSummary`]s, e.g. to a file, Kafka or ClickHouse.
+///
+/// Writes happen on the [`ClaimsExportTask`], off the request path. A failed batch is
+/// logged and dropped. Any `Fn(Vec<ClaimsSummary>) -> impl Future<Output = Result<(),
+/// ClaimsSinkError>>` closure implements this trait.
+pub trait ClaimsSink: Send + Sync + 'static {
+    /// Writes one batch of summaries.
+    fn write(&self, batch: Vec<ClaimsSummary>) -> BoxFuture<'_, Result<(), ClaimsSinkError>>;
+}
+
+impl<F, Fut> ClaimsSink for F
+where
+    F: Fn(Vec<ClaimsSummary>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), ClaimsSinkError>> + Send + 'static,
+{
+    fn write(&self, batch: Vec<ClaimsSummary>) -> BoxFuture<'_, Result<(), ClaimsSinkError>> {
+        Box::pin(self(batch))
+    }
+}
+
+/// Queues a [`ClaimsSummary`] of each authenticated request for a [`ClaimsSink`].
+///
+/// Set with [`OidcAuthLayer::with_claims_export`](crate::OidcAuthLayer::with_claims_export).
+/// The queue is bounded: when the sink falls behind, new summaries are dropped and counted
+/// rather than slowing down requests. The returned [`ClaimsExportTask`] drains the queue
+/// in batches of whatever is ready, up to `max_batch`, and must be spawned on the runtime.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{ClaimsExporter, ClaimsSinkError, ClaimsSummary};
+///
+/// # async fn run(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let (exporter, task) = ClaimsExporter::new(
+///     |batch: Vec<ClaimsSummary>| async move {
+///         // Write the batch to your analytics store...
+///         Ok::<_, ClaimsSinkError>(())
+///     },
+///     10_000,
+///     500,
+/// );
+/// tokio::spawn(task);
+/// let auth_layer = auth_layer.with_claims_export(exporter);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClaimsExporter {
+    sender: Arc<Mutex<mpsc::Sender<ClaimsSummary>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ClaimsExporter {
+    /// Creates an exporter queueing up to `capacity` summaries (at least one) for `sink`,
+    /// written in batches of at most `max_batch`.
+    pub fn new(
+        sink: impl ClaimsSink,
+        capacity: usize,
+        max_batch: usize,
+    ) -> (Self, ClaimsExportTask) {
+        // The channel holds one message per sender on top of its buffer.
+        let (sender, receiver) = mpsc::channel(capacity.saturating_sub(1));
+        let exporter = Self {
+            sender: Arc::new(Mutex::new(sender)),
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        let task = ClaimsExportTask(Box::pin(drain(
+            receiver.ready_chunks(max_batch.max(1)).boxed(),
+            sink,
+        )));
+        (exporter, task)
+    }
+
+    /// Returns how many summaries were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn export(&self, req: &Request, now: SystemTime) {
+        let summary = summarize(req, now);
+        let sent = self
+            .sender
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .try_send(summary);
+        if sent.is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped.is_power_of_two() {
+                log::warn!("Claims export queue is full; {dropped} summaries dropped so far");
+            }
+        }
+    }
+}
+
+/// The background task of a [`ClaimsExporter`], writing queued summaries to its sink.
+///
+/// Completes once every clone of the exporter, including those held by layers, is dropped.
+pub struct ClaimsExportTask(BoxFuture<'static, ()>);
+
+impl Future for ClaimsExportTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+async fn drain(mut batches: BoxStream<'static, Vec<ClaimsSummary>>, sink: impl ClaimsSink) {
+    while let Some(batch) = batches.next().await {
+        let len = batch.len();
+        if let Err(e) = sink.write(batch).await {
+            log::warn!("Failed to export {len} claims summaries: {e}");
+        }
+    }
+}
+
+/// The claims summarized for export, read from the already validated token.
+#[derive(Debug, Default, Deserialize)]
+struct SummaryClaims {
+    sub: Option<String>,
+    iss: Option<String>,
+    client_id: Option<String>,
+    azp: Option<String>,
+}
+
+fn summarize(req: &Request, now: SystemTime) -> ClaimsSummary {
+    let claims = req
+        .extensions()
+        .get::<ValidatedPayload>()
+        .and_then(|payload| payload.decode::<SummaryClaims>().ok())
+        .unwrap_or_default();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+
+    ClaimsSummary {
+        subject: claims.sub,
+        issuer: claims.iss,
+        client_id: claims.client_id.or(claims.azp),
+        route,
+        method: req.method().clone(),
+        timestamp: now,
+    }
+}
@@ -0,0 +1,245 @@
+use axum::{
+    extract::{FromRequestParts, Request},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http::{header, request::Parts, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{any::type_name, sync::Arc};
+
+use crate::error::AuthError;
+
+/// Marks requests that passed through an [`OidcAuthLayer`](crate::OidcAuthLayer), so
+/// extractors can tell an unauthenticated request from a route without the layer.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AuthLayerInstalled;
+
+/// The encoded payload of the validated token, kept so claim views can be decoded on demand.
+#[derive(Debug, Clone)]
+pub(crate) struct ValidatedPayload(pub(crate) Arc<str>);
+
+impl ValidatedPayload {
+    pub(crate) fn from_token(token: &str) -> Option<Self> {
+        token
+            .split('.')
+            .nth(1)
+            .map(|payload| Self(Arc::from(payload)))
+    }
+
+    pub(crate) fn decode<P: DeserializeOwned>(&self) -> Result<P, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(&*self.0)
+            .map_err(|e| e.to_string())?;
+        serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the payload of an authenticated request, or why authentication failed.
+pub(crate) fn authenticated_payload(req: &Request) -> Result<&ValidatedPayload, AuthError> {
+    req.extensions().get::<ValidatedPayload>().ok_or_else(|| {
+        req.extensions()
+            .get::<AuthError>()
+            .cloned()
+            .unwrap_or(AuthError::MissingToken)
+    })
+}
+
+/// Extracts the validated claims as `T`.
+///
+/// Unauthenticated requests are rejected with `401 Unauthorized`. If the route is not
+/// covered by an [`OidcAuthLayer`](crate::OidcAuthLayer) at all, the request is rejected with
+/// `500 Internal Server Error` and a message naming the misconfiguration, rather than being
+/// treated as unauthenticated.
+///
+/// `T` does not have to be the claims type of the [`OidcAuthLayer`](crate::OidcAuthLayer):
+/// any narrower view (say, a struct with only `sub`) is decoded lazily from the validated
+/// token the first time it is extracted and then cached in the request extensions. This lets
+/// modules that only need a few claims avoid depending on the application's full claims type.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::Claims;
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct MinimalView {
+///     sub: String,
+/// }
+///
+/// async fn handler(Claims(view): Claims<MinimalView>) -> String {
+///     view.sub
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Claims<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for Claims<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    type Rejection = ClaimsRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        claims_from_parts(parts, "Claims").map(Claims)
+    }
+}
+
+/// Extracts the validated claims as `T` if the request is authenticated, for handlers that
+/// serve both anonymous and authenticated users.
+///
+/// Behaves like [`Claims`], except that unauthenticated requests yield `None` instead of a
+/// rejection. Tokens whose claims cannot be decoded as `T`, and routes without an
+/// [`OidcAuthLayer`](crate::OidcAuthLayer), are still rejected.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::OptionalClaims;
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct MinimalView {
+///     sub: String,
+/// }
+///
+/// async fn handler(OptionalClaims(view): OptionalClaims<MinimalView>) -> String {
+///     match view {
+///         Some(view) => format!("Hello, {}", view.sub),
+///         None => "Hello, guest".to_string(),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct OptionalClaims<T>(pub Option<T>);
+
+impl<S, T> FromRequestParts<S> for OptionalClaims<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    type Rejection = ClaimsRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match claims_from_parts(parts, "OptionalClaims") {
+            Ok(claims) => Ok(OptionalClaims(Some(claims))),
+            Err(ClaimsRejection::Missing) => Ok(OptionalClaims(None)),
+            Err(rejection) => Err(rejection),
+        }
+    }
+}
+
+/// Extracts the validated claims as `T`, or the reason authentication failed.
+///
+/// This lets handlers behind a layer in [`AuthMode::Optional`](crate::AuthMode::Optional)
+/// tailor their response to the failure, e.g. prompting for a fresh token when it expired.
+/// Like [`Claims`], it rejects requests on routes without an
+/// [`OidcAuthLayer`](crate::OidcAuthLayer).
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{AuthError, AuthResult};
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct MinimalView {
+///     sub: String,
+/// }
+///
+/// async fn handler(AuthResult(result): AuthResult<MinimalView>) -> String {
+///     match result {
+///         Ok(view) => format!("Hello, {}", view.sub),
+///         Err(AuthError::MissingToken) => "Hello, guest".to_string(),
+///         Err(error) => format!("Please sign in again: {error}"),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuthResult<T>(pub Result<T, AuthError>);
+
+impl<S, T> FromRequestParts<S> for AuthResult<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    type Rejection = ClaimsRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match claims_from_parts(parts, "AuthResult") {
+            Ok(claims) => Ok(AuthResult(Ok(claims))),
+            Err(ClaimsRejection::Missing) => {
+                let error = parts
+                    .extensions
+                    .get::<AuthError>()
+                    .cloned()
+                    .unwrap_or(AuthError::MissingToken);
+                Ok(AuthResult(Err(error)))
+            }
+            Err(ClaimsRejection::Invalid) => Ok(AuthResult(Err(AuthError::ClaimsDeserialization(
+                format!("claims could not be decoded as {}", type_name::<T>()),
+            )))),
+            Err(rejection) => Err(rejection),
+        }
+    }
+}
+
+/// Returns the claims view `T` of an authenticated request, decoding and caching it on first
+/// use. `extractor` names the calling extractor in log messages.
+pub(crate) fn claims_from_parts<T>(parts: &mut Parts, extractor: &str) -> Result<T, ClaimsRejection>
+where
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    if let Some(claims) = parts.extensions.get::<T>() {
+        return Ok(claims.clone());
+    }
+
+    let payload = parts.extensions.get::<ValidatedPayload>().ok_or_else(|| {
+        if parts.extensions.get::<AuthLayerInstalled>().is_some() {
+            ClaimsRejection::Missing
+        } else {
+            log::error!(
+                "{extractor}<{}> extracted on a route without OidcAuthLayer",
+                type_name::<T>()
+            );
+            ClaimsRejection::LayerMissing
+        }
+    })?;
+    let claims: T = payload.decode().map_err(|e| {
+        log::warn!("Failed to decode claims as {}: {e}", type_name::<T>());
+        ClaimsRejection::Invalid
+    })?;
+
+    parts.extensions.insert(claims.clone());
+    Ok(claims)
+}
+
+/// Rejection returned by the [`Claims`], [`OptionalClaims`] and [`AuthResult`] extractors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimsRejection {
+    /// The request was not authenticated.
+    Missing,
+    /// The token's claims could not be deserialized into the requested type.
+    Invalid,
+    /// The route is not covered by an [`OidcAuthLayer`](crate::OidcAuthLayer).
+    LayerMissing,
+}
+
+impl IntoResponse for ClaimsRejection {
+    fn into_response(self) -> Response {
+        match self {
+            ClaimsRejection::Missing => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Authentication required",
+            )
+                .into_response(),
+            ClaimsRejection::Invalid => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+                "The token does not carry the required claims",
+            )
+                .into_response(),
+            ClaimsRejection::LayerMissing => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Claims were requested on a route without OidcAuthLayer",
+            )
+                .into_response(),
+        }
+    }
+}
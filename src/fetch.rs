@@ -0,0 +1,357 @@
+use futures::future::BoxFuture;
+use reqwest::{header, StatusCode};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use zeroize::Zeroizing;
+
+use crate::clock::{self, Clock};
+use crate::error::ConfigError;
+use crate::issuer::is_plain_http;
+use crate::jwks::StaticJwks;
+use crate::key_cache::KeyCache;
+use crate::readiness::KeyStatus;
+
+/// Where [`OidcAuthLayer::with_jwks_fetcher`](crate::OidcAuthLayer::with_jwks_fetcher)
+/// fetches its JWKS document, and the HTTP client it fetches it with. Requires the
+/// `jwks-fetch` feature.
+///
+/// Unlike the validators of [`OidcAuthLayer::new`](crate::OidcAuthLayer::new), which fetch
+/// keys with a client of their own, the document is fetched by the crate through the given
+/// [`reqwest::Client`], e.g. to configure timeouts, proxies or TLS. A JWKS endpoint
+/// protected by a [static bearer token](Self::bearer_token) or mutual TLS can be fetched
+/// without affecting the tokens being validated.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{JwksFetcher, OidcAuthLayer, Validation};
+/// use std::time::Duration;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = reqwest::Client::builder()
+///     .timeout(Duration::from_secs(2))
+///     .build()?;
+/// let fetcher = JwksFetcher::new("https://your-oidc-provider.com/jwks").with_http_client(client);
+/// let auth_layer =
+///     OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(fetcher, Validation::default())
+///         .await?;
+/// tokio::spawn(auth_layer.jwks_fetch_task(Duration::from_secs(300)));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct JwksFetcher {
+    url: String,
+    client: reqwest::Client,
+    bearer_token: Option<Zeroizing<String>>,
+    key_cache: Option<(Arc<dyn KeyCache>, Duration)>,
+    allow_insecure_http: bool,
+}
+
+impl JwksFetcher {
+    /// Fetches the JWKS document at `url` with a client timing out after 5 seconds.
+    pub fn new(url: impl Into<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self {
+            url: url.into(),
+            client,
+            bearer_token: None,
+            key_cache: None,
+            allow_insecure_http: false,
+        }
+    }
+
+    /// Fetches the document with `client` instead.
+    ///
+    /// For an endpoint requiring mutual TLS, build `client` with the certificate and key to
+    /// present, using `reqwest::ClientBuilder::identity` and one of reqwest's TLS features.
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Authenticates to the JWKS endpoint with `token` in an `Authorization: Bearer`
+    /// header. The token is redacted from `Debug` output and zeroed in memory when dropped.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(Zeroizing::new(token.into()));
+        self
+    }
+
+    /// Shares the fetched document through `cache` for `ttl`, e.g. a [`RedisKeyCache`]
+    /// shared by the replicas of a service. The document is read from the cache when the
+    /// layer is built and before each fetch of the [`JwksFetchTask`], and only fetched from
+    /// the identity provider while the cache holds none.
+    ///
+    /// [`RedisKeyCache`]: crate::RedisKeyCache
+    pub fn with_key_cache(mut self, cache: impl KeyCache, ttl: Duration) -> Self {
+        self.key_cache = Some((Arc::new(cache), ttl));
+        self
+    }
+
+    /// Accepts a URL using plain `http`, and makes the layer
+    /// [allow insecure `http`](crate::OidcAuthLayer::allow_insecure_http). Only for local
+    /// development.
+    pub fn allow_insecure_http(mut self) -> Self {
+        self.allow_insecure_http = true;
+        self
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn allows_insecure_http(&self) -> bool {
+        self.allow_insecure_http
+    }
+
+    /// Fails with [`ConfigError::InsecureUrl`] if the URL uses plain `http` without
+    /// [`allow_insecure_http`](Self::allow_insecure_http).
+    pub(crate) fn check_url(&self) -> Result<(), ConfigError> {
+        match !self.allow_insecure_http && is_plain_http(&self.url) {
+            true => Err(ConfigError::InsecureUrl(self.url.clone())),
+            false => Ok(()),
+        }
+    }
+
+    /// Returns the document held by the key cache, if any. Failures are logged, as the
+    /// document can still be fetched.
+    pub(crate) async fn cached(&self) -> Option<String> {
+        let (cache, _) = self.key_cache.as_ref()?;
+        cache.get(&self.url).await.unwrap_or_else(|e| {
+            log::warn!(
+                "Failed to read JWKS for {} from the key cache: {e}",
+                self.url
+            );
+            None
+        })
+    }
+
+    /// Shares `jwks_json` through the key cache, if any.
+    pub(crate) async fn share(&self, jwks_json: &str) {
+        if let Some((cache, ttl)) = &self.key_cache {
+            if let Err(e) = cache.put(&self.url, jwks_json, *ttl).await {
+                log::warn!(
+                    "Failed to write JWKS for {} to the key cache: {e}",
+                    self.url
+                );
+            }
+        }
+    }
+
+    /// Fetches the JWKS document, only if it has changed when `etag` is given, returning
+    /// why it failed otherwise.
+    pub(crate) async fn fetch(&self, etag: Option<&str>) -> Result<Fetched, String> {
+        let url = &self.url;
+        let mut request = self.client.get(url);
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token.as_str());
+        }
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await.map_err(|e| format!("{url}: {e}"))?;
+        let status = response.status();
+        let not_modified = status == StatusCode::NOT_MODIFIED && etag.is_some();
+        let headers = response.headers();
+        let cache = CacheHeaders {
+            etag: headers
+                .get(header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .or(etag.filter(|_| not_modified))
+                .map(str::to_string),
+            max_age: headers
+                .get_all(header::CACHE_CONTROL)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .find_map(max_age),
+        };
+        if not_modified {
+            return Ok(Fetched {
+                jwks_json: None,
+                cache,
+            });
+        }
+        if !status.is_success() {
+            return Err(format!("{url}: status {status}"));
+        }
+        let jwks_json = response.text().await.map_err(|e| format!("{url}: {e}"))?;
+        Ok(Fetched {
+            jwks_json: Some(jwks_json),
+            cache,
+        })
+    }
+}
+
+/// A JWKS document answered by [`JwksFetcher::fetch`].
+pub(crate) struct Fetched {
+    /// The document, or `None` if it has not changed since the ETag sent.
+    pub(crate) jwks_json: Option<String>,
+    pub(crate) cache: CacheHeaders,
+}
+
+/// The caching headers of the last fetched JWKS document.
+#[derive(Default, Clone)]
+pub(crate) struct CacheHeaders {
+    /// Sent back in `If-None-Match`, so an unchanged document is answered with
+    /// `304 Not Modified`.
+    pub(crate) etag: Option<String>,
+    /// How long the provider says the document may be cached, from `Cache-Control`.
+    pub(crate) max_age: Option<Duration>,
+}
+
+/// Returns the `max-age` directive of a `Cache-Control` header value, if any.
+fn max_age(cache_control: &str) -> Option<Duration> {
+    cache_control.split(',').find_map(|directive| {
+        let (name, value) = directive.split_once('=')?;
+        match name.trim().eq_ignore_ascii_case("max-age") {
+            true => value
+                .trim()
+                .trim_matches('"')
+                .parse()
+                .ok()
+                .map(Duration::from_secs),
+            false => None,
+        }
+    })
+}
+
+/// The shortest delay between two fetches, however short the provider's `max-age`.
+const MIN_FETCH_INTERVAL: Duration = Duration::from_secs(1);
+
+impl fmt::Debug for JwksFetcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwksFetcher")
+            .field("url", &self.url)
+            .field(
+                "bearer_token",
+                &self.bearer_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field(
+                "key_cache_ttl",
+                &self.key_cache.as_ref().map(|(_, ttl)| ttl),
+            )
+            .field("allow_insecure_http", &self.allow_insecure_http)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Fetches the keys of `jwks` again every `interval`, or sooner if the `max-age` of the
+/// document says it expires sooner, swapping them in when the document changes, until
+/// `jwks` is dropped, recording the outcome in `status`.
+pub(crate) fn fetch_task(
+    jwks: Option<Weak<StaticJwks>>,
+    interval: Duration,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) -> JwksFetchTask {
+    JwksFetchTask(Box::pin(async move {
+        if let Some(jwks) = jwks {
+            run(jwks, interval, status, clock).await;
+        }
+    }))
+}
+
+async fn run(
+    jwks: Weak<StaticJwks>,
+    interval: Duration,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) {
+    let mut last = None;
+    let Some(mut cache) = jwks.upgrade().map(|jwks| jwks.cache_headers()) else {
+        return;
+    };
+    loop {
+        let delay = cache.max_age.map_or(interval, |max_age| {
+            max_age.max(MIN_FETCH_INTERVAL).min(interval)
+        });
+        tokio::time::sleep(delay).await;
+        let Some(jwks) = jwks.upgrade() else {
+            return;
+        };
+        let Some(fetcher) = jwks.fetcher() else {
+            return;
+        };
+        // Another replica may have fetched the document already.
+        if let Some(content) = fetcher.cached().await {
+            match swap_in(&jwks, &content, &mut last, &status) {
+                Ok(()) => {
+                    status.loaded(clock::now(clock.as_deref()));
+                    continue;
+                }
+                Err(e) => log::warn!("Ignoring invalid cached JWKS for {}: {e}", fetcher.url()),
+            }
+        }
+        let fetched = match fetcher.fetch(cache.etag.as_deref()).await {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                log::warn!("Failed to fetch JWKS: {e}");
+                status.failed(e, clock::now(clock.as_deref()));
+                continue;
+            }
+        };
+        let Some(content) = fetched.jwks_json else {
+            // The provider confirmed that the keys have not changed.
+            cache = fetched.cache;
+            if let Some(last) = &last {
+                fetcher.share(last).await;
+            }
+            status.loaded(clock::now(clock.as_deref()));
+            continue;
+        };
+        match swap_in(&jwks, &content, &mut last, &status) {
+            Ok(()) => {
+                cache = fetched.cache;
+                fetcher.share(&content).await;
+                status.loaded(clock::now(clock.as_deref()));
+            }
+            Err(e) => {
+                log::warn!(
+                    "Keeping current keys, JWKS at {} is invalid: {e}",
+                    fetcher.url()
+                );
+                status.failed(e, clock::now(clock.as_deref()));
+            }
+        }
+    }
+}
+
+/// Swaps in the keys of `content`, unless it is the `last` document swapped in, which is
+/// not parsed again, recording in `status` if keys were removed.
+fn swap_in(
+    jwks: &StaticJwks,
+    content: &str,
+    last: &mut Option<String>,
+    status: &KeyStatus,
+) -> Result<(), ConfigError> {
+    if last.as_deref() != Some(content) {
+        let (count, removed) = jwks.replace(content)?;
+        log::info!("Loaded {count} JWKS keys");
+        if removed {
+            status.keys_replaced();
+        }
+        *last = Some(content.to_string());
+    }
+    Ok(())
+}
+
+/// The background task of
+/// [`OidcAuthLayer::jwks_fetch_task`](crate::OidcAuthLayer::jwks_fetch_task), fetching the
+/// layer's signing keys again through its [`JwksFetcher`].
+pub struct JwksFetchTask(BoxFuture<'static, ()>);
+
+impl Future for JwksFetchTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Identity context for feature-flag evaluation, inserted into request extensions.
+///
+/// The shape mirrors the evaluation contexts of LaunchDarkly and Unleash: a stable `key`
+/// (the `sub` claim) plus targeting attributes. Serializes to JSON for SDKs that accept
+/// contexts as JSON objects.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FlagContext {
+    /// The `sub` claim of the authenticated token.
+    pub key: String,
+    /// The tenant the user belongs to, if the tenant claim is present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Group memberships from the groups claim.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Additional claims copied verbatim for custom targeting rules.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, Value>,
+}
+
+/// Controls which claims populate the [`FlagContext`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagContextConfig {
+    /// The claim holding the tenant identifier. Defaults to `tenant`.
+    pub tenant_claim: String,
+    /// The claim holding group memberships, either an array or a space-delimited string.
+    /// Defaults to `groups`.
+    pub groups_claim: String,
+    /// Claims copied into [`FlagContext::attributes`] when present.
+    pub attribute_claims: Vec<String>,
+}
+
+impl Default for FlagContextConfig {
+    fn default() -> Self {
+        Self {
+            tenant_claim: "tenant".to_string(),
+            groups_claim: "groups".to_string(),
+            attribute_claims: Vec::new(),
+        }
+    }
+}
+
+impl FlagContextConfig {
+    /// Sets the claim holding the tenant identifier.
+    pub fn tenant_claim(mut self, claim: impl Into<String>) -> Self {
+        self.tenant_claim = claim.into();
+        self
+    }
+
+    /// Sets the claim holding group memberships.
+    pub fn groups_claim(mut self, claim: impl Into<String>) -> Self {
+        self.groups_claim = claim.into();
+        self
+    }
+
+    /// Adds a claim to copy into [`FlagContext::attributes`].
+    pub fn attribute(mut self, claim: impl Into<String>) -> Self {
+        self.attribute_claims.push(claim.into());
+        self
+    }
+
+    pub(crate) fn build(&self, payload: &Map<String, Value>) -> Option<FlagContext> {
+        let key = payload.get("sub")?.as_str()?.to_string();
+        let tenant = payload.get(&self.tenant_claim).and_then(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        });
+        let groups = match payload.get(&self.groups_claim) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect(),
+            Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        let attributes = self
+            .attribute_claims
+            .iter()
+            .filter_map(|claim| Some((claim.clone(), payload.get(claim)?.clone())))
+            .collect();
+
+        Some(FlagContext {
+            key,
+            tenant,
+            groups,
+            attributes,
+        })
+    }
+}
@@ -0,0 +1,152 @@
+use futures::future::BoxFuture;
+use http::{header::AUTHORIZATION, HeaderValue, Request};
+use std::{
+    future::Future,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+use zeroize::Zeroizing;
+
+use crate::context::AccessToken;
+
+tokio::task_local! {
+    static FORWARDED_TOKEN: AccessToken;
+}
+
+/// Returns the token to forward from the current request scope, if any.
+///
+/// The scope is set by [`ForwardScopeLayer`] or [`forward_token`]. Use it to attach the token
+/// to clients that are not tower services, e.g. with `reqwest`'s `bearer_auth`:
+///
+/// ```rust
+/// let mut request = http::Request::get("https://orders.internal/orders");
+/// if let Some(token) = axum_jwt_oidc::forwarded_token() {
+///     request = request.header("Authorization", format!("Bearer {}", token.expose()));
+/// }
+/// ```
+pub fn forwarded_token() -> Option<AccessToken> {
+    FORWARDED_TOKEN.try_with(AccessToken::clone).ok()
+}
+
+/// Runs `future` with `token` as the token to forward, e.g. a token obtained by exchanging
+/// the inbound one for a downstream audience.
+pub async fn forward_token<F: Future>(token: AccessToken, future: F) -> F::Output {
+    FORWARDED_TOKEN.scope(token, future).await
+}
+
+/// Runs each request with its [`AccessToken`] as the token to forward, so outbound clients
+/// wrapped in a [`ForwardAuthLayer`] attach it. Requires the `forward` feature.
+///
+/// Add it inside an [`OidcAuthLayer`](crate::OidcAuthLayer) configured with
+/// [`with_access_token`](crate::OidcAuthLayer::with_access_token); requests without a token
+/// run unscoped. Tasks spawned by the handler do not inherit the scope; wrap their futures
+/// in [`forward_token`] instead.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::ForwardScopeLayer;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/orders", get(|| async { "orders" }))
+///     .layer(ForwardScopeLayer)
+///     .layer(auth_layer.with_access_token());
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ForwardScopeLayer;
+
+impl<S> Layer<S> for ForwardScopeLayer {
+    type Service = ForwardScope<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ForwardScope { inner }
+    }
+}
+
+/// The service created by [`ForwardScopeLayer`].
+#[derive(Debug, Clone)]
+pub struct ForwardScope<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for ForwardScope<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let token = req.extensions().get::<AccessToken>().cloned();
+        let future = self.inner.call(req);
+        match token {
+            Some(token) => Box::pin(forward_token(token, future)),
+            None => Box::pin(future),
+        }
+    }
+}
+
+/// Attaches the token of the current request scope as a bearer token to outbound requests
+/// of an HTTP client service, such as a `hyper-util` client. Requires the `forward` feature.
+///
+/// Requests that already carry an `Authorization` header, or are sent outside the scope of
+/// a [`ForwardScopeLayer`] or [`forward_token`], are sent unchanged.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::ForwardAuthLayer;
+/// use tower::{Layer, Service};
+///
+/// # fn client<C: Service<http::Request<String>>>(client: C) {
+/// let client = ForwardAuthLayer.layer(client);
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ForwardAuthLayer;
+
+impl<S> Layer<S> for ForwardAuthLayer {
+    type Service = ForwardAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ForwardAuth { inner }
+    }
+}
+
+/// The service created by [`ForwardAuthLayer`].
+#[derive(Debug, Clone)]
+pub struct ForwardAuth<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for ForwardAuth<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if !req.headers().contains_key(AUTHORIZATION) {
+            let value = forwarded_token().and_then(|token| {
+                let bearer = Zeroizing::new(format!("Bearer {}", token.expose()));
+                HeaderValue::from_str(&bearer).ok()
+            });
+            if let Some(mut value) = value {
+                value.set_sensitive(true);
+                req.headers_mut().insert(AUTHORIZATION, value);
+            }
+        }
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,104 @@
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+    Engine,
+};
+use http::{HeaderMap, HeaderName};
+use serde::de::DeserializeOwned;
+use std::{fmt, sync::Arc};
+use zeroize::Zeroizing;
+
+use crate::compare::constant_time_eq;
+use crate::error::AuthError;
+use crate::extract::ValidatedPayload;
+
+/// Trusts a JWT payload forwarded by a gateway that has already verified the token.
+///
+/// Envoy's `jwt_authn` filter (and Istio's `RequestAuthentication` with
+/// `outputPayloadToHeader`) can verify the token at the edge and forward its payload as
+/// base64-encoded JSON. In this mode the middleware decodes that header into the claims type
+/// **without verifying any signature** and never contacts the JWKS endpoint.
+///
+/// This is only safe if every request reaches the service through a gateway that verifies
+/// tokens and strips any client-supplied copy of the header. Otherwise anyone can forge
+/// claims by setting the header themselves. Where clients might reach the service around the
+/// gateway, also [require a secret](Self::require_secret) only the gateway sends.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TrustedGatewayPayload {
+    pub(crate) header: HeaderName,
+    secret: Option<(HeaderName, Zeroizing<String>)>,
+}
+
+impl fmt::Debug for TrustedGatewayPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrustedGatewayPayload")
+            .field("header", &self.header)
+            .field("secret_header", &self.secret.as_ref().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl TrustedGatewayPayload {
+    /// Reads the payload from `x-jwt-payload`.
+    pub fn envoy() -> Self {
+        Self::new(HeaderName::from_static("x-jwt-payload"))
+    }
+
+    /// Reads the payload from `header`.
+    pub fn new(header: HeaderName) -> Self {
+        Self {
+            header,
+            secret: None,
+        }
+    }
+
+    /// Only trusts payloads of requests carrying `secret` in `header`, a value the gateway
+    /// adds to every request it forwards. Other requests are rejected with
+    /// [`AuthError::InvalidToken`]. The secret is compared in constant time.
+    ///
+    /// ```rust
+    /// use axum_jwt_oidc::TrustedGatewayPayload;
+    /// use http::HeaderName;
+    ///
+    /// let gateway = TrustedGatewayPayload::envoy()
+    ///     .require_secret(HeaderName::from_static("x-gateway-secret"), "s3cret");
+    /// ```
+    pub fn require_secret(mut self, header: HeaderName, secret: impl Into<String>) -> Self {
+        self.secret = Some((header, Zeroizing::new(secret.into())));
+        self
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<(T, ValidatedPayload), AuthError> {
+        if let Some((header, secret)) = &self.secret {
+            let presented = headers
+                .get(header)
+                .map_or(&[][..], |value| value.as_bytes());
+            if !constant_time_eq(presented, secret.as_bytes()) {
+                log::warn!("Rejecting gateway payload without the secret in {header}");
+                return Err(AuthError::InvalidToken(
+                    "request did not come through the trusted gateway".to_string(),
+                ));
+            }
+        }
+        let encoded = headers
+            .get(&self.header)
+            .ok_or(AuthError::MissingToken)?
+            .to_str()
+            .map_err(|e| AuthError::MalformedHeader(e.to_string()))?
+            .trim();
+
+        let json = [URL_SAFE_NO_PAD, URL_SAFE, STANDARD_NO_PAD, STANDARD]
+            .iter()
+            .find_map(|engine| engine.decode(encoded).ok())
+            .ok_or_else(|| {
+                AuthError::MalformedHeader(format!("{} is not valid base64", self.header))
+            })?;
+        let claims = serde_json::from_slice(&json)
+            .map_err(|e| AuthError::ClaimsDeserialization(e.to_string()))?;
+
+        let payload = ValidatedPayload(Arc::from(URL_SAFE_NO_PAD.encode(&json)));
+        Ok((claims, payload))
+    }
+}
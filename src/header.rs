@@ -0,0 +1,29 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+
+/// The JOSE header of a validated token, such as the key and algorithm that signed it.
+///
+/// Inserted into the request extensions by layers configured with
+/// [`OidcAuthLayer::with_token_header`](crate::OidcAuthLayer::with_token_header), for
+/// routing or audit decisions that depend on the signing key.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct TokenHeader {
+    /// The signing algorithm (`alg`), e.g. `RS256`.
+    pub alg: String,
+    /// The identifier of the signing key (`kid`).
+    pub kid: Option<String>,
+    /// The media type of the token (`typ`), e.g. `JWT` or `at+jwt`.
+    pub typ: Option<String>,
+    /// The content type of the payload (`cty`).
+    pub cty: Option<String>,
+}
+
+impl TokenHeader {
+    /// Decodes the header of `token`, which must already have been validated.
+    pub(crate) fn from_token(token: &str) -> Option<Self> {
+        let header = token.split('.').next()?;
+        let bytes = URL_SAFE_NO_PAD.decode(header).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
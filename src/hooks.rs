@@ -0,0 +1,185 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    response::Response,
+};
+use futures::future::BoxFuture;
+use http::{request::Parts, Method, StatusCode};
+use serde::Deserialize;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::error::AuthError;
+use crate::extract::ValidatedPayload;
+
+/// Rewrites a request before the middleware looks for its token.
+///
+/// Use it to support client conventions the built-in sources do not, e.g. copying a
+/// gateway's custom token header into `Authorization` or unwrapping a proprietary envelope,
+/// without writing a whole [`TokenExtractor`](crate::TokenExtractor). Hooks added with
+/// [`OidcAuthLayer::with_pre_auth`](crate::OidcAuthLayer::with_pre_auth) run in order, and
+/// their changes are visible to the inner service. Any
+/// `Fn(&mut Parts) + Send + Sync + 'static` closure implements this trait.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::OidcAuthLayer;
+/// use http::{header, request::Parts};
+///
+/// # fn layer(auth_layer: OidcAuthLayer<serde_json::Value>) {
+/// let auth_layer = auth_layer.with_pre_auth(|parts: &mut Parts| {
+///     if let Some(token) = parts.headers.remove("x-legacy-token") {
+///         let bearer = format!("Bearer {}", token.to_str().unwrap_or_default());
+///         if let Ok(value) = bearer.parse() {
+///             parts.headers.insert(header::AUTHORIZATION, value);
+///         }
+///     }
+/// });
+/// # }
+/// ```
+pub trait PreAuthHook: Send + Sync + 'static {
+    /// Rewrites the request parts in place.
+    fn before_auth(&self, parts: &mut Parts);
+}
+
+impl<F> PreAuthHook for F
+where
+    F: Fn(&mut Parts) + Send + Sync + 'static,
+{
+    fn before_auth(&self, parts: &mut Parts) {
+        self(parts)
+    }
+}
+
+/// How the middleware handled the authentication of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuthOutcome {
+    /// The request was authenticated and passed on to the inner service.
+    Authenticated,
+    /// Authentication failed, but the request was passed on in
+    /// [`AuthMode::Optional`](crate::AuthMode::Optional).
+    Unauthenticated(AuthError),
+    /// The middleware rejected the request without calling the inner service.
+    Rejected(AuthError),
+}
+
+/// What a [`PostResponseHook`] learns about a request once it has been answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseEvent {
+    /// How the middleware handled authentication.
+    pub outcome: AuthOutcome,
+    /// The `sub` claim of the token, if the request was authenticated.
+    pub subject: Option<String>,
+    /// The matched route pattern (e.g. `/orders/{id}`), or the request path if unavailable.
+    pub route: String,
+    /// The request method.
+    pub method: Method,
+    /// The response status code.
+    pub status: StatusCode,
+    /// Time spent in the middleware and the inner service.
+    pub duration: Duration,
+}
+
+/// Observes each response together with the authentication outcome of its request.
+///
+/// Correlating the two shows patterns that neither side sees alone, such as authenticated
+/// requests the application answers with `403 Forbidden`, and can feed SLO dashboards.
+/// The hook is awaited before the response is returned, so slow work should be handed off.
+/// Any `Fn(ResponseEvent) -> impl Future<Output = ()>` closure implements this trait.
+pub trait PostResponseHook: Send + Sync + 'static {
+    /// Handles one answered request.
+    fn after_response(&self, event: ResponseEvent) -> BoxFuture<'_, ()>;
+}
+
+impl<F, Fut> PostResponseHook for F
+where
+    F: Fn(ResponseEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn after_response(&self, event: ResponseEvent) -> BoxFuture<'_, ()> {
+        Box::pin(self(event))
+    }
+}
+
+/// A change of an issuer's JWKS URL, picked up by a
+/// [`DiscoveryRefreshTask`](crate::DiscoveryRefreshTask). Requires the `discovery` feature.
+#[cfg(feature = "discovery")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DiscoveryChange {
+    /// The issuer whose discovery document changed.
+    pub issuer: String,
+    /// The JWKS URL used until now.
+    pub previous_jwks_uri: String,
+    /// The JWKS URL used from now on, whose keys are already loaded.
+    pub jwks_uri: String,
+}
+
+/// Observes changes of an issuer's JWKS URL, such as those of an identity provider
+/// migration, e.g. to alert on them. Requires the `discovery` feature. Any
+/// `Fn(DiscoveryChange) + Send + Sync + 'static` closure implements this trait.
+#[cfg(feature = "discovery")]
+pub trait DiscoveryHook: Send + Sync + 'static {
+    /// Handles one change, after the layer has switched to the new JWKS URL.
+    fn jwks_uri_changed(&self, change: DiscoveryChange);
+}
+
+#[cfg(feature = "discovery")]
+impl<F> DiscoveryHook for F
+where
+    F: Fn(DiscoveryChange) + Send + Sync + 'static,
+{
+    fn jwks_uri_changed(&self, change: DiscoveryChange) {
+        self(change)
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct Subject {
+    sub: Option<String>,
+}
+
+/// The request half of a [`ResponseEvent`], captured before the request is handed to the
+/// inner service.
+pub(crate) struct PendingResponse {
+    subject: Option<String>,
+    route: String,
+    method: Method,
+    started: Instant,
+}
+
+impl PendingResponse {
+    pub(crate) fn capture(req: &Request, started: Instant) -> Self {
+        let subject = req
+            .extensions()
+            .get::<ValidatedPayload>()
+            .and_then(|payload| payload.decode::<Subject>().ok())
+            .unwrap_or_default()
+            .sub;
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| req.uri().path().to_string());
+
+        Self {
+            subject,
+            route,
+            method: req.method().clone(),
+            started,
+        }
+    }
+
+    pub(crate) fn finish(self, outcome: AuthOutcome, response: &Response) -> ResponseEvent {
+        ResponseEvent {
+            outcome,
+            subject: self.subject,
+            route: self.route,
+            method: self.method,
+            status: response.status(),
+            duration: self.started.elapsed(),
+        }
+    }
+}
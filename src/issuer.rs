@@ -0,0 +1,501 @@
+use async_oidc_jwt_validator::{OidcValidator, Validation};
+use http::request::Parts;
+use serde::{de::DeserializeOwned, Deserialize};
+#[cfg(feature = "jwks-refresh")]
+use std::sync::Weak;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::auth::{validate_claims, SigningKeys, ValidationOverrides};
+use crate::clock::Clock;
+#[cfg(feature = "discovery")]
+use crate::discovery::DiscoveredIssuer;
+use crate::error::{AuthError, ConfigError};
+use crate::extract::ValidatedPayload;
+use crate::jwks::StaticJwks;
+use crate::tenant::{TenantDirectory, TenantId, TenantResolver};
+
+/// A trusted token issuer together with the validator for its tokens.
+#[derive(Clone)]
+pub struct Issuer {
+    pub(crate) issuer: String,
+    pub(crate) oidc_validator: Arc<OidcValidator>,
+    pub(crate) validation: Validation,
+}
+
+impl Issuer {
+    /// Trusts tokens whose `iss` claim equals `issuer`, validating them with `oidc_validator`
+    /// and `validation`.
+    ///
+    /// The expected issuer of `validation` is set to `issuer`, so the routing decision is
+    /// re-checked after the signature has been verified.
+    pub fn new(
+        issuer: impl Into<String>,
+        oidc_validator: OidcValidator,
+        mut validation: Validation,
+    ) -> Self {
+        let issuer = issuer.into();
+        validation.set_issuer(&[&issuer]);
+        Self {
+            issuer,
+            oidc_validator: Arc::new(oidc_validator),
+            validation,
+        }
+    }
+}
+
+/// The error type of [`ClaimsDeserializer`]s.
+pub type ClaimsDeserializerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Turns the validated claims of one issuer into the claims type `T`, for providers whose
+/// claims do not deserialize as `T` does, such as numeric strings or comma-joined lists.
+///
+/// Registered per issuer with
+/// [`OidcAuthLayer::with_issuer_deserializer`](crate::OidcAuthLayer::with_issuer_deserializer).
+/// Any `Fn(serde_json::Value) -> Result<T, ClaimsDeserializerError>` closure implements this
+/// trait.
+pub trait ClaimsDeserializer<T>: Send + Sync + 'static {
+    /// Converts the claims of a token whose signature has been verified.
+    fn deserialize(&self, claims: serde_json::Value) -> Result<T, ClaimsDeserializerError>;
+}
+
+impl<T, F> ClaimsDeserializer<T> for F
+where
+    F: Fn(serde_json::Value) -> Result<T, ClaimsDeserializerError> + Send + Sync + 'static,
+{
+    fn deserialize(&self, claims: serde_json::Value) -> Result<T, ClaimsDeserializerError> {
+        self(claims)
+    }
+}
+
+/// The [`ClaimsDeserializer`]s of a layer, by issuer.
+pub(crate) type IssuerDeserializers<T> = HashMap<String, Arc<dyn ClaimsDeserializer<T>>>;
+
+/// A family of issuers that differ only in a tenant identifier, such as Azure AD's
+/// `https://login.microsoftonline.com/{tid}/v2.0`.
+///
+/// The placeholder names the claim whose value is substituted into the template. A token is
+/// accepted only if its `iss` claim equals the template with its own placeholder claim
+/// substituted, and the signature is then verified with the shared validator.
+#[derive(Clone)]
+pub struct IssuerTemplate {
+    prefix: String,
+    suffix: String,
+    claim: String,
+    allowed: Option<HashSet<String>>,
+    oidc_validator: Arc<OidcValidator>,
+    validation: Validation,
+}
+
+impl IssuerTemplate {
+    /// Trusts tokens whose `iss` claim matches `template`, validating them with
+    /// `oidc_validator` and `validation`.
+    ///
+    /// Fails with [`ConfigError::InvalidIssuerTemplate`] if `template` does not contain a
+    /// `{claim}` placeholder naming a claim.
+    pub fn new(
+        template: &str,
+        oidc_validator: OidcValidator,
+        validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        let invalid =
+            |reason: &str| ConfigError::InvalidIssuerTemplate(format!("{template}: {reason}"));
+        let (prefix, rest) = template
+            .split_once('{')
+            .ok_or_else(|| invalid("no `{claim}` placeholder"))?;
+        let (claim, suffix) = rest
+            .split_once('}')
+            .ok_or_else(|| invalid("placeholder not closed with `}`"))?;
+        if claim.is_empty() {
+            return Err(invalid("placeholder names no claim"));
+        }
+        Ok(Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            claim: claim.to_string(),
+            allowed: None,
+            oidc_validator: Arc::new(oidc_validator),
+            validation,
+        })
+    }
+
+    /// Only accepts tokens whose placeholder claim is one of `tenants`.
+    ///
+    /// Without an allowlist, tokens from any tenant of the identity provider are accepted,
+    /// which is rarely what a multi-tenant application wants.
+    pub fn allow_tenants<I, S>(mut self, tenants: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = Some(tenants.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Checks the unverified `iss` and placeholder claims of `payload` against the template,
+    /// returning the tenant and the validation rules pinned to its issuer.
+    fn resolve(&self, payload: &serde_json::Value) -> Result<(TenantId, Validation), AuthError> {
+        let claim = |name: &str| {
+            payload
+                .get(name)
+                .and_then(|value| value.as_str())
+                .ok_or_else(|| AuthError::InvalidToken(format!("missing `{name}` claim")))
+        };
+        let iss = claim("iss")?;
+        let tenant = claim(&self.claim)?;
+
+        let expected = format!("{}{tenant}{}", self.prefix, self.suffix);
+        if iss != expected {
+            log::warn!("Rejecting token whose issuer {iss} does not match its tenant {tenant}");
+            return Err(AuthError::UnknownIssuer(iss.to_string()));
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(tenant) {
+                log::warn!("Rejecting token for tenant {tenant} not in the allowlist");
+                return Err(AuthError::UnknownTenant(Some(TenantId::from(tenant))));
+            }
+        }
+
+        let mut validation = self.validation.clone();
+        validation.set_issuer(&[iss]);
+        Ok((TenantId::from(tenant), validation))
+    }
+}
+
+/// Returns whether `url` uses plain `http`. Issuers that are not URLs do not.
+pub(crate) fn is_plain_http(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://"))
+}
+
+/// The validators a layer routes tokens to.
+#[derive(Clone)]
+pub(crate) enum Validators {
+    /// One validator handles every token.
+    Single(Arc<OidcValidator>, Arc<Validation>),
+    /// Tokens are routed by their unverified `iss` claim.
+    Multi(Arc<HashMap<String, Issuer>>),
+    /// Tokens are routed by the tenant of the request.
+    Tenants(Arc<dyn TenantResolver>, Arc<HashMap<TenantId, Issuer>>),
+    /// Tokens are routed by the tenant of the request, whose issuer is looked up on demand.
+    Directory(Arc<dyn TenantResolver>, Arc<TenantDirectory>),
+    /// Tokens are accepted from any issuer matching a template.
+    Template(Arc<IssuerTemplate>),
+    /// One set of keys given up front handles every token.
+    Static(Arc<StaticJwks>, Arc<Validation>),
+    /// One validator for a discovered JWKS URL, which may change, handles every token.
+    #[cfg(feature = "discovery")]
+    Discovered(Arc<DiscoveredIssuer>),
+}
+
+/// A validator whose keys a background task refreshes, held without keeping it alive.
+#[cfg(feature = "jwks-refresh")]
+pub(crate) enum KeySource {
+    Fixed(Weak<OidcValidator>),
+    /// The validator of a discovered issuer, which is replaced when its JWKS URL changes.
+    #[cfg(feature = "discovery")]
+    Discovered(Weak<DiscoveredIssuer>),
+}
+
+#[cfg(feature = "jwks-refresh")]
+impl KeySource {
+    /// Returns the current validator, unless the layer has been dropped.
+    pub(crate) fn upgrade(&self) -> Option<Arc<OidcValidator>> {
+        match self {
+            KeySource::Fixed(validator) => validator.upgrade(),
+            #[cfg(feature = "discovery")]
+            KeySource::Discovered(issuer) => issuer.upgrade().map(|issuer| issuer.validator()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UnverifiedIssuer {
+    iss: Option<String>,
+}
+
+impl Validators {
+    pub(crate) fn multi(issuers: impl IntoIterator<Item = Issuer>) -> Self {
+        let issuers = issuers
+            .into_iter()
+            .map(|issuer| (issuer.issuer.clone(), issuer))
+            .collect();
+        Validators::Multi(Arc::new(issuers))
+    }
+
+    pub(crate) fn tenants(
+        resolver: impl TenantResolver,
+        tenants: impl IntoIterator<Item = (TenantId, Issuer)>,
+    ) -> Self {
+        Validators::Tenants(Arc::new(resolver), Arc::new(tenants.into_iter().collect()))
+    }
+
+    /// Returns the validators whose keys can be fetched ahead of time. Those of a
+    /// [`TenantDirectory`] come and go with its cache, so they are not included.
+    pub(crate) fn oidc_validators(&self) -> Vec<Arc<OidcValidator>> {
+        match self {
+            Validators::Single(oidc_validator, _) => vec![oidc_validator.clone()],
+            Validators::Multi(issuers) => issuers
+                .values()
+                .map(|issuer| issuer.oidc_validator.clone())
+                .collect(),
+            Validators::Tenants(_, tenants) => tenants
+                .values()
+                .map(|issuer| issuer.oidc_validator.clone())
+                .collect(),
+            Validators::Directory(..) | Validators::Static(..) => Vec::new(),
+            Validators::Template(template) => vec![template.oidc_validator.clone()],
+            #[cfg(feature = "discovery")]
+            Validators::Discovered(issuer) => vec![issuer.validator()],
+        }
+    }
+
+    /// Returns the validators of [`oidc_validators`](Self::oidc_validators) as key sources
+    /// for a background task, following changes of a discovered JWKS URL.
+    #[cfg(feature = "jwks-refresh")]
+    pub(crate) fn key_sources(&self) -> Vec<KeySource> {
+        match self {
+            #[cfg(feature = "discovery")]
+            Validators::Discovered(issuer) => vec![KeySource::Discovered(Arc::downgrade(issuer))],
+            _ => self
+                .oidc_validators()
+                .iter()
+                .map(|validator| KeySource::Fixed(Arc::downgrade(validator)))
+                .collect(),
+        }
+    }
+
+    /// Returns the issuer, discovery and JWKS URLs known to the layer. The JWKS URL of an
+    /// [`OidcValidator`] cannot be read back, so only discovered ones and those of a
+    /// [`JwksFetcher`](crate::JwksFetcher) are included, and the URLs of a
+    /// [`TenantDirectory`] are checked when each tenant is looked up.
+    pub(crate) fn urls(&self) -> Vec<String> {
+        match self {
+            Validators::Single(_, validation) => validation.iss.iter().flatten().cloned().collect(),
+            Validators::Static(_jwks, validation) => {
+                let urls = validation.iss.iter().flatten().cloned();
+                #[cfg(feature = "jwks-fetch")]
+                let urls = urls.chain(_jwks.fetcher().map(|fetcher| fetcher.url().to_string()));
+                urls.collect()
+            }
+            Validators::Multi(issuers) => issuers.keys().cloned().collect(),
+            Validators::Tenants(_, tenants) => tenants
+                .values()
+                .map(|issuer| issuer.issuer.clone())
+                .collect(),
+            Validators::Directory(..) => Vec::new(),
+            Validators::Template(template) => vec![template.prefix.clone()],
+            #[cfg(feature = "discovery")]
+            Validators::Discovered(issuer) => issuer.urls(),
+        }
+    }
+
+    /// Returns the discovered issuer, if tokens are validated with its keys.
+    #[cfg(feature = "discovery")]
+    pub(crate) fn discovered(&self) -> Option<&Arc<DiscoveredIssuer>> {
+        match self {
+            Validators::Discovered(issuer) => Some(issuer),
+            _ => None,
+        }
+    }
+
+    /// Returns whether validating tokens may fetch keys or configuration over the network.
+    pub(crate) fn fetches_keys(&self) -> bool {
+        match self {
+            Validators::Static(jwks, _) => jwks.is_fetched(),
+            _ => true,
+        }
+    }
+
+    /// Returns the keys given up front or fetched by the crate, if tokens are validated
+    /// with them.
+    #[cfg(any(feature = "jwks-file", feature = "jwks-fetch"))]
+    pub(crate) fn static_jwks(&self) -> Option<&Arc<StaticJwks>> {
+        match self {
+            Validators::Static(jwks, _) => Some(jwks),
+            _ => None,
+        }
+    }
+
+    /// Returns how long before `exp` tokens are rejected, if validation results depend on
+    /// nothing but the token, so they can be cached and shared between requests. Results
+    /// depending on the tenant of the request cannot.
+    pub(crate) fn cache_margin(&self) -> Option<u64> {
+        match self {
+            Validators::Single(_, validation) | Validators::Static(_, validation) => {
+                Some(validation.reject_tokens_expiring_in_less_than)
+            }
+            Validators::Multi(issuers) => issuers
+                .values()
+                .map(|issuer| issuer.validation.reject_tokens_expiring_in_less_than)
+                .max()
+                .or(Some(0)),
+            #[cfg(feature = "discovery")]
+            Validators::Discovered(issuer) => {
+                Some(issuer.validation.reject_tokens_expiring_in_less_than)
+            }
+            Validators::Tenants(..) | Validators::Directory(..) | Validators::Template(_) => None,
+        }
+    }
+
+    /// Validates `token` like [`validate`](Self::validate), converting the claims with the
+    /// deserializer registered for their issuer, if any.
+    pub(crate) async fn validate_with<T>(
+        &self,
+        token: &str,
+        parts: &mut Parts,
+        clock: Option<&dyn Clock>,
+        overrides: &ValidationOverrides,
+        deserializers: &IssuerDeserializers<T>,
+    ) -> Result<T, AuthError>
+    where
+        T: DeserializeOwned + Clone + 'static,
+    {
+        if deserializers.is_empty() {
+            return self.validate(token, parts, clock, overrides).await;
+        }
+        let claims: serde_json::Value = self.validate(token, parts, clock, overrides).await?;
+        let deserializer = claims
+            .get("iss")
+            .and_then(|iss| iss.as_str())
+            .and_then(|iss| deserializers.get(iss));
+        match deserializer {
+            Some(deserializer) => deserializer.deserialize(claims).map_err(|e| {
+                log::warn!("Authentication failed: issuer claims deserializer failed: {e}");
+                AuthError::ClaimsDeserialization(e.to_string())
+            }),
+            None => serde_json::from_value(claims)
+                .map_err(|e| AuthError::ClaimsDeserialization(e.to_string())),
+        }
+    }
+
+    /// Validates `token`, recording the resolved [`TenantId`] in `parts` if tenants are used.
+    pub(crate) async fn validate<T>(
+        &self,
+        token: &str,
+        parts: &mut Parts,
+        clock: Option<&dyn Clock>,
+        overrides: &ValidationOverrides,
+    ) -> Result<T, AuthError>
+    where
+        T: DeserializeOwned + Clone,
+    {
+        match self {
+            Validators::Single(oidc_validator, validation) => {
+                validate_claims(
+                    token,
+                    SigningKeys::Remote(oidc_validator),
+                    validation,
+                    clock,
+                    overrides,
+                )
+                .await
+            }
+            Validators::Multi(issuers) => {
+                // The issuer is only used to pick a validator; it is verified again afterwards.
+                let iss = ValidatedPayload::from_token(token)
+                    .and_then(|payload| payload.decode::<UnverifiedIssuer>().ok())
+                    .and_then(|unverified| unverified.iss)
+                    .ok_or_else(|| AuthError::InvalidToken("missing `iss` claim".to_string()))?;
+                let issuer = issuers.get(&iss).ok_or_else(|| {
+                    log::warn!("Rejecting token from unknown issuer {iss}");
+                    AuthError::UnknownIssuer(iss)
+                })?;
+                validate_claims(
+                    token,
+                    SigningKeys::Remote(&issuer.oidc_validator),
+                    &issuer.validation,
+                    clock,
+                    overrides,
+                )
+                .await
+            }
+            Validators::Tenants(resolver, tenants) => {
+                let tenant = resolver
+                    .resolve(parts)
+                    .ok_or(AuthError::UnknownTenant(None))?;
+                let issuer = tenants.get(&tenant).ok_or_else(|| {
+                    log::warn!("Rejecting token for unknown tenant {tenant}");
+                    AuthError::UnknownTenant(Some(tenant.clone()))
+                })?;
+                parts.extensions.insert(tenant);
+                validate_claims(
+                    token,
+                    SigningKeys::Remote(&issuer.oidc_validator),
+                    &issuer.validation,
+                    clock,
+                    overrides,
+                )
+                .await
+            }
+            Validators::Directory(resolver, directory) => {
+                let tenant = resolver
+                    .resolve(parts)
+                    .ok_or(AuthError::UnknownTenant(None))?;
+                let issuer = directory
+                    .get(&tenant, clock, overrides.allow_insecure_http)
+                    .await?;
+                parts.extensions.insert(tenant);
+                validate_claims(
+                    token,
+                    SigningKeys::Remote(&issuer.oidc_validator),
+                    &issuer.validation,
+                    clock,
+                    overrides,
+                )
+                .await
+            }
+            Validators::Template(template) => {
+                // The claims are only used to pick the expected issuer; it is verified afterwards.
+                let payload = ValidatedPayload::from_token(token)
+                    .and_then(|payload| payload.decode::<serde_json::Value>().ok())
+                    .ok_or_else(|| AuthError::InvalidToken("malformed payload".to_string()))?;
+                let (tenant, validation) = template.resolve(&payload)?;
+                let claims = validate_claims(
+                    token,
+                    SigningKeys::Remote(&template.oidc_validator),
+                    &validation,
+                    clock,
+                    overrides,
+                )
+                .await?;
+                parts.extensions.insert(tenant);
+                Ok(claims)
+            }
+            Validators::Static(jwks, validation) => {
+                validate_claims(
+                    token,
+                    SigningKeys::Static(jwks),
+                    validation,
+                    clock,
+                    overrides,
+                )
+                .await
+            }
+            #[cfg(feature = "discovery")]
+            Validators::Discovered(issuer) => {
+                let oidc_validator = issuer.validator();
+                validate_claims(
+                    token,
+                    SigningKeys::Remote(&oidc_validator),
+                    &issuer.validation,
+                    clock,
+                    overrides,
+                )
+                .await
+                .map_err(|error| match error {
+                    // The signature has been verified, so the `iss` claim can be reported.
+                    AuthError::WrongIssuer => AuthError::IssuerMismatch(
+                        ValidatedPayload::from_token(token)
+                            .and_then(|payload| payload.decode::<UnverifiedIssuer>().ok())
+                            .and_then(|unverified| unverified.iss)
+                            .unwrap_or_default(),
+                    ),
+                    error => error,
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use async_oidc_jwt_validator::Validation;
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet, KeyOperations, PublicKeyUse},
+    DecodingKey,
+};
+use serde::de::DeserializeOwned;
+#[cfg(feature = "jwks-file")]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::error::{AuthError, ConfigError, NO_MATCHING_KEY};
+#[cfg(feature = "jwks-fetch")]
+use crate::fetch::{CacheHeaders, JwksFetcher};
+use crate::header::TokenHeader;
+
+type KeySet = Vec<(Option<String>, DecodingKey)>;
+
+/// Signing keys parsed from a JWKS document given up front or read from a file, or fetched
+/// by the crate through a [`JwksFetcher`](crate::JwksFetcher) rather than by a validator.
+pub(crate) struct StaticJwks {
+    /// Swapped as a whole when the document is reloaded, so a token never sees a mix of the
+    /// old and new keys.
+    keys: RwLock<Arc<KeySet>>,
+    #[cfg(feature = "jwks-file")]
+    path: Option<PathBuf>,
+    #[cfg(feature = "jwks-fetch")]
+    fetcher: Option<JwksFetcher>,
+    /// The caching headers of the document first fetched by `fetcher`.
+    #[cfg(feature = "jwks-fetch")]
+    cache_headers: CacheHeaders,
+}
+
+impl StaticJwks {
+    /// Parses a JWKS document such as `{"keys": [...]}`.
+    pub(crate) fn parse(jwks_json: &str) -> Result<Self, ConfigError> {
+        Ok(Self {
+            keys: RwLock::new(Arc::new(parse_keys(jwks_json)?)),
+            #[cfg(feature = "jwks-file")]
+            path: None,
+            #[cfg(feature = "jwks-fetch")]
+            fetcher: None,
+            #[cfg(feature = "jwks-fetch")]
+            cache_headers: CacheHeaders::default(),
+        })
+    }
+
+    /// Reads and parses the JWKS document at `path`.
+    #[cfg(feature = "jwks-file")]
+    pub(crate) fn load(path: PathBuf) -> Result<Self, ConfigError> {
+        let jwks_json = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::InvalidJwks(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            path: Some(path),
+            ..Self::parse(&jwks_json)?
+        })
+    }
+
+    /// Returns the file the keys were loaded from, if any.
+    #[cfg(feature = "jwks-file")]
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Fetches and parses the JWKS document of `fetcher`, or reads it from its key cache,
+    /// failing with
+    /// [`ConfigError::InsecureUrl`] before fetching a plain `http` URL it does not allow.
+    #[cfg(feature = "jwks-fetch")]
+    pub(crate) async fn fetch(fetcher: JwksFetcher) -> Result<Self, ConfigError> {
+        fetcher.check_url()?;
+        if let Some(jwks_json) = fetcher.cached().await {
+            match Self::parse(&jwks_json) {
+                Ok(jwks) => {
+                    return Ok(Self {
+                        fetcher: Some(fetcher),
+                        ..jwks
+                    })
+                }
+                Err(e) => log::warn!("Ignoring invalid cached JWKS for {}: {e}", fetcher.url()),
+            }
+        }
+        let fetched = fetcher.fetch(None).await.map_err(ConfigError::JwksFetch)?;
+        // Without an ETag sent, the document is never reported as unchanged.
+        let jwks_json = fetched.jwks_json.unwrap_or_default();
+        let jwks = Self::parse(&jwks_json)?;
+        fetcher.share(&jwks_json).await;
+        Ok(Self {
+            fetcher: Some(fetcher),
+            cache_headers: fetched.cache,
+            ..jwks
+        })
+    }
+
+    /// Returns the caching headers of the document first fetched by the fetcher.
+    #[cfg(feature = "jwks-fetch")]
+    pub(crate) fn cache_headers(&self) -> CacheHeaders {
+        self.cache_headers.clone()
+    }
+
+    /// Returns the fetcher the keys are fetched with, if any.
+    #[cfg(feature = "jwks-fetch")]
+    pub(crate) fn fetcher(&self) -> Option<&JwksFetcher> {
+        self.fetcher.as_ref()
+    }
+
+    /// Returns whether the keys are fetched over the network.
+    pub(crate) fn is_fetched(&self) -> bool {
+        #[cfg(feature = "jwks-fetch")]
+        return self.fetcher.is_some();
+        #[cfg(not(feature = "jwks-fetch"))]
+        false
+    }
+
+    /// Replaces the keys with those of `jwks_json`, keeping the current ones if it is invalid.
+    /// Returns the number of keys, and whether a current key is missing from the new ones.
+    /// Keys without a `kid` cannot be told apart, so replacing one counts as removing it.
+    #[cfg(any(feature = "jwks-file", feature = "jwks-fetch"))]
+    pub(crate) fn replace(&self, jwks_json: &str) -> Result<(usize, bool), ConfigError> {
+        let keys = parse_keys(jwks_json)?;
+        let count = keys.len();
+        let mut current = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+        let removed = current
+            .iter()
+            .any(|(kid, _)| kid.is_none() || !keys.iter().any(|(new_kid, _)| new_kid == kid));
+        *current = Arc::new(keys);
+        Ok((count, removed))
+    }
+
+    /// Verifies `token` with the key named by its `kid`. A set holding a single key also
+    /// verifies tokens naming no key, and tokens naming any key if its own `kid` is unset.
+    pub(crate) fn verify<T>(&self, token: &str, validation: &Validation) -> Result<T, AuthError>
+    where
+        T: DeserializeOwned,
+    {
+        let keys = self
+            .keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let kid = TokenHeader::from_token(token).and_then(|header| header.kid);
+        let key = match (&kid, keys.as_slice()) {
+            (None, [(_, key)]) | (Some(_), [(None, key)]) => Some(key),
+            (Some(kid), keys) => keys
+                .iter()
+                .find(|(key_id, _)| key_id.as_ref() == Some(kid))
+                .map(|(_, key)| key),
+            (None, _) => None,
+        };
+        let key = key.ok_or_else(|| AuthError::InvalidSignature(NO_MATCHING_KEY.to_string()))?;
+        jsonwebtoken::decode::<T>(token, key, validation)
+            .map(|data| data.claims)
+            .map_err(AuthError::from_jwt)
+    }
+}
+
+fn parse_keys(jwks_json: &str) -> Result<KeySet, ConfigError> {
+    let jwks: JwkSet =
+        serde_json::from_str(jwks_json).map_err(|e| ConfigError::InvalidJwks(e.to_string()))?;
+    let keys = jwks
+        .keys
+        .iter()
+        .filter(|jwk| verifies_signatures(jwk))
+        .map(|jwk| {
+            DecodingKey::from_jwk(jwk)
+                .map(|key| (jwk.common.key_id.clone(), key))
+                .map_err(|e| ConfigError::InvalidJwks(e.to_string()))
+        })
+        .collect::<Result<KeySet, _>>()?;
+    if keys.is_empty() {
+        return Err(ConfigError::InvalidJwks("no signing keys".to_string()));
+    }
+    Ok(keys)
+}
+
+/// Whether `jwk` may be used to verify signatures: encryption keys, and keys whose
+/// `key_ops` do not include `verify`, are skipped.
+fn verifies_signatures(jwk: &Jwk) -> bool {
+    let usable = !matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption))
+        && jwk
+            .common
+            .key_operations
+            .as_ref()
+            .is_none_or(|ops| ops.contains(&KeyOperations::Verify));
+    if !usable {
+        log::debug!(
+            "Skipping JWKS key {:?}, not usable for verifying signatures",
+            jwk.common.key_id
+        );
+    }
+    usable
+}
@@ -0,0 +1,90 @@
+use futures::future::BoxFuture;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::clock::{self, Clock};
+use crate::jwks::StaticJwks;
+use crate::readiness::KeyStatus;
+
+/// Reloads the keys of `jwks` from their file every `interval` while the file changes, until
+/// `jwks` is dropped, recording the outcome in `status`.
+pub(crate) fn watch_task(
+    jwks: Option<Weak<StaticJwks>>,
+    interval: Duration,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) -> JwksFileWatchTask {
+    JwksFileWatchTask(Box::pin(async move {
+        if let Some(jwks) = jwks {
+            run(jwks, interval, status, clock).await;
+        }
+    }))
+}
+
+async fn run(
+    jwks: Weak<StaticJwks>,
+    interval: Duration,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) {
+    let mut last = None;
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(jwks) = jwks.upgrade() else {
+            return;
+        };
+        let Some(path) = jwks.path() else {
+            return;
+        };
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("Failed to read JWKS file {}: {e}", path.display());
+                status.failed(
+                    format!("{}: {e}", path.display()),
+                    clock::now(clock.as_deref()),
+                );
+                continue;
+            }
+        };
+        if last.as_ref() == Some(&content) {
+            continue;
+        }
+        match jwks.replace(&content) {
+            Ok((count, removed)) => {
+                log::info!("Reloaded {count} keys from JWKS file {}", path.display());
+                if removed {
+                    status.keys_replaced();
+                }
+                status.loaded(clock::now(clock.as_deref()));
+            }
+            Err(e) => {
+                log::warn!(
+                    "Keeping current keys, JWKS file {} is invalid: {e}",
+                    path.display()
+                );
+                status.failed(e, clock::now(clock.as_deref()));
+            }
+        }
+        // An invalid file is not parsed again until it changes.
+        last = Some(content);
+    }
+}
+
+/// The background task of
+/// [`OidcAuthLayer::jwks_file_watch_task`](crate::OidcAuthLayer::jwks_file_watch_task),
+/// reloading the layer's signing keys when their file changes.
+pub struct JwksFileWatchTask(BoxFuture<'static, ()>);
+
+impl Future for JwksFileWatchTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
@@ -0,0 +1,240 @@
+use futures::future::BoxFuture;
+use lru::LruCache;
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::budget::{self, CacheBudget, CacheKind, ENTRY_OVERHEAD};
+
+/// The error type of [`KeyCache`] implementations.
+pub type KeyCacheError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Shares the JWKS documents fetched by a [`JwksFetcher`](crate::JwksFetcher), e.g.
+/// between the replicas of a horizontally scaled service, so that they do not each fetch
+/// the keys from the identity provider on a cold start. Requires the `jwks-fetch` feature.
+///
+/// The cache is only an optimization: documents read from it are parsed like fetched ones,
+/// and a failing cache is logged and bypassed.
+pub trait KeyCache: Send + Sync + 'static {
+    /// Returns the document cached for the JWKS URL `url`, or `None` if there is none.
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>, KeyCacheError>>;
+
+    /// Caches `jwks_json`, the document at `url`, for `ttl`.
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        jwks_json: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), KeyCacheError>>;
+}
+
+/// A document cached by a [`MemoryKeyCache`], with when it expires, if its TTL can be
+/// represented, and the approximate size of the entry.
+type Document = (String, Option<Instant>, usize);
+
+/// A [`KeyCache`] in the memory of the process, shared by the layers built with clones of
+/// the same [`JwksFetcher`](crate::JwksFetcher). Requires the `jwks-fetch` feature.
+///
+/// Holds up to 1 000 documents by default, dropping the one stored longest ago to make room.
+/// A [`CacheBudget`] set with [`with_budget`](Self::with_budget) bounds the cache together
+/// with others.
+pub struct MemoryKeyCache {
+    /// The documents by URL, stored longest ago first.
+    documents: Mutex<LruCache<String, Document>>,
+    max_entries: usize,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+impl Default for MemoryKeyCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryKeyCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self {
+            documents: Mutex::new(LruCache::unbounded()),
+            max_entries: 1_000,
+            budget: None,
+        }
+    }
+
+    /// Sets the maximum number of cached documents.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Counts the cached documents against `budget`, shared with other caches. When the
+    /// budget is exhausted, the documents stored longest ago are dropped to make room, and a
+    /// document is not cached if that is not enough.
+    pub fn with_budget(mut self, budget: Arc<CacheBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Drops the document stored longest ago, adding its size to `evicted` unless it had
+    /// expired anyway. Returns `false` if the cache is empty.
+    fn remove_oldest(
+        &self,
+        documents: &mut LruCache<String, Document>,
+        now: Instant,
+        evicted: &mut Vec<usize>,
+    ) -> bool {
+        let Some((_, (_, expires_at, bytes))) = documents.pop_lru() else {
+            return false;
+        };
+        budget::release(self.budget.as_deref(), 1, bytes);
+        if expires_at.is_none_or(|expires_at| expires_at > now) {
+            evicted.push(bytes);
+        }
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<String, Document>> {
+        self.documents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl KeyCache for MemoryKeyCache {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>, KeyCacheError>> {
+        let mut documents = self.lock();
+        let jwks_json = match documents.peek(url) {
+            Some((_, Some(expires_at), _)) if *expires_at <= Instant::now() => {
+                if let Some((_, _, bytes)) = documents.pop(url) {
+                    budget::release(self.budget.as_deref(), 1, bytes);
+                }
+                None
+            }
+            Some((jwks_json, _, _)) => Some(jwks_json.clone()),
+            None => None,
+        };
+        Box::pin(async move { Ok(jwks_json) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        jwks_json: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), KeyCacheError>> {
+        let now = Instant::now();
+        let bytes = ENTRY_OVERHEAD + url.len() + jwks_json.len();
+        let budget = self.budget.as_deref();
+        let mut documents = self.lock();
+        if let Some((_, _, previous)) = documents.pop(url) {
+            budget::release(budget, 1, previous);
+        }
+        let mut evicted = Vec::new();
+        if documents.len() >= self.max_entries {
+            self.remove_oldest(&mut documents, now, &mut evicted);
+        }
+        let mut reserved = budget::reserve(budget, bytes);
+        while !reserved && self.remove_oldest(&mut documents, now, &mut evicted) {
+            reserved = budget::reserve(budget, bytes);
+        }
+        if reserved {
+            let document = (jwks_json.to_string(), now.checked_add(ttl), bytes);
+            documents.push(url.to_string(), document);
+        }
+        drop(documents);
+
+        if let Some(budget) = budget.filter(|_| !evicted.is_empty()) {
+            budget.evicted(CacheKind::Keys, &evicted);
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Drop for MemoryKeyCache {
+    fn drop(&mut self) {
+        let documents = self
+            .documents
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let bytes = documents.iter().map(|(_, (_, _, bytes))| bytes).sum();
+        budget::release(self.budget.as_deref(), documents.len(), bytes);
+    }
+}
+
+/// A [`KeyCache`] in Redis, shared by every replica connected to it. Requires the `redis`
+/// feature.
+///
+/// Documents are stored as strings under their JWKS URL, behind an optional key prefix, and
+/// expire through Redis' own TTLs.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{JwksFetcher, RedisKeyCache};
+/// use std::time::Duration;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = redis::Client::open("redis://redis.internal:6379")?;
+/// let cache = RedisKeyCache::new(client.get_multiplexed_async_connection().await?)
+///     .with_prefix("jwks:");
+/// let fetcher = JwksFetcher::new("https://your-oidc-provider.com/jwks")
+///     .with_key_cache(cache, Duration::from_secs(300));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "redis")]
+pub struct RedisKeyCache {
+    connection: redis::aio::MultiplexedConnection,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisKeyCache {
+    /// Caches documents through `connection`.
+    pub fn new(connection: redis::aio::MultiplexedConnection) -> Self {
+        Self {
+            connection,
+            prefix: String::new(),
+        }
+    }
+
+    /// Prefixes the JWKS URL with `prefix` to form the Redis key of each document.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+#[cfg(feature = "redis")]
+impl KeyCache for RedisKeyCache {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Option<String>, KeyCacheError>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            let jwks_json: Option<String> = redis::cmd("GET")
+                .arg(format!("{}{url}", self.prefix))
+                .query_async(&mut connection)
+                .await?;
+            Ok(jwks_json)
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        url: &'a str,
+        jwks_json: &'a str,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), KeyCacheError>> {
+        Box::pin(async move {
+            let mut connection = self.connection.clone();
+            // Redis rejects a zero expiry.
+            let seconds = ttl.as_secs().max(1);
+            redis::cmd("SET")
+                .arg(format!("{}{url}", self.prefix))
+                .arg(jwks_json)
+                .arg("EX")
+                .arg(seconds)
+                .query_async::<()>(&mut connection)
+                .await?;
+            Ok(())
+        })
+    }
+}
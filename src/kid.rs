@@ -0,0 +1,173 @@
+use lru::LruCache;
+use serde::Deserialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::budget::{self, CacheBudget, CacheKind, ENTRY_OVERHEAD};
+use crate::error::{AuthError, NO_MATCHING_KEY};
+use crate::extract::ValidatedPayload;
+use crate::header::TokenHeader;
+
+/// The most key identifiers remembered at once, bounding the memory a flood of distinct
+/// bogus identifiers can take. A [`CacheBudget`] may bound them further.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Unverified issuer and `kid` pairs, with when they were found unknown by the layer's clock
+/// and by the monotonic clock, and the approximate size of the entry. Entries are kept in the
+/// order they were found unknown, so the oldest, which expire first, are dropped first.
+type Entries = LruCache<(String, String), (Found, usize)>;
+
+type Found = (SystemTime, Instant);
+
+/// Key identifiers recently found to name no signing key of their issuer, so tokens carrying
+/// them are rejected without fetching the JWKS again.
+pub(crate) struct UnknownKids {
+    ttl: Duration,
+    entries: Mutex<Entries>,
+    budget: Option<Arc<CacheBudget>>,
+    time_anomalies: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct UnverifiedIssuer {
+    iss: Option<String>,
+}
+
+impl UnknownKids {
+    pub(crate) fn new(ttl: Duration, budget: Option<Arc<CacheBudget>>) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(LruCache::unbounded()),
+            budget,
+            time_anomalies: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns how often the clock was found to have gone backwards since a `kid` was found
+    /// unknown.
+    pub(crate) fn time_anomalies(&self) -> u64 {
+        self.time_anomalies.load(Ordering::Relaxed)
+    }
+
+    /// Rejects `token` if its `kid` was recently found unknown.
+    pub(crate) fn check(&self, token: &str, now: SystemTime) -> Result<(), AuthError> {
+        let Some(key) = issuer_and_kid(token) else {
+            return Ok(());
+        };
+        let mut entries = self.lock();
+        match entries.peek(&key) {
+            Some((found, _)) if self.is_fresh(*found, now) => {
+                log::debug!("Rejecting token with recently unknown kid {}", key.1);
+                Err(AuthError::InvalidSignature(NO_MATCHING_KEY.to_string()))
+            }
+            Some(((found, _), _)) => {
+                if *found > now {
+                    self.time_anomalies.fetch_add(1, Ordering::Relaxed);
+                    log::warn!("Clock went backwards since kid {} was found unknown", key.1);
+                }
+                if let Some((_, bytes)) = entries.pop(&key) {
+                    budget::release(self.budget.as_deref(), 1, bytes);
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Remembers the `kid` of `token` if `result` shows it names no key of the issuer.
+    pub(crate) fn record<T>(&self, token: &str, result: &Result<T, AuthError>, now: SystemTime) {
+        let unknown =
+            matches!(result, Err(AuthError::InvalidSignature(reason)) if reason == NO_MATCHING_KEY);
+        let Some(key) = issuer_and_kid(token).filter(|_| unknown) else {
+            return;
+        };
+        log::warn!("Caching unknown kid {} for {:?}", key.1, self.ttl);
+        let bytes = ENTRY_OVERHEAD + key.0.len() + key.1.len();
+        let budget = self.budget.as_deref();
+        let mut entries = self.lock();
+        if let Some((_, previous)) = entries.pop(&key) {
+            budget::release(budget, 1, previous);
+        }
+        self.remove_expired(&mut entries, now);
+        let mut evicted = Vec::new();
+        if entries.len() >= MAX_ENTRIES {
+            evicted.extend(self.remove_oldest(&mut entries));
+        }
+        let mut reserved = budget::reserve(budget, bytes);
+        while !reserved {
+            let Some(oldest) = self.remove_oldest(&mut entries) else {
+                break;
+            };
+            evicted.push(oldest);
+            reserved = budget::reserve(budget, bytes);
+        }
+        if reserved {
+            entries.push(key, ((now, Instant::now()), bytes));
+        }
+        drop(entries);
+
+        if let Some(budget) = budget.filter(|_| !evicted.is_empty()) {
+            budget.evicted(CacheKind::UnknownKids, &evicted);
+        }
+    }
+
+    /// Drops the oldest entries while they are no longer fresh.
+    fn remove_expired(&self, entries: &mut Entries, now: SystemTime) {
+        while entries
+            .peek_lru()
+            .is_some_and(|(_, (found, _))| !self.is_fresh(*found, now))
+        {
+            self.remove_oldest(entries);
+        }
+    }
+
+    /// Drops the entry found unknown longest ago, returning its size.
+    fn remove_oldest(&self, entries: &mut Entries) -> Option<usize> {
+        let (_, (_, bytes)) = entries.pop_lru()?;
+        budget::release(self.budget.as_deref(), 1, bytes);
+        Some(bytes)
+    }
+
+    /// Whether an entry found unknown at `found` is still within the TTL, both by the layer's
+    /// clock and by the monotonic clock. An entry from the future after the clock went
+    /// backwards is treated as expired.
+    fn is_fresh(&self, (found, found_instant): Found, now: SystemTime) -> bool {
+        now.duration_since(found)
+            .is_ok_and(|elapsed| elapsed < self.ttl)
+            && found_instant.elapsed() < self.ttl
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for UnknownKids {
+    fn drop(&mut self) {
+        let entries = self
+            .entries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let bytes = entries.iter().map(|(_, (_, bytes))| bytes).sum();
+        budget::release(self.budget.as_deref(), entries.len(), bytes);
+    }
+}
+
+/// Returns the unverified issuer and `kid` of `token`, if it names a key.
+pub(crate) fn issuer_and_kid(token: &str) -> Option<(String, String)> {
+    let kid = TokenHeader::from_token(token)?.kid?;
+    let iss = ValidatedPayload::from_token(token)
+        .and_then(|payload| payload.decode::<UnverifiedIssuer>().ok())
+        .and_then(|unverified| unverified.iss)
+        .unwrap_or_default();
+    Some((iss, kid))
+}
@@ -1,29 +1,1043 @@
-use async_oidc_jwt_validator::{OidcValidator, Validation};
-use std::{marker::PhantomData, sync::Arc};
+use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
+use futures::future::try_join_all;
+use http::HeaderName;
+#[cfg(feature = "jwks-file")]
+use std::path::PathBuf;
+use std::{marker::PhantomData, sync::Arc, time::Duration};
 use tower::Layer;
 
-use crate::middleware::OidcAuthMiddleware;
+use crate::audience::AudienceCheck;
+use crate::auth::ValidationOverrides;
+use crate::breaker::{Breaker, JwksCircuitBreaker};
+use crate::budget::CacheBudget;
+use crate::cache::ValidationCache;
+use crate::clock::{self, Clock};
+use crate::coalesce::InFlight;
+#[cfg(feature = "discovery")]
+use crate::discovery::{
+    refresh_task as discovery_refresh_task, DiscoveredIssuer, Discovery, DiscoveryRefresh,
+    DiscoveryRefreshTask,
+};
+use crate::env::{EnvConfig, EnvKeys};
+use crate::error::{AuthError, ConfigError, ErrorFormat, OFFLINE_FETCH};
+use crate::export::ClaimsExporter;
+#[cfg(feature = "jwks-fetch")]
+use crate::fetch::{fetch_task, JwksFetchTask, JwksFetcher};
+use crate::flags::FlagContextConfig;
+use crate::gateway::TrustedGatewayPayload;
+use crate::hooks::{PostResponseHook, PreAuthHook};
+use crate::issuer::{
+    is_plain_http, ClaimsDeserializer, Issuer, IssuerDeserializers, IssuerTemplate, Validators,
+};
+use crate::jwks::StaticJwks;
+#[cfg(feature = "jwks-file")]
+use crate::jwks_file::{watch_task, JwksFileWatchTask};
+use crate::kid::UnknownKids;
+use crate::metering::MeteringSink;
+use crate::middleware::{OidcAuthMiddleware, Shared};
+use crate::policy::AuthorizationPolicy;
+use crate::readiness::{KeyStatus, ReadinessHandle};
+use crate::redirect::LoginRedirect;
+#[cfg(feature = "jwks-refresh")]
+use crate::refresh::{
+    refresh_task, JwksCacheTtl, JwksRefresh, JwksRefreshTask, JwksRetry, KeyExpiry,
+};
+use crate::reject::{KeysUnavailable, Rejections};
+use crate::render::Renderer;
+use crate::sampling::Sampling;
+use crate::subject::SubjectOverrides;
+use crate::tenant::{TenantDirectory, TenantId, TenantResolver};
+use crate::token::{
+    CookieExtractor, HeaderExtractor, MalformedCredentials, QueryExtractor, TokenExtractor,
+    TokenExtractorChain, TokenSources,
+};
+
+/// Controls what the middleware does with requests that fail authentication.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMode {
+    /// Requests without valid claims are passed through to the inner service without claims
+    /// in their extensions.
+    #[default]
+    Optional,
+    /// Requests without valid claims are rejected with `401 Unauthorized`.
+    Strict,
+}
 
 /// A Tower layer that adds OIDC JWT authentication to your Axum application.
 ///
-/// This layer will extract JWT tokens from the Authorization header, validate them
-/// using the provided OIDC validator, and inject the claims into the request extensions.
+/// This layer will extract JWT tokens from the Authorization header (or, if configured,
+/// another header, a cookie or a query parameter), validate them using the provided OIDC
+/// validator, and inject the claims into the request extensions.
 #[derive(Clone)]
 pub struct OidcAuthLayer<T> {
-    pub(crate) oidc_validator: Arc<OidcValidator>,
-    pub(crate) validation: Validation,
+    pub(crate) validators: Validators,
+    pub(crate) mode: AuthMode,
+    pub(crate) rejections: Rejections,
+    pub(crate) token_sources: TokenSources,
+    pub(crate) metering: Option<Arc<dyn MeteringSink>>,
+    pub(crate) claims_export: Option<ClaimsExporter>,
+    pub(crate) sampling: Option<Sampling>,
+    pub(crate) flag_context: Option<Arc<FlagContextConfig>>,
+    pub(crate) raw_claims: bool,
+    pub(crate) offline: bool,
+    pub(crate) token_header: bool,
+    pub(crate) auth_context: bool,
+    pub(crate) access_token: bool,
+    pub(crate) trusted_gateway: Option<Arc<TrustedGatewayPayload>>,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) pre_auth: Vec<Arc<dyn PreAuthHook>>,
+    pub(crate) post_response: Option<Arc<dyn PostResponseHook>>,
+    pub(crate) subject_overrides: Option<Arc<SubjectOverrides>>,
+    pub(crate) validation_cache: Option<Arc<ValidationCache>>,
+    pub(crate) in_flight: Option<Arc<InFlight<T>>>,
+    pub(crate) unknown_kids: Option<Arc<UnknownKids>>,
+    pub(crate) cache_budget: Option<Arc<CacheBudget>>,
+    pub(crate) overrides: ValidationOverrides,
+    pub(crate) breaker: Option<Arc<Breaker>>,
+    pub(crate) key_status: Arc<KeyStatus>,
+    #[cfg(feature = "jwks-refresh")]
+    pub(crate) key_expiry: Option<Arc<KeyExpiry>>,
+    #[cfg(feature = "jwks-refresh")]
+    pub(crate) jwks_retry: Option<JwksRetry>,
+    pub(crate) policy: Option<Arc<dyn AuthorizationPolicy>>,
+    pub(crate) deserializers: IssuerDeserializers<T>,
     pub(crate) _phantom: PhantomData<T>,
 }
 
 impl<T> OidcAuthLayer<T> {
     /// Creates a new authentication layer with the provided OIDC validator and validation rules.
+    ///
+    /// The JWKS URL of `oidc_validator` cannot be read back, so neither this nor
+    /// [`validate`](Self::validate) can check that it uses `https`; [`from_env`](Self::from_env)
+    /// and [`discover`](Self::discover) do.
     pub fn new(oidc_validator: OidcValidator, validation: Validation) -> Self {
+        Self::with_validators(Validators::Single(
+            Arc::new(oidc_validator),
+            Arc::new(validation),
+        ))
+    }
+
+    /// Creates an authentication layer that accepts tokens from several issuers.
+    ///
+    /// Each token is routed to the validator of the issuer named in its (not yet verified)
+    /// `iss` claim. Tokens from issuers not in `issuers` are rejected.
+    pub fn multi_issuer(issuers: impl IntoIterator<Item = Issuer>) -> Self {
+        Self::with_validators(Validators::multi(issuers))
+    }
+
+    /// Creates an authentication layer for a multi-tenant service where each tenant has its
+    /// own issuer.
+    ///
+    /// `resolver` determines the tenant of each request (from its host, path or headers)
+    /// before the token is validated with that tenant's issuer. Requests whose tenant cannot
+    /// be resolved or is not in `tenants` are rejected. The resolved
+    /// [`TenantId`](crate::TenantId) is inserted into the request extensions.
+    pub fn multi_tenant(
+        resolver: impl TenantResolver,
+        tenants: impl IntoIterator<Item = (TenantId, Issuer)>,
+    ) -> Self {
+        Self::with_validators(Validators::tenants(resolver, tenants))
+    }
+
+    /// Like [`multi_tenant`](Self::multi_tenant), but looks up each tenant's issuer at request
+    /// time from `directory`, so tenants can be added without redeploying.
+    ///
+    /// Requests are rejected with `503 Service Unavailable` in strict mode if the lookup
+    /// fails. Pass an `Arc<TenantDirectory>` to keep a handle for
+    /// [invalidating](TenantDirectory::invalidate) or monitoring its cache.
+    pub fn dynamic_tenants(
+        resolver: impl TenantResolver,
+        directory: impl Into<Arc<TenantDirectory>>,
+    ) -> Self {
+        Self::with_validators(Validators::Directory(Arc::new(resolver), directory.into()))
+    }
+
+    /// Creates an authentication layer that accepts tokens from every issuer matching
+    /// `template`, such as the per-tenant issuers of an Azure AD multi-tenant application.
+    ///
+    /// The tenant named in each token is inserted into the request extensions as a
+    /// [`TenantId`](crate::TenantId).
+    pub fn issuer_template(template: IssuerTemplate) -> Self {
+        Self::with_validators(Validators::Template(Arc::new(template)))
+    }
+
+    /// Creates an authentication layer validating tokens with the keys of `jwks_json`, a
+    /// JWKS document such as `{"keys": [...]}`, without ever fetching keys over the network.
+    ///
+    /// For air-gapped environments, and for tokens signed with keys distributed out of band.
+    /// Tokens are verified with the key named by their `kid` header. A document holding a
+    /// single key also verifies tokens without a `kid`, and every token if the key itself
+    /// has none. Fails with [`ConfigError::InvalidJwks`] if the document cannot be parsed
+    /// or holds no usable keys.
+    ///
+    /// ```rust
+    /// use axum_jwt_oidc::{OidcAuthLayer, Validation};
+    ///
+    /// # fn run() -> Result<(), axum_jwt_oidc::ConfigError> {
+    /// let jwks = r#"{"keys": [{"kty": "oct", "kid": "shared", "k": "c2VjcmV0"}]}"#;
+    /// let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+    /// validation.set_issuer(&["https://issuer.example.com"]);
+    /// let auth_layer = OidcAuthLayer::<serde_json::Value>::with_static_jwks(jwks, validation)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_static_jwks(jwks_json: &str, validation: Validation) -> Result<Self, ConfigError> {
+        let jwks = StaticJwks::parse(jwks_json)?;
+        Ok(Self::with_validators(Validators::Static(
+            Arc::new(jwks),
+            Arc::new(validation),
+        )))
+    }
+
+    /// Creates an authentication layer configured by environment variables:
+    ///
+    /// | Variable | Meaning |
+    /// |---|---|
+    /// | `OIDC_ISSUER` | The expected `iss` claim. Required. |
+    /// | `OIDC_AUDIENCE` | The accepted audiences, separated by commas. Required. |
+    /// | `OIDC_JWKS_URL` | The URL of the issuer's JWKS document. |
+    /// | `OIDC_JWKS` | An inline JWKS document, validated as by [`with_static_jwks`](Self::with_static_jwks). |
+    /// | `OIDC_ALGORITHMS` | The accepted signing algorithms, separated by commas. Defaults to `RS256`. |
+    /// | `OIDC_LEEWAY_SECS` | The leeway for `exp` and `nbf` checks. Defaults to 60. |
+    /// | `OIDC_AUTH_MODE` | `strict` or `optional`. Defaults to `optional`. |
+    /// | `OIDC_ALLOW_INSECURE_HTTP` | `true` to accept `http` URLs, as with [`allow_insecure_http`](Self::allow_insecure_http). Defaults to `false`. |
+    ///
+    /// Exactly one of `OIDC_JWKS_URL` and `OIDC_JWKS` must be set. Empty variables count as
+    /// unset. Fails with [`ConfigError::MissingEnv`] listing every required variable that
+    /// is missing, with [`ConfigError::InvalidEnv`] or [`ConfigError::InvalidJwks`] for an
+    /// invalid value, or with [`ConfigError::InsecureUrl`] for an `http` issuer or JWKS URL.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::OidcAuthLayer;
+    ///
+    /// # fn run() {
+    /// let auth_layer = OidcAuthLayer::<serde_json::Value>::from_env()
+    ///     .unwrap_or_else(|e| panic!("invalid authentication settings: {e}"));
+    /// # }
+    /// ```
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let config = EnvConfig::read(|name| std::env::var(name).ok())?;
+        let validation = config.validation();
+        let layer = match config.keys {
+            EnvKeys::Url(jwks_url) => {
+                // The client ID of the validator is not used; `validation` checks the audiences.
+                let audience = config.audiences.join(",");
+                let oidc_config = OidcConfig::new(config.issuer, audience, jwks_url);
+                Self::new(OidcValidator::new(oidc_config), validation)
+            }
+            EnvKeys::Inline(jwks_json) => Self::with_static_jwks(&jwks_json, validation)?,
+        };
+        let layer = layer.with_mode(config.mode);
+        Ok(match config.allow_insecure_http {
+            true => layer.allow_insecure_http(),
+            false => layer,
+        })
+    }
+
+    /// Creates an authentication layer for `issuer`, finding its JWKS URL in the issuer's
+    /// `/.well-known/openid-configuration` discovery document, or in its
+    /// [RFC 8414](https://www.rfc-editor.org/rfc/rfc8414) authorization server metadata if
+    /// the issuer publishes no discovery document (`404 Not Found` or `410 Gone`). Requires
+    /// the `discovery` feature.
+    ///
+    /// The document must name `issuer` exactly, and tokens must carry the issuer of the
+    /// document as their `iss` claim, replacing any issuers `validation` expects. Tokens of
+    /// other issuers are rejected with [`AuthError::IssuerMismatch`]. The document is fetched
+    /// once, unless the [`discovery_refresh_task`](Self::discovery_refresh_task) is spawned;
+    /// fails with [`ConfigError::Discovery`] if it is unavailable or invalid, and with
+    /// [`ConfigError::InsecureUrl`] if `issuer` or the discovered JWKS URL uses plain `http`,
+    /// before anything is fetched from `issuer`. Use
+    /// [`discover_insecure`](Self::discover_insecure) for local development, and
+    /// [`discover_with`](Self::discover_with) to fetch the document with a client of your own.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::{OidcAuthLayer, Validation};
+    ///
+    /// # async fn run() -> Result<(), axum_jwt_oidc::ConfigError> {
+    /// let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
+    /// validation.set_audience(&["your-client-id"]);
+    /// let auth_layer =
+    ///     OidcAuthLayer::<serde_json::Value>::discover("https://your-oidc-provider.com", validation)
+    ///         .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "discovery")]
+    pub async fn discover(issuer: &str, validation: Validation) -> Result<Self, ConfigError> {
+        Self::discover_with(Discovery::new(issuer), validation).await
+    }
+
+    /// Like [`discover`](Self::discover), but also accepts an `issuer` and JWKS URL using
+    /// plain `http`, and returns a layer that [allows insecure `http`](Self::allow_insecure_http).
+    /// Only for local development against an identity provider on `localhost`. Requires the
+    /// `discovery` feature.
+    #[cfg(feature = "discovery")]
+    pub async fn discover_insecure(
+        issuer: &str,
+        validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        Self::discover_with(Discovery::new(issuer).allow_insecure_http(), validation).await
+    }
+
+    /// Like [`discover`](Self::discover), but fetches the discovery document as configured by
+    /// `discovery`, e.g. [with an HTTP client](Discovery::with_http_client) of your own, also
+    /// used by the [discovery refresh task](Self::discovery_refresh_task). Requires the
+    /// `discovery` feature.
+    ///
+    /// The signing keys are still fetched by the validator's own client. To fetch them
+    /// through yours as well, pass the [discovered JWKS URL](Discovery::jwks_uri) to
+    /// [`with_jwks_fetcher`](Self::with_jwks_fetcher).
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::{Discovery, OidcAuthLayer, Validation};
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = reqwest::Client::builder()
+    ///     .timeout(Duration::from_secs(2))
+    ///     .build()?;
+    /// let discovery = Discovery::new("https://your-oidc-provider.com").with_http_client(client);
+    /// let auth_layer =
+    ///     OidcAuthLayer::<serde_json::Value>::discover_with(discovery, Validation::default())
+    ///         .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "discovery")]
+    pub async fn discover_with(
+        discovery: Discovery,
+        validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        let allow_insecure_http = discovery.allows_insecure_http();
+        let issuer = DiscoveredIssuer::discover(discovery, validation).await?;
+        let layer = Self::with_validators(Validators::Discovered(Arc::new(issuer)));
+        Ok(match allow_insecure_http {
+            true => layer.allow_insecure_http(),
+            false => layer,
+        })
+    }
+
+    /// Like [`with_static_jwks`](Self::with_static_jwks), but fetches the JWKS document with
+    /// `fetcher`, through the crate's own HTTP client rather than the validator's. Requires
+    /// the `jwks-fetch` feature.
+    ///
+    /// Spawn the [`jwks_fetch_task`](Self::jwks_fetch_task) to fetch it again periodically.
+    /// Fails with [`ConfigError::InsecureUrl`] if the URL uses plain `http` without
+    /// [`JwksFetcher::allow_insecure_http`], before fetching it, with
+    /// [`ConfigError::JwksFetch`] if the document cannot be fetched, and with
+    /// [`ConfigError::InvalidJwks`] if it holds no usable keys.
+    #[cfg(feature = "jwks-fetch")]
+    pub async fn with_jwks_fetcher(
+        fetcher: JwksFetcher,
+        validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        let allow_insecure_http = fetcher.allows_insecure_http();
+        let jwks = StaticJwks::fetch(fetcher).await?;
+        let layer = Self::with_validators(Validators::Static(Arc::new(jwks), Arc::new(validation)));
+        Ok(match allow_insecure_http {
+            true => layer.allow_insecure_http(),
+            false => layer,
+        })
+    }
+
+    /// Like [`with_static_jwks`](Self::with_static_jwks), but reads the JWKS document from
+    /// the file at `path`, such as one mounted by cert-manager or a Vault agent. Requires the
+    /// `jwks-file` feature.
+    ///
+    /// Spawn the [`jwks_file_watch_task`](Self::jwks_file_watch_task) to pick up changes
+    /// to the file. Fails with [`ConfigError::InvalidJwks`] if the file cannot be read or
+    /// holds no usable keys.
+    #[cfg(feature = "jwks-file")]
+    pub fn with_jwks_file(
+        path: impl Into<PathBuf>,
+        validation: Validation,
+    ) -> Result<Self, ConfigError> {
+        let jwks = StaticJwks::load(path.into())?;
+        Ok(Self::with_validators(Validators::Static(
+            Arc::new(jwks),
+            Arc::new(validation),
+        )))
+    }
+
+    fn with_validators(validators: Validators) -> Self {
         Self {
-            oidc_validator: Arc::new(oidc_validator),
-            validation,
+            validators,
+            mode: AuthMode::default(),
+            rejections: Rejections::default(),
+            token_sources: TokenSources::default(),
+            metering: None,
+            claims_export: None,
+            sampling: None,
+            flag_context: None,
+            raw_claims: false,
+            offline: false,
+            token_header: false,
+            auth_context: false,
+            access_token: false,
+            trusted_gateway: None,
+            clock: None,
+            pre_auth: Vec::new(),
+            post_response: None,
+            subject_overrides: None,
+            validation_cache: None,
+            in_flight: None,
+            unknown_kids: None,
+            cache_budget: None,
+            overrides: ValidationOverrides::default(),
+            breaker: None,
+            key_status: Arc::default(),
+            #[cfg(feature = "jwks-refresh")]
+            key_expiry: None,
+            #[cfg(feature = "jwks-refresh")]
+            jwks_retry: None,
+            policy: None,
+            deserializers: IssuerDeserializers::new(),
             _phantom: PhantomData,
         }
     }
+
+    /// Sets how requests that fail authentication are handled. Defaults to [`AuthMode::Optional`].
+    pub fn with_mode(mut self, mode: AuthMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the body format of rejections generated by the middleware.
+    /// Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.rejections.error_format = error_format;
+        self
+    }
+
+    /// Reads the token from `header_name` instead of `Authorization`.
+    ///
+    /// If `prefix` is set (it is `Some("Bearer ")` by default), it is stripped from the header
+    /// value when present. Use `None` for headers that carry the bare token, such as
+    /// `X-Auth-Token`.
+    pub fn with_header(mut self, header_name: HeaderName, prefix: Option<&str>) -> Self {
+        self.token_sources.header = HeaderExtractor::new(header_name, prefix);
+        self
+    }
+
+    /// Reads the token from the named cookie when the token header is absent.
+    ///
+    /// The token header always takes precedence over the cookie.
+    pub fn with_cookie(mut self, cookie_name: impl Into<String>) -> Self {
+        self.token_sources.cookie = Some(CookieExtractor::new(cookie_name));
+        self
+    }
+
+    /// Reads the token from the named query parameter (e.g. `access_token`) when neither the
+    /// token header nor the cookie provides one.
+    ///
+    /// This exists for clients that cannot set headers, such as `EventSource` or download
+    /// links. See [`QueryExtractor`] for the caveats of tokens in URLs.
+    pub fn with_query_param(mut self, param: impl Into<String>) -> Self {
+        self.token_sources.query = Some(QueryExtractor::new(param));
+        self
+    }
+
+    /// Removes the token query parameter from the request URI before it reaches the inner
+    /// service, so handlers and downstream logging never see it.
+    pub fn strip_query_param(mut self) -> Self {
+        self.token_sources.strip_without_query = self.token_sources.query.is_none();
+        self.token_sources.query = self.token_sources.query.take().map(QueryExtractor::strip);
+        self
+    }
+
+    /// Sets what happens when a token source is malformed or ambiguous, such as a repeated
+    /// `Authorization` header. Defaults to [`MalformedCredentials::Ignore`].
+    pub fn with_malformed_credentials(mut self, malformed: MalformedCredentials) -> Self {
+        self.token_sources.malformed = malformed;
+        self
+    }
+
+    /// Reads the token with `extractor` instead of the built-in header, cookie and query
+    /// parameter sources.
+    pub fn with_token_extractor(self, extractor: impl TokenExtractor) -> Self {
+        self.with_token_extractors(TokenExtractorChain::new().then(extractor))
+    }
+
+    /// Reads the token from the first extractor in `chain` that finds one, instead of the
+    /// built-in header, cookie and query parameter sources.
+    ///
+    /// The [`TokenSource`](crate::TokenSource) of the matching extractor is inserted into the
+    /// request extensions.
+    pub fn with_token_extractors(mut self, chain: TokenExtractorChain) -> Self {
+        self.token_sources.chain = Some(chain);
+        self
+    }
+
+    /// Emits a [`UsageRecord`](crate::UsageRecord) to `sink` after each authenticated request.
+    pub fn with_metering(mut self, sink: impl MeteringSink) -> Self {
+        self.metering = Some(Arc::new(sink));
+        self
+    }
+
+    /// Runs `hook` on each request before its token is extracted, after any hooks added
+    /// earlier.
+    pub fn with_pre_auth(mut self, hook: impl PreAuthHook) -> Self {
+        self.pre_auth.push(Arc::new(hook));
+        self
+    }
+
+    /// Runs `hook` after each response, with the authentication outcome of the request.
+    pub fn with_post_response(mut self, hook: impl PostResponseHook) -> Self {
+        self.post_response = Some(Arc::new(hook));
+        self
+    }
+
+    /// Rejects authenticated requests whose subject is denied by `overrides` with
+    /// `403 Forbidden`, before any policy is evaluated. Pass an `Arc<SubjectOverrides>` to
+    /// keep a handle for changing the lists while the service runs.
+    pub fn with_subject_overrides(mut self, overrides: impl Into<Arc<SubjectOverrides>>) -> Self {
+        self.subject_overrides = Some(overrides.into());
+        self
+    }
+
+    /// Answers tokens validated before from `cache` instead of verifying them again. Pass
+    /// an `Arc<ValidationCache>` to keep a handle for reading its hit rate. Layers resolving
+    /// tenants per request do not use the cache.
+    pub fn with_validation_cache(mut self, cache: impl Into<Arc<ValidationCache>>) -> Self {
+        self.validation_cache = Some(cache.into());
+        self
+    }
+
+    /// Checks the `aud` claim of tokens with `audience` instead of the audience check of the
+    /// [`Validation`], e.g. to accept tokens issued for any of several registered clients.
+    ///
+    /// The check replaces that of every issuer and tenant of the layer. Tokens failing it
+    /// are rejected with [`AuthError::AudienceMismatch`], telling why.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::{AudienceCheck, OidcAuthLayer};
+    ///
+    /// # fn layer(auth_layer: OidcAuthLayer<serde_json::Value>) {
+    /// let auth_layer = auth_layer.with_audience(AudienceCheck::any_of(["web-app", "cli"]));
+    /// # }
+    /// ```
+    pub fn with_audience(mut self, audience: AudienceCheck) -> Self {
+        self.overrides.audience = Some(Arc::new(audience));
+        self
+    }
+
+    /// Accepts tokens up to `leeway` past their `exp` claim, or before their `nbf` claim, to
+    /// absorb clock drift between this service and the identity provider.
+    ///
+    /// Replaces the `leeway` of the [`Validation`] of every issuer and tenant of the layer,
+    /// which defaults to 60 seconds. Sub-second parts are ignored. Keep it to a few minutes,
+    /// as it lengthens the lifetime of every token, revoked or not.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::OidcAuthLayer;
+    /// use std::time::Duration;
+    ///
+    /// # fn layer(auth_layer: OidcAuthLayer<serde_json::Value>) {
+    /// let auth_layer = auth_layer.with_leeway(Duration::from_secs(120));
+    /// # }
+    /// ```
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.overrides.leeway = Some(leeway.as_secs());
+        self
+    }
+
+    /// Validates each token once at a time, so concurrent requests carrying the same token
+    /// await the result of a single validation, and of at most one JWKS fetch, instead of
+    /// each validating it. Layers resolving tenants per request do not coalesce requests.
+    pub fn with_request_coalescing(mut self) -> Self {
+        self.in_flight = Some(Arc::new(InFlight::new(self.cache_budget.clone())));
+        self
+    }
+
+    /// Remembers for `ttl` the `kid` of tokens that named no signing key of their issuer
+    /// even after its keys were fetched again, rejecting further tokens with that `kid`
+    /// without another fetch. This keeps a flood of tokens with bogus key identifiers from
+    /// hammering the JWKS endpoint, at the cost of rejecting tokens signed with a key the
+    /// issuer publishes during the `ttl`, so keep it short.
+    pub fn with_unknown_kid_ttl(mut self, ttl: Duration) -> Self {
+        self.unknown_kids = Some(Arc::new(UnknownKids::new(ttl, self.cache_budget.clone())));
+        self
+    }
+
+    /// Counts the key identifiers remembered by
+    /// [`with_unknown_kid_ttl`](Self::with_unknown_kid_ttl) and the validations coalesced by
+    /// [`with_request_coalescing`](Self::with_request_coalescing) against `budget`, bounding
+    /// their memory together with the caches given the same budget through
+    /// [`ValidationCache::with_budget`] and [`TenantDirectory::with_budget`].
+    ///
+    /// Validations beyond the budget are not coalesced.
+    ///
+    /// [`TenantDirectory::with_budget`]: crate::TenantDirectory::with_budget
+    pub fn with_cache_budget(mut self, budget: Arc<CacheBudget>) -> Self {
+        if let Some(unknown_kids) = &self.unknown_kids {
+            let ttl = unknown_kids.ttl();
+            self.unknown_kids = Some(Arc::new(UnknownKids::new(ttl, Some(budget.clone()))));
+        }
+        if self.in_flight.is_some() {
+            self.in_flight = Some(Arc::new(InFlight::new(Some(budget.clone()))));
+        }
+        self.cache_budget = Some(budget);
+        self
+    }
+
+    /// Stops fetching signing keys from an unavailable identity provider as configured by
+    /// `breaker`, validating tokens with the keys already cached meanwhile. Layers whose keys
+    /// are given up front never fetch them, so the breaker has no effect on them.
+    pub fn with_jwks_circuit_breaker(mut self, breaker: JwksCircuitBreaker) -> Self {
+        self.breaker = Some(Arc::new(Breaker::new(breaker, self.key_status.clone())));
+        self
+    }
+
+    /// Evaluates `policy` after each successful authentication, rejecting denied requests
+    /// with `403 Forbidden`.
+    pub fn with_policy(mut self, policy: impl AuthorizationPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Queues a [`ClaimsSummary`](crate::ClaimsSummary) of each authenticated request on
+    /// `exporter`, for offline analysis of who calls what.
+    pub fn with_claims_export(mut self, exporter: ClaimsExporter) -> Self {
+        self.claims_export = Some(exporter);
+        self
+    }
+
+    /// Records the authentication telemetry of only some requests, as decided by
+    /// `sampling`: the authentication result log, the post-response hook and the claims
+    /// export. Defaults to recording every request.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Inserts a [`FlagContext`](crate::FlagContext) built from the validated claims into
+    /// the request extensions, for feature-flag targeting by identity.
+    pub fn with_flag_context(mut self, config: FlagContextConfig) -> Self {
+        self.flag_context = Some(Arc::new(config));
+        self
+    }
+
+    /// Converts the claims of tokens issued by `issuer` with `deserializer` instead of
+    /// deserializing them into `T` directly.
+    ///
+    /// Use it for providers that emit claims `T` cannot read, without complicating `T` for
+    /// every other issuer. The deserializer is chosen by the `iss` claim after the token has
+    /// been validated; claims of other issuers are deserialized as usual.
+    pub fn with_issuer_deserializer(
+        mut self,
+        issuer: impl Into<String>,
+        deserializer: impl ClaimsDeserializer<T>,
+    ) -> Self {
+        self.deserializers
+            .insert(issuer.into(), Arc::new(deserializer));
+        self
+    }
+
+    /// Also inserts every claim of the validated token into the request extensions as a
+    /// [`serde_json::Value`], for generic middleware such as audit logging that does not know
+    /// the claims type `T`.
+    ///
+    /// Handlers can read any claims with the [`Claims`](crate::Claims) extractor without
+    /// this option; it is only needed to read them straight from the extensions.
+    pub fn with_raw_claims(mut self) -> Self {
+        self.raw_claims = true;
+        self
+    }
+
+    /// Inserts the [`TokenHeader`](crate::TokenHeader) of each validated token into the
+    /// request extensions, exposing the key and algorithm that signed it.
+    pub fn with_token_header(mut self) -> Self {
+        self.token_header = true;
+        self
+    }
+
+    /// Inserts an [`AuthContext<T>`](crate::AuthContext) into the request extensions
+    /// alongside the claims, bundling them with the token, its scopes, expiry and issuer.
+    ///
+    /// The context holds the raw token, so only enable this when handlers need to forward it.
+    pub fn with_auth_context(mut self) -> Self {
+        self.auth_context = true;
+        self
+    }
+
+    /// Inserts the validated token into the request extensions as an
+    /// [`AccessToken`](crate::AccessToken), for outbound clients that forward it to other APIs
+    /// on behalf of the user. The token is redacted from `Debug` output.
+    ///
+    /// Nothing is inserted in trusted gateway mode, where the layer never sees the token.
+    pub fn with_access_token(mut self) -> Self {
+        self.access_token = true;
+        self
+    }
+
+    /// Sets what happens in [`AuthMode::Strict`] to requests whose token cannot be validated
+    /// because the signing keys cannot be fetched and none are cached for it. Defaults to
+    /// [`KeysUnavailable::ServiceUnavailable`].
+    ///
+    /// With [`KeysUnavailable::Unauthorized`], such requests are rejected with
+    /// [`AuthError::InvalidToken`] instead of [`AuthError::JwksUnavailable`].
+    pub fn with_keys_unavailable(mut self, policy: KeysUnavailable) -> Self {
+        self.rejections.keys_unavailable = policy;
+        self
+    }
+
+    /// Negotiates strict-mode rejections by content type: requests accepting `text/html` that
+    /// fail with `401 Unauthorized` are redirected to the login page, while requests accepting
+    /// JSON receive an `application/problem+json` error. Other requests, and HTML requests
+    /// rejected with any other status, use the configured [`ErrorFormat`].
+    pub fn with_login_redirect(mut self, login_redirect: LoginRedirect) -> Self {
+        self.rejections.login_redirect = Some(Arc::new(login_redirect));
+        self
+    }
+
+    /// Serves strict-mode rejections of requests accepting `text/html` as pages rendered by
+    /// `renderer`, unless a [login redirect](Self::with_login_redirect) applies.
+    pub fn with_renderer(mut self, renderer: impl Renderer) -> Self {
+        self.rejections.renderer = Some(Arc::new(renderer));
+        self
+    }
+
+    /// Forbids the layer from making network calls, for regulated environments and hermetic
+    /// tests where accidental network access must be a hard error.
+    ///
+    /// Signing keys must then come from [`with_static_jwks`](Self::with_static_jwks),
+    /// [`with_jwks_file`](Self::with_jwks_file) or an inline `OIDC_JWKS` with
+    /// [`from_env`](Self::from_env). [`validate`](Self::validate) fails with
+    /// [`ConfigError::NetworkInOfflineMode`] otherwise, and if it is not called, requests are
+    /// rejected with [`AuthError::ConfigUnavailable`] instead of fetching keys or tenant
+    /// configuration. [`warm_up`](Self::warm_up) fails the same way, and the
+    /// [background refresh task](Self::jwks_refresh_task) ends at once.
+    pub fn with_offline_mode(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    /// Accepts issuer, discovery and JWKS URLs using plain `http`, for local development
+    /// against an identity provider such as dex or Keycloak on `localhost`.
+    ///
+    /// Without it, [`validate`](Self::validate) fails with [`ConfigError::InsecureUrl`] for
+    /// such issuer URLs, tenants of a [`TenantDirectory`](crate::TenantDirectory) using them
+    /// are rejected at lookup, and the [discovery refresh task](Self::discovery_refresh_task)
+    /// does not switch to them. [`discover`](Self::discover) and [`from_env`](Self::from_env)
+    /// check their URLs themselves. The JWKS URL inside an [`OidcValidator`] passed to
+    /// [`new`](Self::new) or an [`Issuer`] cannot be read back, so it is not checked. Tokens
+    /// and keys fetched over plain `http` can be tampered with in transit, so never use this
+    /// in production.
+    pub fn allow_insecure_http(mut self) -> Self {
+        self.overrides.allow_insecure_http = true;
+        self
+    }
+
+    /// Reads the current time from `clock` instead of the system clock when checking token
+    /// expiry and expiring cached tenant configuration, so tests can control time.
+    ///
+    /// The `exp` and `nbf` checks honour the `leeway` and
+    /// `reject_tokens_expiring_in_less_than` settings of the [`Validation`].
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Checks that every configured option can take effect, so misconfigurations fail at
+    /// startup instead of silently changing behaviour at runtime.
+    ///
+    /// ```rust,no_run
+    /// # use axum_jwt_oidc::{AuthMode, OidcAuthLayer, OidcValidator, Validation};
+    /// # fn validator() -> OidcValidator { unimplemented!() }
+    /// let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator(), Validation::default())
+    ///     .with_mode(AuthMode::Strict)
+    ///     .with_cookie("session")
+    ///     .validate()
+    ///     .expect("invalid authentication configuration");
+    /// ```
+    pub fn validate(self) -> Result<Self, ConfigError> {
+        let sources = &self.token_sources;
+        if sources.strip_without_query {
+            return Err(ConfigError::StripWithoutQueryParam);
+        }
+        if sources.has_empty_name() {
+            return Err(ConfigError::EmptySourceName);
+        }
+        if sources.chain.is_some() && sources.has_named_sources() {
+            return Err(ConfigError::SourcesReplacedByChain);
+        }
+        let reads_tokens = sources.chain.is_some()
+            || sources.has_named_sources()
+            || sources.malformed != MalformedCredentials::default();
+        if self.trusted_gateway.is_some() && reads_tokens {
+            return Err(ConfigError::IgnoredByTrustedGateway);
+        }
+        let rejections = &self.rejections;
+        let customizes_rejections = rejections.error_format != ErrorFormat::default()
+            || rejections.login_redirect.is_some()
+            || rejections.renderer.is_some()
+            || rejections.keys_unavailable != KeysUnavailable::default();
+        if self.mode == AuthMode::Optional && customizes_rejections {
+            return Err(ConfigError::RejectionsWithoutStrictMode);
+        }
+        if self.offline && self.trusted_gateway.is_none() && self.validators.fetches_keys() {
+            return Err(ConfigError::NetworkInOfflineMode);
+        }
+        if self
+            .overrides
+            .audience
+            .as_ref()
+            .is_some_and(|audience| audience.is_empty())
+        {
+            return Err(ConfigError::EmptyAudienceCheck);
+        }
+        if !self.overrides.allow_insecure_http {
+            if let Some(url) = self
+                .validators
+                .urls()
+                .into_iter()
+                .find(|url| is_plain_http(url))
+            {
+                return Err(ConfigError::InsecureUrl(url));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns a task refreshing the signing keys of the layer's validators as configured by
+    /// `refresh`, which must be spawned on the runtime. Requires the `jwks-refresh` feature.
+    ///
+    /// The task completes once every clone of the layer and the services built from it are
+    /// dropped. The keys of tenants looked up through a
+    /// [`TenantDirectory`](crate::TenantDirectory) are still fetched on demand.
+    #[cfg(feature = "jwks-refresh")]
+    pub fn jwks_refresh_task(&self, refresh: JwksRefresh) -> JwksRefreshTask {
+        let sources = match self.offline {
+            true => Vec::new(),
+            false => self.validators.key_sources(),
+        };
+        refresh_task(
+            refresh,
+            sources,
+            self.key_status.clone(),
+            self.clock.clone(),
+        )
+    }
+
+    /// Returns a task fetching the discovery document of a layer built with
+    /// [`discover`](Self::discover) again as configured by `refresh`, so that a changed
+    /// JWKS URL, e.g. after an identity provider migration, is picked up without
+    /// redeploying. Requires the `discovery` feature.
+    ///
+    /// The layer switches to a new JWKS URL once its keys are loaded, and keeps the current
+    /// one while the document or the new keys are unavailable. The task must be spawned on
+    /// the runtime, and completes once every clone of the layer and the services built from
+    /// it are dropped, or at once for layers not built by discovery or in offline mode.
+    #[cfg(feature = "discovery")]
+    pub fn discovery_refresh_task(&self, refresh: DiscoveryRefresh) -> DiscoveryRefreshTask {
+        let issuer = self
+            .validators
+            .discovered()
+            .filter(|_| !self.offline)
+            .map(Arc::downgrade);
+        discovery_refresh_task(
+            issuer,
+            refresh,
+            self.overrides.allow_insecure_http,
+            self.key_status.clone(),
+        )
+    }
+
+    /// Returns a task checking the JWKS file of a layer built with
+    /// [`with_jwks_file`](Self::with_jwks_file) every `interval`, and swapping in its keys
+    /// when it changes. Requires the `jwks-file` feature.
+    ///
+    /// Tokens are validated with either the old or the new keys, never a mix. If the changed
+    /// file cannot be read or parsed, the current keys are kept and the error is reported by
+    /// [`readiness`](Self::readiness). The task must be spawned on the runtime, and completes
+    /// once every clone of the layer and the services built from it are dropped, or at once
+    /// for layers not built from a file.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::{OidcAuthLayer, Validation};
+    /// use std::time::Duration;
+    ///
+    /// # fn run() -> Result<(), axum_jwt_oidc::ConfigError> {
+    /// let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_file(
+    ///     "/var/run/secrets/jwks/keys.json",
+    ///     Validation::default(),
+    /// )?;
+    /// tokio::spawn(auth_layer.jwks_file_watch_task(Duration::from_secs(10)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "jwks-file")]
+    pub fn jwks_file_watch_task(&self, interval: Duration) -> JwksFileWatchTask {
+        let jwks = self
+            .validators
+            .static_jwks()
+            .filter(|jwks| jwks.path().is_some())
+            .map(Arc::downgrade);
+        watch_task(jwks, interval, self.key_status.clone(), self.clock.clone())
+    }
+
+    /// Returns a task fetching the JWKS document of a layer built with
+    /// [`with_jwks_fetcher`](Self::with_jwks_fetcher) again every `interval`, and swapping in
+    /// its keys when it changes. Requires the `jwks-fetch` feature.
+    ///
+    /// The provider's caching headers are honoured: the document is fetched again sooner if
+    /// its `Cache-Control: max-age` expires before `interval` does, though at most once a
+    /// second, and its `ETag` is sent back in `If-None-Match`, so an unchanged document costs
+    /// a `304 Not Modified`.
+    ///
+    /// Tokens are validated with either the old or the new keys, never a mix. If the
+    /// document cannot be fetched or parsed, the current keys are kept and the error is
+    /// reported by [`readiness`](Self::readiness). The task must be spawned on the runtime,
+    /// and completes once every clone of the layer and the services built from it are
+    /// dropped, or at once for layers without a fetcher or in offline mode.
+    #[cfg(feature = "jwks-fetch")]
+    pub fn jwks_fetch_task(&self, interval: Duration) -> JwksFetchTask {
+        let jwks = self
+            .validators
+            .static_jwks()
+            .filter(|jwks| jwks.is_fetched() && !self.offline)
+            .map(Arc::downgrade);
+        fetch_task(jwks, interval, self.key_status.clone(), self.clock.clone())
+    }
+
+    /// Fetches the signing keys again once they are older than `ttl`, optionally refreshing
+    /// them in the background while serving requests with the stale ones. Requires the
+    /// `jwks-refresh` feature.
+    ///
+    /// The keys of tenants looked up through a [`TenantDirectory`](crate::TenantDirectory)
+    /// are not affected. Validators fetch keys with a client of their own that ignores the
+    /// provider's `Cache-Control` and `ETag` headers, so `ttl` applies regardless; the
+    /// `jwks_fetch_task` of a layer built with `with_jwks_fetcher` (`jwks-fetch` feature)
+    /// honours them.
+    #[cfg(feature = "jwks-refresh")]
+    pub fn with_jwks_cache_ttl(mut self, ttl: JwksCacheTtl) -> Self {
+        self.key_expiry = Some(Arc::new(KeyExpiry::new(ttl, self.key_status.clone())));
+        self
+    }
+
+    /// Retries failed JWKS fetches as configured by `retry` before rejecting a request with
+    /// [`AuthError::JwksUnavailable`], and before [`warm_up`](Self::warm_up) fails. Requires
+    /// the `jwks-refresh` feature.
+    #[cfg(feature = "jwks-refresh")]
+    pub fn with_jwks_retry(mut self, retry: JwksRetry) -> Self {
+        self.jwks_retry = Some(retry);
+        self
+    }
+
+    /// Fetches the signing keys of the layer's validators, so the first requests do not wait
+    /// for them and a misconfigured JWKS endpoint is reported before the server starts
+    /// accepting traffic.
+    ///
+    /// Fails with [`AuthError::JwksUnavailable`] if the keys of any issuer cannot be fetched,
+    /// or with [`AuthError::ConfigUnavailable`] if they would be fetched in
+    /// [offline mode](Self::with_offline_mode).
+    /// The keys of tenants looked up through a [`TenantDirectory`](crate::TenantDirectory)
+    /// are fetched on demand.
+    ///
+    /// ```rust,no_run
+    /// use axum::{routing::get, Router};
+    /// use axum_jwt_oidc::OidcAuthLayer;
+    ///
+    /// # async fn run(auth_layer: OidcAuthLayer<serde_json::Value>) {
+    /// auth_layer
+    ///     .warm_up()
+    ///     .await
+    ///     .expect("identity provider is unreachable or misconfigured");
+    ///
+    /// let app: Router = Router::new()
+    ///     .route("/", get(|| async { "ok" }))
+    ///     .layer(auth_layer);
+    /// let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    /// axum::serve(listener, app).await.unwrap();
+    /// # }
+    /// ```
+    // Without `jwks-refresh`, failed fetches are not retried.
+    #[cfg_attr(not(feature = "jwks-refresh"), allow(clippy::never_loop))]
+    pub async fn warm_up(&self) -> Result<(), AuthError> {
+        let status = &self.key_status;
+        if self.offline && self.validators.fetches_keys() {
+            log::error!("Failed to fetch JWKS during warm-up: {OFFLINE_FETCH}");
+            status.failed(OFFLINE_FETCH, clock::now(self.clock.as_deref()));
+            return Err(AuthError::ConfigUnavailable(OFFLINE_FETCH.to_string()));
+        }
+        let fetches = self
+            .validators
+            .oidc_validators()
+            .into_iter()
+            .map(|validator| async move {
+                #[cfg(feature = "jwks-refresh")]
+                let mut retries = 0;
+                loop {
+                    let result = validator.refresh_jwks_cache().await.map_err(|e| {
+                        log::error!("Failed to fetch JWKS during warm-up: {e}");
+                        status.failed(&e, clock::now(self.clock.as_deref()));
+                        AuthError::from_jwt(e)
+                    });
+                    #[cfg(feature = "jwks-refresh")]
+                    if let Some(delay) = self
+                        .jwks_retry
+                        .and_then(|retry| retry.delay(&result, retries))
+                    {
+                        retries += 1;
+                        tokio::time::sleep(delay).await;
+                        continue;
+                    }
+                    break result;
+                }
+            });
+        try_join_all(fetches).await?;
+        status.keys_replaced();
+        status.loaded(clock::now(self.clock.as_deref()));
+        Ok(())
+    }
+
+    /// Returns a handle reporting whether the layer's signing keys are loaded, for a
+    /// readiness probe. Call it after [`with_clock`](Self::with_clock), if used.
+    pub fn readiness(&self) -> ReadinessHandle {
+        let needs_keys = !self.validators.oidc_validators().is_empty();
+        ReadinessHandle::new(self.key_status.clone(), needs_keys, self.clock.clone())
+    }
+
+    /// Returns how often the layer's caches found the clock to have gone backwards since they
+    /// cached an entry, e.g. after an NTP correction or a VM snapshot restore, as one metric
+    /// for monitoring: the [`ValidationCache`], the unknown `kid` values of
+    /// [`with_unknown_kid_ttl`](Self::with_unknown_kid_ttl) and the
+    /// [`TenantDirectory`](crate::TenantDirectory), if used.
+    ///
+    /// Such entries are dropped rather than kept until the clock catches up. The validation
+    /// cache and the unknown `kid` values also measure their TTLs on the monotonic clock, so
+    /// a clock set back cannot keep them cached.
+    pub fn time_anomalies(&self) -> u64 {
+        let directory = match &self.validators {
+            Validators::Directory(_, directory) => directory.time_anomalies(),
+            _ => 0,
+        };
+        let cache = self
+            .validation_cache
+            .as_ref()
+            .map_or(0, |cache| cache.time_anomalies());
+        let unknown_kids = self
+            .unknown_kids
+            .as_ref()
+            .map_or(0, |unknown| unknown.time_anomalies());
+        directory + cache + unknown_kids
+    }
+
+    /// **Disables signature verification** and reads the claims from a payload header
+    /// forwarded by a gateway that has already verified the token.
+    ///
+    /// The OIDC validator and token extractors are not used in this mode. See
+    /// [`TrustedGatewayPayload`](crate::TrustedGatewayPayload) for the deployment
+    /// requirements that make this safe.
+    pub fn dangerously_trust_gateway_payload(mut self, gateway: TrustedGatewayPayload) -> Self {
+        log::warn!(
+            "JWT signature verification is DISABLED: trusting claims forwarded by a gateway in \
+             the `{}` header",
+            gateway.header
+        );
+        self.trusted_gateway = Some(Arc::new(gateway));
+        self
+    }
 }
 
 impl<S, T> Layer<S> for OidcAuthLayer<T>
@@ -33,11 +1047,44 @@ where
     type Service = OidcAuthMiddleware<S, T>;
 
     fn layer(&self, inner: S) -> Self::Service {
+        let shared = Shared {
+            validators: self.validators.clone(),
+            mode: self.mode,
+            rejections: self.rejections.clone(),
+            token_sources: self.token_sources.clone(),
+            metering: self.metering.clone(),
+            claims_export: self.claims_export.clone(),
+            sampling: self.sampling,
+            flag_context: self.flag_context.clone(),
+            raw_claims: self.raw_claims,
+            offline: self.offline,
+            token_header: self.token_header,
+            auth_context: self.auth_context,
+            access_token: self.access_token,
+            trusted_gateway: self.trusted_gateway.clone(),
+            clock: self.clock.clone(),
+            pre_auth: self.pre_auth.clone(),
+            post_response: self.post_response.clone(),
+            subject_overrides: self.subject_overrides.clone(),
+            validation_cache: self.validation_cache.clone(),
+            key_status: self.key_status.clone(),
+            in_flight: self.in_flight.clone(),
+            unknown_kids: self.unknown_kids.clone(),
+            overrides: self.overrides.clone(),
+            breaker: self
+                .breaker
+                .clone()
+                .filter(|_| self.validators.fetches_keys()),
+            #[cfg(feature = "jwks-refresh")]
+            key_expiry: self.key_expiry.clone(),
+            #[cfg(feature = "jwks-refresh")]
+            jwks_retry: self.jwks_retry,
+            policy: self.policy.clone(),
+            deserializers: self.deserializers.clone(),
+        };
         OidcAuthMiddleware {
             inner,
-            oidc_validator: self.oidc_validator.clone(),
-            validation: self.validation.clone(),
-            _phantom: PhantomData,
+            shared: Arc::new(shared),
         }
     }
 }
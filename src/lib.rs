@@ -4,15 +4,48 @@
 //!
 //! - Easy integration with Axum applications
 //! - Automatic JWT token extraction from Authorization header
-//! - Custom claims support with type-safe deserialization
+//! - Optional token extraction from a named cookie for browser clients
+//! - Custom claims support with type-safe deserialization, or the ready-made [`StandardClaims`]
+//! - Configurable audience matching: any of several audiences, all of them, or none for internal tokens,
+//!   with per-route overrides through [`RequireAudienceLayer`]
+//! - Configurable clock skew leeway for `exp` and `nbf` checks through [`OidcAuthLayer::with_leeway`]
 //! - Token validation using OIDC provider discovery
+//! - Eager JWKS prefetch at startup with `OidcAuthLayer::warm_up`
+//! - Readiness probes reporting whether signing keys are loaded through a [`ReadinessHandle`]
 //! - Claims are injected into request extensions for easy access
+//! - Optional per-identity usage metering through a [`MeteringSink`]
+//! - Optional subject allowlists and denylists, changeable at runtime, through [`SubjectOverrides`]
+//! - Optional caching of validation results with hit-rate metrics through a [`ValidationCache`]
+//! - Optional coalescing of concurrent validations of the same token
+//! - Optional negative caching of unknown `kid` values, sparing the JWKS endpoint from bogus tokens
+//! - Shared entry and byte bounds across the internal caches, with an aggregate gauge and an
+//!   eviction hook, through a [`CacheBudget`]
+//! - Validation against a static in-memory JWKS document, without network access
+//! - Configuration from `OIDC_*` environment variables through [`OidcAuthLayer::from_env`]
+//! - Optional offline mode refusing any network access for keys
+//! - HTTPS enforced for issuer, discovery and JWKS URLs, with `allow_insecure_http` for local development
+//! - Optional circuit breaker around JWKS fetches, falling back to cached keys during provider outages
+//! - Configurable fail-closed or fail-open handling of requests while signing keys are unavailable
+//! - Optional `#[require_scopes]` and `#[require_roles]` handler attributes (`macros` feature)
+//! - Optional token extraction through the typed `Authorization<Bearer>` header (`typed-header` feature)
+//! - Optional `auth_stack` composing the layer with rate limiting and HTTP tracing (`stack` feature)
+//! - Optional validation of tokens carried by message envelopes (`messages` feature)
+//! - Optional forwarding of the inbound token on outbound requests (`forward` feature)
+//! - Optional RFC 8693 token exchange and Azure AD on-behalf-of flow for downstream audiences (`exchange` feature)
+//! - Optional client credentials tokens for outbound service calls (`client-credentials` feature)
+//! - Optional background JWKS refresh with jitter and backoff, a key cache TTL with
+//!   stale-while-revalidate, and retries of failed fetches (`jwks-refresh` feature)
+//! - Optional JWKS file source reloaded when the file changes (`jwks-file` feature)
+//! - Optional JWKS fetching through a `reqwest::Client` of your own (`jwks-fetch` feature), with
+//!   fetched keys shared between replicas through a `KeyCache` such as Redis (`redis` feature)
+//! - Optional OIDC discovery of the JWKS URL from the issuer alone, falling back to RFC 8414
+//!   authorization server metadata, and refreshed to follow JWKS URL changes (`discovery` feature)
 //!
 //! # Usage
 //!
 //! ```rust,no_run
-//! use axum::{Router, routing::get, Extension};
-//! use axum_jwt_oidc::{OidcAuthLayer, OidcConfig, OidcValidator, Validation};
+//! use axum::{Router, routing::get};
+//! use axum_jwt_oidc::{Claims, OidcAuthLayer, OidcConfig, OidcValidator, Validation};
 //! use serde::{Deserialize, Serialize};
 //!
 //! #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -51,7 +84,7 @@
 //! }
 //!
 //! async fn protected_handler(
-//!     Extension(claims): Extension<CustomClaims>,
+//!     Claims(claims): Claims<CustomClaims>,
 //! ) -> &'static str {
 //!     // Access validated claims here
 //!     println!("User ID: {}", claims.sub);
@@ -61,20 +94,199 @@
 //!
 //! # How it works
 //!
-//! 1. The middleware extracts the JWT token from the `Authorization` header (Bearer token)
+//! 1. The middleware extracts the JWT token from the `Authorization` header (Bearer token),
+//!    or from the cookie configured with [`OidcAuthLayer::with_cookie`]
 //! 2. Validates the token using the configured OIDC validator
 //! 3. Deserializes the claims into your custom type
 //! 4. Injects the claims into the request extensions
 //! 5. Continues to the next handler if validation succeeds
 //!
-//! If validation fails, the request continues without claims in the extensions. You can implement your own authorization logic based on the presence or absence of claims.
+//! If validation fails, the request continues without claims in the extensions, carrying the [`AuthError`] instead. You can implement your own authorization logic based on the presence or absence of claims.
+//!
+//! To reject unauthenticated requests in the middleware instead, use [`AuthMode::Strict`].
+//! Rejections are plain text by default; [`ErrorFormat::ProblemJson`] switches them to
+//! [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807) `application/problem+json` bodies.
 
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+#[path = "macro_support.rs"]
+pub mod __private;
+mod access;
+mod audience;
 mod auth;
+mod breaker;
+mod budget;
+mod cache;
+mod capabilities;
+mod claim;
+mod clock;
+mod coalesce;
+mod compare;
+mod context;
+#[cfg(feature = "client-credentials")]
+mod credentials;
+#[cfg(feature = "discovery")]
+mod discovery;
+mod env;
+mod error;
+#[cfg(feature = "exchange")]
+mod exchange;
+mod export;
+mod extract;
+#[cfg(feature = "jwks-fetch")]
+mod fetch;
+mod flags;
+#[cfg(feature = "forward")]
+mod forward;
+mod gateway;
+mod header;
+mod hooks;
+mod issuer;
+mod jwks;
+#[cfg(feature = "jwks-file")]
+mod jwks_file;
+#[cfg(feature = "jwks-fetch")]
+mod key_cache;
+mod kid;
 mod layer;
+#[cfg(feature = "messages")]
+mod message;
+mod metering;
 mod middleware;
+mod policy;
+mod readiness;
+mod redirect;
+#[cfg(feature = "jwks-refresh")]
+mod refresh;
+mod reject;
+mod render;
+mod require;
+mod requirement;
+mod roles;
+mod sampling;
+mod scope;
+#[cfg(feature = "stack")]
+mod stack;
+mod standard;
+mod subject;
+mod tenant;
+mod token;
+#[cfg(any(feature = "exchange", feature = "client-credentials"))]
+mod token_endpoint;
 
 // Re-export the public API
-pub use layer::OidcAuthLayer;
+pub use access::ClaimsAccess;
+pub use audience::{AudienceCheck, AudienceMismatch, RequireAudience, RequireAudienceLayer};
+pub use auth::{validate_token, TokenMetadata};
+/// Rejects requests to an axum handler unless the token grants every listed Keycloak role.
+///
+/// Realm roles are checked unless a `client = "..."` argument names the client whose roles
+/// to check, as with [`RequireRolesLayer::client`]. Otherwise this behaves like
+/// [`require_scopes`]. Requires the `macros` feature.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::require_roles;
+///
+/// #[require_roles("admin")]
+/// async fn admin() -> &'static str {
+///     "admin"
+/// }
+///
+/// #[require_roles("deployer", client = "ops-console")]
+/// async fn deploy() -> &'static str {
+///     "deployed"
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use axum_jwt_oidc_macros::require_roles;
+/// Rejects requests to an axum handler unless the token grants every listed scope.
+///
+/// The attribute adds a [`Claims`] extractor in front of the handler's own arguments, so
+/// the route must be covered by an [`OidcAuthLayer`] and the handler can take at most 15
+/// extractors. Scopes are read like [`RequireScopesLayer`] does, and missing ones are
+/// rejected with `403 Forbidden` before any other extractor runs. The handler may return
+/// any [`IntoResponse`](axum::response::IntoResponse) type. Requires the `macros` feature.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::require_scopes;
+///
+/// #[require_scopes("read:orders", "write:orders")]
+/// async fn update_order() -> &'static str {
+///     "updated"
+/// }
+/// ```
+#[cfg(feature = "macros")]
+pub use axum_jwt_oidc_macros::require_scopes;
+pub use breaker::JwksCircuitBreaker;
+pub use budget::{CacheBudget, CacheEviction, CacheKind};
+pub use cache::ValidationCache;
+pub use capabilities::{capabilities, Capabilities};
+pub use claim::{RequireClaim, RequireClaimLayer};
+pub use clock::{Clock, ManualClock};
+pub use compare::constant_time_eq;
+pub use context::{AccessToken, AuthContext};
+#[cfg(feature = "client-credentials")]
+pub use credentials::{ClientCredentialsError, ClientCredentialsManager, ClientCredentialsTask};
+#[cfg(feature = "discovery")]
+pub use discovery::{Discovery, DiscoveryRefresh, DiscoveryRefreshTask};
+pub use error::{AuthError, ConfigError, ErrorFormat, ProblemDetails};
+#[cfg(feature = "exchange")]
+pub use exchange::{DownstreamTokens, DownstreamTokensRejection, ExchangeError, TokenExchanger};
+pub use export::{ClaimsExportTask, ClaimsExporter, ClaimsSink, ClaimsSinkError, ClaimsSummary};
+pub use extract::{AuthResult, Claims, ClaimsRejection, OptionalClaims};
+#[cfg(feature = "jwks-fetch")]
+pub use fetch::{JwksFetchTask, JwksFetcher};
+pub use flags::{FlagContext, FlagContextConfig};
+#[cfg(feature = "forward")]
+pub use forward::{
+    forward_token, forwarded_token, ForwardAuth, ForwardAuthLayer, ForwardScope, ForwardScopeLayer,
+};
+pub use gateway::TrustedGatewayPayload;
+pub use header::TokenHeader;
+pub use hooks::{AuthOutcome, PostResponseHook, PreAuthHook, ResponseEvent};
+#[cfg(feature = "discovery")]
+pub use hooks::{DiscoveryChange, DiscoveryHook};
+pub use issuer::{ClaimsDeserializer, ClaimsDeserializerError, Issuer, IssuerTemplate};
+#[cfg(feature = "jwks-file")]
+pub use jwks_file::JwksFileWatchTask;
+#[cfg(feature = "redis")]
+pub use key_cache::RedisKeyCache;
+#[cfg(feature = "jwks-fetch")]
+pub use key_cache::{KeyCache, KeyCacheError, MemoryKeyCache};
+pub use layer::{AuthMode, OidcAuthLayer};
+#[cfg(feature = "messages")]
+pub use message::MessageAuthenticator;
+pub use metering::{MeteringSink, UsageRecord};
+#[cfg(feature = "cedar")]
+pub use policy::CedarPolicy;
+#[cfg(feature = "opa")]
+pub use policy::OpaPolicy;
+pub use policy::{AuthorizationPolicy, PolicyDecision, PolicyError, PolicyInput};
+pub use readiness::ReadinessHandle;
+pub use redirect::{LoginRedirect, RedirectPolicy};
+#[cfg(feature = "jwks-refresh")]
+pub use refresh::{JwksCacheTtl, JwksRefresh, JwksRefreshTask, JwksRetry};
+pub use reject::KeysUnavailable;
+pub use render::{escape_html, ErrorPage, Renderer};
+pub use require::{ClaimsPredicate, Require, RequireLayer};
+pub use requirement::{AuthRequirement, EnforceRequirement};
+pub use roles::{KeycloakRoles, RequireRoles, RequireRolesLayer};
+pub use sampling::Sampling;
+pub use scope::{GrantedScopes, RequireScopes, RequireScopesLayer};
+#[cfg(feature = "stack")]
+pub use stack::{auth_stack, AuthStack, AuthStackLayer};
+pub use standard::StandardClaims;
+pub use subject::{SubjectList, SubjectMatch, SubjectOverrides};
+pub use tenant::{
+    HeaderTenantResolver, HostTenantResolver, PathPrefixTenantResolver, TenantConfig,
+    TenantConfigStore, TenantDirectory, TenantId, TenantResolver, TenantStoreError,
+};
+#[cfg(feature = "typed-header")]
+pub use token::TypedBearerExtractor;
+pub use token::{
+    CookieExtractor, HeaderExtractor, MalformedCredentials, QueryExtractor, TokenExtractor,
+    TokenExtractorChain, TokenSource, WebSocketProtocolExtractor,
+};
 
 // Re-export commonly used types from async-oidc-jwt-validator
 pub use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
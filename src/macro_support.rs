@@ -0,0 +1,18 @@
+//! Items used by the code that `#[require_scopes]` and `#[require_roles]` expand to.
+
+pub use axum::response::{IntoResponse, Response};
+
+pub use crate::GrantedScopes;
+use crate::{AuthError, KeycloakRoles};
+
+pub fn require_scopes(granted: &GrantedScopes, required: &[&str]) -> Result<(), AuthError> {
+    granted.require(required)
+}
+
+pub fn require_roles(
+    granted: &KeycloakRoles,
+    client: Option<&str>,
+    required: &[&str],
+) -> Result<(), AuthError> {
+    granted.require(client, required)
+}
@@ -0,0 +1,94 @@
+use async_oidc_jwt_validator::{OidcValidator, Validation};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{marker::PhantomData, sync::Arc};
+
+use crate::auth::{validate_token, TokenMetadata};
+use crate::claim::lookup;
+use crate::error::AuthError;
+
+/// Validates the tokens carried by message envelopes, such as Kafka or NATS events that
+/// propagate the user who caused them. Requires the `messages` feature.
+///
+/// The token is read from a string field of the JSON envelope, given as a dot-separated path
+/// (`meta.auth.token`) or a JSON pointer (`/meta/auth/token`), and validated with
+/// [`validate_token`](crate::validate_token). Build it from a clone of the validator given to
+/// the HTTP layer to share its cache of signing keys.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{MessageAuthenticator, OidcValidator, Validation};
+///
+/// # async fn consume(validator: OidcValidator, payload: &[u8]) {
+/// let authenticator =
+///     MessageAuthenticator::<serde_json::Value>::new(validator, Validation::default(), "meta.token");
+/// match authenticator.authenticate(payload).await {
+///     Ok((claims, _metadata)) => println!("event by {}", claims["sub"]),
+///     Err(error) => eprintln!("dropping event: {error}"),
+/// }
+/// # }
+/// ```
+pub struct MessageAuthenticator<T> {
+    oidc_validator: Arc<OidcValidator>,
+    validation: Arc<Validation>,
+    path: String,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for MessageAuthenticator<T> {
+    fn clone(&self) -> Self {
+        Self {
+            oidc_validator: self.oidc_validator.clone(),
+            validation: self.validation.clone(),
+            path: self.path.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> MessageAuthenticator<T>
+where
+    T: DeserializeOwned + Clone,
+{
+    /// Reads the token at `path` of each envelope and validates it with `oidc_validator` and
+    /// `validation`.
+    pub fn new(
+        oidc_validator: OidcValidator,
+        validation: Validation,
+        path: impl Into<String>,
+    ) -> Self {
+        Self {
+            oidc_validator: Arc::new(oidc_validator),
+            validation: Arc::new(validation),
+            path: path.into(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Validates the token of a JSON-encoded envelope.
+    ///
+    /// Fails with [`AuthError::MalformedCredentials`] if the envelope is not JSON or the
+    /// token field is not a string, and with [`AuthError::MissingToken`] if it is absent.
+    pub async fn authenticate(&self, envelope: &[u8]) -> Result<(T, TokenMetadata), AuthError> {
+        let envelope: Value = serde_json::from_slice(envelope)
+            .map_err(|e| AuthError::MalformedCredentials(format!("envelope is not JSON: {e}")))?;
+        self.authenticate_value(&envelope).await
+    }
+
+    /// Validates the token of an already decoded envelope.
+    pub async fn authenticate_value(
+        &self,
+        envelope: &Value,
+    ) -> Result<(T, TokenMetadata), AuthError> {
+        let token = match lookup(envelope, &self.path) {
+            None | Some(Value::Null) => return Err(AuthError::MissingToken),
+            Some(Value::String(token)) => token,
+            Some(_) => {
+                return Err(AuthError::MalformedCredentials(format!(
+                    "envelope field `{}` is not a string",
+                    self.path
+                )))
+            }
+        };
+        validate_token(&self.oidc_validator, &self.validation, token).await
+    }
+}
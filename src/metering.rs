@@ -0,0 +1,99 @@
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request},
+    response::Response,
+};
+use http::{Method, StatusCode};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::extract::ValidatedPayload;
+
+/// A usage record emitted after each authenticated request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageRecord {
+    /// The `sub` claim of the authenticated token, if present.
+    pub subject: Option<String>,
+    /// The `client_id` claim of the authenticated token, falling back to `azp`.
+    pub client_id: Option<String>,
+    /// The matched route pattern (e.g. `/orders/{id}`), or the request path if unavailable.
+    pub route: String,
+    /// The request method.
+    pub method: Method,
+    /// The response status code.
+    pub status: StatusCode,
+    /// The response body size in bytes, when known up front.
+    pub response_bytes: Option<u64>,
+    /// Time spent in the middleware and the inner service.
+    pub duration: Duration,
+}
+
+/// Receives usage records for authenticated requests, e.g. to feed a billing pipeline.
+///
+/// `record` is called on the request path, so implementations should hand records off
+/// (for example to a channel) rather than perform I/O directly. Any
+/// `Fn(UsageRecord) + Send + Sync + 'static` closure implements this trait.
+pub trait MeteringSink: Send + Sync + 'static {
+    /// Records the usage of one authenticated request.
+    fn record(&self, record: UsageRecord);
+}
+
+impl<F> MeteringSink for F
+where
+    F: Fn(UsageRecord) + Send + Sync + 'static,
+{
+    fn record(&self, record: UsageRecord) {
+        self(record)
+    }
+}
+
+/// The identity claims used for metering, read from the already validated token.
+#[derive(Debug, Default, Deserialize)]
+struct MeteringIdentity {
+    sub: Option<String>,
+    client_id: Option<String>,
+    azp: Option<String>,
+}
+
+/// The request half of a [`UsageRecord`], captured before the request is handed to the
+/// inner service.
+pub(crate) struct PendingUsage {
+    identity: MeteringIdentity,
+    route: String,
+    method: Method,
+    started: Instant,
+}
+
+impl PendingUsage {
+    pub(crate) fn capture(req: &Request, started: Instant) -> Self {
+        let identity = req
+            .extensions()
+            .get::<ValidatedPayload>()
+            .and_then(|payload| payload.decode::<MeteringIdentity>().ok())
+            .unwrap_or_default();
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| req.uri().path().to_string());
+
+        Self {
+            identity,
+            route,
+            method: req.method().clone(),
+            started,
+        }
+    }
+
+    pub(crate) fn finish(self, sink: &dyn MeteringSink, response: &Response) {
+        sink.record(UsageRecord {
+            subject: self.identity.sub,
+            client_id: self.identity.client_id.or(self.identity.azp),
+            route: self.route,
+            method: self.method,
+            status: response.status(),
+            response_bytes: response.body().size_hint().exact(),
+            duration: self.started.elapsed(),
+        });
+    }
+}
@@ -1,15 +1,41 @@
-use async_oidc_jwt_validator::{OidcValidator, Validation};
 use axum::{extract::Request, response::Response};
 use futures::future::BoxFuture;
+use http::request::Parts;
+use http::Request as BareRequest;
 use serde::de::DeserializeOwned;
 use std::{
-    marker::PhantomData,
     sync::Arc,
     task::{Context, Poll},
+    time::{Instant, SystemTime},
 };
 use tower::Service;
+use zeroize::Zeroizing;
 
-use crate::auth::validate_auth_header;
+use crate::auth::{log_result, ValidationOverrides};
+use crate::breaker::Breaker;
+use crate::cache::ValidationCache;
+use crate::clock::{self, Clock};
+use crate::coalesce::InFlight;
+use crate::context::{AccessToken, AuthContext};
+use crate::error::{AuthError, OFFLINE_FETCH};
+use crate::export::ClaimsExporter;
+use crate::extract::{AuthLayerInstalled, ValidatedPayload};
+use crate::flags::FlagContextConfig;
+use crate::gateway::TrustedGatewayPayload;
+use crate::header::TokenHeader;
+use crate::hooks::{AuthOutcome, PendingResponse, PostResponseHook, PreAuthHook};
+use crate::issuer::{IssuerDeserializers, Validators};
+use crate::kid::UnknownKids;
+use crate::layer::AuthMode;
+use crate::metering::{MeteringSink, PendingUsage};
+use crate::policy::{authorize, AuthorizationPolicy, PolicyInput};
+use crate::readiness::KeyStatus;
+#[cfg(feature = "jwks-refresh")]
+use crate::refresh::{JwksRetry, KeyExpiry};
+use crate::reject::Rejections;
+use crate::sampling::Sampling;
+use crate::subject::SubjectOverrides;
+use crate::token::{echo_websocket_protocol, TokenSource, TokenSources};
 
 /// The middleware service that performs JWT validation.
 ///
@@ -17,9 +43,43 @@ use crate::auth::validate_auth_header;
 #[derive(Clone)]
 pub struct OidcAuthMiddleware<S, T> {
     pub(crate) inner: S,
-    pub(crate) oidc_validator: Arc<OidcValidator>,
-    pub(crate) validation: Validation,
-    pub(crate) _phantom: PhantomData<T>,
+    pub(crate) shared: Arc<Shared<T>>,
+}
+
+/// The configuration and state of an [`OidcAuthMiddleware`], shared by its clones and the
+/// requests they handle.
+pub(crate) struct Shared<T> {
+    pub(crate) validators: Validators,
+    pub(crate) mode: AuthMode,
+    pub(crate) rejections: Rejections,
+    pub(crate) token_sources: TokenSources,
+    pub(crate) metering: Option<Arc<dyn MeteringSink>>,
+    pub(crate) claims_export: Option<ClaimsExporter>,
+    pub(crate) sampling: Option<Sampling>,
+    pub(crate) flag_context: Option<Arc<FlagContextConfig>>,
+    pub(crate) raw_claims: bool,
+    pub(crate) offline: bool,
+    pub(crate) token_header: bool,
+    pub(crate) auth_context: bool,
+    pub(crate) access_token: bool,
+    pub(crate) trusted_gateway: Option<Arc<TrustedGatewayPayload>>,
+    pub(crate) clock: Option<Arc<dyn Clock>>,
+    pub(crate) pre_auth: Vec<Arc<dyn PreAuthHook>>,
+    pub(crate) post_response: Option<Arc<dyn PostResponseHook>>,
+    pub(crate) subject_overrides: Option<Arc<SubjectOverrides>>,
+    pub(crate) validation_cache: Option<Arc<ValidationCache>>,
+    pub(crate) key_status: Arc<KeyStatus>,
+    pub(crate) in_flight: Option<Arc<InFlight<T>>>,
+    pub(crate) unknown_kids: Option<Arc<UnknownKids>>,
+    pub(crate) overrides: ValidationOverrides,
+    /// Only set for validators fetching their keys.
+    pub(crate) breaker: Option<Arc<Breaker>>,
+    #[cfg(feature = "jwks-refresh")]
+    pub(crate) key_expiry: Option<Arc<KeyExpiry>>,
+    #[cfg(feature = "jwks-refresh")]
+    pub(crate) jwks_retry: Option<JwksRetry>,
+    pub(crate) policy: Option<Arc<dyn AuthorizationPolicy>>,
+    pub(crate) deserializers: IssuerDeserializers<T>,
 }
 
 impl<S, T> Service<Request> for OidcAuthMiddleware<S, T>
@@ -36,23 +96,324 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, mut req: Request) -> Self::Future {
+    fn call(&mut self, req: Request) -> Self::Future {
         let not_ready_inner = self.inner.clone();
-        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
-        let oidc_validator = self.oidc_validator.clone();
-        let validation = self.validation.clone();
-
-        Box::pin(async move {
-            // Extract and validate claims
-            if let Some(claims) =
-                validate_auth_header::<T>(req.headers(), &oidc_validator, &validation).await
+        let inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        let shared = self.shared.clone();
+        Box::pin(async move { shared.handle(inner, req).await })
+    }
+}
+
+impl<T> Shared<T>
+where
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    /// Authenticates `req`, then passes it on to `inner` unless it is rejected.
+    async fn handle<S>(self: &Arc<Self>, mut inner: S, req: Request) -> Result<Response, S::Error>
+    where
+        S: Service<Request, Response = Response>,
+    {
+        let started = Instant::now();
+        let sample = Sampling::decide(self.sampling.as_ref());
+        log::debug!("Extracting claims from headers...");
+
+        // Extract and validate claims
+        let (mut parts, body) = req.into_parts();
+        parts.extensions.insert(AuthLayerInstalled);
+        // Lets route-level requirements reject requests the same way.
+        parts.extensions.insert(self.rejections.clone());
+        for hook in &self.pre_auth {
+            hook.before_auth(&mut parts);
+        }
+        let (extracted, malformed) = match self.trusted_gateway {
+            Some(_) => (None, None),
+            None => match self.token_sources.extract(&mut parts) {
+                Ok(extracted) => (extracted, None),
+                Err(error) => (None, Some(error)),
+            },
+        };
+        let (token, source) = extracted.unzip();
+        if let Some(source) = &source {
+            parts.extensions.insert(source.clone());
+        }
+        let result = match (&self.trusted_gateway, &token) {
+            (Some(gateway), _) => gateway
+                .decode::<T>(&parts.headers)
+                .map(|(claims, payload)| (claims, Some(payload))),
+            (None, Some(token)) => {
+                let result = self.authenticate(token, &mut parts).await;
+                if sample.includes(result.is_err()) {
+                    log_result(&result);
+                }
+                result.map(|claims| (claims, ValidatedPayload::from_token(token)))
+            }
+            (None, None) => Err(malformed.unwrap_or(AuthError::MissingToken)),
+        };
+        let header = token
+            .as_deref()
+            .filter(|_| self.token_header && result.is_ok())
+            .and_then(|token| TokenHeader::from_token(token));
+        let raw_token = token
+            .as_deref()
+            .filter(|_| (self.auth_context || self.access_token) && result.is_ok())
+            .map(|token| AccessToken::new(token));
+        // Do not keep the raw token around while the inner service runs.
+        drop(token);
+        let mut req = Request::from_parts(parts, body);
+
+        let mut outcome = AuthOutcome::Authenticated;
+        let rejection = match result {
+            Ok((claims, payload)) => {
+                self.authorize_request(&mut req, claims, payload, raw_token, header)
+                    .await
+            }
+            Err(error)
+                if self.mode == AuthMode::Strict && !self.rejections.passes_through(&error) =>
+            {
+                Some(self.rejections.rejection(error))
+            }
+            Err(error) => {
+                outcome = AuthOutcome::Unauthenticated(error.clone());
+                // Let downstream middleware, handlers and telemetry see why.
+                req.extensions_mut().insert(error);
+                None
+            }
+        };
+
+        let failed = rejection.is_some() || outcome != AuthOutcome::Authenticated;
+        let pending = self
+            .post_response
+            .as_deref()
+            .filter(|_| sample.includes(failed))
+            .map(|hook| (hook, PendingResponse::capture(&req, started)));
+        if let Some(error) = rejection {
+            let response = self.rejections.respond(&error, &req);
+            if let Some((hook, pending)) = pending {
+                let event = pending.finish(AuthOutcome::Rejected(error), &response);
+                hook.after_response(event).await;
+            }
+            return Ok(response);
+        }
+
+        let authenticated = outcome == AuthOutcome::Authenticated;
+
+        let exported = self
+            .claims_export
+            .as_ref()
+            .filter(|_| authenticated && sample.includes(false));
+        if let Some(exporter) = exported {
+            exporter.export(&req, clock::now(self.clock.as_deref()));
+        }
+        let usage = self
+            .metering
+            .as_deref()
+            .filter(|_| authenticated)
+            .map(|sink| (sink, PendingUsage::capture(&req, started)));
+
+        // Call the inner service
+        let mut response = inner.call(req).await?;
+
+        if let Some(TokenSource::WebSocketProtocol(protocol)) = &source {
+            echo_websocket_protocol(&mut response, protocol);
+        }
+        if let Some((sink, usage)) = usage {
+            usage.finish(sink, &response);
+        }
+        if let Some((hook, pending)) = pending {
+            hook.after_response(pending.finish(outcome, &response))
+                .await;
+        }
+
+        Ok(response)
+    }
+
+    /// Returns the claims of `token` from the validation cache, or validates it and caches
+    /// them.
+    async fn authenticate(
+        self: &Arc<Self>,
+        token: &str,
+        parts: &mut Parts,
+    ) -> Result<T, AuthError> {
+        let cache = self
+            .validation_cache
+            .as_deref()
+            .zip(self.validators.cache_margin());
+        let now = clock::now(self.clock.as_deref());
+        let cached =
+            cache.and_then(|(cache, _)| cache.get::<T>(token, now, self.key_status.generation()));
+        if let Some(claims) = cached {
+            return Ok(claims);
+        }
+
+        self.check(token, now)?;
+        #[cfg(feature = "jwks-refresh")]
+        if let Some(expiry) = self.key_expiry.as_ref().filter(|_| !self.breaker_open(now)) {
+            expiry
+                .ensure_fresh(&self.validators, self.clock.clone())
+                .await;
+        }
+        // Read before validating, so results validated with keys replaced meanwhile are not
+        // cached as current.
+        let keys = self.key_status.generation();
+        let result = self.validate_with_retries(token, parts).await;
+        let now = clock::now(self.clock.as_deref());
+        if let Some(unknown) = &self.unknown_kids {
+            unknown.record(token, &result, now);
+        }
+        if let (Some((cache, margin)), Ok(claims)) = (cache, &result) {
+            cache.insert(token, claims, margin, now, keys);
+        }
+        result
+    }
+
+    /// Rejects `token` without validating it if its keys would have to be fetched offline,
+    /// or its `kid` or the circuit breaker show that fetching them would fail.
+    fn check(&self, token: &str, now: SystemTime) -> Result<(), AuthError> {
+        if self.offline && self.validators.fetches_keys() {
+            log::error!("Refusing to validate token: {OFFLINE_FETCH}");
+            return Err(AuthError::ConfigUnavailable(OFFLINE_FETCH.to_string()));
+        }
+        if let Some(unknown) = &self.unknown_kids {
+            unknown.check(token, now)?;
+        }
+        if let Some(breaker) = &self.breaker {
+            breaker.check(token, now)?;
+        }
+        Ok(())
+    }
+
+    /// Validates `token`, recording the outcome in the circuit breaker and retrying failed
+    /// key fetches as configured by the [`JwksRetry`].
+    // Without `jwks-refresh`, failed fetches are not retried.
+    #[cfg_attr(not(feature = "jwks-refresh"), allow(clippy::never_loop))]
+    async fn validate_with_retries(
+        self: &Arc<Self>,
+        token: &str,
+        parts: &mut Parts,
+    ) -> Result<T, AuthError> {
+        #[cfg(feature = "jwks-refresh")]
+        let mut retries = 0;
+        loop {
+            let result = self.validate(token, parts).await;
+            let now = clock::now(self.clock.as_deref());
+            if let Some(breaker) = &self.breaker {
+                breaker.record(token, &result, now);
+            }
+            #[cfg(feature = "jwks-refresh")]
+            if let Some(delay) = self
+                .jwks_retry
+                .filter(|_| !self.breaker_open(now))
+                .and_then(|retry| retry.delay(&result, retries))
             {
-                // Store claims directly in request extensions
-                req.extensions_mut().insert(claims);
+                retries += 1;
+                tokio::time::sleep(delay).await;
+                continue;
             }
+            break result;
+        }
+    }
+
+    /// Validates `token`, sharing the validation with concurrent requests carrying the same
+    /// token if coalescing is enabled and the result depends on nothing but the token.
+    async fn validate(self: &Arc<Self>, token: &str, parts: &mut Parts) -> Result<T, AuthError> {
+        let Some(in_flight) = self
+            .in_flight
+            .as_deref()
+            .filter(|_| self.validators.cache_margin().is_some())
+        else {
+            return self
+                .validators
+                .validate_with(
+                    token,
+                    parts,
+                    self.clock.as_deref(),
+                    &self.overrides,
+                    &self.deserializers,
+                )
+                .await;
+        };
+        let shared = self.clone();
+        let owned = Zeroizing::new(token.to_string());
+        in_flight
+            .validate(token, async move {
+                // These validators do not read the request, so any request parts will do.
+                let (mut parts, ()) = BareRequest::new(()).into_parts();
+                shared
+                    .validators
+                    .validate_with(
+                        &owned,
+                        &mut parts,
+                        shared.clock.as_deref(),
+                        &shared.overrides,
+                        &shared.deserializers,
+                    )
+                    .await
+            })
+            .await
+    }
+
+    #[cfg(feature = "jwks-refresh")]
+    fn breaker_open(&self, now: SystemTime) -> bool {
+        self.breaker
+            .as_deref()
+            .is_some_and(|breaker| breaker.is_open(now))
+    }
 
-            // Call the inner service
-            inner.call(req).await
-        })
+    /// Adds the claims of the authenticated `req`, and whatever else the layer provides with
+    /// them, to its extensions, then checks the subject overrides and the authorization
+    /// policy. Returns the error to reject `req` with, if any.
+    async fn authorize_request(
+        &self,
+        req: &mut Request,
+        claims: T,
+        payload: Option<ValidatedPayload>,
+        raw_token: Option<AccessToken>,
+        header: Option<TokenHeader>,
+    ) -> Option<AuthError> {
+        if self.raw_claims {
+            let raw = payload.as_ref().and_then(|payload| payload.decode().ok());
+            if let Some(raw) = raw {
+                req.extensions_mut().insert::<serde_json::Value>(raw);
+            }
+        }
+        if let Some(token) = raw_token.clone().filter(|_| self.access_token) {
+            req.extensions_mut().insert(token);
+        }
+        if self.auth_context {
+            let now = clock::now(self.clock.as_deref());
+            let context = AuthContext::new(claims.clone(), raw_token, payload.as_ref(), now);
+            req.extensions_mut().insert(context);
+        }
+        // Store claims directly in request extensions
+        req.extensions_mut().insert(claims);
+        if let Some(header) = header {
+            req.extensions_mut().insert(header);
+        }
+
+        if let Some(config) = &self.flag_context {
+            let context = payload
+                .as_ref()
+                .and_then(|payload| payload.decode().ok())
+                .and_then(|payload| config.build(&payload));
+            if let Some(context) = context {
+                req.extensions_mut().insert(context);
+            }
+        }
+        let overridden = self
+            .subject_overrides
+            .as_ref()
+            .and_then(|overrides| overrides.check(payload.as_ref()).err());
+        if let Some(payload) = payload {
+            req.extensions_mut().insert(payload);
+        }
+
+        match (overridden, &self.policy) {
+            (Some(error), _) => Some(error),
+            (None, Some(policy)) => {
+                let input = PolicyInput::new(req);
+                authorize(policy.as_ref(), input).await.err()
+            }
+            (None, None) => None,
+        }
     }
 }
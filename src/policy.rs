@@ -0,0 +1,353 @@
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request},
+    RequestExt,
+};
+use futures::{future::BoxFuture, FutureExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::error::AuthError;
+use crate::extract::ValidatedPayload;
+
+/// What an [`AuthorizationPolicy`] decides on: the validated claims and the request target.
+///
+/// Serializes to `{"method": ..., "path": ..., "route": ..., "params": {...}, "claims": {...}}`,
+/// which is the `input` document sent by `OpaPolicy`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct PolicyInput {
+    /// The request method, e.g. `GET`.
+    pub method: String,
+    /// The request path, without the query string.
+    pub path: String,
+    /// The matched route pattern (e.g. `/orders/{id}`), if the layer runs inside a router.
+    pub route: Option<String>,
+    /// The path parameters of the matched route (e.g. `id`), percent-decoded.
+    pub params: BTreeMap<String, String>,
+    /// The claims of the validated token.
+    pub claims: Value,
+}
+
+impl PolicyInput {
+    pub(crate) fn new(req: &mut Request) -> Self {
+        // Extracting path parameters never waits, so there is no need to await.
+        let params = req
+            .extract_parts::<RawPathParams>()
+            .now_or_never()
+            .and_then(Result::ok)
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            route: req
+                .extensions()
+                .get::<MatchedPath>()
+                .map(|path| path.as_str().to_string()),
+            params,
+            claims: req
+                .extensions()
+                .get::<ValidatedPayload>()
+                .and_then(|payload| payload.decode().ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The outcome of an [`AuthorizationPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    /// The request may proceed.
+    Allow,
+    /// The request is rejected with `403 Forbidden`, for the given reason if any.
+    Deny(Option<String>),
+}
+
+/// The error type of [`AuthorizationPolicy`] evaluations.
+pub type PolicyError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Decides whether an authenticated request may proceed, e.g. by asking a policy engine.
+///
+/// Set with [`OidcAuthLayer::with_policy`](crate::OidcAuthLayer::with_policy), the policy is
+/// evaluated by the middleware after every successful authentication, in both
+/// [`AuthMode`](crate::AuthMode)s. Denied requests are rejected with `403 Forbidden`; if the
+/// policy cannot be evaluated, with `503 Service Unavailable`. Unauthenticated requests in
+/// [`AuthMode::Optional`](crate::AuthMode::Optional) are not evaluated.
+pub trait AuthorizationPolicy: Send + Sync + 'static {
+    /// Evaluates the policy for `input`.
+    fn evaluate<'a>(
+        &'a self,
+        input: &'a PolicyInput,
+    ) -> BoxFuture<'a, Result<PolicyDecision, PolicyError>>;
+}
+
+/// Evaluates `policy` for an authenticated request.
+pub(crate) async fn authorize(
+    policy: &dyn AuthorizationPolicy,
+    input: PolicyInput,
+) -> Result<(), AuthError> {
+    match policy.evaluate(&input).await {
+        Ok(PolicyDecision::Allow) => Ok(()),
+        Ok(PolicyDecision::Deny(reason)) => {
+            log::warn!(
+                "Authorization policy denied {} {}",
+                input.method,
+                input.path
+            );
+            Err(AuthError::AccessDenied(reason))
+        }
+        Err(e) => {
+            log::error!("Failed to evaluate authorization policy: {e}");
+            Err(AuthError::PolicyUnavailable(e.to_string()))
+        }
+    }
+}
+
+#[cfg(feature = "opa")]
+pub use opa::OpaPolicy;
+
+#[cfg(feature = "opa")]
+mod opa {
+    use futures::future::BoxFuture;
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+
+    use super::{AuthorizationPolicy, PolicyDecision, PolicyError, PolicyInput};
+
+    /// An [`AuthorizationPolicy`] that queries an [Open Policy Agent](https://www.openpolicyagent.org)
+    /// server, typically a sidecar, through its data API.
+    ///
+    /// The [`PolicyInput`] is posted as the `input` document to the URL of a rule, such as
+    /// `http://localhost:8181/v1/data/httpapi/authz/allow`. The rule may evaluate to a
+    /// boolean, or to an object with a boolean `allow` and an optional string `reason`. An
+    /// undefined rule denies the request. Requires the `opa` feature.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::OpaPolicy;
+    ///
+    /// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+    /// let auth_layer = auth_layer
+    ///     .with_policy(OpaPolicy::new("http://localhost:8181/v1/data/httpapi/authz/allow"));
+    /// # }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct OpaPolicy {
+        url: String,
+        client: reqwest::Client,
+    }
+
+    impl OpaPolicy {
+        /// Queries the rule at `url`, giving up after five seconds.
+        pub fn new(url: impl Into<String>) -> Self {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default();
+            Self::with_client(url, client)
+        }
+
+        /// Queries the rule at `url` with `client`, e.g. to configure timeouts or TLS.
+        pub fn with_client(url: impl Into<String>, client: reqwest::Client) -> Self {
+            Self {
+                url: url.into(),
+                client,
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Query<'a> {
+        input: &'a PolicyInput,
+    }
+
+    #[derive(Deserialize)]
+    struct QueryResult {
+        result: Option<Decision>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Decision {
+        Allow(bool),
+        Detailed { allow: bool, reason: Option<String> },
+    }
+
+    impl AuthorizationPolicy for OpaPolicy {
+        fn evaluate<'a>(
+            &'a self,
+            input: &'a PolicyInput,
+        ) -> BoxFuture<'a, Result<PolicyDecision, PolicyError>> {
+            Box::pin(async move {
+                let response: QueryResult = self
+                    .client
+                    .post(&self.url)
+                    .json(&Query { input })
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+
+                Ok(match response.result {
+                    Some(Decision::Allow(true)) | Some(Decision::Detailed { allow: true, .. }) => {
+                        PolicyDecision::Allow
+                    }
+                    Some(Decision::Detailed { reason, .. }) => PolicyDecision::Deny(reason),
+                    Some(Decision::Allow(false)) | None => PolicyDecision::Deny(None),
+                })
+            })
+        }
+    }
+}
+
+#[cfg(feature = "cedar")]
+pub use cedar::CedarPolicy;
+
+#[cfg(feature = "cedar")]
+mod cedar {
+    use cedar_policy::{
+        Authorizer, Context, Decision, Entities, EntityId, EntityTypeName, EntityUid, PolicySet,
+        Request,
+    };
+    use futures::future::BoxFuture;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+
+    use super::{AuthorizationPolicy, PolicyDecision, PolicyError, PolicyInput};
+
+    /// An [`AuthorizationPolicy`] that evaluates [Cedar](https://www.cedarpolicy.com)
+    /// policies in process. Requires the `cedar` feature.
+    ///
+    /// Each request is authorized as:
+    ///
+    /// - principal `User::"<sub>"`, from the `sub` claim;
+    /// - action `Action::"<method>"`, e.g. `Action::"GET"`;
+    /// - resource `Resource::"<id>"`, where the id is the path parameter named with
+    ///   [`resource_param`](Self::resource_param), or else the request path;
+    /// - context `{ claims, path, route, params }`, where `claims` holds the validated claims.
+    ///
+    /// Claims that Cedar cannot represent, such as `null` or fractional numbers, are left out
+    /// of the context.
+    ///
+    /// ```rust,no_run
+    /// use axum_jwt_oidc::CedarPolicy;
+    ///
+    /// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+    /// let policy = CedarPolicy::new(
+    ///     r#"
+    ///     permit(principal, action == Action::"GET", resource);
+    ///     permit(principal, action == Action::"DELETE", resource)
+    ///         when { context.claims.groups.contains("admins") };
+    ///     "#,
+    /// )
+    /// .unwrap()
+    /// .resource_param("id");
+    /// let auth_layer = auth_layer.with_policy(policy);
+    /// # }
+    /// ```
+    #[derive(Debug)]
+    pub struct CedarPolicy {
+        policies: PolicySet,
+        entities: Entities,
+        resource_param: Option<String>,
+        authorizer: Authorizer,
+    }
+
+    impl CedarPolicy {
+        /// Parses `policies`, written in the Cedar policy language.
+        pub fn new(policies: &str) -> Result<Self, PolicyError> {
+            Ok(Self {
+                policies: PolicySet::from_str(policies)?,
+                entities: Entities::empty(),
+                resource_param: None,
+                authorizer: Authorizer::new(),
+            })
+        }
+
+        /// Uses the path parameter `name` as the resource id, e.g. `id` for `/orders/{id}`.
+        pub fn resource_param(mut self, name: impl Into<String>) -> Self {
+            self.resource_param = Some(name.into());
+            self
+        }
+
+        /// Evaluates policies against `entities`, e.g. to give resources owners or parents.
+        pub fn with_entities(mut self, entities: Entities) -> Self {
+            self.entities = entities;
+            self
+        }
+
+        fn request(&self, input: &PolicyInput) -> Result<Request, PolicyError> {
+            let uid = |type_name: &str, id: &str| -> Result<EntityUid, PolicyError> {
+                Ok(EntityUid::from_type_name_and_id(
+                    EntityTypeName::from_str(type_name)?,
+                    EntityId::from_str(id)?,
+                ))
+            };
+            let subject = input.claims["sub"].as_str().unwrap_or_default();
+            let resource = self
+                .resource_param
+                .as_ref()
+                .and_then(|name| input.params.get(name))
+                .unwrap_or(&input.path);
+            let context = json!({
+                "claims": to_cedar(input.claims.clone()).unwrap_or_else(|| json!({})),
+                "path": input.path,
+                "route": input.route.as_deref().unwrap_or(&input.path),
+                "params": input.params,
+            });
+
+            Ok(Request::new(
+                Some(uid("User", subject)?),
+                Some(uid("Action", &input.method)?),
+                Some(uid("Resource", resource)?),
+                Context::from_json_value(context, None)?,
+            ))
+        }
+    }
+
+    impl AuthorizationPolicy for CedarPolicy {
+        fn evaluate<'a>(
+            &'a self,
+            input: &'a PolicyInput,
+        ) -> BoxFuture<'a, Result<PolicyDecision, PolicyError>> {
+            Box::pin(async move {
+                let request = self.request(input)?;
+                let response =
+                    self.authorizer
+                        .is_authorized(&request, &self.policies, &self.entities);
+                for error in response.diagnostics().errors() {
+                    log::warn!("Cedar policy evaluation error: {error}");
+                }
+                Ok(match response.decision() {
+                    Decision::Allow => PolicyDecision::Allow,
+                    Decision::Deny => PolicyDecision::Deny(None),
+                })
+            })
+        }
+    }
+
+    /// Drops the parts of a JSON value that have no Cedar representation.
+    fn to_cedar(value: Value) -> Option<Value> {
+        match value {
+            Value::Null => None,
+            Value::Number(n) if !n.is_i64() => None,
+            Value::Array(items) => Some(Value::Array(
+                items.into_iter().filter_map(to_cedar).collect(),
+            )),
+            Value::Object(fields) => Some(Value::Object(
+                fields
+                    .into_iter()
+                    .filter_map(|(k, v)| to_cedar(v).map(|v| (k, v)))
+                    .collect(),
+            )),
+            value => Some(value),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::clock::{self, Clock};
+
+/// When the layer's signing keys were last loaded, shared by its clones.
+#[derive(Default)]
+pub(crate) struct KeyStatus {
+    state: Mutex<KeyState>,
+    /// Counts the times the keys were replaced by a set that may lack some of them.
+    generation: AtomicU64,
+}
+
+#[derive(Default, Clone)]
+struct KeyState {
+    loaded_at: Option<SystemTime>,
+    last_error: Option<String>,
+    /// The failed fetches since the keys were last loaded.
+    failures: u32,
+    failed_at: Option<SystemTime>,
+}
+
+impl KeyStatus {
+    /// Records that the keys of every validator were fetched at `now`.
+    pub(crate) fn loaded(&self, now: SystemTime) {
+        let mut state = self.lock();
+        state.loaded_at = Some(now);
+        state.last_error = None;
+        state.failures = 0;
+    }
+
+    /// Records that fetching the keys failed with `error` at `now`.
+    pub(crate) fn failed(&self, error: impl ToString, now: SystemTime) {
+        let mut state = self.lock();
+        state.last_error = Some(error.to_string());
+        state.failures = state.failures.saturating_add(1);
+        state.failed_at = Some(now);
+    }
+
+    /// Records that the keys were replaced by a set that may lack some of them, so tokens
+    /// validated with the old set are validated again.
+    pub(crate) fn keys_replaced(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Returns how often the keys were replaced by a set that may lack some of them.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Returns the failed fetches since the keys were last loaded, and when the last one
+    /// failed.
+    pub(crate) fn failures(&self) -> (u32, Option<SystemTime>) {
+        let state = self.lock();
+        (state.failures, state.failed_at)
+    }
+
+    /// Returns when the keys of every validator were last fetched.
+    pub(crate) fn loaded_at(&self) -> Option<SystemTime> {
+        self.lock().loaded_at
+    }
+
+    fn get(&self) -> KeyState {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, KeyState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reports whether an [`OidcAuthLayer`](crate::OidcAuthLayer) can validate tokens, for a
+/// readiness probe such as a Kubernetes `/readyz` endpoint.
+///
+/// The layer is ready once its signing keys have been loaded by
+/// [`warm_up`](crate::OidcAuthLayer::warm_up) or the
+/// [background refresh task](crate::OidcAuthLayer::jwks_refresh_task), and, if
+/// [`max_age`](Self::max_age) is set, were refreshed recently enough. Layers whose keys are
+/// only fetched on demand, as with a [`TenantDirectory`](crate::TenantDirectory), are always
+/// ready.
+///
+/// The handle converts into a `200 OK` response when ready and `503 Service Unavailable`
+/// otherwise, so it can be returned from a handler directly:
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+///
+/// # async fn run(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let readiness = auth_layer.readiness();
+/// let probes = Router::new().route("/readyz", get(move || async move { readiness }));
+///
+/// // Fetch the keys in the background, so the pod becomes ready once they are loaded.
+/// let warm_up_layer = auth_layer.clone();
+/// tokio::spawn(async move { warm_up_layer.warm_up().await });
+///
+/// let app: Router = Router::new()
+///     .route("/", get(|| async { "ok" }))
+///     .layer(auth_layer)
+///     .merge(probes);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ReadinessHandle {
+    status: Arc<KeyStatus>,
+    needs_keys: bool,
+    max_age: Option<Duration>,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl ReadinessHandle {
+    pub(crate) fn new(
+        status: Arc<KeyStatus>,
+        needs_keys: bool,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Self {
+        Self {
+            status,
+            needs_keys,
+            max_age: None,
+            clock,
+        }
+    }
+
+    /// Reports the layer as not ready once its keys have not been refreshed for `max_age`,
+    /// e.g. a few refresh intervals of the background refresh task.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns whether the layer's signing keys are loaded and fresh.
+    pub fn is_ready(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    /// Returns when the signing keys of every validator were last loaded.
+    pub fn loaded_at(&self) -> Option<SystemTime> {
+        self.status.loaded_at()
+    }
+
+    /// Returns the error of the last attempt to load the keys, if it failed.
+    pub fn last_error(&self) -> Option<String> {
+        self.status.get().last_error
+    }
+
+    /// Returns `Ok` if the layer is ready, or the reason it is not.
+    pub fn check(&self) -> Result<(), String> {
+        if !self.needs_keys {
+            return Ok(());
+        }
+        let state = self.status.get();
+        let Some(loaded_at) = state.loaded_at else {
+            return Err(match state.last_error {
+                Some(error) => format!("signing keys not loaded: {error}"),
+                None => "signing keys not loaded yet".to_string(),
+            });
+        };
+        let age = clock::now(self.clock.as_deref())
+            .duration_since(loaded_at)
+            .unwrap_or_default();
+        match self.max_age {
+            Some(max_age) if age > max_age => Err(format!(
+                "signing keys last refreshed {}s ago{}",
+                age.as_secs(),
+                state
+                    .last_error
+                    .map(|error| format!(": {error}"))
+                    .unwrap_or_default()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl IntoResponse for ReadinessHandle {
+    fn into_response(self) -> Response {
+        match self.check() {
+            Ok(()) => (StatusCode::OK, "ready").into_response(),
+            Err(reason) => (StatusCode::SERVICE_UNAVAILABLE, reason).into_response(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use axum::response::{IntoResponse, Response};
+use http::{header, HeaderMap, StatusCode, Uri};
+
+/// Redirects unauthenticated browser requests to a login page with `302 Found`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginRedirect {
+    login_url: String,
+    next_param: String,
+    redirect_policy: RedirectPolicy,
+    default_return: String,
+}
+
+impl LoginRedirect {
+    /// Redirects to `login_url`, passing the original URL in the `next` query parameter.
+    pub fn new(login_url: impl Into<String>) -> Self {
+        Self {
+            login_url: login_url.into(),
+            next_param: "next".to_string(),
+            redirect_policy: RedirectPolicy::default(),
+            default_return: "/".to_string(),
+        }
+    }
+
+    /// Sets the name of the query parameter carrying the original URL. Defaults to `next`.
+    pub fn next_param(mut self, name: impl Into<String>) -> Self {
+        self.next_param = name.into();
+        self
+    }
+
+    /// Allows returning to absolute URLs on `origin` (e.g. `https://app.example.com`) after
+    /// login. Relative paths on the current origin are always allowed.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.redirect_policy = self.redirect_policy.allow_origin(origin);
+        self
+    }
+
+    /// Replaces the policy deciding which return targets are allowed after login.
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Sets where [`return_to`](Self::return_to) sends users whose original URL is missing
+    /// or not allowed. Defaults to `/`.
+    pub fn default_return(mut self, path: impl Into<String>) -> Self {
+        self.default_return = path.into();
+        self
+    }
+
+    /// Resolves where to send the user once the login callback completes.
+    ///
+    /// `next` is the original URL captured on redirect-to-login, as received back by the
+    /// callback handler. It is returned only if it is a same-origin relative path or an
+    /// absolute URL on an [allowed origin](Self::allow_origin); anything else, including
+    /// protocol-relative URLs like `//evil.example`, yields the default return path so the
+    /// callback cannot be used as an open redirect.
+    pub fn return_to<'a>(&'a self, next: Option<&'a str>) -> &'a str {
+        self.redirect_policy.resolve(next, &self.default_return)
+    }
+
+    /// Builds the login URL for a request to `original`.
+    pub fn location(&self, original: &Uri) -> String {
+        let next = original
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+        let separator = if self.login_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let encoded: String = form_urlencoded::byte_serialize(next.as_bytes()).collect();
+        format!("{}{separator}{}={encoded}", self.login_url, self.next_param)
+    }
+
+    pub(crate) fn response(&self, original: &Uri) -> Response {
+        (
+            StatusCode::FOUND,
+            [(header::LOCATION, self.location(original))],
+        )
+            .into_response()
+    }
+}
+
+/// Decides which redirect targets are safe, to prevent open redirects.
+///
+/// Relative paths on the current origin (`/orders?tab=2`) are always allowed, while
+/// protocol-relative URLs (`//evil.example`), backslash tricks, userinfo in the authority and
+/// non-HTTP schemes are always rejected. Absolute URLs are allowed only on explicitly
+/// allowlisted origins, so the default policy is relative-only; allowlisting the application's
+/// own origin gives same-origin semantics.
+///
+/// Use this in any handler that redirects to a user-supplied URL, such as login callbacks
+/// and logout endpoints.
+///
+/// ```rust
+/// use axum_jwt_oidc::RedirectPolicy;
+///
+/// let policy = RedirectPolicy::relative_only().allow_origin("https://app.example.com");
+///
+/// assert!(policy.is_allowed("/dashboard"));
+/// assert!(policy.is_allowed("https://app.example.com/reports"));
+/// assert!(!policy.is_allowed("https://evil.example.com/"));
+/// assert_eq!(policy.resolve(Some("//evil.example.com"), "/"), "/");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    allowed_origins: Vec<String>,
+}
+
+impl RedirectPolicy {
+    /// Allows relative paths on the current origin only.
+    pub fn relative_only() -> Self {
+        Self::default()
+    }
+
+    /// Additionally allows absolute URLs on `origin` (`scheme://host[:port]`).
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins
+            .push(origin.into().trim_end_matches('/').to_ascii_lowercase());
+        self
+    }
+
+    /// Whether redirecting to `target` is allowed by this policy.
+    pub fn is_allowed(&self, target: &str) -> bool {
+        if target.is_empty() || target.contains('\\') || target.chars().any(char::is_control) {
+            return false;
+        }
+
+        if target.starts_with('/') {
+            return !target.starts_with("//");
+        }
+
+        let Ok(uri) = target.parse::<Uri>() else {
+            return false;
+        };
+        let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) else {
+            return false;
+        };
+        if !matches!(scheme, "http" | "https") || authority.as_str().contains('@') {
+            return false;
+        }
+
+        let origin = format!("{scheme}://{authority}").to_ascii_lowercase();
+        self.allowed_origins.contains(&origin)
+    }
+
+    /// Returns `target` if it is allowed, and `fallback` otherwise.
+    pub fn resolve<'a>(&self, target: Option<&'a str>, fallback: &'a str) -> &'a str {
+        match target {
+            Some(target) if self.is_allowed(target) => target,
+            Some(target) => {
+                log::warn!("Refusing to redirect to disallowed URL: {target}");
+                fallback
+            }
+            None => fallback,
+        }
+    }
+}
+
+/// Whether the client prefers a JSON response, based on its `Accept` header.
+pub(crate) fn accepts_json(headers: &HeaderMap) -> bool {
+    accept(headers)
+        .is_some_and(|accept| accept.contains("application/json") || accept.contains("+json"))
+}
+
+/// Whether the client is a browser asking for an HTML page rather than an API response.
+pub(crate) fn accepts_html(headers: &HeaderMap) -> bool {
+    !accepts_json(headers) && accept(headers).is_some_and(|accept| accept.contains("text/html"))
+}
+
+fn accept(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::ACCEPT).and_then(|h| h.to_str().ok())
+}
@@ -0,0 +1,365 @@
+use async_oidc_jwt_validator::OidcValidator;
+use futures::future::BoxFuture;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::clock::{self, Clock};
+use crate::error::AuthError;
+use crate::issuer::{KeySource, Validators};
+use crate::readiness::KeyStatus;
+
+/// How long the task first waits after a failed refresh, doubling with each further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// When a [`JwksRefreshTask`] refreshes the signing keys. Requires the `jwks-refresh`
+/// feature.
+///
+/// Without the task, keys are fetched on the request path the first time a token signed
+/// with an unknown key arrives, so requests around a key rotation wait for the JWKS
+/// endpoint. Refreshing ahead of time keeps their latency flat.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::JwksRefresh;
+/// use std::time::Duration;
+///
+/// # fn run(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let refresh = JwksRefresh::new(Duration::from_secs(600)).jitter(0.2);
+/// tokio::spawn(auth_layer.jwks_refresh_task(refresh));
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JwksRefresh {
+    interval: Duration,
+    jitter: f64,
+    max_backoff: Duration,
+}
+
+impl JwksRefresh {
+    /// Refreshes the keys every `interval`, varied by up to 10% so that replicas started
+    /// together do not hit the JWKS endpoint at the same time.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            jitter: 0.1,
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets the share of the interval, from `0.0` to `1.0`, by which each wait is randomly
+    /// lengthened or shortened. Defaults to `0.1`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Sets the longest wait between retries after failed refreshes, which start after one
+    /// second and double each time. Defaults to five minutes.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns how long to wait before the next refresh, after `failures` consecutive
+    /// failed ones.
+    fn delay(&self, failures: u32) -> Duration {
+        let delay = match failures {
+            0 => self.interval,
+            n => INITIAL_BACKOFF
+                .saturating_mul(2u32.saturating_pow(n - 1))
+                .min(self.max_backoff),
+        };
+        jittered(delay, self.jitter)
+    }
+}
+
+/// Randomly lengthens or shortens `delay` by up to the share `jitter` of it, keeping
+/// `delay` as it is where that would overflow, as for a `Duration::MAX` interval.
+fn jittered(delay: Duration, jitter: f64) -> Duration {
+    let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+    Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(delay)
+}
+
+/// Refreshes the keys of `sources` until every one of them is dropped, recording the
+/// outcome in `status`.
+pub(crate) fn refresh_task(
+    refresh: JwksRefresh,
+    sources: Vec<KeySource>,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) -> JwksRefreshTask {
+    JwksRefreshTask(Box::pin(run(refresh, sources, status, clock)))
+}
+
+async fn run(
+    refresh: JwksRefresh,
+    sources: Vec<KeySource>,
+    status: Arc<KeyStatus>,
+    clock: Option<Arc<dyn Clock>>,
+) {
+    let mut failures = 0;
+    loop {
+        tokio::time::sleep(refresh.delay(failures)).await;
+        let mut alive = false;
+        let mut failed = false;
+        for source in &sources {
+            let Some(validator) = source.upgrade() else {
+                continue;
+            };
+            alive = true;
+            match validator.refresh_jwks_cache().await {
+                // The validator does not tell which keys it dropped, if any.
+                Ok(()) => status.keys_replaced(),
+                Err(e) => {
+                    log::warn!("Failed to refresh JWKS in the background: {e}");
+                    status.failed(e, clock::now(clock.as_deref()));
+                    failed = true;
+                }
+            }
+        }
+        if !alive {
+            return;
+        }
+        if !failed {
+            status.loaded(clock::now(clock.as_deref()));
+        }
+        failures = if failed {
+            failures.saturating_add(1)
+        } else {
+            0
+        };
+    }
+}
+
+/// The background task of [`OidcAuthLayer::jwks_refresh_task`](crate::OidcAuthLayer::jwks_refresh_task),
+/// refreshing the layer's signing keys.
+pub struct JwksRefreshTask(BoxFuture<'static, ()>);
+
+impl Future for JwksRefreshTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+/// How long the signing keys of an [`OidcAuthLayer`](crate::OidcAuthLayer) are used before
+/// they are fetched again, set with
+/// [`with_jwks_cache_ttl`](crate::OidcAuthLayer::with_jwks_cache_ttl). Requires the
+/// `jwks-refresh` feature.
+///
+/// By default, keys are kept until a token signed with an unknown key arrives. With a TTL,
+/// the first request after the keys expire fetches them again, and concurrent requests wait
+/// for that one fetch. Within the [stale-while-revalidate](Self::stale_while_revalidate)
+/// window after the TTL, requests are instead validated with the cached keys while they are
+/// refreshed in the background. If a refresh fails, requests are validated with the keys
+/// already cached.
+///
+/// ```rust
+/// use axum_jwt_oidc::JwksCacheTtl;
+/// use std::time::Duration;
+///
+/// // Refresh the keys hourly, in the background during the first five minutes after expiry.
+/// let ttl = JwksCacheTtl::new(Duration::from_secs(3600))
+///     .stale_while_revalidate(Duration::from_secs(300));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JwksCacheTtl {
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+}
+
+impl JwksCacheTtl {
+    /// Uses the keys for `ttl` after they were fetched.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            stale_while_revalidate: Duration::ZERO,
+        }
+    }
+
+    /// Keeps using expired keys for up to `window` after the TTL while they are refreshed in
+    /// the background. Defaults to zero, so requests wait for the refresh.
+    pub fn stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale_while_revalidate = window;
+        self
+    }
+}
+
+/// The state of a layer's [`JwksCacheTtl`], shared by its clones.
+pub(crate) struct KeyExpiry {
+    ttl: JwksCacheTtl,
+    status: Arc<KeyStatus>,
+    /// Set while a background refresh runs, so only one is started.
+    revalidating: AtomicBool,
+    /// Serializes refreshes on the request path.
+    refresh_lock: tokio::sync::Mutex<()>,
+    /// Counts refreshes on the request path, so requests that waited for one do not repeat it.
+    refreshes: AtomicU64,
+}
+
+impl KeyExpiry {
+    pub(crate) fn new(ttl: JwksCacheTtl, status: Arc<KeyStatus>) -> Self {
+        Self {
+            ttl,
+            status,
+            revalidating: AtomicBool::new(false),
+            refresh_lock: tokio::sync::Mutex::new(()),
+            refreshes: AtomicU64::new(0),
+        }
+    }
+
+    /// Refreshes the keys of `validators` if they have expired, in the background while they
+    /// are within the stale-while-revalidate window.
+    pub(crate) async fn ensure_fresh(
+        self: &Arc<Self>,
+        validators: &Validators,
+        clock: Option<Arc<dyn Clock>>,
+    ) {
+        let age = self.age(clock.as_deref());
+        if age.is_some_and(|age| age <= self.ttl.ttl) {
+            return;
+        }
+        let validators = validators.oidc_validators();
+        if validators.is_empty() {
+            return;
+        }
+
+        let stale_limit = self.ttl.ttl.saturating_add(self.ttl.stale_while_revalidate);
+        if age.is_some_and(|age| age <= stale_limit) {
+            if !self.revalidating.swap(true, Ordering::AcqRel) {
+                let expiry = self.clone();
+                tokio::spawn(async move {
+                    expiry.refresh(&validators, clock.as_deref()).await;
+                    expiry.revalidating.store(false, Ordering::Release);
+                });
+            }
+            return;
+        }
+
+        let refreshes = self.refreshes.load(Ordering::Acquire);
+        let _guard = self.refresh_lock.lock().await;
+        // Another request refreshed the keys, or failed to, while this one waited.
+        if self.refreshes.load(Ordering::Acquire) != refreshes {
+            return;
+        }
+        self.refresh(&validators, clock.as_deref()).await;
+        self.refreshes.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn age(&self, clock: Option<&dyn Clock>) -> Option<Duration> {
+        let now = clock::now(clock);
+        self.status
+            .loaded_at()
+            .map(|loaded_at| now.duration_since(loaded_at).unwrap_or_default())
+    }
+
+    async fn refresh(&self, validators: &[Arc<OidcValidator>], clock: Option<&dyn Clock>) {
+        let mut failed = false;
+        for validator in validators {
+            match validator.refresh_jwks_cache().await {
+                Ok(()) => self.status.keys_replaced(),
+                Err(e) => {
+                    log::warn!("Failed to refresh expired JWKS, using cached keys: {e}");
+                    self.status.failed(e, clock::now(clock));
+                    failed = true;
+                }
+            }
+        }
+        if !failed {
+            self.status.loaded(clock::now(clock));
+        }
+    }
+}
+
+/// How failed JWKS fetches are retried before a request is rejected, set with
+/// [`with_jwks_retry`](crate::OidcAuthLayer::with_jwks_retry). Requires the `jwks-refresh`
+/// feature.
+///
+/// Transient DNS failures or `5xx` responses of the identity provider are otherwise
+/// rejected at once with [`AuthError::JwksUnavailable`]. With a retry policy, the token is
+/// validated again, fetching the keys anew, after a backoff that starts at
+/// [`initial_backoff`](Self::initial_backoff) and doubles with each attempt. The retries add
+/// to the latency of the requests that wait for them, so keep the attempts and backoff
+/// within what clients tolerate.
+///
+/// ```rust
+/// use axum_jwt_oidc::JwksRetry;
+/// use std::time::Duration;
+///
+/// // Try up to three times, waiting about 100ms, then 200ms.
+/// let retry = JwksRetry::new(3).initial_backoff(Duration::from_millis(100));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JwksRetry {
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+}
+
+impl JwksRetry {
+    /// Fetches the keys up to `attempts` times in all, so `1` disables retries.
+    pub fn new(attempts: u32) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            jitter: 0.1,
+        }
+    }
+
+    /// Sets the wait before the first retry, doubling for each further one. Defaults to
+    /// 100 milliseconds.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the longest wait between retries. Defaults to two seconds.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the share of each wait, from `0.0` to `1.0`, by which it is randomly lengthened
+    /// or shortened. Defaults to `0.1`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    /// Returns how long to wait before retrying, if `result` failed to fetch the keys and
+    /// `retries` retries have not used up the attempts.
+    pub(crate) fn delay<T>(&self, result: &Result<T, AuthError>, retries: u32) -> Option<Duration> {
+        let Err(AuthError::JwksUnavailable(reason)) = result else {
+            return None;
+        };
+        if retries.saturating_add(1) >= self.attempts {
+            return None;
+        }
+        let delay = jittered(
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(retries))
+                .min(self.max_backoff),
+            self.jitter,
+        );
+        log::warn!("Retrying JWKS fetch in {delay:?} after failure: {reason}");
+        Some(delay)
+    }
+}
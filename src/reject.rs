@@ -0,0 +1,94 @@
+use axum::{
+    extract::Request,
+    response::{Html, IntoResponse, Response},
+};
+use http::{header, StatusCode};
+use std::sync::Arc;
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::redirect::{accepts_html, accepts_json, LoginRedirect};
+use crate::render::{ErrorPage, Renderer};
+
+/// What the middleware does in [`AuthMode::Strict`](crate::AuthMode::Strict) when a token
+/// cannot be validated because the issuer's signing keys cannot be fetched, set with
+/// [`OidcAuthLayer::with_keys_unavailable`](crate::OidcAuthLayer::with_keys_unavailable).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeysUnavailable {
+    /// Rejects the request with `503 Service Unavailable`, so clients retry it later.
+    #[default]
+    ServiceUnavailable,
+    /// Rejects the request with `401 Unauthorized`, as if the token were invalid, for
+    /// clients that only handle authentication failures.
+    Unauthorized,
+    /// **Fails open**: passes the request to the inner service unauthenticated, as
+    /// [`AuthMode::Optional`](crate::AuthMode::Optional) does, with the
+    /// [`AuthError::JwksUnavailable`] in its extensions. Only for routes that serve
+    /// unauthenticated requests safely, preferring availability over enforcement.
+    PassThrough,
+}
+
+/// How the middleware turns an [`AuthError`] into a response.
+#[derive(Clone, Default)]
+pub(crate) struct Rejections {
+    pub(crate) error_format: ErrorFormat,
+    pub(crate) login_redirect: Option<Arc<LoginRedirect>>,
+    pub(crate) renderer: Option<Arc<dyn Renderer>>,
+    pub(crate) keys_unavailable: KeysUnavailable,
+}
+
+impl Rejections {
+    /// Returns whether a request failing with `error` is passed through unauthenticated in
+    /// strict mode.
+    pub(crate) fn passes_through(&self, error: &AuthError) -> bool {
+        self.keys_unavailable == KeysUnavailable::PassThrough
+            && matches!(error, AuthError::JwksUnavailable(_))
+    }
+
+    /// Returns the error a request failing with `error` is rejected with in strict mode.
+    pub(crate) fn rejection(&self, error: AuthError) -> AuthError {
+        match error {
+            AuthError::JwksUnavailable(reason)
+                if self.keys_unavailable == KeysUnavailable::Unauthorized =>
+            {
+                // The reason names internal URLs and transport errors: log it, don't send it.
+                log::warn!("Rejecting token as unauthorized, signing keys unavailable: {reason}");
+                AuthError::InvalidToken("signing keys unavailable".into())
+            }
+            error => error,
+        }
+    }
+
+    pub(crate) fn respond(&self, error: &AuthError, req: &Request) -> Response {
+        let path = req.uri().path();
+
+        if accepts_html(req.headers()) {
+            // Only a missing or invalid token is fixed by logging in again; redirecting on
+            // anything else would send the browser round the login page in a loop.
+            let unauthorized = error.status() == StatusCode::UNAUTHORIZED;
+            if let Some(login_redirect) = self.login_redirect.as_ref().filter(|_| unauthorized) {
+                return login_redirect.response(req.uri());
+            }
+            if let Some(renderer) = &self.renderer {
+                let status = error.status();
+                let page = ErrorPage {
+                    status,
+                    title: status.canonical_reason().unwrap_or("Error"),
+                    detail: error.to_string(),
+                    instance: path,
+                };
+                let mut response = (status, Html(renderer.render_error(&page))).into_response();
+                if let Some(challenge) = error.www_authenticate() {
+                    response
+                        .headers_mut()
+                        .insert(header::WWW_AUTHENTICATE, challenge);
+                }
+                return response;
+            }
+        }
+
+        if self.login_redirect.is_some() && accepts_json(req.headers()) {
+            return error.to_response(ErrorFormat::ProblemJson, Some(path));
+        }
+        error.to_response(self.error_format, Some(path))
+    }
+}
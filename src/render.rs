@@ -0,0 +1,66 @@
+use http::StatusCode;
+
+/// The data available to a [`Renderer`] when the middleware rejects a browser request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorPage<'a> {
+    /// The response status.
+    pub status: StatusCode,
+    /// A short summary, the canonical reason phrase of `status`.
+    pub title: &'a str,
+    /// A human-readable explanation of the failure.
+    pub detail: String,
+    /// The path of the rejected request.
+    pub instance: &'a str,
+}
+
+/// Renders the HTML pages the middleware serves to browsers, so applications can brand them.
+///
+/// Any `Fn(&ErrorPage<'_>) -> String + Send + Sync + 'static` closure implements this trait.
+/// The returned string is served as `text/html`; implementations must escape the page data
+/// they interpolate, e.g. with [`escape_html`], as the path comes from the request.
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::{escape_html, ErrorPage};
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let auth_layer = auth_layer.with_renderer(|page: &ErrorPage<'_>| {
+///     format!(
+///         "<h1>{} {}</h1><p>{}</p><p>Requested: {}</p>",
+///         page.status.as_u16(),
+///         escape_html(page.title),
+///         escape_html(&page.detail),
+///         escape_html(page.instance),
+///     )
+/// });
+/// # }
+/// ```
+pub trait Renderer: Send + Sync + 'static {
+    /// Renders the page for a rejected request.
+    fn render_error(&self, page: &ErrorPage<'_>) -> String;
+}
+
+impl<F> Renderer for F
+where
+    F: Fn(&ErrorPage<'_>) -> String + Send + Sync + 'static,
+{
+    fn render_error(&self, page: &ErrorPage<'_>) -> String {
+        self(page)
+    }
+}
+
+/// Escapes `text` for interpolation into HTML, as element content or a quoted attribute
+/// value.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
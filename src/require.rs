@@ -0,0 +1,175 @@
+use axum::{extract::Request, response::Response};
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::extract::authenticated_payload;
+
+/// An asynchronous authorization check on the validated claims of a request.
+///
+/// Any `Fn(T) -> impl Future<Output = bool>` closure implements this trait, receiving its
+/// own copy of the claims so the future can outlive the call. Implement the trait directly
+/// to borrow the claims instead.
+pub trait ClaimsPredicate<T>: Send + Sync + 'static {
+    /// Returns whether the request may proceed.
+    fn check<'a>(&'a self, claims: &'a T) -> BoxFuture<'a, bool>;
+}
+
+impl<T, F, Fut> ClaimsPredicate<T> for F
+where
+    T: Clone + Send + Sync + 'static,
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = bool> + Send + 'static,
+{
+    fn check<'a>(&'a self, claims: &'a T) -> BoxFuture<'a, bool> {
+        Box::pin(self(claims.clone()))
+    }
+}
+
+/// A Tower layer that runs a [`ClaimsPredicate`] on the claims of each request and rejects
+/// it with `403 Forbidden` when the predicate returns `false`.
+///
+/// This is the escape hatch for per-route authorization that needs I/O, such as a database
+/// lookup or a feature flag. It must be applied inside an
+/// [`OidcAuthLayer`](crate::OidcAuthLayer); `T` may be the layer's claims type or any
+/// narrower view, as with the [`Claims`](crate::Claims) extractor. Unauthenticated requests
+/// are rejected with `401 Unauthorized`.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::RequireLayer;
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct Subject {
+///     sub: String,
+/// }
+///
+/// async fn is_billing_admin(sub: &str) -> bool {
+///     // Look the subject up in a database...
+/// #   sub == "alice"
+/// }
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/billing", get(|| async { "invoices" }))
+///     .layer(RequireLayer::new(|subject: Subject| async move {
+///         is_billing_admin(&subject.sub).await
+///     }))
+///     .layer(auth_layer);
+/// # }
+/// ```
+pub struct RequireLayer<T> {
+    predicate: Arc<dyn ClaimsPredicate<T>>,
+    error_format: ErrorFormat,
+}
+
+impl<T> RequireLayer<T> {
+    /// Allows only requests for which `predicate` returns `true`.
+    pub fn new(predicate: impl ClaimsPredicate<T>) -> Self {
+        Self {
+            predicate: Arc::new(predicate),
+            error_format: ErrorFormat::default(),
+        }
+    }
+
+    /// Sets the body format of rejections. Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+}
+
+impl<T> Clone for RequireLayer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            predicate: self.predicate.clone(),
+            error_format: self.error_format,
+        }
+    }
+}
+
+impl<S, T> Layer<S> for RequireLayer<T> {
+    type Service = Require<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Require {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`RequireLayer`].
+pub struct Require<S, T> {
+    inner: S,
+    layer: RequireLayer<T>,
+}
+
+impl<S: Clone, T> Clone for Require<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, T> Service<Request> for Require<S, T>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let allowed = match claims::<T>(&mut req) {
+                Ok(claims) => layer.predicate.check(&claims).await,
+                Err(error) => {
+                    return Ok(error.to_response(layer.error_format, Some(req.uri().path())));
+                }
+            };
+            if !allowed {
+                log::warn!("Rejecting request denied by the route's authorization predicate");
+                let error = AuthError::AccessDenied(None);
+                return Ok(error.to_response(layer.error_format, Some(req.uri().path())));
+            }
+
+            inner.call(req).await
+        })
+    }
+}
+
+/// Returns the claims of an authenticated request as `T`, decoding and caching them if `T`
+/// is not the layer's claims type.
+fn claims<T>(req: &mut Request) -> Result<T, AuthError>
+where
+    T: DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    if let Some(claims) = req.extensions().get::<T>() {
+        return Ok(claims.clone());
+    }
+    let claims: T = authenticated_payload(req)?
+        .decode()
+        .map_err(AuthError::ClaimsDeserialization)?;
+    req.extensions_mut().insert(claims.clone());
+    Ok(claims)
+}
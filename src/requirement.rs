@@ -0,0 +1,171 @@
+use axum::{
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use crate::claim::RequireClaimLayer;
+use crate::error::AuthError;
+use crate::extract::{authenticated_payload, ClaimsRejection};
+use crate::reject::Rejections;
+use crate::roles::RequireRolesLayer;
+use crate::scope::RequireScopesLayer;
+
+#[derive(Debug, Clone)]
+enum Requirement {
+    Authenticated,
+    Scopes(RequireScopesLayer),
+    Roles(RequireRolesLayer),
+    Claim(RequireClaimLayer),
+}
+
+/// An authorization requirement declared on a route, enforced the way the enclosing
+/// [`OidcAuthLayer`](crate::OidcAuthLayer) rejects requests.
+///
+/// Apply it next to the routes it protects — with `.layer` on a method router, or
+/// `route_layer` on a router of related routes — and the `OidcAuthLayer` once at the top.
+/// Unlike the standalone `Require*` layers, rejections follow the top-level layer's
+/// configuration (its [`ErrorFormat`](crate::ErrorFormat), login redirect and error page
+/// renderer), so that policy is set in one place. Several requirements on one route must
+/// all be met. Routes without an `OidcAuthLayer` fail with `500 Internal Server Error`,
+/// like the [`Claims`](crate::Claims) extractor.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::{AuthRequirement, ErrorFormat};
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let admin = Router::new()
+///     .route("/users", get(|| async { "users" }))
+///     .route("/audit", get(|| async { "audit" }))
+///     .route_layer(AuthRequirement::realm_roles(["admin"]));
+///
+/// let app: Router = Router::new()
+///     .route(
+///         "/profile",
+///         get(|| async { "profile" }).layer(AuthRequirement::authenticated()),
+///     )
+///     .route(
+///         "/orders",
+///         get(|| async { "orders" }).layer(AuthRequirement::scopes(["read:orders"])),
+///     )
+///     .nest("/admin", admin)
+///     .layer(auth_layer.with_error_format(ErrorFormat::ProblemJson));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuthRequirement {
+    requirement: Requirement,
+}
+
+impl AuthRequirement {
+    /// Requires the request to be authenticated, even under
+    /// [`AuthMode::Optional`](crate::AuthMode::Optional).
+    pub fn authenticated() -> Self {
+        Self {
+            requirement: Requirement::Authenticated,
+        }
+    }
+
+    /// Requires every scope in `scopes`, read like [`RequireScopesLayer`] does.
+    pub fn scopes<I, S>(scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            requirement: Requirement::Scopes(RequireScopesLayer::new(scopes)),
+        }
+    }
+
+    /// Requires every Keycloak realm role in `roles`.
+    pub fn realm_roles<I, S>(roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            requirement: Requirement::Roles(RequireRolesLayer::realm(roles)),
+        }
+    }
+
+    /// Requires every Keycloak role in `roles` for `client`.
+    pub fn client_roles<I, S>(client: impl Into<String>, roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            requirement: Requirement::Roles(RequireRolesLayer::client(client, roles)),
+        }
+    }
+
+    /// Requires the claim at `path` to equal `expected`, as with [`RequireClaimLayer`].
+    pub fn claim(path: impl Into<String>, expected: impl Into<Value>) -> Self {
+        Self {
+            requirement: Requirement::Claim(RequireClaimLayer::new(path, expected)),
+        }
+    }
+
+    /// Fails if the request does not meet the requirement.
+    fn check(&self, req: &Request) -> Result<(), AuthError> {
+        match &self.requirement {
+            Requirement::Authenticated => authenticated_payload(req).map(|_| ()),
+            Requirement::Scopes(layer) => layer.check(req),
+            Requirement::Roles(layer) => layer.check(req),
+            Requirement::Claim(layer) => layer.check(req),
+        }
+    }
+}
+
+impl<S> Layer<S> for AuthRequirement {
+    type Service = EnforceRequirement<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EnforceRequirement {
+            inner,
+            requirement: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`AuthRequirement`].
+#[derive(Debug, Clone)]
+pub struct EnforceRequirement<S> {
+    inner: S,
+    requirement: AuthRequirement,
+}
+
+impl<S> Service<Request> for EnforceRequirement<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Err(error) = self.requirement.check(&req) {
+            let response = match req.extensions().get::<Rejections>() {
+                Some(rejections) => rejections.respond(&error, &req),
+                None => {
+                    log::error!("AuthRequirement applied to a route without OidcAuthLayer");
+                    ClaimsRejection::LayerMissing.into_response()
+                }
+            };
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
@@ -0,0 +1,191 @@
+use axum::{extract::Request, response::Response};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::extract::authenticated_payload;
+
+/// The roles Keycloak grants in a token, as a claims view for the
+/// [`Claims`](crate::Claims) extractor.
+///
+/// Realm roles are read from `realm_access.roles` and client roles from
+/// `resource_access.<client>.roles`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct KeycloakRoles {
+    #[serde(default)]
+    realm_access: RoleList,
+    #[serde(default)]
+    resource_access: HashMap<String, RoleList>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct RoleList {
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+impl KeycloakRoles {
+    /// Returns the realm roles.
+    pub fn realm_roles(&self) -> &[String] {
+        &self.realm_access.roles
+    }
+
+    /// Returns the roles granted for `client`.
+    pub fn client_roles(&self, client: &str) -> &[String] {
+        self.resource_access
+            .get(client)
+            .map_or(&[], |access| &access.roles)
+    }
+
+    /// Fails unless every role in `required` is granted, for `client` or else in the realm.
+    pub(crate) fn require<S: AsRef<str>>(
+        &self,
+        client: Option<&str>,
+        required: &[S],
+    ) -> Result<(), AuthError> {
+        let granted = match client {
+            None => self.realm_roles(),
+            Some(client) => self.client_roles(client),
+        };
+        if required
+            .iter()
+            .all(|role| granted.iter().any(|granted| granted == role.as_ref()))
+        {
+            Ok(())
+        } else {
+            let required: Vec<String> = required.iter().map(|r| r.as_ref().to_string()).collect();
+            log::warn!("Rejecting token without required roles {required:?}");
+            Err(AuthError::MissingRoles(required))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RoleSource {
+    Realm,
+    Client(String),
+}
+
+/// A Tower layer that rejects requests whose token lacks any of the required Keycloak roles.
+///
+/// Like [`RequireScopesLayer`](crate::RequireScopesLayer), it must be applied inside an
+/// [`OidcAuthLayer`](crate::OidcAuthLayer). Requests missing a role are rejected with
+/// `403 Forbidden`; unauthenticated requests with `401 Unauthorized`.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::RequireRolesLayer;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route(
+///         "/admin",
+///         get(|| async { "admin" }).layer(RequireRolesLayer::realm(["admin"])),
+///     )
+///     .route(
+///         "/deploy",
+///         get(|| async { "deploy" }).layer(RequireRolesLayer::client("ops-console", ["deployer"])),
+///     )
+///     .layer(auth_layer);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireRolesLayer {
+    roles: Arc<[String]>,
+    source: RoleSource,
+    error_format: ErrorFormat,
+}
+
+impl RequireRolesLayer {
+    /// Requires every realm role in `roles` (`realm_access.roles`).
+    pub fn realm<I, S>(roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            roles: roles.into_iter().map(Into::into).collect(),
+            source: RoleSource::Realm,
+            error_format: ErrorFormat::default(),
+        }
+    }
+
+    /// Requires every role in `roles` for `client` (`resource_access.<client>.roles`).
+    pub fn client<I, S>(client: impl Into<String>, roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            source: RoleSource::Client(client.into()),
+            ..Self::realm(roles)
+        }
+    }
+
+    /// Sets the body format of rejections. Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Fails if the request is unauthenticated or its token lacks a required role.
+    pub(crate) fn check(&self, req: &Request) -> Result<(), AuthError> {
+        let granted = authenticated_payload(req)?
+            .decode::<KeycloakRoles>()
+            .unwrap_or_default();
+        let client = match &self.source {
+            RoleSource::Realm => None,
+            RoleSource::Client(client) => Some(client.as_str()),
+        };
+        granted.require(client, &self.roles)
+    }
+}
+
+impl<S> Layer<S> for RequireRolesLayer {
+    type Service = RequireRoles<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireRoles {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`RequireRolesLayer`].
+#[derive(Debug, Clone)]
+pub struct RequireRoles<S> {
+    inner: S,
+    layer: RequireRolesLayer,
+}
+
+impl<S> Service<Request> for RequireRoles<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Err(error) = self.layer.check(&req) {
+            let response = error.to_response(self.layer.error_format, Some(req.uri().path()));
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
@@ -0,0 +1,75 @@
+/// How many requests emit authentication telemetry, for gateways where recording every
+/// request costs too much.
+///
+/// Set with [`OidcAuthLayer::with_sampling`](crate::OidcAuthLayer::with_sampling), one
+/// decision per request applies to the authentication result log, the
+/// [`PostResponseHook`](crate::PostResponseHook) and the [`ClaimsExporter`](crate::ClaimsExporter),
+/// so a sampled request is recorded by all of them and other requests by none. Usage
+/// metering is billing data and is never sampled.
+///
+/// ```rust
+/// use axum_jwt_oidc::Sampling;
+///
+/// // Record 1% of successful requests, and every failure.
+/// let sampling = Sampling::new(0.01);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    rate: f64,
+    always_sample_failures: bool,
+}
+
+impl Sampling {
+    /// Samples `rate` of the requests, from `0.0` (none) to `1.0` (all). Requests that fail
+    /// authentication or authorization are always sampled.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate: if rate.is_nan() {
+                0.0
+            } else {
+                rate.clamp(0.0, 1.0)
+            },
+            always_sample_failures: true,
+        }
+    }
+
+    /// Sets whether failed requests are always sampled, or at the same rate as others.
+    /// Defaults to `true`.
+    pub fn always_sample_failures(mut self, always: bool) -> Self {
+        self.always_sample_failures = always;
+        self
+    }
+
+    /// Returns the sampling rate.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Decides whether a request is sampled.
+    pub(crate) fn decide(sampling: Option<&Self>) -> Sample {
+        match sampling {
+            None => Sample {
+                drawn: true,
+                failures: true,
+            },
+            Some(sampling) => Sample {
+                drawn: fastrand::f64() < sampling.rate,
+                failures: sampling.always_sample_failures,
+            },
+        }
+    }
+}
+
+/// The sampling decision for one request.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sample {
+    drawn: bool,
+    failures: bool,
+}
+
+impl Sample {
+    /// Returns whether the request's telemetry is recorded, given whether it failed.
+    pub(crate) fn includes(self, failed: bool) -> bool {
+        self.drawn || (failed && self.failures)
+    }
+}
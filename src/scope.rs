@@ -0,0 +1,221 @@
+use axum::{extract::Request, response::Response};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::error::{AuthError, ErrorFormat};
+use crate::extract::authenticated_payload;
+
+/// A Tower layer that rejects requests whose token lacks any of the required scopes.
+///
+/// It must be applied inside an [`OidcAuthLayer`](crate::OidcAuthLayer), so that it runs
+/// after authentication. Scopes are read from the space-delimited `scope` claim, or from
+/// `scp` (a string or an array, as issued by Azure AD and Okta). Requests missing a scope
+/// are rejected with `403 Forbidden` and an `insufficient_scope` challenge; unauthenticated
+/// requests are rejected with `401 Unauthorized`.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::RequireScopesLayer;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let app: Router = Router::new()
+///     .route("/orders", get(|| async { "orders" }))
+///     .layer(RequireScopesLayer::new(["read:orders"]))
+///     .layer(auth_layer);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireScopesLayer {
+    scopes: Arc<[String]>,
+    error_format: ErrorFormat,
+}
+
+impl RequireScopesLayer {
+    /// Requires every scope in `scopes`.
+    pub fn new<I, S>(scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            error_format: ErrorFormat::default(),
+        }
+    }
+
+    /// Sets the body format of rejections. Defaults to [`ErrorFormat::PlainText`].
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Fails if the request is unauthenticated or its token lacks a required scope.
+    pub(crate) fn check(&self, req: &Request) -> Result<(), AuthError> {
+        let granted = authenticated_payload(req)?
+            .decode::<GrantedScopes>()
+            .unwrap_or_default();
+        granted.require(&self.scopes)
+    }
+}
+
+impl<S> Layer<S> for RequireScopesLayer {
+    type Service = RequireScopes<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireScopes {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The middleware service created by [`RequireScopesLayer`].
+#[derive(Debug, Clone)]
+pub struct RequireScopes<S> {
+    inner: S,
+    layer: RequireScopesLayer,
+}
+
+impl<S> Service<Request> for RequireScopes<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static + Clone,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Err(error) = self.layer.check(&req) {
+            let response = error.to_response(self.layer.error_format, Some(req.uri().path()));
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let not_ready_inner = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, not_ready_inner);
+        Box::pin(async move { inner.call(req).await })
+    }
+}
+
+/// The scopes granted by a token, read from its space-delimited `scope` claim and from `scp`
+/// (a string or an array).
+///
+/// Use it as a claims view with the [`Claims`](crate::Claims) extractor, or flatten it into
+/// your own claims type with `#[serde(flatten)]`.
+///
+/// ```rust
+/// use axum_jwt_oidc::GrantedScopes;
+///
+/// let scopes = GrantedScopes::parse("openid read:orders write:orders");
+/// assert!(scopes.has_scope("read:orders"));
+/// assert!(scopes.has_any(["admin", "write:orders"]));
+/// assert!(!scopes.has_all(["read:orders", "admin"]));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "ScopeClaims")]
+pub struct GrantedScopes(HashSet<String>);
+
+impl GrantedScopes {
+    /// Parses a space-delimited scope string, as carried by the `scope` claim.
+    pub fn parse(scope: &str) -> Self {
+        Self(scope.split_whitespace().map(str::to_string).collect())
+    }
+
+    /// Returns whether `scope` is granted.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.0.contains(scope)
+    }
+
+    /// Returns whether at least one of `scopes` is granted.
+    pub fn has_any<I, S>(&self, scopes: I) -> bool
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        scopes
+            .into_iter()
+            .any(|scope| self.has_scope(scope.as_ref()))
+    }
+
+    /// Returns whether every one of `scopes` is granted. True if `scopes` is empty.
+    pub fn has_all<I, S>(&self, scopes: I) -> bool
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        scopes
+            .into_iter()
+            .all(|scope| self.has_scope(scope.as_ref()))
+    }
+
+    /// Returns the granted scopes.
+    pub fn as_set(&self) -> &HashSet<String> {
+        &self.0
+    }
+
+    /// Fails unless every scope in `required` is granted.
+    pub(crate) fn require<S: AsRef<str>>(&self, required: &[S]) -> Result<(), AuthError> {
+        if self.has_all(required) {
+            Ok(())
+        } else {
+            let required: Vec<String> = required.iter().map(|s| s.as_ref().to_string()).collect();
+            log::warn!("Rejecting token without required scopes {required:?}");
+            Err(AuthError::InsufficientScope(required))
+        }
+    }
+}
+
+impl From<GrantedScopes> for HashSet<String> {
+    fn from(scopes: GrantedScopes) -> Self {
+        scopes.0
+    }
+}
+
+impl From<ScopeClaims> for GrantedScopes {
+    fn from(claims: ScopeClaims) -> Self {
+        Self(claims.into_scopes())
+    }
+}
+
+/// The scope claims of a token: `scope` per RFC 8693, or `scp` as issued by some providers.
+#[derive(Debug, Default, Deserialize)]
+struct ScopeClaims {
+    scope: Option<String>,
+    scp: Option<ScopeList>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScopeList {
+    Delimited(String),
+    List(Vec<String>),
+}
+
+impl ScopeClaims {
+    fn into_scopes(self) -> HashSet<String> {
+        let mut scopes = self
+            .scope
+            .as_deref()
+            .map(GrantedScopes::parse)
+            .unwrap_or_default()
+            .0;
+        match self.scp {
+            Some(ScopeList::Delimited(scp)) => {
+                scopes.extend(scp.split_whitespace().map(str::to_string))
+            }
+            Some(ScopeList::List(scp)) => scopes.extend(scp),
+            None => {}
+        }
+        scopes
+    }
+}
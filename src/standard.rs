@@ -0,0 +1,77 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::scope::GrantedScopes;
+
+/// The registered JWT claims plus common OIDC claims, for services that need no custom
+/// claims type.
+///
+/// Every claim is optional, as providers differ in which ones they issue; `aud` is read as
+/// either a single string or an array.
+///
+/// ```rust,no_run
+/// use axum::{routing::get, Router};
+/// use axum_jwt_oidc::{Claims, OidcAuthLayer, StandardClaims};
+///
+/// async fn whoami(Claims(claims): Claims<StandardClaims>) -> String {
+///     claims.preferred_username.or(claims.sub).unwrap_or_default()
+/// }
+///
+/// # fn layer(auth_layer: OidcAuthLayer<StandardClaims>) {
+/// let app: Router = Router::new()
+///     .route("/whoami", get(whoami))
+///     .layer(auth_layer);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StandardClaims {
+    /// The issuer (`iss`).
+    pub iss: Option<String>,
+    /// The subject (`sub`).
+    pub sub: Option<String>,
+    /// The audiences (`aud`).
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub aud: Vec<String>,
+    /// The expiration time (`exp`), in seconds since the Unix epoch.
+    pub exp: Option<u64>,
+    /// The time the token was issued at (`iat`), in seconds since the Unix epoch.
+    pub iat: Option<u64>,
+    /// The time before which the token must not be accepted (`nbf`), in seconds since the
+    /// Unix epoch.
+    pub nbf: Option<u64>,
+    /// The token identifier (`jti`).
+    pub jti: Option<String>,
+    /// The end-user's email address (`email`).
+    pub email: Option<String>,
+    /// The end-user's full name (`name`).
+    pub name: Option<String>,
+    /// The end-user's preferred username (`preferred_username`).
+    pub preferred_username: Option<String>,
+    /// The space-delimited scopes (`scope`).
+    pub scope: Option<String>,
+}
+
+impl StandardClaims {
+    /// Returns the scopes of the `scope` claim.
+    pub fn scopes(&self) -> GrantedScopes {
+        self.scope
+            .as_deref()
+            .map(GrantedScopes::parse)
+            .unwrap_or_default()
+    }
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(aud)) => vec![aud],
+        Some(OneOrMany::Many(aud)) => aud,
+        None => Vec::new(),
+    })
+}
@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::sync::{PoisonError, RwLock};
+
+use crate::error::AuthError;
+use crate::extract::ValidatedPayload;
+
+/// Which list of a [`SubjectOverrides`] a subject matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubjectList {
+    /// The allowlist.
+    Allow,
+    /// The denylist.
+    Deny,
+}
+
+/// A subject that matched a pattern of a [`SubjectOverrides`], passed to its
+/// [`on_match`](SubjectOverrides::on_match) hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SubjectMatch {
+    /// The `sub` claim of the token.
+    pub subject: String,
+    /// The pattern it matched.
+    pub pattern: String,
+    /// The list the pattern belongs to.
+    pub list: SubjectList,
+}
+
+type MatchHook = Box<dyn Fn(&SubjectMatch) + Send + Sync>;
+
+#[derive(Default)]
+struct Lists {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+/// Allowlists and denylists of token subjects, evaluated after each successful validation.
+///
+/// Patterns are exact `sub` values, or contain `*` wildcards matching any run of characters,
+/// e.g. `svc-billing-*`. Requests whose subject matches the denylist are rejected with
+/// `403 Forbidden`. When the allowlist is not empty, so are requests whose subject matches
+/// none of its patterns, including tokens without a `sub` claim. The denylist wins over the
+/// allowlist.
+///
+/// The lists can be changed while the service runs. Pass an `Arc<SubjectOverrides>` to
+/// [`OidcAuthLayer::with_subject_overrides`](crate::OidcAuthLayer::with_subject_overrides)
+/// and keep a clone as a handle, e.g. to block a compromised service account at once:
+///
+/// ```rust,no_run
+/// use axum_jwt_oidc::SubjectOverrides;
+/// use std::sync::Arc;
+///
+/// # fn layer(auth_layer: axum_jwt_oidc::OidcAuthLayer<serde_json::Value>) {
+/// let overrides = Arc::new(
+///     SubjectOverrides::new().on_match(|event| log::info!("Subject override: {event:?}")),
+/// );
+/// let auth_layer = auth_layer.with_subject_overrides(overrides.clone());
+///
+/// // Later, from an admin endpoint or a configuration watcher:
+/// overrides.deny("svc-reporting");
+/// # }
+/// ```
+#[derive(Default)]
+pub struct SubjectOverrides {
+    lists: RwLock<Lists>,
+    on_match: Option<MatchHook>,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    sub: Option<String>,
+}
+
+impl SubjectOverrides {
+    /// Creates empty lists, which let every subject through.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `on_match` with every subject matching a pattern, e.g. to record audit events.
+    /// Each match is also logged. The lists are not locked while `on_match` runs, so it may
+    /// change them.
+    pub fn on_match(mut self, on_match: impl Fn(&SubjectMatch) + Send + Sync + 'static) -> Self {
+        self.on_match = Some(Box::new(on_match));
+        self
+    }
+
+    /// Adds `pattern` to the allowlist.
+    pub fn allow(&self, pattern: impl Into<String>) {
+        self.write().allow.push(pattern.into());
+    }
+
+    /// Adds `pattern` to the denylist.
+    pub fn deny(&self, pattern: impl Into<String>) {
+        self.write().deny.push(pattern.into());
+    }
+
+    /// Removes `pattern` from both lists. Returns whether it was in either.
+    pub fn remove(&self, pattern: &str) -> bool {
+        let mut lists = self.write();
+        let before = lists.allow.len() + lists.deny.len();
+        lists.allow.retain(|p| p != pattern);
+        lists.deny.retain(|p| p != pattern);
+        lists.allow.len() + lists.deny.len() != before
+    }
+
+    /// Returns the patterns of `list`, in the order they were added.
+    pub fn patterns(&self, list: SubjectList) -> Vec<String> {
+        let lists = self.lists.read().unwrap_or_else(PoisonError::into_inner);
+        match list {
+            SubjectList::Allow => lists.allow.clone(),
+            SubjectList::Deny => lists.deny.clone(),
+        }
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Lists> {
+        self.lists.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks the subject of an authenticated request.
+    pub(crate) fn check(&self, payload: Option<&ValidatedPayload>) -> Result<(), AuthError> {
+        let subject = payload
+            .and_then(|payload| payload.decode::<Subject>().ok())
+            .and_then(|subject| subject.sub);
+        // Release the lists before reporting, so the hook may change them.
+        let (denied, allowed, allowlisting) = {
+            let lists = self.lists.read().unwrap_or_else(PoisonError::into_inner);
+            let matching = |patterns: &[String]| {
+                let subject = subject.as_deref()?;
+                patterns
+                    .iter()
+                    .find(|pattern| matches(pattern, subject))
+                    .cloned()
+            };
+            (
+                matching(&lists.deny),
+                matching(&lists.allow),
+                !lists.allow.is_empty(),
+            )
+        };
+        let Some(subject) = subject else {
+            if !allowlisting {
+                return Ok(());
+            }
+            log::warn!("Subject allowlist rejected a token without a subject");
+            return Err(AuthError::AccessDenied(Some("subject not allowed".into())));
+        };
+
+        if let Some(pattern) = denied {
+            log::warn!("Subject {subject} matched denylist pattern {pattern}");
+            self.report(subject, pattern, SubjectList::Deny);
+            return Err(AuthError::AccessDenied(Some("subject blocked".into())));
+        }
+        if !allowlisting {
+            return Ok(());
+        }
+        match allowed {
+            Some(pattern) => {
+                log::info!("Subject {subject} matched allowlist pattern {pattern}");
+                self.report(subject, pattern, SubjectList::Allow);
+                Ok(())
+            }
+            None => {
+                log::warn!("Subject {subject} is not on the allowlist");
+                Err(AuthError::AccessDenied(Some("subject not allowed".into())))
+            }
+        }
+    }
+
+    fn report(&self, subject: String, pattern: String, list: SubjectList) {
+        if let Some(on_match) = &self.on_match {
+            on_match(&SubjectMatch {
+                subject,
+                pattern,
+                list,
+            });
+        }
+    }
+}
+
+/// Matches `subject` against `pattern`, where `*` matches any run of characters.
+fn matches(pattern: &str, subject: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = subject.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard: the pattern must match exactly.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,576 @@
+use async_oidc_jwt_validator::{Algorithm, OidcConfig, OidcValidator, Validation};
+use futures::future::BoxFuture;
+use http::{header, request::Parts, HeaderName};
+use lru::LruCache;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::budget::{self, CacheBudget, CacheKind, ENTRY_OVERHEAD};
+use crate::clock::{self, Clock};
+use crate::coalesce::InFlight;
+use crate::error::AuthError;
+use crate::issuer::{is_plain_http, Issuer};
+
+/// Identifies the tenant a request belongs to.
+///
+/// Inserted into the request extensions when a [`TenantResolver`] is configured.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(pub Arc<str>);
+
+impl TenantId {
+    /// Creates a tenant identifier.
+    pub fn new(id: impl AsRef<str>) -> Self {
+        Self(Arc::from(id.as_ref()))
+    }
+
+    /// Returns the identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for TenantId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for TenantId {
+    fn from(id: String) -> Self {
+        Self(Arc::from(id))
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Determines the tenant of a request before its token is validated, so each tenant can
+/// have its own issuer (e.g. one Keycloak realm per tenant).
+///
+/// Built-in implementations resolve the tenant from the [host](HostTenantResolver), a
+/// [path prefix](PathPrefixTenantResolver) or a [header](HeaderTenantResolver). Any
+/// `Fn(&Parts) -> Option<TenantId> + Send + Sync + 'static` closure implements this trait.
+pub trait TenantResolver: Send + Sync + 'static {
+    /// Returns the tenant of the request, or `None` if it cannot be determined.
+    fn resolve(&self, parts: &Parts) -> Option<TenantId>;
+}
+
+impl<F> TenantResolver for F
+where
+    F: Fn(&Parts) -> Option<TenantId> + Send + Sync + 'static,
+{
+    fn resolve(&self, parts: &Parts) -> Option<TenantId> {
+        self(parts)
+    }
+}
+
+/// Uses the first label of the `Host` header, so `acme.api.example.com` resolves to `acme`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostTenantResolver;
+
+impl TenantResolver for HostTenantResolver {
+    fn resolve(&self, parts: &Parts) -> Option<TenantId> {
+        let host = parts
+            .headers
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| parts.uri.host())?;
+        host.split(['.', ':'])
+            .next()
+            .filter(|label| !label.is_empty())
+            .map(TenantId::from)
+    }
+}
+
+/// Uses the first path segment, so `/acme/orders` resolves to `acme`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathPrefixTenantResolver;
+
+impl TenantResolver for PathPrefixTenantResolver {
+    fn resolve(&self, parts: &Parts) -> Option<TenantId> {
+        parts
+            .uri
+            .path()
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .filter(|segment| !segment.is_empty())
+            .map(TenantId::from)
+    }
+}
+
+/// Uses the value of a header, such as `X-Tenant-Id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTenantResolver {
+    name: HeaderName,
+}
+
+impl HeaderTenantResolver {
+    /// Reads the tenant from the header `name`.
+    pub fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl TenantResolver for HeaderTenantResolver {
+    fn resolve(&self, parts: &Parts) -> Option<TenantId> {
+        parts
+            .headers
+            .get(&self.name)
+            .and_then(|h| h.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(TenantId::from)
+    }
+}
+
+/// The identity provider settings of one tenant, as returned by a [`TenantConfigStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantConfig {
+    /// The expected `iss` claim.
+    pub issuer: String,
+    /// The JWKS endpoint of the tenant's identity provider.
+    pub jwks_uri: String,
+    /// The accepted `aud` values. Must not be empty.
+    pub audiences: Vec<String>,
+    /// The accepted signing algorithms. Defaults to `RS256` when empty.
+    pub algorithms: Vec<Algorithm>,
+}
+
+impl TenantConfig {
+    /// Creates a tenant configuration accepting `RS256` tokens for `audience`.
+    pub fn new(
+        issuer: impl Into<String>,
+        jwks_uri: impl Into<String>,
+        audience: impl Into<String>,
+    ) -> Self {
+        Self {
+            issuer: issuer.into(),
+            jwks_uri: jwks_uri.into(),
+            audiences: vec![audience.into()],
+            algorithms: Vec::new(),
+        }
+    }
+
+    fn to_issuer(&self) -> Issuer {
+        let mut validation = Validation::new(Algorithm::RS256);
+        if !self.algorithms.is_empty() {
+            validation.algorithms = self.algorithms.clone();
+        }
+        validation.set_audience(&self.audiences);
+
+        let client_id = self.audiences.first().cloned().unwrap_or_default();
+        let oidc_validator = OidcValidator::new(OidcConfig::new(
+            self.issuer.clone(),
+            client_id,
+            self.jwks_uri.clone(),
+        ));
+        Issuer::new(self.issuer.clone(), oidc_validator, validation)
+    }
+}
+
+/// The error type of [`TenantConfigStore`] lookups.
+pub type TenantStoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Looks up tenant configuration at request time, e.g. from a database or control plane.
+///
+/// Lookups are cached by [`TenantDirectory`], so new tenants become usable without
+/// redeploying while existing tenants keep their JWKS caches.
+pub trait TenantConfigStore: Send + Sync + 'static {
+    /// Returns the configuration of `tenant`, or `None` if the tenant does not exist.
+    fn lookup<'a>(
+        &'a self,
+        tenant: &'a TenantId,
+    ) -> BoxFuture<'a, Result<Option<TenantConfig>, TenantStoreError>>;
+}
+
+type EvictionHook = Box<dyn Fn(&TenantId) + Send + Sync>;
+
+/// The approximate bytes of the signing keys a tenant's validator caches, which cannot be
+/// measured.
+const TENANT_KEYS_BYTES: usize = 4096;
+
+/// A cached tenant.
+struct Entry {
+    /// The configuration `issuer` was built from, so it is kept while that is unchanged.
+    config: TenantConfig,
+    issuer: Issuer,
+    /// When the configuration was looked up.
+    fetched: SystemTime,
+    /// The approximate size of the entry.
+    bytes: usize,
+}
+
+/// Tenants the store did not know, remembered longest ago first, with when it was asked and
+/// the approximate size of the entry.
+type UnknownTenants = LruCache<TenantId, (SystemTime, usize)>;
+
+/// A tenant dropped from the cache, and whether it had expired.
+struct Removed {
+    tenant: TenantId,
+    bytes: usize,
+    expired: bool,
+}
+
+/// A [`TenantConfigStore`] with a bounded cache of the validators built from its results.
+///
+/// Each cached tenant holds its own JWKS cache, so the number of cached tenants is capped
+/// (10 000 by default). A tenant whose configuration is unchanged when its entry expires
+/// keeps its validator and JWKS cache, and concurrent lookups of a tenant share one request
+/// to the store. Unknown tenants are remembered for ten seconds, up to the same cap and
+/// within the same budget, so a flood of requests for them does not reach the store. Tenants whose issuer or JWKS URL
+/// uses plain `http` are rejected with [`AuthError::ConfigUnavailable`], unless the layer
+/// [allows insecure `http`](crate::OidcAuthLayer::allow_insecure_http). A [`CacheBudget`]
+/// set with [`with_budget`](Self::with_budget) bounds the cache together with others.
+pub struct TenantDirectory {
+    store: Arc<dyn TenantConfigStore>,
+    ttl: Duration,
+    unknown_ttl: Duration,
+    max_entries: usize,
+    on_evict: Option<EvictionHook>,
+    cache: RwLock<HashMap<TenantId, Entry>>,
+    unknown: Mutex<UnknownTenants>,
+    lookups: InFlight<Option<TenantConfig>, TenantId>,
+    time_anomalies: AtomicU64,
+    budget: Option<Arc<CacheBudget>>,
+}
+
+impl TenantDirectory {
+    /// Caches lookups from `store` for five minutes.
+    pub fn new(store: impl TenantConfigStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: Duration::from_secs(300),
+            unknown_ttl: Duration::from_secs(10),
+            max_entries: 10_000,
+            on_evict: None,
+            cache: RwLock::new(HashMap::new()),
+            unknown: Mutex::new(LruCache::unbounded()),
+            lookups: InFlight::new(None),
+            time_anomalies: AtomicU64::new(0),
+            budget: None,
+        }
+    }
+
+    /// Sets the maximum number of cached tenants, and of remembered unknown tenants. When the
+    /// cache is full, expired entries are dropped first, then the one cached longest.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Calls `on_evict` with each tenant dropped from the cache to make room for another,
+    /// e.g. to count evictions in a metric.
+    pub fn on_evict(mut self, on_evict: impl Fn(&TenantId) + Send + Sync + 'static) -> Self {
+        self.on_evict = Some(Box::new(on_evict));
+        self
+    }
+
+    /// Counts the cached tenants against `budget`, shared with other caches, each taking about
+    /// 4 KiB for its signing keys besides its configuration. When the budget is exhausted,
+    /// the tenants cached longest are dropped to make room, and reported to
+    /// [`on_evict`](Self::on_evict) too. Remembered unknown tenants count against the budget
+    /// as well, but only make room among themselves.
+    pub fn with_budget(mut self, budget: Arc<CacheBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Returns the number of currently cached tenants.
+    pub fn cached_tenants(&self) -> usize {
+        self.cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns how often the clock was found to have gone backwards since a tenant was cached,
+    /// e.g. after an NTP correction or a VM snapshot restore, for monitoring.
+    ///
+    /// Such entries are looked up again rather than kept until the clock catches up.
+    pub fn time_anomalies(&self) -> u64 {
+        self.time_anomalies.load(Ordering::Relaxed)
+    }
+
+    /// Sets how long a tenant's configuration is cached before it is looked up again.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how long a tenant unknown to the store is rejected without asking it again.
+    /// Defaults to ten seconds; zero asks the store on every request for an unknown tenant.
+    pub fn unknown_tenant_ttl(mut self, ttl: Duration) -> Self {
+        self.unknown_ttl = ttl;
+        self
+    }
+
+    /// Drops the cached configuration of `tenant`, e.g. after it was created, changed or
+    /// deleted.
+    pub fn invalidate(&self, tenant: &TenantId) {
+        let removed = self
+            .cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(tenant);
+        if let Some(entry) = removed {
+            budget::release(self.budget.as_deref(), 1, entry.bytes);
+        }
+        let removed = self
+            .unknown
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop(tenant);
+        if let Some((_, bytes)) = removed {
+            budget::release(self.budget.as_deref(), 1, bytes);
+        }
+    }
+
+    /// Returns the issuer of `tenant`, rejecting configurations using plain `http` unless
+    /// `allow_insecure_http`.
+    pub(crate) async fn get(
+        &self,
+        tenant: &TenantId,
+        clock: Option<&dyn Clock>,
+        allow_insecure_http: bool,
+    ) -> Result<Issuer, AuthError> {
+        let now = clock::now(clock);
+        let cached = self
+            .cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(tenant)
+            .map(|entry| (entry.issuer.clone(), entry.fetched));
+        match cached {
+            Some((_, fetched)) if fetched > now => {
+                self.time_anomalies.fetch_add(1, Ordering::Relaxed);
+                log::warn!("Clock went backwards since tenant {tenant} was cached, refreshing it");
+            }
+            Some((issuer, fetched)) if !is_expired(fetched, now, self.ttl) => return Ok(issuer),
+            _ => {}
+        }
+        if self.is_unknown(tenant, now) {
+            return Err(AuthError::UnknownTenant(Some(tenant.clone())));
+        }
+
+        let store = self.store.clone();
+        let id = tenant.clone();
+        let lookup = async move {
+            log::debug!("Looking up configuration of tenant {id}");
+            store.lookup(&id).await.map_err(|e| {
+                log::error!("Failed to look up configuration of tenant {id}: {e}");
+                AuthError::ConfigUnavailable(e.to_string())
+            })
+        };
+        let Some(config) = self.lookups.run(tenant.clone(), lookup).await? else {
+            self.remember_unknown(tenant, now);
+            return Err(AuthError::UnknownTenant(Some(tenant.clone())));
+        };
+        let insecure = [&config.issuer, &config.jwks_uri]
+            .into_iter()
+            .find(|url| is_plain_http(url));
+        if let Some(url) = insecure.filter(|_| !allow_insecure_http) {
+            log::error!("Configuration of tenant {tenant} uses {url}, which does not use https");
+            return Err(AuthError::ConfigUnavailable(
+                "tenant configuration does not use https".to_string(),
+            ));
+        }
+        Ok(self.insert(tenant, config, now))
+    }
+
+    /// Caches the issuer of `tenant` for `config`, keeping the cached one, and with it its
+    /// signing keys, if it was built from the same configuration.
+    fn insert(&self, tenant: &TenantId, config: TenantConfig, now: SystemTime) -> Issuer {
+        let bytes = ENTRY_OVERHEAD
+            + tenant.as_str().len()
+            + config.issuer.len()
+            + config.jwks_uri.len()
+            + TENANT_KEYS_BYTES;
+        let budget = self.budget.as_deref();
+        let (issuer, removed) = {
+            let mut cache = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+            match cache.get_mut(tenant) {
+                Some(entry) if entry.config == config => {
+                    entry.fetched = now;
+                    return entry.issuer.clone();
+                }
+                Some(_) => {
+                    if let Some(previous) = cache.remove(tenant) {
+                        budget::release(budget, 1, previous.bytes);
+                    }
+                }
+                None => {}
+            }
+            let issuer = config.to_issuer();
+            let mut removed = self.make_room(&mut cache, now);
+            let mut reserved = budget::reserve(budget, bytes);
+            while !reserved {
+                let Some(oldest) = self.remove_oldest(&mut cache) else {
+                    break;
+                };
+                removed.push(oldest);
+                reserved = budget::reserve(budget, bytes);
+            }
+            if reserved {
+                let entry = Entry {
+                    config,
+                    issuer: issuer.clone(),
+                    fetched: now,
+                    bytes,
+                };
+                cache.insert(tenant.clone(), entry);
+            }
+            (issuer, removed)
+        };
+        if !removed.is_empty() {
+            log::debug!("Evicted {} tenants from the directory cache", removed.len());
+        }
+        if let Some(on_evict) = &self.on_evict {
+            for removed in &removed {
+                on_evict(&removed.tenant);
+            }
+        }
+        if let Some(budget) = budget {
+            let sizes: Vec<usize> = removed
+                .iter()
+                .filter(|removed| !removed.expired)
+                .map(|removed| removed.bytes)
+                .collect();
+            if !sizes.is_empty() {
+                budget.evicted(CacheKind::Tenants, &sizes);
+            }
+        }
+        issuer
+    }
+
+    /// Returns whether the store recently did not know `tenant`.
+    fn is_unknown(&self, tenant: &TenantId, now: SystemTime) -> bool {
+        self.unknown
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .peek(tenant)
+            .is_some_and(|&(found, _)| !is_expired(found, now, self.unknown_ttl))
+    }
+
+    /// Remembers that the store did not know `tenant`, dropping the expired and, when a bound
+    /// is reached, the oldest unknown tenants.
+    fn remember_unknown(&self, tenant: &TenantId, now: SystemTime) {
+        if self.unknown_ttl.is_zero() {
+            return;
+        }
+        let bytes = ENTRY_OVERHEAD + tenant.as_str().len();
+        let budget = self.budget.as_deref();
+        let mut unknown = self.unknown.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, previous)) = unknown.pop(tenant) {
+            budget::release(budget, 1, previous);
+        }
+        while unknown
+            .peek_lru()
+            .is_some_and(|(_, (found, _))| is_expired(*found, now, self.unknown_ttl))
+        {
+            self.forget_oldest_unknown(&mut unknown);
+        }
+        let mut evicted = Vec::new();
+        if unknown.len() >= self.max_entries {
+            evicted.extend(self.forget_oldest_unknown(&mut unknown));
+        }
+        let mut reserved = budget::reserve(budget, bytes);
+        while !reserved {
+            let Some(oldest) = self.forget_oldest_unknown(&mut unknown) else {
+                break;
+            };
+            evicted.push(oldest);
+            reserved = budget::reserve(budget, bytes);
+        }
+        if reserved {
+            unknown.push(tenant.clone(), (now, bytes));
+        }
+        drop(unknown);
+
+        if let Some(budget) = budget.filter(|_| !evicted.is_empty()) {
+            budget.evicted(CacheKind::Tenants, &evicted);
+        }
+    }
+
+    /// Drops the unknown tenant remembered longest ago, returning its size.
+    fn forget_oldest_unknown(&self, unknown: &mut UnknownTenants) -> Option<usize> {
+        let (_, (_, bytes)) = unknown.pop_lru()?;
+        budget::release(self.budget.as_deref(), 1, bytes);
+        Some(bytes)
+    }
+
+    /// Removes entries until another can be inserted without exceeding the bound.
+    fn make_room(&self, cache: &mut HashMap<TenantId, Entry>, now: SystemTime) -> Vec<Removed> {
+        if cache.len() < self.max_entries {
+            return Vec::new();
+        }
+
+        let expired: Vec<TenantId> = cache
+            .iter()
+            .filter(|(_, entry)| is_expired(entry.fetched, now, self.ttl))
+            .map(|(tenant, _)| tenant.clone())
+            .collect();
+        let mut removed: Vec<Removed> = expired
+            .into_iter()
+            .filter_map(|tenant| {
+                let entry = cache.remove(&tenant)?;
+                budget::release(self.budget.as_deref(), 1, entry.bytes);
+                Some(Removed {
+                    tenant,
+                    bytes: entry.bytes,
+                    expired: true,
+                })
+            })
+            .collect();
+        if removed.is_empty() {
+            removed.extend(self.remove_oldest(cache));
+        }
+        removed
+    }
+
+    /// Removes the entry cached longest.
+    fn remove_oldest(&self, cache: &mut HashMap<TenantId, Entry>) -> Option<Removed> {
+        let tenant = cache
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched)
+            .map(|(tenant, _)| tenant.clone())?;
+        let entry = cache.remove(&tenant)?;
+        budget::release(self.budget.as_deref(), 1, entry.bytes);
+        Some(Removed {
+            tenant,
+            bytes: entry.bytes,
+            expired: false,
+        })
+    }
+}
+
+/// Returns whether an entry fetched at `fetched` is older than `ttl`. Entries fetched after
+/// `now` are expired too, so a clock going backwards cannot keep them cached until it catches
+/// up.
+fn is_expired(fetched: SystemTime, now: SystemTime, ttl: Duration) -> bool {
+    now.duration_since(fetched).map_or(true, |age| age >= ttl)
+}
+
+impl Drop for TenantDirectory {
+    fn drop(&mut self) {
+        let cache = self.cache.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let bytes = cache.values().map(|entry| entry.bytes).sum();
+        budget::release(self.budget.as_deref(), cache.len(), bytes);
+        let unknown = self
+            .unknown
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let bytes = unknown.iter().map(|(_, (_, bytes))| bytes).sum();
+        budget::release(self.budget.as_deref(), unknown.len(), bytes);
+    }
+}
@@ -0,0 +1,554 @@
+use axum::response::Response;
+use http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode, Uri};
+use std::{fmt, sync::Arc};
+use zeroize::Zeroizing;
+
+use crate::error::AuthError;
+
+/// Where the token of a request was found.
+///
+/// Inserted into the request extensions whenever a token is extracted, so handlers and
+/// metrics can tell which credential source a client used.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TokenSource {
+    /// A request header.
+    Header(HeaderName),
+    /// A cookie with the given name.
+    Cookie(String),
+    /// A query parameter with the given name.
+    Query(String),
+    /// The `Sec-WebSocket-Protocol` header, authenticated under the given subprotocol.
+    WebSocketProtocol(String),
+    /// A user-provided [`TokenExtractor`], identified by its own label.
+    Custom(String),
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::Header(name) => write!(f, "header:{name}"),
+            TokenSource::Cookie(name) => write!(f, "cookie:{name}"),
+            TokenSource::Query(name) => write!(f, "query:{name}"),
+            TokenSource::WebSocketProtocol(protocol) => write!(f, "websocket:{protocol}"),
+            TokenSource::Custom(label) => write!(f, "custom:{label}"),
+        }
+    }
+}
+
+/// A source the middleware can read the raw token from.
+///
+/// Built-in implementations cover the common cases ([`HeaderExtractor`],
+/// [`CookieExtractor`] and [`QueryExtractor`]); implement this trait to support other
+/// conventions. Any `Fn(&Parts) -> Option<String> + Send + Sync + 'static` closure
+/// implements it as well.
+pub trait TokenExtractor: Send + Sync + 'static {
+    /// Returns the raw token carried by the request, if any.
+    fn extract(&self, parts: &Parts) -> Option<String>;
+
+    /// Returns the raw token carried by the request, if any, or the reason the credential
+    /// this extractor reads is malformed or ambiguous.
+    ///
+    /// What the middleware does with a malformed credential is configured with
+    /// [`OidcAuthLayer::with_malformed_credentials`](crate::OidcAuthLayer::with_malformed_credentials).
+    /// Defers to [`extract`](Self::extract) by default, which never reports one.
+    fn try_extract(&self, parts: &Parts) -> Result<Option<String>, String> {
+        Ok(self.extract(parts))
+    }
+
+    /// Describes where this extractor reads the token from. Recorded in the request
+    /// extensions when this extractor supplies the token.
+    fn source(&self) -> TokenSource {
+        TokenSource::Custom("custom".to_string())
+    }
+
+    /// Removes the token from the request before it is passed to the inner service.
+    ///
+    /// Called for every configured extractor, whether or not it matched. Does nothing by
+    /// default.
+    fn scrub(&self, _parts: &mut Parts) {}
+}
+
+impl<F> TokenExtractor for F
+where
+    F: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+{
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        self(parts)
+    }
+}
+
+/// Reads the token from a header, stripping an optional prefix such as `Bearer `.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderExtractor {
+    name: HeaderName,
+    prefix: Option<String>,
+}
+
+impl HeaderExtractor {
+    /// Reads `Authorization: Bearer <token>`.
+    pub fn bearer() -> Self {
+        Self::new(header::AUTHORIZATION, Some("Bearer "))
+    }
+
+    /// Reads `X-Forwarded-Access-Token`, set by oauth2-proxy with `--pass-access-token`.
+    pub fn forwarded_access_token() -> Self {
+        Self::new(HeaderName::from_static("x-forwarded-access-token"), None)
+    }
+
+    /// Reads `X-Auth-Request-Access-Token`, set by oauth2-proxy in auth-request mode with
+    /// `--set-xauthrequest` and `--pass-access-token`.
+    pub fn auth_request_access_token() -> Self {
+        Self::new(HeaderName::from_static("x-auth-request-access-token"), None)
+    }
+
+    /// Reads the token from `name`, stripping `prefix` from the value when present.
+    pub fn new(name: HeaderName, prefix: Option<&str>) -> Self {
+        Self {
+            name,
+            prefix: prefix.map(str::to_string),
+        }
+    }
+}
+
+impl Default for HeaderExtractor {
+    fn default() -> Self {
+        Self::bearer()
+    }
+}
+
+impl TokenExtractor for HeaderExtractor {
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        self.try_extract(parts).ok().flatten()
+    }
+
+    /// Reports a header that is repeated or contains bytes other than visible ASCII.
+    fn try_extract(&self, parts: &Parts) -> Result<Option<String>, String> {
+        let mut values = parts.headers.get_all(&self.name).iter();
+        let Some(value) = values.next() else {
+            return Ok(None);
+        };
+        if values.next().is_some() {
+            return Err(format!("the `{}` header is repeated", self.name));
+        }
+        let value = value
+            .to_str()
+            .map_err(|_| format!("the `{}` header is not visible ASCII", self.name))?;
+        let token = match &self.prefix {
+            Some(prefix) => value.strip_prefix(prefix.as_str()).unwrap_or(value),
+            None => value,
+        };
+        Ok(Some(token.to_string()))
+    }
+
+    fn source(&self) -> TokenSource {
+        TokenSource::Header(self.name.clone())
+    }
+}
+
+/// Reads the token from the typed `Authorization<Bearer>` header of the `headers` crate, as
+/// re-exported by `axum_extra::headers`. Requires the `typed-header` feature.
+///
+/// Unlike [`HeaderExtractor::bearer`], the header is parsed exactly as a
+/// `TypedHeader<Authorization<Bearer>>` extractor would parse it, so the layer and handlers
+/// that use typed headers agree on which requests carry a token.
+#[cfg(feature = "typed-header")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypedBearerExtractor;
+
+#[cfg(feature = "typed-header")]
+impl TokenExtractor for TypedBearerExtractor {
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        use headers::{authorization::Bearer, Authorization, HeaderMapExt};
+
+        let authorization = parts.headers.typed_get::<Authorization<Bearer>>()?;
+        Some(authorization.token().to_string())
+    }
+
+    fn source(&self) -> TokenSource {
+        TokenSource::Header(header::AUTHORIZATION)
+    }
+}
+
+/// Reads the token from a named cookie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookieExtractor {
+    name: String,
+}
+
+impl CookieExtractor {
+    /// Reads the token from the cookie called `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl TokenExtractor for CookieExtractor {
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        self.try_extract(parts).ok().flatten()
+    }
+
+    /// Reports a cookie that is set more than once with different values, as happens when
+    /// cookies of the same name are scoped to different paths or domains. `Cookie` headers
+    /// that are not visible ASCII are skipped, as they cannot be attributed to a cookie.
+    fn try_extract(&self, parts: &Parts) -> Result<Option<String>, String> {
+        let mut values = find_cookies(&parts.headers, &self.name);
+        let Some(value) = values.next() else {
+            return Ok(None);
+        };
+        if values.any(|other| other != value) {
+            return Err(format!(
+                "the `{}` cookie is set more than once with different values",
+                self.name
+            ));
+        }
+        Ok(Some(value.to_string()))
+    }
+
+    fn source(&self) -> TokenSource {
+        TokenSource::Cookie(self.name.clone())
+    }
+}
+
+/// Reads the token from a named query parameter.
+///
+/// Tokens in URLs end up in access logs, browser history and `Referer` headers. Only use this
+/// for clients that cannot set headers (such as `EventSource` or download links), prefer
+/// short-lived tokens, and consider [`strip`](Self::strip).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryExtractor {
+    param: String,
+    strip: bool,
+}
+
+impl QueryExtractor {
+    /// Reads the token from the query parameter `param`.
+    pub fn new(param: impl Into<String>) -> Self {
+        let param = param.into();
+        log::warn!("Accepting bearer tokens from the `{param}` query parameter");
+        Self {
+            param,
+            strip: false,
+        }
+    }
+
+    /// Removes the parameter from the request URI before it reaches the inner service, so
+    /// handlers and downstream logging never see it.
+    pub fn strip(mut self) -> Self {
+        self.strip = true;
+        self
+    }
+}
+
+impl TokenExtractor for QueryExtractor {
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        self.try_extract(parts).ok().flatten()
+    }
+
+    /// Percent-decodes the parameter, and reports one that is repeated with different values
+    /// or does not decode to printable UTF-8.
+    fn try_extract(&self, parts: &Parts) -> Result<Option<String>, String> {
+        let Some(query) = parts.uri.query() else {
+            return Ok(None);
+        };
+        let mut values = form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| *key == self.param)
+            .map(|(_, value)| value)
+            .filter(|value| !value.is_empty());
+        let Some(value) = values.next() else {
+            return Ok(None);
+        };
+        if values.any(|other| other != value) {
+            return Err(format!(
+                "the `{}` query parameter is repeated with different values",
+                self.param
+            ));
+        }
+        // Invalid UTF-8 sequences are decoded to U+FFFD.
+        if value
+            .chars()
+            .any(|c| c == char::REPLACEMENT_CHARACTER || c.is_control() || c.is_whitespace())
+        {
+            return Err(format!(
+                "the `{}` query parameter does not decode to a printable token",
+                self.param
+            ));
+        }
+        Ok(Some(value.into_owned()))
+    }
+
+    fn source(&self) -> TokenSource {
+        TokenSource::Query(self.param.clone())
+    }
+
+    fn scrub(&self, parts: &mut Parts) {
+        if !self.strip {
+            return;
+        }
+        if let Some(uri) = strip_query_param(&parts.uri, &self.param) {
+            parts.uri = uri;
+        }
+    }
+}
+
+/// Reads the token from the `Sec-WebSocket-Protocol` header of a WebSocket upgrade request.
+///
+/// Browsers cannot set `Authorization` on WebSocket connections, so clients offer the token as
+/// a subprotocol next to a marker protocol instead:
+///
+/// ```js
+/// new WebSocket(url, ["bearer", accessToken]);
+/// ```
+///
+/// The token is removed from the header before the request reaches the handler, and if the
+/// handler completes the upgrade without choosing a subprotocol itself, the middleware
+/// echoes the marker protocol back so the browser accepts the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketProtocolExtractor {
+    protocol: String,
+}
+
+impl WebSocketProtocolExtractor {
+    /// Expects the token to follow the `bearer` subprotocol.
+    pub fn new() -> Self {
+        Self::with_protocol("bearer")
+    }
+
+    /// Expects the token to follow the given marker subprotocol.
+    pub fn with_protocol(protocol: impl Into<String>) -> Self {
+        Self {
+            protocol: protocol.into(),
+        }
+    }
+
+    fn protocols(parts: &Parts) -> Vec<&str> {
+        parts
+            .headers
+            .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+}
+
+impl Default for WebSocketProtocolExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenExtractor for WebSocketProtocolExtractor {
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        let protocols = Self::protocols(parts);
+        let marker = protocols.iter().position(|p| *p == self.protocol)?;
+        protocols.get(marker + 1).map(|token| token.to_string())
+    }
+
+    fn source(&self) -> TokenSource {
+        TokenSource::WebSocketProtocol(self.protocol.clone())
+    }
+
+    fn scrub(&self, parts: &mut Parts) {
+        let Some(token) = self.extract(parts).map(Zeroizing::new) else {
+            return;
+        };
+        let remaining = Self::protocols(parts)
+            .into_iter()
+            .filter(|p| *p != token.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match HeaderValue::from_str(&remaining) {
+            Ok(value) => {
+                parts.headers.insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+            }
+            Err(_) => {
+                parts.headers.remove(header::SEC_WEBSOCKET_PROTOCOL);
+            }
+        }
+    }
+}
+
+/// Selects the marker subprotocol on a successful upgrade response that did not choose one.
+pub(crate) fn echo_websocket_protocol(response: &mut Response, protocol: &str) {
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS
+        || response
+            .headers()
+            .contains_key(header::SEC_WEBSOCKET_PROTOCOL)
+    {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(protocol) {
+        response
+            .headers_mut()
+            .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+    }
+}
+
+/// What the middleware does when a token source is malformed or ambiguous.
+///
+/// The built-in extractors report a header that is repeated or not visible ASCII, a cookie
+/// set more than once with different values, and a query parameter that is repeated with
+/// different values or does not percent-decode to printable UTF-8. See
+/// [`TokenExtractor::try_extract`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MalformedCredentials {
+    /// Treats the source as if it carried no token and moves on to the next one.
+    #[default]
+    Ignore,
+    /// Fails authentication with [`AuthError::MalformedCredentials`], answered with
+    /// `400 Bad Request` in [`AuthMode::Strict`](crate::AuthMode::Strict).
+    Reject,
+}
+
+/// An ordered list of token extractors with first-match semantics.
+///
+/// ```rust
+/// use axum_jwt_oidc::{CookieExtractor, HeaderExtractor, QueryExtractor, TokenExtractorChain};
+///
+/// let chain = TokenExtractorChain::new()
+///     .then(HeaderExtractor::bearer())
+///     .then(CookieExtractor::new("access_token"))
+///     .then(QueryExtractor::new("access_token").strip());
+/// ```
+#[derive(Clone, Default)]
+pub struct TokenExtractorChain {
+    extractors: Vec<Arc<dyn TokenExtractor>>,
+}
+
+impl TokenExtractorChain {
+    /// Creates an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the access token forwarded by an [oauth2-proxy](https://oauth2-proxy.github.io/)
+    /// in front of the service, from `X-Forwarded-Access-Token` and then
+    /// `X-Auth-Request-Access-Token`.
+    ///
+    /// Only use this when the service is reachable exclusively through the proxy, since
+    /// clients can set these headers themselves. The token is still validated as usual.
+    pub fn oauth2_proxy() -> Self {
+        Self::new()
+            .then(HeaderExtractor::forwarded_access_token())
+            .then(HeaderExtractor::auth_request_access_token())
+    }
+
+    /// Appends `extractor`, which is consulted only if every earlier extractor found nothing.
+    pub fn then(mut self, extractor: impl TokenExtractor) -> Self {
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+}
+
+/// Where the middleware looks for the token, in order of precedence.
+#[derive(Clone, Default)]
+pub(crate) struct TokenSources {
+    pub(crate) header: HeaderExtractor,
+    pub(crate) cookie: Option<CookieExtractor>,
+    pub(crate) query: Option<QueryExtractor>,
+    /// Replaces the built-in sources when set.
+    pub(crate) chain: Option<TokenExtractorChain>,
+    /// Set when stripping was requested without a query parameter, so it can be reported.
+    pub(crate) strip_without_query: bool,
+    pub(crate) malformed: MalformedCredentials,
+}
+
+impl TokenSources {
+    /// Whether a cookie or query parameter source is configured.
+    pub(crate) fn has_named_sources(&self) -> bool {
+        self.cookie.is_some() || self.query.is_some()
+    }
+
+    /// Whether a cookie or query parameter source is configured with an empty name.
+    pub(crate) fn has_empty_name(&self) -> bool {
+        self.cookie.as_ref().is_some_and(|c| c.name.is_empty())
+            || self.query.as_ref().is_some_and(|q| q.param.is_empty())
+    }
+
+    fn extractors(&self) -> Vec<&dyn TokenExtractor> {
+        if let Some(chain) = &self.chain {
+            return chain.extractors.iter().map(|e| e.as_ref()).collect();
+        }
+        let mut extractors: Vec<&dyn TokenExtractor> = vec![&self.header];
+        if let Some(cookie) = &self.cookie {
+            extractors.push(cookie);
+        }
+        if let Some(query) = &self.query {
+            extractors.push(query);
+        }
+        extractors
+    }
+
+    /// Returns the first token found along with its source, and scrubs every source from
+    /// the request. The token is zeroed in memory when dropped.
+    ///
+    /// Fails on the first malformed source when configured to reject them; otherwise skips it.
+    pub(crate) fn extract(
+        &self,
+        parts: &mut Parts,
+    ) -> Result<Option<(Zeroizing<String>, TokenSource)>, AuthError> {
+        let extractors = self.extractors();
+        let mut token = Ok(None);
+        for extractor in &extractors {
+            match extractor.try_extract(parts) {
+                Ok(Some(found)) => {
+                    token = Ok(Some((Zeroizing::new(found), extractor.source())));
+                    break;
+                }
+                Ok(None) => {}
+                Err(reason) => {
+                    log::warn!(
+                        "Malformed credentials from {}: {reason}",
+                        extractor.source()
+                    );
+                    if self.malformed == MalformedCredentials::Reject {
+                        token = Err(AuthError::MalformedCredentials(reason));
+                        break;
+                    }
+                }
+            }
+        }
+        for extractor in &extractors {
+            extractor.scrub(parts);
+        }
+        token
+    }
+}
+
+/// Returns `uri` with every occurrence of the query parameter `name` removed.
+fn strip_query_param(uri: &Uri, name: &str) -> Option<Uri> {
+    let query = uri.query()?;
+    let remaining: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .is_none_or(|(key, _)| key != name)
+        })
+        .collect();
+
+    let mut path_and_query = uri.path().to_string();
+    if !remaining.is_empty() {
+        path_and_query.push('?');
+        path_and_query.push_str(&remaining.join("&"));
+    }
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Returns the non-empty values of every cookie called `name`, in order.
+fn find_cookies<'a>(headers: &'a HeaderMap, name: &'a str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter(move |(key, _)| *key == name)
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|value| !value.is_empty())
+}
@@ -0,0 +1,60 @@
+use serde::Deserialize;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use crate::context::AccessToken;
+
+/// The shortest time an issued token is used before another is requested, however short its
+/// lifetime.
+pub(crate) const MIN_TOKEN_USE: Duration = Duration::from_secs(1);
+
+/// The error type of requests to an identity provider's token endpoint.
+pub(crate) type TokenEndpointError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Sends a token request and returns the issued token and its lifetime, if given.
+/// `purpose` describes the request in errors, e.g. `token exchange for api://orders`.
+pub(crate) async fn request_token(
+    request: reqwest::RequestBuilder,
+    purpose: &str,
+) -> Result<(AccessToken, Option<Duration>), TokenEndpointError> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        return Err(match response.json::<ErrorResponse>().await {
+            Ok(error) => format!(
+                "{purpose} rejected with {status}: {}{}",
+                error.error,
+                error
+                    .error_description
+                    .map(|description| format!(" ({description})"))
+                    .unwrap_or_default()
+            ),
+            Err(_) => format!("{purpose} failed with {status}"),
+        }
+        .into());
+    }
+    let response: TokenResponse = response.json().await?;
+    let token = AccessToken::new(&Zeroizing::new(response.access_token));
+    Ok((token, response.expires_in.map(Duration::from_secs)))
+}
+
+/// Returns how long after it was issued a token valid for `expires_in` is replaced: `margin`
+/// before it expires, or halfway through its lifetime if that is sooner, and never within a
+/// second, so short-lived tokens are not requested in a loop.
+pub(crate) fn renew_after(expires_in: Duration, margin: Duration) -> Duration {
+    expires_in
+        .saturating_sub(margin.min(expires_in / 2))
+        .max(MIN_TOKEN_USE)
+}
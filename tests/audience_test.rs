@@ -0,0 +1,145 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Extension, Router,
+};
+use axum_jwt_oidc::{
+    AudienceCheck, AudienceMismatch, AuthError, OidcAuthLayer, RequireAudienceLayer,
+};
+use tower::ServiceExt;
+
+/// Returns the error of a request carrying a token with the given `aud` claim, if any,
+/// formatted with `Debug`.
+async fn error(audience: AudienceCheck, aud: Option<serde_json::Value>) -> String {
+    let mut claims = serde_json::json!({
+        "sub": "alice",
+        "iss": common::ISSUER,
+        "exp": common::now() + 3600,
+    });
+    if let Some(aud) = aud {
+        claims["aud"] = aud;
+    }
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::new(common::validator().await, common::validation())
+            .with_audience(audience);
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|error: Option<Extension<AuthError>>| async move {
+                format!("{:?}", error.map(|Extension(error)| error))
+            }),
+        )
+        .layer(auth_layer);
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {}", common::sign(&claims)))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// Formats `error` as the handler of [`error`] does.
+fn expected(mismatch: Option<AudienceMismatch>) -> String {
+    format!("{:?}", mismatch.map(AuthError::AudienceMismatch))
+}
+
+#[tokio::test]
+async fn test_any_of_accepts_tokens_for_one_of_the_audiences() {
+    let any_of = || AudienceCheck::any_of(["a", "b"]);
+    assert_eq!(error(any_of(), Some("b".into())).await, expected(None));
+    assert_eq!(
+        error(any_of(), Some(serde_json::json!(["x", "a"]))).await,
+        expected(None)
+    );
+    assert_eq!(
+        error(any_of(), Some("x".into())).await,
+        expected(Some(AudienceMismatch::NoneAccepted))
+    );
+    assert_eq!(
+        error(any_of(), None).await,
+        expected(Some(AudienceMismatch::Missing))
+    );
+}
+
+#[tokio::test]
+async fn test_all_of_requires_every_audience() {
+    let all_of = || AudienceCheck::all_of(["a", "b"]);
+    assert_eq!(
+        error(all_of(), Some(serde_json::json!(["a", "b", "c"]))).await,
+        expected(None)
+    );
+    assert_eq!(
+        error(all_of(), Some(serde_json::json!(["a"]))).await,
+        expected(Some(AudienceMismatch::MissingRequired(vec![
+            "b".to_string()
+        ])))
+    );
+}
+
+#[tokio::test]
+async fn test_missing_audience_can_be_allowed() {
+    let internal = || AudienceCheck::any_of(["a"]).allow_missing();
+    assert_eq!(error(internal(), None).await, expected(None));
+    assert_eq!(
+        error(internal(), Some("x".into())).await,
+        expected(Some(AudienceMismatch::NoneAccepted))
+    );
+}
+
+#[tokio::test]
+async fn test_route_audiences_share_the_outer_layer() {
+    let admin = Router::new()
+        .route("/admin", get(|| async { "admin" }))
+        .route_layer(RequireAudienceLayer::new(AudienceCheck::any_of([
+            "api://admin",
+        ])));
+    let public = Router::new()
+        .route("/public", get(|| async { "public" }))
+        .route_layer(RequireAudienceLayer::new(AudienceCheck::any_of([
+            "api://public",
+        ])));
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::new(common::validator().await, common::validation())
+            .with_audience(AudienceCheck::any_of(["api://admin", "api://public"]));
+    let app = admin.merge(public).layer(auth_layer);
+
+    let status = |uri: &'static str, aud: Option<&'static str>| {
+        let app = app.clone();
+        async move {
+            let mut request = Request::builder().uri(uri);
+            if let Some(aud) = aud {
+                let token = common::sign(&serde_json::json!({
+                    "sub": "alice",
+                    "iss": common::ISSUER,
+                    "aud": aud,
+                    "exp": common::now() + 3600,
+                }));
+                request = request.header("Authorization", format!("Bearer {token}"));
+            }
+            let request = request.body(Body::empty()).unwrap();
+            app.oneshot(request).await.unwrap().status()
+        }
+    };
+
+    assert_eq!(status("/admin", Some("api://admin")).await, StatusCode::OK);
+    assert_eq!(
+        status("/admin", Some("api://public")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status("/public", Some("api://public")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status("/public", Some("api://admin")).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(status("/public", None).await, StatusCode::UNAUTHORIZED);
+}
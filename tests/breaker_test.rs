@@ -0,0 +1,115 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use axum_jwt_oidc::{
+    AuthMode, JwksCircuitBreaker, ManualClock, OidcAuthLayer, OidcConfig, OidcValidator,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+/// Serves the test JWKS, counting fetches and failing them while `failing` is set.
+async fn jwks_server(fetches: Arc<AtomicUsize>, failing: Arc<AtomicBool>) -> String {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            if failing.load(Ordering::SeqCst) {
+                return StatusCode::SERVICE_UNAVAILABLE.into_response();
+            }
+            Json(common::jwks()).into_response()
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/jwks")
+}
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_breaker_skips_fetches_and_falls_back_to_cached_keys() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let failing = Arc::new(AtomicBool::new(false));
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_server(fetches.clone(), failing.clone()).await,
+    ));
+    let clock = ManualClock::new(SystemTime::now());
+    let breaker = JwksCircuitBreaker::new(2)
+        .cooldown(Duration::from_secs(60))
+        .max_staleness(Duration::from_secs(600));
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_clock(clock.clone())
+        .with_jwks_circuit_breaker(breaker);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let alice = common::token_for("alice");
+    let rotated = common::token_with_kid("bob", "rotated-key");
+
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Two failed fetches open the breaker.
+    failing.store(true, Ordering::SeqCst);
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    // While it is open, tokens needing a fetch are rejected without one, and tokens signed
+    // with a cached key are still accepted.
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    // After the cooldown, a fetch is attempted again, and reopens the breaker on failure.
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 4);
+
+    // Once the cached keys are too stale, every token is rejected.
+    clock.advance(Duration::from_secs(55));
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 4);
+    clock.advance(Duration::from_secs(500));
+    assert_eq!(status(&app, &rotated).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 5);
+    assert_eq!(status(&app, &alice).await, 503);
+
+    // A successful fetch closes the breaker.
+    failing.store(false, Ordering::SeqCst);
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(status(&app, &rotated).await, 401);
+    assert_eq!(fetches.load(Ordering::SeqCst), 6);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(status(&app, &rotated).await, 401);
+    assert_eq!(fetches.load(Ordering::SeqCst), 7);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{
+    AuthMode, CacheBudget, CacheEviction, CacheKind, OidcAuthLayer, ValidationCache,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+fn static_layer() -> OidcAuthLayer<serde_json::Value> {
+    OidcAuthLayer::<serde_json::Value>::with_static_jwks(
+        &common::jwks().to_string(),
+        common::validation(),
+    )
+    .unwrap()
+    .with_mode(AuthMode::Strict)
+}
+
+fn app(auth_layer: OidcAuthLayer<serde_json::Value>) -> Router {
+    Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer)
+}
+
+/// A budget recording the evictions it reports.
+fn budget(
+    max_entries: usize,
+    max_bytes: usize,
+) -> (Arc<CacheBudget>, Arc<Mutex<Vec<CacheEviction>>>) {
+    let evictions = Arc::new(Mutex::new(Vec::new()));
+    let recorded = evictions.clone();
+    let budget = CacheBudget::new(max_entries, max_bytes).on_evict(move |eviction| {
+        recorded.lock().unwrap().push(eviction.clone());
+    });
+    (Arc::new(budget), evictions)
+}
+
+#[tokio::test]
+async fn test_caches_share_the_entry_bound_of_their_budget() {
+    let (budget, evictions) = budget(2, 1 << 20);
+    let cache = Arc::new(ValidationCache::new(100).with_budget(budget.clone()));
+    let app = app(static_layer()
+        .with_validation_cache(cache.clone())
+        .with_unknown_kid_ttl(Duration::from_secs(60))
+        .with_cache_budget(budget.clone()));
+
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+    assert_eq!(status(&app, &common::token_for("bob")).await, 200);
+    assert_eq!((cache.len(), budget.entries()), (2, 2));
+    assert!(budget.bytes() > 0);
+
+    // The unknown-kid cache holds nothing it could drop, so the kid is not remembered
+    // rather than evicting results of the validation cache.
+    let bogus = common::token_with_kid("mallory", "bogus-key");
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert_eq!((budget.entries(), budget.evictions()), (2, 0));
+
+    // The least recently used result makes room for a new one.
+    assert_eq!(status(&app, &common::token_for("carol")).await, 200);
+    assert_eq!((cache.len(), budget.entries()), (2, 2));
+    assert_eq!(budget.evictions(), 1);
+    let evictions = evictions.lock().unwrap().clone();
+    assert_eq!(evictions.len(), 1);
+    assert_eq!(evictions[0].cache, CacheKind::Validation);
+    assert!(evictions[0].bytes > 0);
+
+    // Dropping the caches gives their share back.
+    drop(app);
+    drop(cache);
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_unknown_kids_are_evicted_within_the_budget() {
+    let (budget, evictions) = budget(1, 1 << 20);
+    let app = app(static_layer()
+        .with_unknown_kid_ttl(Duration::from_secs(60))
+        .with_cache_budget(budget.clone()));
+
+    for kid in ["bogus-1", "bogus-2"] {
+        let token = common::token_with_kid("mallory", kid);
+        assert_eq!(status(&app, &token).await, 401);
+        assert_eq!(budget.entries(), 1);
+    }
+    let evictions = evictions.lock().unwrap().clone();
+    assert_eq!(evictions.len(), 1);
+    assert_eq!(evictions[0].cache, CacheKind::UnknownKids);
+}
+
+#[tokio::test]
+async fn test_results_beyond_the_byte_bound_are_not_cached() {
+    let (budget, evictions) = budget(100, 16);
+    let cache = Arc::new(ValidationCache::new(100).with_budget(budget.clone()));
+    let app = app(static_layer()
+        .with_validation_cache(cache.clone())
+        .with_request_coalescing()
+        .with_cache_budget(budget.clone()));
+
+    // Without room, tokens are still validated, but neither cached nor coalesced.
+    let alice = common::token_for("alice");
+    for _ in 0..2 {
+        assert_eq!(status(&app, &alice).await, 200);
+    }
+    assert_eq!((cache.len(), cache.hits()), (0, 0));
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+    assert!(evictions.lock().unwrap().is_empty());
+}
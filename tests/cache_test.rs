@@ -0,0 +1,208 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Json, Router};
+use axum_jwt_oidc::{
+    AuthMode, CacheBudget, ManualClock, OidcAuthLayer, OidcConfig, OidcValidator, ValidationCache,
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+fn token_expiring_in(sub: &str, seconds: i64) -> String {
+    common::sign(&json!({
+        "sub": sub,
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + seconds,
+    }))
+}
+
+#[tokio::test]
+async fn test_validated_tokens_are_cached_until_they_expire() {
+    let cache = Arc::new(ValidationCache::new(2));
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_validation_cache(cache.clone())
+            .with_clock(clock.clone());
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let alice = token_expiring_in("alice", 120);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    assert_eq!(cache.hit_rate(), 0.5);
+
+    // Invalid tokens are not cached.
+    assert_eq!(status(&app, "not-a-token").await, 401);
+    assert_eq!(status(&app, "not-a-token").await, 401);
+    assert_eq!((cache.hits(), cache.misses()), (1, 3));
+    assert_eq!(cache.len(), 1);
+
+    // The least recently used token makes room for new ones.
+    let bob = token_expiring_in("bob", 3600);
+    let carol = token_expiring_in("carol", 3600);
+    assert_eq!(status(&app, &bob).await, 200);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(status(&app, &carol).await, 200);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(status(&app, &bob).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (3, 6));
+
+    // Once expired, the token is validated again and rejected.
+    clock.advance(Duration::from_secs(200));
+    assert_eq!(status(&app, &alice).await, 401);
+    assert_eq!(status(&app, &bob).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (4, 7));
+}
+
+#[tokio::test]
+async fn test_cached_results_are_dropped_when_the_clock_goes_backwards() {
+    let cache = Arc::new(ValidationCache::new(10));
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_validation_cache(cache.clone())
+            .with_clock(clock.clone());
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer.clone());
+
+    let alice = token_expiring_in("alice", 3600);
+    assert_eq!(status(&app, &alice).await, 200);
+    clock.set(SystemTime::now() - Duration::from_secs(600));
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (0, 2));
+    assert_eq!(cache.time_anomalies(), 1);
+    assert_eq!(auth_layer.time_anomalies(), 1);
+
+    // Cached again at the corrected time, the token is answered from the cache.
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!((cache.hits(), cache.time_anomalies()), (1, 1));
+}
+
+#[tokio::test]
+async fn test_max_age_passes_on_the_monotonic_clock() {
+    let cache = Arc::new(ValidationCache::new(10).max_age(Duration::from_millis(50)));
+    // The layer's clock stands still, as a clock stuck or set back would.
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_validation_cache(cache.clone())
+            .with_clock(clock);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let alice = token_expiring_in("alice", 3600);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!(cache.hits(), 1);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(status(&app, &alice).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (1, 2));
+}
+
+/// Serves the test JWKS slowly, counting fetches, and returns its URL.
+async fn slow_jwks_server(fetches: Arc<AtomicUsize>) -> String {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Json(common::jwks())
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/jwks")
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_with_the_same_token_share_one_validation() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        slow_jwks_server(fetches.clone()).await,
+    ));
+    let auth_layer = OidcAuthLayer::<TestClaims>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_request_coalescing();
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let token = common::token_for("alice");
+    let statuses = join_all((0..10).map(|_| status(&app, &token))).await;
+    assert_eq!(statuses, vec![200; 10]);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Other tokens are still validated, and failures are shared like successes.
+    let statuses = join_all((0..3).map(|_| status(&app, "not-a-token"))).await;
+    assert_eq!(statuses, vec![401; 3]);
+    assert_eq!(status(&app, &common::token_for("bob")).await, 200);
+}
+
+#[tokio::test]
+async fn test_cancelled_validations_leave_no_entry_behind() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        slow_jwks_server(fetches.clone()).await,
+    ));
+    let budget = Arc::new(CacheBudget::new(100, 1 << 20));
+    let auth_layer = OidcAuthLayer::<TestClaims>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_request_coalescing()
+        .with_cache_budget(budget.clone());
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    // Every request awaiting the validations gives up before the keys are fetched.
+    let tokens = ["alice", "bob", "carol"].map(common::token_for);
+    let requests = join_all(tokens.iter().map(|token| status(&app, token)));
+    assert!(tokio::time::timeout(Duration::from_millis(20), requests)
+        .await
+        .is_err());
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+
+    assert_eq!(status(&app, &tokens[0]).await, 200);
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
@@ -0,0 +1,51 @@
+use axum_jwt_oidc::capabilities;
+
+#[test]
+fn test_capabilities_match_enabled_features() {
+    let capabilities = capabilities();
+    assert_eq!(capabilities.cedar, cfg!(feature = "cedar"));
+    assert_eq!(
+        capabilities.client_credentials,
+        cfg!(feature = "client-credentials")
+    );
+    assert_eq!(capabilities.discovery, cfg!(feature = "discovery"));
+    assert_eq!(capabilities.exchange, cfg!(feature = "exchange"));
+    assert_eq!(capabilities.forward, cfg!(feature = "forward"));
+    assert_eq!(capabilities.jwks_file, cfg!(feature = "jwks-file"));
+    assert_eq!(capabilities.jwks_fetch, cfg!(feature = "jwks-fetch"));
+    assert_eq!(capabilities.jwks_refresh, cfg!(feature = "jwks-refresh"));
+    assert_eq!(capabilities.macros, cfg!(feature = "macros"));
+    assert_eq!(capabilities.messages, cfg!(feature = "messages"));
+    assert_eq!(capabilities.opa, cfg!(feature = "opa"));
+    assert_eq!(capabilities.redis, cfg!(feature = "redis"));
+    assert_eq!(capabilities.stack, cfg!(feature = "stack"));
+    assert_eq!(capabilities.typed_header, cfg!(feature = "typed-header"));
+
+    let enabled = capabilities.enabled();
+    assert_eq!(enabled.contains(&"cedar"), cfg!(feature = "cedar"));
+    assert_eq!(
+        enabled.contains(&"client-credentials"),
+        cfg!(feature = "client-credentials")
+    );
+    assert_eq!(enabled.contains(&"discovery"), cfg!(feature = "discovery"));
+    assert_eq!(enabled.contains(&"exchange"), cfg!(feature = "exchange"));
+    assert_eq!(enabled.contains(&"forward"), cfg!(feature = "forward"));
+    assert_eq!(enabled.contains(&"jwks-file"), cfg!(feature = "jwks-file"));
+    assert_eq!(
+        enabled.contains(&"jwks-fetch"),
+        cfg!(feature = "jwks-fetch")
+    );
+    assert_eq!(
+        enabled.contains(&"jwks-refresh"),
+        cfg!(feature = "jwks-refresh")
+    );
+    assert_eq!(enabled.contains(&"macros"), cfg!(feature = "macros"));
+    assert_eq!(enabled.contains(&"messages"), cfg!(feature = "messages"));
+    assert_eq!(enabled.contains(&"opa"), cfg!(feature = "opa"));
+    assert_eq!(enabled.contains(&"redis"), cfg!(feature = "redis"));
+    assert_eq!(enabled.contains(&"stack"), cfg!(feature = "stack"));
+    assert_eq!(
+        enabled.contains(&"typed-header"),
+        cfg!(feature = "typed-header")
+    );
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{OidcAuthLayer, RequireClaimLayer};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn send(layer: RequireClaimLayer, claims: serde_json::Value) -> axum::response::Response {
+    let mut payload = json!({
+        "sub": "heidi",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    });
+    payload
+        .as_object_mut()
+        .unwrap()
+        .extend(claims.as_object().unwrap().clone());
+
+    Router::new()
+        .route("/reports", get(|| async { "reports" }))
+        .layer(layer)
+        .layer(OidcAuthLayer::<TestClaims>::new(
+            common::validator().await,
+            common::validation(),
+        ))
+        .oneshot(
+            Request::builder()
+                .uri("/reports")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", common::sign(&payload)),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_required_claims_must_match() {
+    let layer = RequireClaimLayer::new("email_verified", true).and("org.tenant_id", "acme");
+
+    let claims = json!({ "email_verified": true, "org": { "tenant_id": "acme" } });
+    assert_eq!(send(layer.clone(), claims).await.status(), 200);
+
+    let claims = json!({ "email_verified": false, "org": { "tenant_id": "acme" } });
+    assert_eq!(send(layer.clone(), claims).await.status(), 403);
+
+    let claims = json!({ "email_verified": true });
+    assert_eq!(send(layer, claims).await.status(), 403);
+}
+
+#[tokio::test]
+async fn test_json_pointer_addresses_claims_with_dots() {
+    let layer = RequireClaimLayer::new("/https:~1~1example.com~1tenant", "acme");
+
+    let claims = json!({ "https://example.com/tenant": "acme" });
+    assert_eq!(send(layer.clone(), claims).await.status(), 200);
+
+    let claims = json!({ "https://example.com/tenant": "globex" });
+    assert_eq!(send(layer, claims).await.status(), 403);
+}
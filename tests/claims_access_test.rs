@@ -0,0 +1,45 @@
+use axum_jwt_oidc::{ClaimsAccess, StandardClaims};
+use std::time::{Duration, SystemTime};
+
+fn describe(claims: &impl ClaimsAccess) -> (Option<String>, bool, Option<SystemTime>) {
+    (
+        claims.subject().map(str::to_string),
+        claims.scopes().has_scope("read:orders"),
+        claims.expires_at(),
+    )
+}
+
+#[test]
+fn test_claims_access_on_json_and_standard_claims() {
+    let json = serde_json::json!({
+        "sub": "frank",
+        "exp": 1_700_000_000,
+        "scp": ["read:orders"],
+    });
+    let expected = (
+        Some("frank".to_string()),
+        true,
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+    );
+    assert_eq!(describe(&json), expected);
+
+    let standard: StandardClaims = serde_json::from_value(serde_json::json!({
+        "sub": "frank",
+        "exp": 1_700_000_000,
+        "scope": "openid read:orders",
+    }))
+    .unwrap();
+    assert_eq!(describe(&standard), expected);
+}
+
+#[test]
+fn test_claims_access_tolerates_missing_and_odd_claims() {
+    let json = serde_json::json!({ "sub": 42, "exp": -1, "scope": 7 });
+    assert_eq!(describe(&json), (None, false, None));
+
+    let json = serde_json::json!({ "exp": 1.5 });
+    assert_eq!(
+        json.expires_at(),
+        Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1500))
+    );
+}
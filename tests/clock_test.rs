@@ -0,0 +1,87 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthMode, ManualClock, OidcAuthLayer};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+#[tokio::test]
+async fn test_token_expires_when_clock_advances() {
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_clock(clock.clone());
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let token = common::sign(&serde_json::json!({
+        "sub": "erin",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 300,
+    }));
+    let send = |app: Router| {
+        app.oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    let response = send(app.clone()).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Past `exp` plus the default leeway of 60 seconds.
+    clock.advance(Duration::from_secs(400));
+    let response = send(app).await.unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_leeway_absorbs_clock_drift() {
+    let validator = std::sync::Arc::new(common::validator().await);
+    let status = |leeway: Option<u64>, clock: Option<ManualClock>, claims: serde_json::Value| {
+        let validator = validator.clone();
+        async move {
+            let mut auth_layer =
+                OidcAuthLayer::<TestClaims>::new((*validator).clone(), common::validation())
+                    .with_mode(AuthMode::Strict);
+            if let Some(leeway) = leeway {
+                auth_layer = auth_layer.with_leeway(Duration::from_secs(leeway));
+            }
+            if let Some(clock) = clock {
+                auth_layer = auth_layer.with_clock(clock);
+            }
+            let app = Router::new()
+                .route("/test", get(|| async { "ok" }))
+                .layer(auth_layer);
+            let request = Request::builder()
+                .uri("/test")
+                .header("Authorization", format!("Bearer {}", common::sign(&claims)))
+                .body(Body::empty())
+                .unwrap();
+            app.oneshot(request).await.unwrap().status().as_u16()
+        }
+    };
+    // Expired two minutes ago, beyond the default leeway of 60 seconds.
+    let expired = serde_json::json!({
+        "sub": "erin",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() - 120,
+    });
+    assert_eq!(status(None, None, expired.clone()).await, 401);
+    assert_eq!(status(Some(300), None, expired.clone()).await, 200);
+    let clock = ManualClock::new(SystemTime::now());
+    assert_eq!(status(Some(300), Some(clock), expired).await, 200);
+}
@@ -0,0 +1,104 @@
+use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
+use axum_jwt_oidc::{
+    AudienceCheck, AuthMode, ConfigError, ErrorFormat, ManualClock, OidcAuthLayer,
+    TokenExtractorChain, TrustedGatewayPayload,
+};
+use serde::Deserialize;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Deserialize)]
+struct TestClaims {
+    #[allow(dead_code)]
+    sub: String,
+}
+
+fn layer() -> OidcAuthLayer<TestClaims> {
+    let config = OidcConfig::new(
+        "https://example.com".to_string(),
+        "test-client-id".to_string(),
+        "https://example.com/.well-known/jwks.json".to_string(),
+    );
+    OidcAuthLayer::new(OidcValidator::new(config), Validation::default())
+}
+
+#[test]
+fn test_consistent_configuration_is_accepted() {
+    let result = layer()
+        .with_mode(AuthMode::Strict)
+        .with_error_format(ErrorFormat::ProblemJson)
+        .with_query_param("access_token")
+        .strip_query_param()
+        .validate();
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_trusted_gateways_accept_a_clock() {
+    // The clock still dates the claims, e.g. in the `AuthContext`.
+    let result = layer()
+        .with_clock(ManualClock::new(SystemTime::now()))
+        .dangerously_trust_gateway_payload(TrustedGatewayPayload::envoy())
+        .validate();
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_conflicting_options_are_rejected() {
+    let cases = [
+        (
+            layer().strip_query_param().with_query_param("access_token"),
+            ConfigError::StripWithoutQueryParam,
+        ),
+        (layer().with_cookie(""), ConfigError::EmptySourceName),
+        (
+            layer()
+                .with_cookie("session")
+                .with_token_extractors(TokenExtractorChain::new()),
+            ConfigError::SourcesReplacedByChain,
+        ),
+        (
+            layer()
+                .with_cookie("session")
+                .dangerously_trust_gateway_payload(TrustedGatewayPayload::envoy()),
+            ConfigError::IgnoredByTrustedGateway,
+        ),
+        (
+            layer().with_error_format(ErrorFormat::ProblemJson),
+            ConfigError::RejectionsWithoutStrictMode,
+        ),
+        (
+            layer().with_audience(AudienceCheck::any_of(Vec::<String>::new())),
+            ConfigError::EmptyAudienceCheck,
+        ),
+        (
+            layer().with_audience(AudienceCheck::all_of(Vec::<String>::new())),
+            ConfigError::EmptyAudienceCheck,
+        ),
+    ];
+
+    for (layer, expected) in cases {
+        assert_eq!(layer.validate().err(), Some(expected));
+    }
+}
+
+#[test]
+fn test_plain_http_issuers_require_explicit_opt_in() {
+    let mut validation = Validation::default();
+    validation.set_issuer(&["http://localhost:8080/realms/dev"]);
+    let layer = || {
+        let config = OidcConfig::new(
+            "http://localhost:8080/realms/dev".to_string(),
+            "test-client-id".to_string(),
+            "http://localhost:8080/realms/dev/protocol/openid-connect/certs".to_string(),
+        );
+        OidcAuthLayer::<TestClaims>::new(OidcValidator::new(config), validation.clone())
+    };
+
+    assert_eq!(
+        layer().validate().err().unwrap(),
+        ConfigError::InsecureUrl("http://localhost:8080/realms/dev".to_string())
+    );
+    assert!(layer().allow_insecure_http().validate().is_ok());
+}
@@ -0,0 +1,334 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Extension, Json, Router,
+};
+use axum_jwt_oidc::{
+    AuthError, ConfigError, Discovery, DiscoveryChange, DiscoveryRefresh, OidcAuthLayer,
+    StandardClaims, Validation,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+
+/// Serves a discovery document claiming `document_issuer` (or the server's own URL) and
+/// the test JWKS, returning the server's URL.
+async fn start_provider(document_issuer: Option<&str>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let document = serde_json::json!({
+        "issuer": document_issuer.unwrap_or(&base),
+        "jwks_uri": format!("{base}/jwks"),
+    });
+    let app = Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(move || async move { Json(document) }),
+        )
+        .route("/jwks", get(|| async { Json(common::jwks()) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    base
+}
+
+fn validation() -> Validation {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::RS256);
+    validation.set_audience(&[common::AUDIENCE]);
+    validation
+}
+
+fn token(issuer: &str) -> String {
+    common::sign(&serde_json::json!({
+        "sub": "alice",
+        "iss": issuer,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }))
+}
+
+async fn status(auth_layer: OidcAuthLayer<StandardClaims>, token: &str) -> StatusCode {
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer.with_mode(axum_jwt_oidc::AuthMode::Strict));
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn test_discovered_keys_validate_tokens_of_the_issuer() {
+    let issuer = start_provider(None).await;
+    // The local provider does not use https.
+    assert_eq!(
+        OidcAuthLayer::<StandardClaims>::discover(&issuer, validation())
+            .await
+            .err()
+            .unwrap(),
+        ConfigError::InsecureUrl(issuer.clone())
+    );
+    let auth_layer = OidcAuthLayer::discover_insecure(&issuer, validation())
+        .await
+        .unwrap();
+    assert!(auth_layer.clone().validate().is_ok());
+    assert_eq!(
+        status(auth_layer.clone(), &token(&issuer)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(auth_layer, &token(common::ISSUER)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+/// Serves only RFC 8414 metadata for the issuer at `path` on the server, returning the
+/// issuer.
+async fn start_oauth_server(path: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let issuer = format!("{base}{path}");
+    let document = serde_json::json!({
+        "issuer": issuer,
+        "jwks_uri": format!("{base}/jwks"),
+    });
+    let app = Router::new()
+        .route(
+            &format!("/.well-known/oauth-authorization-server{path}"),
+            get(move || async move { Json(document) }),
+        )
+        .route("/jwks", get(|| async { Json(common::jwks()) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    issuer
+}
+
+#[tokio::test]
+async fn test_oauth_metadata_is_used_without_discovery_document() {
+    for path in ["", "/tenant"] {
+        let issuer = start_oauth_server(path).await;
+        let auth_layer = OidcAuthLayer::discover_insecure(&issuer, validation())
+            .await
+            .unwrap();
+        assert_eq!(status(auth_layer, &token(&issuer)).await, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_failing_discovery_document_does_not_fall_back() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let document = serde_json::json!({
+        "issuer": issuer,
+        "jwks_uri": format!("{issuer}/jwks"),
+    });
+    let app = Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+        )
+        .route(
+            "/.well-known/oauth-authorization-server",
+            get(move || async move { Json(document) }),
+        );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let error = OidcAuthLayer::<StandardClaims>::discover_insecure(&issuer, validation())
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, ConfigError::Discovery(reason) if reason.contains("503")),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_discovered_issuer_replaces_configured_issuers() {
+    let issuer = start_provider(None).await;
+    let mut validation = validation();
+    validation.set_issuer(&[common::ISSUER]);
+    let auth_layer = OidcAuthLayer::<StandardClaims>::discover_insecure(&issuer, validation)
+        .await
+        .unwrap();
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|error: Option<Extension<AuthError>>| async move {
+                format!("{:?}", error.map(|Extension(error)| error))
+            }),
+        )
+        .layer(auth_layer);
+
+    for (iss, expected) in [
+        (issuer.as_str(), "None".to_string()),
+        (
+            common::ISSUER,
+            format!(
+                "{:?}",
+                Some(AuthError::IssuerMismatch(common::ISSUER.to_string()))
+            ),
+        ),
+    ] {
+        let request = Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {}", token(iss)))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, expected.as_bytes());
+    }
+}
+
+#[tokio::test]
+async fn test_document_of_another_issuer_is_rejected() {
+    let issuer = start_provider(Some(common::ISSUER)).await;
+    let error = OidcAuthLayer::<StandardClaims>::discover_insecure(&issuer, validation())
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, ConfigError::Discovery(reason) if reason.contains(common::ISSUER)),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_missing_document_is_rejected() {
+    let base = common::start_jwks_server().await;
+    let issuer = base.trim_end_matches("/jwks");
+    let error = OidcAuthLayer::<StandardClaims>::discover_insecure(issuer, validation())
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, ConfigError::Discovery(reason) if reason.contains("404")),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_discovery_document_is_fetched_with_the_given_client() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let document = serde_json::json!({
+        "issuer": issuer,
+        "jwks_uri": format!("{issuer}/jwks"),
+    });
+    let app = Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(move |headers: axum::http::HeaderMap| async move {
+                match headers.contains_key("x-api-key") {
+                    true => Ok(Json(document)),
+                    false => Err(StatusCode::FORBIDDEN),
+                }
+            }),
+        )
+        .route("/jwks", get(|| async { Json(common::jwks()) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let error = OidcAuthLayer::<StandardClaims>::discover_insecure(&issuer, validation())
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(&error, ConfigError::Discovery(reason) if reason.contains("403")),
+        "{error}"
+    );
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-api-key", "secret".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    let discovery = Discovery::new(&issuer)
+        .with_http_client(client)
+        .allow_insecure_http();
+    assert_eq!(
+        discovery.jwks_uri().await.unwrap(),
+        format!("{issuer}/jwks")
+    );
+    let auth_layer = OidcAuthLayer::discover_with(discovery, validation())
+        .await
+        .unwrap();
+    assert_eq!(status(auth_layer, &token(&issuer)).await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_plain_http_issuer_is_rejected_before_fetching() {
+    // Nothing is fetched from a plain `http` issuer.
+    let error = OidcAuthLayer::<StandardClaims>::discover("http://127.0.0.1:1", validation())
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        error,
+        ConfigError::InsecureUrl("http://127.0.0.1:1".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_switches_to_a_changed_jwks_uri_once_its_keys_load() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let jwks_path = Arc::new(Mutex::new("/old"));
+    let app = Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get({
+                let issuer = issuer.clone();
+                let jwks_path = jwks_path.clone();
+                move || async move {
+                    let path = *jwks_path.lock().unwrap();
+                    Json(serde_json::json!({
+                        "issuer": issuer,
+                        "jwks_uri": format!("{issuer}{path}"),
+                    }))
+                }
+            }),
+        )
+        .route("/old", get(|| async { Json(common::jwks()) }))
+        .route("/new", get(|| async { Json(common::jwks()) }))
+        .route("/broken", get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let auth_layer = OidcAuthLayer::<StandardClaims>::discover_insecure(&issuer, validation())
+        .await
+        .unwrap();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let refresh = DiscoveryRefresh::new(Duration::from_millis(20)).on_change({
+        let changes = changes.clone();
+        move |change: DiscoveryChange| changes.lock().unwrap().push(change)
+    });
+    tokio::spawn(auth_layer.discovery_refresh_task(refresh));
+
+    *jwks_path.lock().unwrap() = "/broken";
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(changes.lock().unwrap().is_empty());
+    assert_eq!(
+        status(auth_layer.clone(), &token(&issuer)).await,
+        StatusCode::OK
+    );
+
+    *jwks_path.lock().unwrap() = "/new";
+    for _ in 0..100 {
+        if !changes.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let change = changes.lock().unwrap()[0].clone();
+    assert_eq!(change.issuer, issuer);
+    assert_eq!(change.previous_jwks_uri, format!("{issuer}/old"));
+    assert_eq!(change.jwks_uri, format!("{issuer}/new"));
+    assert_eq!(status(auth_layer, &token(&issuer)).await, StatusCode::OK);
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{ConfigError, OidcAuthLayer};
+use tower::ServiceExt;
+
+const VARS: [&str; 8] = [
+    "OIDC_ISSUER",
+    "OIDC_AUDIENCE",
+    "OIDC_JWKS_URL",
+    "OIDC_JWKS",
+    "OIDC_ALGORITHMS",
+    "OIDC_LEEWAY_SECS",
+    "OIDC_AUTH_MODE",
+    "OIDC_ALLOW_INSECURE_HTTP",
+];
+
+/// Sets exactly the given variables, unsetting the others.
+fn set_env(vars: &[(&str, &str)]) {
+    for name in VARS {
+        std::env::remove_var(name);
+    }
+    for (name, value) in vars {
+        std::env::set_var(name, value);
+    }
+}
+
+fn from_env() -> Result<OidcAuthLayer<serde_json::Value>, ConfigError> {
+    OidcAuthLayer::from_env()
+}
+
+async fn status(auth_layer: OidcAuthLayer<serde_json::Value>, token: &str) -> u16 {
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.oneshot(request).await.unwrap().status().as_u16()
+}
+
+// The environment is shared by the whole test binary, so every case runs in one test.
+#[tokio::test]
+async fn test_layer_is_configured_from_the_environment() {
+    set_env(&[]);
+    assert_eq!(
+        from_env().err().unwrap(),
+        ConfigError::MissingEnv(vec![
+            "OIDC_ISSUER".to_string(),
+            "OIDC_AUDIENCE".to_string(),
+            "OIDC_JWKS_URL or OIDC_JWKS".to_string(),
+        ])
+    );
+
+    set_env(&[("OIDC_ISSUER", common::ISSUER), ("OIDC_AUDIENCE", " , ")]);
+    assert_eq!(
+        from_env().err().unwrap(),
+        ConfigError::MissingEnv(vec![
+            "OIDC_AUDIENCE".to_string(),
+            "OIDC_JWKS_URL or OIDC_JWKS".to_string(),
+        ])
+    );
+
+    let jwks = common::jwks().to_string();
+    let audience = format!("other-client, {}", common::AUDIENCE);
+    let valid = [
+        ("OIDC_ISSUER", common::ISSUER),
+        ("OIDC_AUDIENCE", audience.as_str()),
+        ("OIDC_JWKS", jwks.as_str()),
+        ("OIDC_AUTH_MODE", "Strict"),
+    ];
+    set_env(&valid);
+    let token = common::token_for("alice");
+    assert_eq!(status(from_env().unwrap(), &token).await, 200);
+    assert_eq!(status(from_env().unwrap(), "not-a-token").await, 401);
+
+    set_env(&[("OIDC_AUTH_MODE", "optional"), valid[0], valid[1], valid[2]]);
+    assert_eq!(status(from_env().unwrap(), "not-a-token").await, 200);
+
+    set_env(&[
+        ("OIDC_ISSUER", "https://other.example.com"),
+        valid[1],
+        valid[2],
+        valid[3],
+    ]);
+    assert_eq!(status(from_env().unwrap(), &token).await, 401);
+
+    let jwks_url = common::start_jwks_server().await;
+    set_env(&[valid[0], valid[1], ("OIDC_JWKS_URL", &jwks_url), valid[3]]);
+    assert_eq!(
+        from_env().err().unwrap(),
+        ConfigError::InsecureUrl(jwks_url.clone())
+    );
+    let allow_http = ("OIDC_ALLOW_INSECURE_HTTP", "true");
+    set_env(&[
+        valid[0],
+        valid[1],
+        ("OIDC_JWKS_URL", &jwks_url),
+        valid[3],
+        allow_http,
+    ]);
+    assert_eq!(status(from_env().unwrap(), &token).await, 200);
+    assert!(from_env().unwrap().validate().is_ok());
+
+    set_env(&[
+        valid[0],
+        valid[1],
+        valid[2],
+        ("OIDC_ALLOW_INSECURE_HTTP", "yes"),
+    ]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidEnv(name, _) if name == "OIDC_ALLOW_INSECURE_HTTP"
+    ));
+
+    set_env(&[valid[0], valid[1], valid[2], ("OIDC_JWKS_URL", &jwks_url)]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidEnv(name, _) if name == "OIDC_JWKS"
+    ));
+
+    set_env(&[valid[0], valid[1], valid[2], ("OIDC_LEEWAY_SECS", "1m")]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidEnv(name, _) if name == "OIDC_LEEWAY_SECS"
+    ));
+
+    set_env(&[valid[0], valid[1], valid[2], ("OIDC_AUTH_MODE", "lenient")]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidEnv(name, _) if name == "OIDC_AUTH_MODE"
+    ));
+
+    let algorithms = ("OIDC_ALGORITHMS", "ES256, PS512");
+    set_env(&[valid[0], valid[1], valid[2], valid[3], algorithms]);
+    assert_eq!(status(from_env().unwrap(), &token).await, 401);
+    set_env(&[
+        valid[0],
+        valid[1],
+        valid[2],
+        ("OIDC_ALGORITHMS", "RS256, XS1"),
+    ]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidEnv(name, _) if name == "OIDC_ALGORITHMS"
+    ));
+
+    set_env(&[valid[0], valid[1], ("OIDC_JWKS", "{}")]);
+    assert!(matches!(
+        from_env().err().unwrap(),
+        ConfigError::InvalidJwks(_)
+    ));
+    set_env(&[]);
+}
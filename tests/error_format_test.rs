@@ -0,0 +1,196 @@
+use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{
+    escape_html, AuthMode, ErrorFormat, ErrorPage, LoginRedirect, MalformedCredentials,
+    OidcAuthLayer,
+};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+    exp: i64,
+}
+
+fn strict_layer() -> OidcAuthLayer<TestClaims> {
+    let config = OidcConfig::new(
+        "https://example.com".to_string(),
+        "test-client-id".to_string(),
+        "https://example.com/.well-known/jwks.json".to_string(),
+    );
+    let oidc_validator = OidcValidator::new(config);
+
+    OidcAuthLayer::<TestClaims>::new(oidc_validator, Validation::default())
+        .with_mode(AuthMode::Strict)
+}
+
+async fn handler() -> &'static str {
+    "Authenticated"
+}
+
+#[tokio::test]
+async fn test_strict_mode_rejects_missing_token_as_plain_text() {
+    let app = Router::new()
+        .route("/test", get(handler))
+        .layer(strict_layer());
+
+    let response = app
+        .oneshot(Request::builder().uri("/test").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+}
+
+#[tokio::test]
+async fn test_strict_mode_rejects_invalid_token_as_problem_json() {
+    let app = Router::new()
+        .route("/test", get(handler))
+        .layer(strict_layer().with_error_format(ErrorFormat::ProblemJson));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Authorization", "Bearer invalid.jwt.token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    assert_eq!(
+        response.headers()["www-authenticate"],
+        "Bearer error=\"invalid_token\""
+    );
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(body["type"], "urn:axum-jwt-oidc:error:invalid-token");
+    assert_eq!(body["title"], "Unauthorized");
+    assert_eq!(body["status"], 401);
+    assert_eq!(body["instance"], "/test");
+    assert!(body["detail"].is_string());
+}
+
+#[tokio::test]
+async fn test_login_redirect_negotiates_on_accept_header() {
+    let app = Router::new()
+        .route("/test", get(handler))
+        .layer(strict_layer().with_login_redirect(LoginRedirect::new("/login")));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/test?tab=2")
+                .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers()["location"],
+        "/login?next=%2Ftest%3Ftab%3D2"
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Accept", "application/json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn test_login_redirect_only_applies_to_unauthorized() {
+    let app = Router::new().route("/test", get(handler)).layer(
+        strict_layer()
+            .with_malformed_credentials(MalformedCredentials::Reject)
+            .with_login_redirect(LoginRedirect::new("/login")),
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Accept", "text/html")
+                .header("Authorization", "Bearer one")
+                .header("Authorization", "Bearer two")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert!(response.headers().get("location").is_none());
+}
+
+#[tokio::test]
+async fn test_renderer_brands_html_rejections() {
+    let renderer = |page: &ErrorPage<'_>| {
+        format!(
+            "<h1>{} {}</h1><p>{}</p>",
+            page.status.as_u16(),
+            escape_html(page.title),
+            escape_html(page.instance)
+        )
+    };
+    let app = Router::new()
+        .route("/test", get(handler))
+        .layer(strict_layer().with_renderer(renderer));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Accept", "text/html")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"<h1>401 Unauthorized</h1><p>/test</p>");
+}
+
+#[test]
+fn test_escape_html_escapes_markup() {
+    assert_eq!(
+        escape_html(r#"<a href="/x?a=1&b='2'">"#),
+        "&lt;a href=&quot;/x?a=1&amp;b=&#x27;2&#x27;&quot;&gt;"
+    );
+    assert_eq!(escape_html("/orders/42"), "/orders/42");
+}
@@ -0,0 +1,297 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::{get, post},
+    Extension, Form, Json, Router,
+};
+use axum_jwt_oidc::{
+    AccessToken, AuthContext, CacheBudget, DownstreamTokens, Issuer, OidcAuthLayer, TokenExchanger,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tower::ServiceExt;
+
+/// Stands in for an identity provider's token endpoint, counting exchanges.
+async fn token_endpoint(exchanges: Arc<AtomicUsize>) -> String {
+    let idp = Router::new().route(
+        "/token",
+        post(
+            move |Form(form): Form<HashMap<String, String>>| async move {
+                assert_eq!(form["client_id"], "billing");
+                if form["grant_type"] == "urn:ietf:params:oauth:grant-type:jwt-bearer" {
+                    assert_eq!(form["requested_token_use"], "on_behalf_of");
+                    assert_eq!(form["client_secret"], "secret");
+                    assert!(!form["assertion"].is_empty());
+                    let token = format!("obo {}", form["scope"]);
+                    return (StatusCode::OK, Json(json!({ "access_token": token })));
+                }
+                assert_eq!(
+                    form["grant_type"],
+                    "urn:ietf:params:oauth:grant-type:token-exchange"
+                );
+                assert_eq!(
+                    form["subject_token_type"],
+                    "urn:ietf:params:oauth:token-type:access_token"
+                );
+                if form["audience"] == "https://unknown.internal" {
+                    let error =
+                        json!({ "error": "invalid_target", "error_description": "unknown" });
+                    return (StatusCode::BAD_REQUEST, Json(error));
+                }
+                let n = exchanges.fetch_add(1, Ordering::SeqCst);
+                let token = format!("{}-{n}", form["audience"]);
+                (
+                    StatusCode::OK,
+                    Json(json!({
+                        "access_token": token,
+                        "issued_token_type": "urn:ietf:params:oauth:token-type:access_token",
+                        "token_type": "Bearer",
+                        "expires_in": 300,
+                    })),
+                )
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, idp).await.unwrap() });
+    format!("http://{addr}/token")
+}
+
+async fn exchange(
+    Extension(exchanger): Extension<Arc<TokenExchanger>>,
+    Extension(context): Extension<AuthContext<Value>>,
+) -> String {
+    let token = context.token.unwrap();
+    let mut results = Vec::new();
+    for audience in [
+        "https://orders.internal",
+        "https://orders.internal",
+        "https://stock.internal",
+        "https://unknown.internal",
+    ] {
+        results.push(match exchanger.exchange(&token, audience).await {
+            Ok(token) => token.expose().to_string(),
+            Err(error) => error.to_string(),
+        });
+    }
+    results.join("\n")
+}
+
+#[tokio::test]
+async fn test_tokens_are_exchanged_and_cached_per_subject_and_audience() {
+    let exchanges = Arc::new(AtomicUsize::new(0));
+    let exchanger = TokenExchanger::new(token_endpoint(exchanges.clone()).await, "billing");
+    let auth_layer = OidcAuthLayer::<Value>::new(common::validator().await, common::validation())
+        .with_auth_context();
+    let app = Router::new()
+        .route("/test", get(exchange))
+        .layer(auth_layer)
+        .layer(Extension(Arc::new(exchanger)));
+
+    let request = Request::builder()
+        .uri("/test")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("alice")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(body.to_vec()).unwrap(),
+        "https://orders.internal-0\n\
+         https://orders.internal-0\n\
+         https://stock.internal-1\n\
+         token exchange for https://unknown.internal rejected with 400 Bad Request: \
+         invalid_target (unknown)"
+    );
+    assert_eq!(exchanges.load(Ordering::SeqCst), 2);
+}
+
+/// Sends a token for `sub` from `iss` to `app`, returning the response body.
+async fn send(app: &Router, iss: &str, sub: &str) -> String {
+    let token = common::sign(&json!({
+        "sub": sub,
+        "iss": iss,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+const SECOND_ISSUER: &str = "https://second.example.com";
+
+async fn multi_issuer_layer() -> OidcAuthLayer<Value> {
+    OidcAuthLayer::<Value>::multi_issuer([
+        Issuer::new(
+            common::ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+        Issuer::new(
+            SECOND_ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+    ])
+    .with_access_token()
+}
+
+#[tokio::test]
+async fn test_users_of_different_issuers_with_the_same_subject_get_their_own_tokens() {
+    let exchanges = Arc::new(AtomicUsize::new(0));
+    let exchanger = TokenExchanger::new(token_endpoint(exchanges.clone()).await, "billing");
+    let auth_layer = multi_issuer_layer().await;
+    let app = Router::new()
+        .route(
+            "/test",
+            get(
+                |Extension(exchanger): Extension<Arc<TokenExchanger>>,
+                 Extension(token): Extension<AccessToken>| async move {
+                    let exchanged = exchanger.exchange(&token, "https://orders.internal").await;
+                    exchanged.unwrap().expose().to_string()
+                },
+            ),
+        )
+        .layer(auth_layer)
+        .layer(Extension(Arc::new(exchanger)));
+
+    assert_eq!(
+        send(&app, common::ISSUER, "judy").await,
+        "https://orders.internal-0"
+    );
+    assert_eq!(
+        send(&app, SECOND_ISSUER, "judy").await,
+        "https://orders.internal-1"
+    );
+    assert_eq!(
+        send(&app, common::ISSUER, "judy").await,
+        "https://orders.internal-0"
+    );
+    assert_eq!(exchanges.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_downstream_tokens_use_the_on_behalf_of_flow() {
+    let endpoint = token_endpoint(Arc::new(AtomicUsize::new(0))).await;
+    let exchanger = TokenExchanger::on_behalf_of(endpoint, "billing", "secret");
+    let auth_layer = OidcAuthLayer::<Value>::new(common::validator().await, common::validation())
+        .with_access_token();
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|tokens: DownstreamTokens| async move {
+                let token = tokens.get("api://other-app").await.unwrap();
+                token.expose().to_string()
+            }),
+        )
+        .layer(auth_layer)
+        .layer(Extension(Arc::new(exchanger)));
+
+    let request = Request::builder()
+        .uri("/test")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("alice")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, "obo api://other-app/.default");
+
+    let request = Request::builder().uri("/test").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_downstream_tokens_are_cached_per_issuer() {
+    let exchanges = Arc::new(AtomicUsize::new(0));
+    let exchanger = TokenExchanger::new(token_endpoint(exchanges.clone()).await, "billing");
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|tokens: DownstreamTokens| async move {
+                let token = tokens.get("https://orders.internal").await.unwrap();
+                token.expose().to_string()
+            }),
+        )
+        .layer(multi_issuer_layer().await)
+        .layer(Extension(Arc::new(exchanger)));
+
+    assert_eq!(
+        send(&app, common::ISSUER, "judy").await,
+        "https://orders.internal-0"
+    );
+    assert_eq!(
+        send(&app, SECOND_ISSUER, "judy").await,
+        "https://orders.internal-1"
+    );
+    assert_eq!(
+        send(&app, SECOND_ISSUER, "judy").await,
+        "https://orders.internal-1"
+    );
+    assert_eq!(exchanges.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_exchanged_tokens_count_against_a_budget() {
+    let exchanges = Arc::new(AtomicUsize::new(0));
+    let budget = Arc::new(CacheBudget::new(1, 1 << 20));
+    let exchanger = TokenExchanger::new(token_endpoint(exchanges.clone()).await, "billing")
+        .with_budget(budget.clone());
+    let app = Router::new()
+        .route(
+            "/test",
+            get(
+                |Extension(exchanger): Extension<Arc<TokenExchanger>>,
+                 Extension(token): Extension<AccessToken>| async move {
+                    for audience in [
+                        "https://orders.internal",
+                        "https://stock.internal",
+                        "https://orders.internal",
+                    ] {
+                        exchanger.exchange(&token, audience).await.unwrap();
+                    }
+                },
+            ),
+        )
+        .layer(
+            OidcAuthLayer::<Value>::new(common::validator().await, common::validation())
+                .with_access_token(),
+        )
+        .layer(Extension(Arc::new(exchanger)));
+
+    send(&app, common::ISSUER, "alice").await;
+    // Each token made room for the next.
+    assert_eq!(exchanges.load(Ordering::SeqCst), 3);
+    assert_eq!((budget.entries(), budget.evictions()), (1, 2));
+
+    drop(app);
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{ClaimsExporter, ClaimsSinkError, ClaimsSummary, OidcAuthLayer};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app(exporter: ClaimsExporter) -> Router {
+    Router::new()
+        .route("/orders/{id}", get(|| async { "order" }))
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_claims_export(exporter),
+        )
+}
+
+async fn send(app: Router, token: Option<String>) {
+    let mut request = Request::builder().uri("/orders/42");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_claims_of_authenticated_requests_are_exported() {
+    let batches = Arc::new(Mutex::new(Vec::<Vec<ClaimsSummary>>::new()));
+    let sink = {
+        let batches = batches.clone();
+        move |batch| {
+            batches.lock().unwrap().push(batch);
+            async { Ok::<_, ClaimsSinkError>(()) }
+        }
+    };
+    let (exporter, task) = ClaimsExporter::new(sink, 100, 10);
+    let task = tokio::spawn(task);
+
+    let app = app(exporter).await;
+    send(app.clone(), Some(common::token_for("alice"))).await;
+    send(app.clone(), Some(common::token_for("bob"))).await;
+    send(app.clone(), None).await;
+
+    // The task finishes once the queue is drained and every exporter is dropped.
+    drop(app);
+    task.await.unwrap();
+
+    let summaries: Vec<ClaimsSummary> = batches.lock().unwrap().concat();
+    let subjects: Vec<_> = summaries.iter().map(|s| s.subject.as_deref()).collect();
+    assert_eq!(subjects, [Some("alice"), Some("bob")]);
+    assert_eq!(summaries[0].issuer.as_deref(), Some(common::ISSUER));
+    assert_eq!(summaries[0].route, "/orders/{id}");
+    assert_eq!(summaries[0].method, "GET");
+}
+
+#[tokio::test]
+async fn test_full_queue_drops_summaries() {
+    let sink = |_batch| async { Ok::<_, ClaimsSinkError>(()) };
+    // The task is never run, so nothing leaves the queue.
+    let (exporter, _task) = ClaimsExporter::new(sink, 2, 10);
+
+    let app = app(exporter.clone()).await;
+    for _ in 0..5 {
+        send(app.clone(), Some(common::token_for("alice"))).await;
+    }
+    assert_eq!(exporter.dropped(), 3);
+}
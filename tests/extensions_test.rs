@@ -0,0 +1,223 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Extension, Router};
+use axum_jwt_oidc::{
+    AccessToken, AuthContext, AuthError, FlagContext, FlagContextConfig, OidcAuthLayer, TokenHeader,
+};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn body_string(response: axum::response::Response) -> String {
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body_bytes.to_vec()).unwrap()
+}
+
+fn bearer(token: &str) -> Request<Body> {
+    Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_flag_context_is_built_from_claims() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_flag_context(
+                FlagContextConfig::default()
+                    .tenant_claim("org_id")
+                    .attribute("plan"),
+            );
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|Extension(context): Extension<FlagContext>| async move {
+                serde_json::to_string(&context).unwrap()
+            }),
+        )
+        .layer(auth_layer);
+
+    let token = common::sign(&serde_json::json!({
+        "sub": "alice",
+        "org_id": "acme",
+        "groups": ["admins", "beta"],
+        "plan": "enterprise",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+    let response = app.oneshot(bearer(&token)).await.unwrap();
+
+    let context: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert_eq!(
+        context,
+        serde_json::json!({
+            "key": "alice",
+            "tenant": "acme",
+            "groups": ["admins", "beta"],
+            "attributes": { "plan": "enterprise" },
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_validation_error_is_inserted_on_failure() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|error: Option<Extension<AuthError>>| async move {
+                match error {
+                    Some(Extension(AuthError::MalformedHeader(_))) => "malformed",
+                    Some(Extension(_)) => "other",
+                    None => "none",
+                }
+            }),
+        )
+        .layer(auth_layer);
+
+    let response = app.clone().oneshot(bearer("not-a-jwt")).await.unwrap();
+    assert_eq!(body_string(response).await, "malformed");
+
+    let response = app
+        .oneshot(bearer(&common::token_for("alice")))
+        .await
+        .unwrap();
+    assert_eq!(body_string(response).await, "none");
+}
+
+#[tokio::test]
+async fn test_raw_claims_are_inserted_when_enabled() {
+    let app = |auth_layer: OidcAuthLayer<TestClaims>| {
+        Router::new()
+            .route(
+                "/test",
+                get(|raw: Option<Extension<serde_json::Value>>| async move {
+                    match raw {
+                        Some(Extension(raw)) => raw["plan"].to_string(),
+                        None => "none".to_string(),
+                    }
+                }),
+            )
+            .layer(auth_layer)
+    };
+    let token = common::sign(&serde_json::json!({
+        "sub": "alice",
+        "plan": "enterprise",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation());
+    let response = app(auth_layer.clone().with_raw_claims())
+        .oneshot(bearer(&token))
+        .await
+        .unwrap();
+    assert_eq!(body_string(response).await, "\"enterprise\"");
+
+    let response = app(auth_layer).oneshot(bearer(&token)).await.unwrap();
+    assert_eq!(body_string(response).await, "none");
+}
+
+#[tokio::test]
+async fn test_token_header_is_inserted_when_enabled() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_token_header();
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|Extension(header): Extension<TokenHeader>| async move {
+                format!(
+                    "{} {} {}",
+                    header.alg,
+                    header.kid.unwrap_or_default(),
+                    header.typ.unwrap_or_default()
+                )
+            }),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(bearer(&common::token_for("alice")))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        body_string(response).await,
+        format!("RS256 {} JWT", common::KID)
+    );
+}
+
+#[tokio::test]
+async fn test_auth_context_bundles_claims_token_scopes_and_expiry() {
+    let token = common::sign(&serde_json::json!({
+        "sub": "alice",
+        "scope": "openid read:orders",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+    let expected_token = token.clone();
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_auth_context();
+    let app = Router::new()
+        .route(
+            "/test",
+            get(
+                |Extension(context): Extension<AuthContext<TestClaims>>| async move {
+                    let token = context.token.unwrap();
+                    assert_eq!(token.expose(), expected_token);
+                    assert_eq!(format!("{token:?}"), "AccessToken(<redacted>)");
+                    assert!(context.scopes.has_scope("read:orders"));
+                    let remaining = context.expires_at.unwrap() - std::time::Instant::now();
+                    assert!(remaining > std::time::Duration::from_secs(3500));
+                    format!("{} {}", context.claims.sub, context.issuer.unwrap())
+                },
+            ),
+        )
+        .layer(auth_layer);
+
+    let response = app.oneshot(bearer(&token)).await.unwrap();
+
+    assert_eq!(
+        body_string(response).await,
+        format!("alice {}", common::ISSUER)
+    );
+}
+
+#[tokio::test]
+async fn test_access_token_is_inserted_only_when_enabled() {
+    let token = common::token_for("alice");
+    let expected_token = token.clone();
+    let handler = |token: Option<Extension<AccessToken>>| async move {
+        token.map_or("none".to_string(), |Extension(token)| {
+            assert_eq!(format!("{token:?}"), "AccessToken(<redacted>)");
+            token.expose().to_string()
+        })
+    };
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation());
+
+    let app = Router::new()
+        .route("/test", get(handler))
+        .layer(auth_layer.clone().with_access_token());
+    let response = app.oneshot(bearer(&token)).await.unwrap();
+    assert_eq!(body_string(response).await, expected_token);
+
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+    let response = app.oneshot(bearer(&token)).await.unwrap();
+    assert_eq!(body_string(response).await, "none");
+}
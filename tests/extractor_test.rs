@@ -0,0 +1,192 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthError, AuthResult, Claims, OidcAuthLayer, OptionalClaims};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct FullClaims {
+    sub: String,
+    email: String,
+    exp: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MinimalView {
+    sub: String,
+}
+
+async fn body_string(response: axum::response::Response) -> String {
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body_bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_nested_router_uses_narrower_claims_view() {
+    let users = Router::new().route(
+        "/me",
+        get(|Claims(view): Claims<MinimalView>| async move { view.sub }),
+    );
+    let auth_layer =
+        OidcAuthLayer::<FullClaims>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/email",
+            get(|Claims(claims): Claims<FullClaims>| async move { claims.email }),
+        )
+        .nest("/users", users)
+        .layer(auth_layer);
+
+    let token = common::sign(&serde_json::json!({
+        "sub": "alice",
+        "email": "alice@example.com",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+    for (uri, expected) in [("/users/me", "alice"), ("/email", "alice@example.com")] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("Authorization", format!("Bearer {token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(body_string(response).await, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_claims_view_rejects_unauthenticated_requests() {
+    let auth_layer =
+        OidcAuthLayer::<FullClaims>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/me",
+            get(|Claims(view): Claims<MinimalView>| async move { view.sub }),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(Request::builder().uri("/me").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_claims_without_layer_is_a_configuration_error() {
+    let app = Router::new().route(
+        "/me",
+        get(|Claims(view): Claims<MinimalView>| async move { view.sub }),
+    );
+
+    let response = app
+        .oneshot(Request::builder().uri("/me").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 500);
+    assert!(body_string(response).await.contains("OidcAuthLayer"));
+}
+
+#[tokio::test]
+async fn test_optional_claims_serve_anonymous_and_authenticated_users() {
+    let auth_layer =
+        OidcAuthLayer::<MinimalView>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/greeting",
+            get(
+                |OptionalClaims(view): OptionalClaims<MinimalView>| async move {
+                    view.map_or_else(|| "guest".to_string(), |view| view.sub)
+                },
+            ),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/greeting")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(body_string(response).await, "guest");
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/greeting")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", common::token_for("bob")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(body_string(response).await, "bob");
+}
+
+#[tokio::test]
+async fn test_auth_result_reports_failure_reason() {
+    let auth_layer =
+        OidcAuthLayer::<MinimalView>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/me",
+            get(|AuthResult(result): AuthResult<MinimalView>| async move {
+                match result {
+                    Ok(view) => view.sub,
+                    Err(AuthError::MissingToken) => "missing".to_string(),
+                    Err(AuthError::Expired) => "expired".to_string(),
+                    Err(AuthError::WrongAudience) => "wrong audience".to_string(),
+                    Err(error) => error.to_string(),
+                }
+            }),
+        )
+        .layer(auth_layer);
+
+    let expired = common::sign(&serde_json::json!({
+        "sub": "carol",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() - 3600,
+    }));
+    let other_audience = common::sign(&serde_json::json!({
+        "sub": "carol",
+        "iss": common::ISSUER,
+        "aud": "another-client",
+        "exp": common::now() + 3600,
+    }));
+    for (token, expected) in [
+        (None, "missing"),
+        (Some(expired), "expired"),
+        (Some(other_audience), "wrong audience"),
+        (Some(common::token_for("carol")), "carol"),
+    ] {
+        let mut request = Request::builder().uri("/me");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(body_string(response).await, expected);
+    }
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{forwarded_token, ForwardAuthLayer, ForwardScopeLayer, OidcAuthLayer};
+use http::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tower::{service_fn, Layer, ServiceExt};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+/// Sends an outbound request through a client that echoes its `Authorization` header.
+async fn outbound(authorization: Option<&str>) -> String {
+    let forwarding = authorization.is_none();
+    let client = ForwardAuthLayer.layer(service_fn(move |req: http::Request<()>| async move {
+        let header = req.headers().get(AUTHORIZATION).cloned();
+        Ok::<_, Infallible>(header.map_or("none".to_string(), |header| {
+            // Forwarded tokens are kept out of client logs.
+            assert_eq!(header.is_sensitive(), forwarding);
+            header.to_str().unwrap().to_string()
+        }))
+    }));
+    let mut request = http::Request::builder().uri("https://orders.internal/orders");
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    client.oneshot(request.body(()).unwrap()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_inbound_token_is_forwarded_within_the_request_scope() {
+    let token = common::token_for("alice");
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_access_token();
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|| async {
+                let forwarded = outbound(None).await;
+                let explicit = outbound(Some("Bearer other")).await;
+                format!("{forwarded} {explicit}")
+            }),
+        )
+        .layer(ForwardScopeLayer)
+        .layer(auth_layer);
+
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    assert_eq!(body, format!("Bearer {token} Bearer other"));
+}
+
+#[tokio::test]
+async fn test_nothing_is_forwarded_outside_a_request_scope() {
+    assert!(forwarded_token().is_none());
+    assert_eq!(outbound(None).await, "none");
+}
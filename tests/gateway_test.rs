@@ -0,0 +1,101 @@
+use async_oidc_jwt_validator::{OidcConfig, OidcValidator, Validation};
+use axum::{
+    body::Body,
+    http::{HeaderName, Request},
+    routing::get,
+    Router,
+};
+use axum_jwt_oidc::{AuthMode, Claims, OidcAuthLayer, TrustedGatewayPayload};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+fn gateway_app() -> Router {
+    app_behind(TrustedGatewayPayload::envoy())
+}
+
+fn app_behind(gateway: TrustedGatewayPayload) -> Router {
+    // The JWKS endpoint is unreachable: trusted payloads must never need it.
+    let config = OidcConfig::new(
+        "https://example.com".to_string(),
+        "test-client-id".to_string(),
+        "http://127.0.0.1:9/jwks".to_string(),
+    );
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(OidcValidator::new(config), Validation::default())
+            .with_mode(AuthMode::Strict)
+            .dangerously_trust_gateway_payload(gateway);
+
+    Router::new()
+        .route(
+            "/test",
+            get(|Claims(claims): Claims<TestClaims>| async move { claims.sub }),
+        )
+        .layer(auth_layer)
+}
+
+#[tokio::test]
+async fn test_trusted_gateway_payload_is_decoded_without_validation() {
+    let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"ivan","iss":"https://edge"}"#);
+    let response = gateway_app()
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("x-jwt-payload", payload)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"ivan");
+}
+
+#[tokio::test]
+async fn test_trusted_gateway_ignores_bearer_tokens() {
+    let response = gateway_app()
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Authorization", "Bearer some.jwt.token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_trusted_gateway_requires_its_secret() {
+    let app = app_behind(
+        TrustedGatewayPayload::envoy()
+            .require_secret(HeaderName::from_static("x-gateway-secret"), "s3cret"),
+    );
+    let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"ivan"}"#);
+
+    for (secret, status) in [(None, 401), (Some("s3cre7"), 401), (Some("s3cret"), 200)] {
+        let mut request = Request::builder()
+            .uri("/test")
+            .header("x-jwt-payload", &payload);
+        if let Some(secret) = secret {
+            request = request.header("x-gateway-secret", secret);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+    }
+}
@@ -0,0 +1,593 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{HeaderName, Request},
+    routing::get,
+    Extension, Router,
+};
+use axum_jwt_oidc::{
+    AuthMode, CacheBudget, ConfigError, ErrorFormat, HeaderTenantResolver, Issuer, IssuerTemplate,
+    ManualClock, OidcAuthLayer, TenantConfig, TenantConfigStore, TenantDirectory, TenantId,
+    TenantStoreError,
+};
+use futures::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+    iss: String,
+}
+
+const SECOND_ISSUER: &str = "https://second.example.com";
+
+fn token(iss: &str) -> String {
+    common::sign(&serde_json::json!({
+        "sub": "judy",
+        "iss": iss,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }))
+}
+
+async fn multi_issuer_app() -> Router {
+    let auth_layer = OidcAuthLayer::<TestClaims>::multi_issuer([
+        Issuer::new(
+            common::ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+        Issuer::new(
+            SECOND_ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+    ])
+    .with_mode(AuthMode::Strict)
+    .with_error_format(ErrorFormat::ProblemJson);
+
+    Router::new()
+        .route(
+            "/test",
+            get(|Extension(claims): Extension<TestClaims>| async move { claims.iss }),
+        )
+        .layer(auth_layer)
+}
+
+async fn send(app: Router, token: String) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_tokens_are_routed_by_issuer() {
+    let app = multi_issuer_app().await;
+
+    for issuer in [common::ISSUER, SECOND_ISSUER] {
+        let response = send(app.clone(), token(issuer)).await;
+        assert_eq!(response.status(), 200);
+        let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body_bytes[..], issuer.as_bytes());
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_issuer_is_rejected() {
+    let response = send(multi_issuer_app().await, token("https://evil.example.com")).await;
+
+    assert_eq!(response.status(), 401);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(body["type"], "urn:axum-jwt-oidc:error:unknown-issuer");
+}
+
+async fn multi_tenant_app() -> Router {
+    let auth_layer = OidcAuthLayer::<TestClaims>::multi_tenant(
+        HeaderTenantResolver::new(HeaderName::from_static("x-tenant-id")),
+        [
+            (
+                TenantId::from("acme"),
+                Issuer::new(
+                    common::ISSUER,
+                    common::validator().await,
+                    common::validation(),
+                ),
+            ),
+            (
+                TenantId::from("globex"),
+                Issuer::new(
+                    SECOND_ISSUER,
+                    common::validator().await,
+                    common::validation(),
+                ),
+            ),
+        ],
+    )
+    .with_mode(AuthMode::Strict);
+
+    Router::new()
+        .route(
+            "/test",
+            get(|Extension(tenant): Extension<TenantId>| async move { tenant.to_string() }),
+        )
+        .layer(auth_layer)
+}
+
+async fn send_for_tenant(tenant: &str, token: String) -> axum::response::Response {
+    multi_tenant_app()
+        .await
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("X-Tenant-Id", tenant)
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_tenant_selects_issuer() {
+    let response = send_for_tenant("globex", token(SECOND_ISSUER)).await;
+    assert_eq!(response.status(), 200);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"globex");
+
+    // A valid token from another tenant's issuer is not accepted.
+    let response = send_for_tenant("globex", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 401);
+
+    let response = send_for_tenant("initech", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 401);
+}
+
+struct TestStore {
+    jwks_uri: String,
+    lookups: Arc<AtomicUsize>,
+    delay: Duration,
+}
+
+impl TenantConfigStore for TestStore {
+    fn lookup<'a>(
+        &'a self,
+        tenant: &'a TenantId,
+    ) -> BoxFuture<'a, Result<Option<TenantConfig>, TenantStoreError>> {
+        Box::pin(async move {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            match tenant.as_str() {
+                "acme" | "globex" => Ok(Some(TenantConfig::new(
+                    common::ISSUER,
+                    self.jwks_uri.clone(),
+                    common::AUDIENCE,
+                ))),
+                "broken" => Err("control plane unreachable".into()),
+                _ => Ok(None),
+            }
+        })
+    }
+}
+
+async fn test_store(lookups: Arc<AtomicUsize>) -> TestStore {
+    TestStore {
+        jwks_uri: common::start_jwks_server().await,
+        lookups,
+        delay: Duration::ZERO,
+    }
+}
+
+/// Serves the test keys, counting how often they are fetched.
+async fn counting_jwks_server(fetches: Arc<AtomicUsize>) -> String {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            axum::Json(common::jwks())
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/jwks")
+}
+
+fn directory_app(directory: impl Into<Arc<TenantDirectory>>) -> Router {
+    let auth_layer = OidcAuthLayer::<TestClaims>::dynamic_tenants(
+        HeaderTenantResolver::new(HeaderName::from_static("x-tenant-id")),
+        directory,
+    )
+    .with_mode(AuthMode::Strict)
+    // The local JWKS server does not use https.
+    .allow_insecure_http();
+
+    Router::new()
+        .route(
+            "/test",
+            get(|Extension(tenant): Extension<TenantId>| async move { tenant.to_string() }),
+        )
+        .layer(auth_layer)
+}
+
+async fn dynamic_tenant_app(lookups: Arc<AtomicUsize>) -> Router {
+    directory_app(TenantDirectory::new(test_store(lookups).await))
+}
+
+async fn send_to(app: Router, tenant: &str, token: String) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .uri("/test")
+            .header("X-Tenant-Id", tenant)
+            .header("Authorization", format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_tenant_config_is_looked_up_and_cached() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let app = dynamic_tenant_app(lookups.clone()).await;
+
+    for _ in 0..2 {
+        let response = send_to(app.clone(), "acme", token(common::ISSUER)).await;
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+    let response = send_to(app.clone(), "initech", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_unchanged_tenants_keep_their_signing_keys() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let store = TestStore {
+        jwks_uri: counting_jwks_server(fetches.clone()).await,
+        lookups: lookups.clone(),
+        delay: Duration::ZERO,
+    };
+    let app = directory_app(TenantDirectory::new(store).cache_ttl(Duration::ZERO));
+
+    for _ in 0..3 {
+        let response = send_to(app.clone(), "acme", token(common::ISSUER)).await;
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_lookups_of_a_tenant_share_one() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let store = TestStore {
+        delay: Duration::from_millis(50),
+        ..test_store(lookups.clone()).await
+    };
+    let app = directory_app(TenantDirectory::new(store));
+
+    let responses =
+        join_all((0..5).map(|_| send_to(app.clone(), "acme", token(common::ISSUER)))).await;
+    assert!(responses.iter().all(|response| response.status() == 200));
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_unknown_tenants_are_remembered_briefly() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let directory = Arc::new(TenantDirectory::new(test_store(lookups.clone()).await));
+    let start = SystemTime::now();
+    let clock = ManualClock::new(start);
+    let auth_layer = OidcAuthLayer::<TestClaims>::dynamic_tenants(
+        HeaderTenantResolver::new(HeaderName::from_static("x-tenant-id")),
+        directory.clone(),
+    )
+    .with_mode(AuthMode::Strict)
+    .with_clock(clock.clone())
+    .allow_insecure_http();
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    for _ in 0..2 {
+        let response = send_to(app.clone(), "initech", token(common::ISSUER)).await;
+        assert_eq!(response.status(), 401);
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+    clock.advance(Duration::from_secs(10));
+    let response = send_to(app.clone(), "initech", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+    // Invalidating a tenant, e.g. once it was created, asks the store again.
+    directory.invalidate(&TenantId::new("initech"));
+    let response = send_to(app, "initech", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_tenant_cache_is_bounded() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let evictions = Arc::new(AtomicUsize::new(0));
+    let evicted = evictions.clone();
+    let directory = Arc::new(
+        TenantDirectory::new(test_store(lookups.clone()).await)
+            .max_entries(1)
+            .on_evict(move |_| {
+                evicted.fetch_add(1, Ordering::SeqCst);
+            }),
+    );
+    let app = directory_app(directory.clone());
+
+    for tenant in ["acme", "globex", "acme"] {
+        let response = send_to(app.clone(), tenant, token(common::ISSUER)).await;
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(directory.cached_tenants(), 1);
+    assert_eq!(evictions.load(Ordering::SeqCst), 2);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_tenant_cache_is_bounded_by_its_budget() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let evictions = Arc::new(AtomicUsize::new(0));
+    let evicted = evictions.clone();
+    let budget = Arc::new(CacheBudget::new(1, 1 << 20));
+    let directory = Arc::new(
+        TenantDirectory::new(test_store(lookups.clone()).await)
+            .with_budget(budget.clone())
+            .on_evict(move |_| {
+                evicted.fetch_add(1, Ordering::SeqCst);
+            }),
+    );
+    let app = directory_app(directory.clone());
+
+    for tenant in ["acme", "globex", "acme"] {
+        let response = send_to(app.clone(), tenant, token(common::ISSUER)).await;
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!((directory.cached_tenants(), budget.entries()), (1, 1));
+    // Each tenant counts the signing keys of its validator.
+    assert!(budget.bytes() > 4096);
+    assert_eq!(budget.evictions(), 2);
+    assert_eq!(evictions.load(Ordering::SeqCst), 2);
+
+    directory.invalidate(&TenantId::new("acme"));
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_unknown_tenants_count_against_the_budget_without_evicting_known_ones() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let budget = Arc::new(CacheBudget::new(2, 1 << 20));
+    let directory = Arc::new(
+        TenantDirectory::new(test_store(lookups.clone()).await).with_budget(budget.clone()),
+    );
+    let app = directory_app(directory.clone());
+
+    for tenant in ["acme", "initech", "hooli"] {
+        send_to(app.clone(), tenant, token(common::ISSUER)).await;
+    }
+    assert_eq!((directory.cached_tenants(), budget.entries()), (1, 2));
+    assert_eq!(budget.evictions(), 1);
+
+    // The first unknown tenant made room for the second.
+    send_to(app.clone(), "initech", token(common::ISSUER)).await;
+    send_to(app, "acme", token(common::ISSUER)).await;
+    assert_eq!(lookups.load(Ordering::SeqCst), 4);
+
+    drop(directory);
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_tenant_cache_survives_clock_going_backwards() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let directory = Arc::new(TenantDirectory::new(test_store(lookups.clone()).await));
+    let start = SystemTime::now();
+    let clock = ManualClock::new(start);
+    let auth_layer = OidcAuthLayer::<TestClaims>::dynamic_tenants(
+        HeaderTenantResolver::new(HeaderName::from_static("x-tenant-id")),
+        directory.clone(),
+    )
+    .with_mode(AuthMode::Strict)
+    .with_clock(clock.clone())
+    .allow_insecure_http();
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let response = send_to(app.clone(), "acme", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 200);
+
+    // An NTP correction sets the clock back by ten minutes.
+    clock.set(start - Duration::from_secs(600));
+    let response = send_to(app.clone(), "acme", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    assert_eq!(directory.time_anomalies(), 1);
+
+    // The refreshed entry is cached against the corrected clock.
+    clock.advance(Duration::from_secs(60));
+    let response = send_to(app, "acme", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    assert_eq!(directory.time_anomalies(), 1);
+}
+
+#[tokio::test]
+async fn test_plain_http_tenants_are_rejected_at_lookup() {
+    let auth_layer = OidcAuthLayer::<TestClaims>::dynamic_tenants(
+        HeaderTenantResolver::new(HeaderName::from_static("x-tenant-id")),
+        TenantDirectory::new(test_store(Arc::new(AtomicUsize::new(0))).await),
+    )
+    .with_mode(AuthMode::Strict);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+
+    let response = send_to(app, "acme", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 503);
+}
+
+#[tokio::test]
+async fn test_store_failure_is_service_unavailable() {
+    let app = dynamic_tenant_app(Arc::new(AtomicUsize::new(0))).await;
+
+    let response = send_to(app, "broken", token(common::ISSUER)).await;
+    assert_eq!(response.status(), 503);
+}
+
+fn azure_token(iss: &str, tid: &str) -> String {
+    common::sign(&serde_json::json!({
+        "sub": "judy",
+        "iss": iss,
+        "tid": tid,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }))
+}
+
+#[tokio::test]
+async fn test_issuer_template_substitutes_tenant_claim() {
+    let template = IssuerTemplate::new(
+        "https://login.example.com/{tid}/v2.0",
+        common::validator().await,
+        common::validation(),
+    )
+    .unwrap()
+    .allow_tenants(["contoso"]);
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|Extension(tenant): Extension<TenantId>| async move { tenant.to_string() }),
+        )
+        .layer(OidcAuthLayer::<TestClaims>::issuer_template(template).with_mode(AuthMode::Strict));
+
+    let response = send(
+        app.clone(),
+        azure_token("https://login.example.com/contoso/v2.0", "contoso"),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"contoso");
+
+    // The issuer must belong to the tenant named in the token.
+    let response = send(
+        app.clone(),
+        azure_token("https://login.example.com/fabrikam/v2.0", "contoso"),
+    )
+    .await;
+    assert_eq!(response.status(), 401);
+
+    // Tenants outside the allowlist are rejected.
+    let response = send(
+        app,
+        azure_token("https://login.example.com/fabrikam/v2.0", "fabrikam"),
+    )
+    .await;
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_malformed_issuer_templates_are_rejected() {
+    for template in [
+        "https://login.example.com/v2.0",
+        "https://login.example.com/{tid/v2.0",
+        "https://login.example.com/{}/v2.0",
+    ] {
+        let error = IssuerTemplate::new(template, common::validator().await, common::validation())
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&error, ConfigError::InvalidIssuerTemplate(reason) if reason.starts_with(template)),
+            "{error}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_issuer_deserializer_converts_claims_of_its_issuer() {
+    let issuers = [
+        Issuer::new(
+            common::ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+        Issuer::new(
+            SECOND_ISSUER,
+            common::validator().await,
+            common::validation(),
+        ),
+    ];
+    // The second issuer puts the subject in `uid` instead of `sub`.
+    let auth_layer = OidcAuthLayer::<TestClaims>::multi_issuer(issuers)
+        .with_mode(AuthMode::Strict)
+        .with_issuer_deserializer(SECOND_ISSUER, |claims: serde_json::Value| {
+            Ok(TestClaims {
+                sub: claims["uid"].as_str().ok_or("missing `uid`")?.to_string(),
+                iss: SECOND_ISSUER.to_string(),
+            })
+        });
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|Extension(claims): Extension<TestClaims>| async move {
+                format!("{} {}", claims.sub, claims.iss)
+            }),
+        )
+        .layer(auth_layer);
+    let token = |iss: &str, subject: &str| {
+        common::sign(&serde_json::json!({
+            subject: "judy",
+            "iss": iss,
+            "aud": common::AUDIENCE,
+            "exp": common::now() + 3600,
+        }))
+    };
+
+    let response = send(app.clone(), token(SECOND_ISSUER, "uid")).await;
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], format!("judy {SECOND_ISSUER}").as_bytes());
+
+    let response = send(app.clone(), token(common::ISSUER, "sub")).await;
+    assert_eq!(response.status(), 200);
+    let response = send(app.clone(), token(SECOND_ISSUER, "sub")).await;
+    assert_eq!(response.status(), 401);
+    let response = send(app, token(common::ISSUER, "uid")).await;
+    assert_eq!(response.status(), 401);
+}
@@ -0,0 +1,361 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use axum_jwt_oidc::{
+    AuthMode, CacheBudget, CacheKind, ConfigError, JwksFetcher, KeyCache, KeyCacheError,
+    MemoryKeyCache, OidcAuthLayer,
+};
+use futures::future::BoxFuture;
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+
+/// Serves `jwks` at `/jwks` to requests carrying an `x-api-key` header, returning its URL.
+async fn start_server(jwks: Arc<Mutex<serde_json::Value>>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+    let app = Router::new().route(
+        "/jwks",
+        get(move |headers: HeaderMap| async move {
+            match headers.contains_key("x-api-key") {
+                true => Ok(Json(jwks.lock().unwrap().clone())),
+                false => Err(StatusCode::FORBIDDEN),
+            }
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+/// A client sending the `x-api-key` header the test server requires.
+fn client() -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-api-key", "secret".parse().unwrap());
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_keys_are_fetched_through_the_given_client() {
+    let url = start_server(Arc::new(Mutex::new(common::jwks()))).await;
+
+    // The local server does not use https.
+    let error = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url).with_http_client(client()),
+        common::validation(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(error, ConfigError::InsecureUrl(url.clone()));
+
+    // The default client does not send the header.
+    let error = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url).allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert!(
+        matches!(&error, ConfigError::JwksFetch(reason) if reason.contains("403")),
+        "{error}"
+    );
+
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url)
+            .with_http_client(client())
+            .allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .unwrap()
+    .with_mode(AuthMode::Strict);
+    assert!(auth_layer.clone().validate().is_ok());
+    // The keys are fetched over the network, which offline mode forbids.
+    assert_eq!(
+        auth_layer.clone().with_offline_mode().validate().err(),
+        Some(ConfigError::NetworkInOfflineMode)
+    );
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+}
+
+#[tokio::test]
+async fn test_bearer_token_authenticates_the_fetch() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+    let app = Router::new().route(
+        "/jwks",
+        get(|headers: HeaderMap| async move {
+            match headers.get("authorization").map(|value| value.as_bytes()) {
+                Some(b"Bearer jwks-secret") => Ok(Json(common::jwks())),
+                _ => Err(StatusCode::UNAUTHORIZED),
+            }
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let fetcher = JwksFetcher::new(&url)
+        .bearer_token("jwks-secret")
+        .allow_insecure_http();
+    assert!(!format!("{fetcher:?}").contains("jwks-secret"));
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(fetcher, common::validation())
+            .await
+            .unwrap()
+            .with_mode(AuthMode::Strict);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    // The token being validated is not the one authenticating the fetch.
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+
+    let error = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url)
+            .bearer_token("wrong")
+            .allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert!(
+        matches!(&error, ConfigError::JwksFetch(reason) if reason.contains("401")),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_task_swaps_in_rotated_keys() {
+    let mut rotated = common::jwks();
+    rotated["keys"][0]["kid"] = json!("rotated-key");
+    let jwks = Arc::new(Mutex::new(common::jwks()));
+    let url = start_server(jwks.clone()).await;
+
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url)
+            .with_http_client(client())
+            .allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .unwrap()
+    .with_mode(AuthMode::Strict);
+    let task = tokio::spawn(auth_layer.jwks_fetch_task(Duration::from_millis(20)));
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let token = common::token_with_kid("alice", "rotated-key");
+    assert_eq!(status(&app, &token).await, 401);
+
+    *jwks.lock().unwrap() = rotated;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while status(&app, &token).await != 200 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("JWKS was not fetched again");
+
+    drop(app);
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("task did not complete once the layer was dropped")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_fetch_task_follows_caching_headers() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+    let not_modified = Arc::new(Mutex::new(0));
+    let app = Router::new().route(
+        "/jwks",
+        get({
+            let not_modified = not_modified.clone();
+            move |headers: HeaderMap| async move {
+                let caching = [("etag", "\"v1\""), ("cache-control", "public, max-age=1")];
+                match headers.get("if-none-match").map(|value| value.as_bytes()) {
+                    Some(b"\"v1\"") => {
+                        *not_modified.lock().unwrap() += 1;
+                        (StatusCode::NOT_MODIFIED, caching).into_response()
+                    }
+                    _ => (caching, Json(common::jwks())).into_response(),
+                }
+            }
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url).allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .unwrap()
+    .with_mode(AuthMode::Strict);
+    // `max-age` brings the next fetch forward from an hour to a second, and the `ETag`
+    // makes it conditional.
+    tokio::spawn(auth_layer.jwks_fetch_task(Duration::from_secs(3600)));
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while *not_modified.lock().unwrap() < 2 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("JWKS was not fetched again conditionally");
+
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+}
+
+/// Serves the test JWKS, counting the requests, and returns its URL.
+async fn start_counting_server(requests: Arc<Mutex<u32>>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            *requests.lock().unwrap() += 1;
+            Json(common::jwks())
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+#[tokio::test]
+async fn test_key_cache_spares_fetches_from_the_provider() {
+    let requests = Arc::new(Mutex::new(0));
+    let url = start_counting_server(requests.clone()).await;
+    let fetcher = JwksFetcher::new(&url)
+        .with_key_cache(MemoryKeyCache::new(), Duration::from_secs(60))
+        .allow_insecure_http();
+
+    for _ in 0..3 {
+        let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+            fetcher.clone(),
+            common::validation(),
+        )
+        .await
+        .unwrap()
+        .with_mode(AuthMode::Strict);
+        let app = Router::new()
+            .route("/test", get(|| async { "ok" }))
+            .layer(auth_layer);
+        assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+    }
+    assert_eq!(*requests.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_memory_key_cache_is_bounded() {
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let recorded = evicted.clone();
+    let budget = Arc::new(CacheBudget::new(100, 1 << 20).on_evict(move |eviction| {
+        recorded.lock().unwrap().push(eviction.cache);
+    }));
+    let cache = MemoryKeyCache::new()
+        .max_entries(1)
+        .with_budget(budget.clone());
+    let ttl = Duration::from_secs(60);
+
+    cache
+        .put("https://a.example.com/jwks", "{}", ttl)
+        .await
+        .unwrap();
+    cache
+        .put("https://b.example.com/jwks", "{}", ttl)
+        .await
+        .unwrap();
+    let a = cache.get("https://a.example.com/jwks").await.unwrap();
+    let b = cache.get("https://b.example.com/jwks").await.unwrap();
+    assert_eq!((a, b.as_deref()), (None, Some("{}")));
+    assert_eq!(budget.entries(), 1);
+    assert_eq!(*evicted.lock().unwrap(), vec![CacheKind::Keys]);
+
+    drop(cache);
+    assert_eq!((budget.entries(), budget.bytes()), (0, 0));
+}
+
+struct FailingCache;
+
+impl KeyCache for FailingCache {
+    fn get<'a>(&'a self, _: &'a str) -> BoxFuture<'a, Result<Option<String>, KeyCacheError>> {
+        Box::pin(async { Err("cache unreachable".into()) })
+    }
+
+    fn put<'a>(
+        &'a self,
+        _: &'a str,
+        _: &'a str,
+        _: Duration,
+    ) -> BoxFuture<'a, Result<(), KeyCacheError>> {
+        Box::pin(async { Err("cache unreachable".into()) })
+    }
+}
+
+#[tokio::test]
+async fn test_failing_key_cache_is_bypassed() {
+    let requests = Arc::new(Mutex::new(0));
+    let url = start_counting_server(requests.clone()).await;
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_jwks_fetcher(
+        JwksFetcher::new(&url)
+            .with_key_cache(FailingCache, Duration::from_secs(60))
+            .allow_insecure_http(),
+        common::validation(),
+    )
+    .await
+    .unwrap()
+    .with_mode(AuthMode::Strict);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+    assert_eq!(*requests.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_fetch_task_completes_for_keys_given_up_front() {
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_static_jwks(
+        &common::jwks().to_string(),
+        common::validation(),
+    )
+    .unwrap();
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        auth_layer.jwks_fetch_task(Duration::from_millis(10)),
+    )
+    .await
+    .expect("task did not complete at once");
+}
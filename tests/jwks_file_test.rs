@@ -0,0 +1,149 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthMode, ConfigError, OidcAuthLayer, ValidationCache};
+use serde_json::json;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+/// Replaces the file at `path` with `contents` at once, as volume mounts do.
+fn write(path: &PathBuf, contents: &str) {
+    let staged = path.with_extension("tmp");
+    std::fs::write(&staged, contents).unwrap();
+    std::fs::rename(&staged, path).unwrap();
+}
+
+/// Waits until a request with `token` is answered with `expected`.
+async fn wait_for(app: &Router, token: &str, expected: u16) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while status(app, token).await != expected {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("JWKS file was not reloaded");
+}
+
+#[tokio::test]
+async fn test_keys_are_reloaded_when_the_file_changes() {
+    let dir = std::env::temp_dir().join(format!("jwks-file-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keys.json");
+    let valid = common::jwks().to_string();
+    let mut rotated = common::jwks();
+    rotated["keys"][0]["kid"] = json!("rotated-key");
+    write(&path, &valid);
+
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::with_jwks_file(&path, common::validation())
+            .unwrap()
+            .with_mode(AuthMode::Strict);
+    let task = tokio::spawn(auth_layer.jwks_file_watch_task(Duration::from_millis(20)));
+    let readiness = auth_layer.readiness();
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let token = common::token_for("alice");
+    assert_eq!(status(&app, &token).await, 200);
+
+    write(&path, &rotated.to_string());
+    wait_for(&app, &token, 401).await;
+
+    // An invalid file keeps the current keys.
+    write(&path, "{\"keys\": [");
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while readiness.last_error().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("invalid JWKS file was not reported");
+    assert_eq!(status(&app, &token).await, 401);
+
+    write(&path, &valid);
+    wait_for(&app, &token, 200).await;
+    assert_eq!(readiness.last_error(), None);
+
+    // The task ends with the layer.
+    drop(app);
+    tokio::time::timeout(Duration::from_secs(3), task)
+        .await
+        .expect("watch task did not end")
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_tokens_are_validated_again_once_their_key_is_removed() {
+    let dir = std::env::temp_dir().join(format!("jwks-file-cache-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keys.json");
+    write(&path, &common::jwks().to_string());
+    let mut added = common::jwks();
+    let mut extra = added["keys"][0].clone();
+    extra["kid"] = json!("added-key");
+    added["keys"].as_array_mut().unwrap().push(extra);
+    let mut rotated = common::jwks();
+    rotated["keys"][0]["kid"] = json!("rotated-key");
+
+    let cache = Arc::new(ValidationCache::new(100));
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::with_jwks_file(&path, common::validation())
+            .unwrap()
+            .with_mode(AuthMode::Strict)
+            .with_validation_cache(cache.clone());
+    let readiness = auth_layer.readiness();
+    let task = tokio::spawn(auth_layer.jwks_file_watch_task(Duration::from_millis(20)));
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let token = common::token_for("alice");
+    assert_eq!(status(&app, &token).await, 200);
+
+    // Adding a key keeps the cached token.
+    let loaded = readiness.loaded_at();
+    write(&path, &added.to_string());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while readiness.loaded_at() == loaded {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("JWKS file was not reloaded");
+    assert_eq!(status(&app, &token).await, 200);
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+    // Removing its key does not.
+    write(&path, &rotated.to_string());
+    wait_for(&app, &token, 401).await;
+
+    drop(app);
+    tokio::time::timeout(Duration::from_secs(3), task)
+        .await
+        .expect("watch task did not end")
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_missing_jwks_file_is_rejected() {
+    let path = std::env::temp_dir().join("jwks-file-test-missing.json");
+    let error = OidcAuthLayer::<serde_json::Value>::with_jwks_file(&path, common::validation())
+        .err()
+        .unwrap();
+    assert!(matches!(error, ConfigError::InvalidJwks(_)), "{error:?}");
+}
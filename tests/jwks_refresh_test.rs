@@ -0,0 +1,267 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use axum_jwt_oidc::{
+    AuthError, AuthMode, Clock, JwksCacheTtl, JwksRefresh, JwksRetry, ManualClock, OidcAuthLayer,
+    OidcConfig, OidcValidator,
+};
+use futures::future::join_all;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+/// Serves the test JWKS, counting fetches and failing them while `failing` is set.
+async fn jwks_server(fetches: Arc<AtomicUsize>, failing: Arc<AtomicBool>) -> String {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            if failing.load(Ordering::SeqCst) {
+                return StatusCode::SERVICE_UNAVAILABLE.into_response();
+            }
+            Json(common::jwks()).into_response()
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/jwks")
+}
+
+/// Waits until the JWKS has been fetched at least `count` times.
+async fn wait_for(fetches: &AtomicUsize, count: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while fetches.load(Ordering::SeqCst) < count {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("JWKS was not refreshed");
+}
+
+#[tokio::test]
+async fn test_keys_are_refreshed_in_the_background_with_backoff() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let failing = Arc::new(AtomicBool::new(false));
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_server(fetches.clone(), failing.clone()).await,
+    ));
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation());
+    let refresh = JwksRefresh::new(Duration::from_millis(50)).jitter(0.0);
+    let task = tokio::spawn(auth_layer.jwks_refresh_task(refresh));
+    let readiness = auth_layer.readiness();
+
+    wait_for(&fetches, 2).await;
+    assert!(readiness.is_ready());
+
+    // After a failure, the next attempt waits a second rather than the interval.
+    failing.store(true, Ordering::SeqCst);
+    let failed = fetches.load(Ordering::SeqCst) + 1;
+    wait_for(&fetches, failed).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(fetches.load(Ordering::SeqCst), failed);
+    assert!(readiness.last_error().unwrap().contains("503"));
+
+    // The task ends with the layer.
+    drop(auth_layer);
+    tokio::time::timeout(Duration::from_secs(3), task)
+        .await
+        .expect("refresh task did not end")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_jitter_does_not_overflow_long_intervals() {
+    let auth_layer =
+        OidcAuthLayer::<serde_json::Value>::new(common::validator().await, common::validation());
+    let refresh = JwksRefresh::new(Duration::MAX).jitter(1.0);
+    let tasks = (0..8).map(|_| {
+        tokio::time::timeout(
+            Duration::from_millis(50),
+            auth_layer.jwks_refresh_task(refresh),
+        )
+    });
+    for waited in join_all(tasks).await {
+        assert!(waited.is_err(), "refresh task ended");
+    }
+}
+
+#[tokio::test]
+async fn test_expired_keys_are_refreshed_on_the_request_path_or_in_the_background() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let failing = Arc::new(AtomicBool::new(false));
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_server(fetches.clone(), failing.clone()).await,
+    ));
+    let clock = ManualClock::new(SystemTime::now());
+    let ttl =
+        JwksCacheTtl::new(Duration::from_secs(60)).stale_while_revalidate(Duration::from_secs(30));
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_clock(clock.clone())
+        .with_jwks_cache_ttl(ttl);
+    let readiness = auth_layer.readiness();
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let token = common::token_for("alice");
+    let status = || async {
+        let request = Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    };
+
+    // The keys are fetched once, then used until they expire.
+    assert_eq!(status().await, 200);
+    assert_eq!(status().await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Within the stale window, requests do not wait for the refresh.
+    clock.advance(Duration::from_secs(70));
+    assert_eq!(status().await, 200);
+    wait_for(&fetches, 2).await;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while readiness.loaded_at() != Some(clock.now()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("background refresh was not recorded");
+
+    // Past it, the first requests share one refresh before they are validated.
+    clock.advance(Duration::from_secs(100));
+    let statuses = join_all((0..5).map(|_| status())).await;
+    assert!(statuses.iter().all(|status| *status == 200));
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    // A failed refresh falls back to the cached keys.
+    clock.advance(Duration::from_secs(100));
+    failing.store(true, Ordering::SeqCst);
+    assert_eq!(status().await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 4);
+    assert!(readiness.last_error().unwrap().contains("503"));
+}
+
+/// Serves the test JWKS, failing the first `failures` fetches.
+async fn flaky_jwks_server(fetches: Arc<AtomicUsize>, failures: usize) -> OidcValidator {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            if fetches.fetch_add(1, Ordering::SeqCst) < failures {
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+            Json(common::jwks()).into_response()
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        format!("http://{addr}/jwks"),
+    ))
+}
+
+#[tokio::test]
+async fn test_failed_fetches_are_retried() {
+    let retry = JwksRetry::new(3)
+        .initial_backoff(Duration::from_millis(10))
+        .jitter(0.0);
+    let token = common::token_for("alice");
+    let status = |auth_layer: OidcAuthLayer<serde_json::Value>| {
+        let app = Router::new()
+            .route("/test", get(|| async { "ok" }))
+            .layer(auth_layer.with_mode(AuthMode::Strict));
+        let request = Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        async move { app.oneshot(request).await.unwrap().status() }
+    };
+
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = flaky_jwks_server(fetches.clone(), 2).await;
+    let auth_layer = OidcAuthLayer::new(validator, common::validation()).with_jwks_retry(retry);
+    assert_eq!(status(auth_layer).await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    // Once the attempts are used up, the request is rejected.
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = flaky_jwks_server(fetches.clone(), 3).await;
+    let auth_layer = OidcAuthLayer::new(validator, common::validation()).with_jwks_retry(retry);
+    assert_eq!(status(auth_layer).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = flaky_jwks_server(fetches.clone(), 1).await;
+    let auth_layer = OidcAuthLayer::new(validator, common::validation());
+    assert_eq!(status(auth_layer).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Warming up retries too.
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = flaky_jwks_server(fetches.clone(), 2).await;
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_jwks_retry(retry);
+    auth_layer.warm_up().await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let validator = flaky_jwks_server(fetches.clone(), 5).await;
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_jwks_retry(JwksRetry::new(2).initial_backoff(Duration::from_millis(10)));
+    assert!(matches!(
+        auth_layer.warm_up().await.unwrap_err(),
+        AuthError::JwksUnavailable(_)
+    ));
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_retry_jitter_does_not_overflow_long_backoffs() {
+    let retry = JwksRetry::new(2)
+        .initial_backoff(Duration::MAX)
+        .max_backoff(Duration::MAX)
+        .jitter(1.0);
+    let validator = flaky_jwks_server(Arc::new(AtomicUsize::new(0)), usize::MAX).await;
+    let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+        OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_jwks_retry(retry),
+    );
+    let requests = (0..8).map(|_| {
+        let request = Request::builder()
+            .uri("/test")
+            .header(
+                "Authorization",
+                format!("Bearer {}", common::token_for("alice")),
+            )
+            .body(Body::empty())
+            .unwrap();
+        tokio::time::timeout(Duration::from_millis(200), app.clone().oneshot(request))
+    });
+    // Each request waits for its retry instead of panicking.
+    for waited in join_all(requests).await {
+        assert!(waited.is_err(), "request was answered");
+    }
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Extension, Router,
+};
+use axum_jwt_oidc::{
+    AuthError, AuthMode, ConfigError, KeysUnavailable, LoginRedirect, OidcAuthLayer, OidcConfig,
+    OidcValidator,
+};
+use tower::ServiceExt;
+
+/// Returns a layer whose JWKS endpoint always fails.
+async fn layer() -> OidcAuthLayer<serde_json::Value> {
+    let app = Router::new().route("/jwks", get(|| async { StatusCode::BAD_GATEWAY }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        format!("http://{addr}/jwks"),
+    ));
+    OidcAuthLayer::new(validator, common::validation()).with_mode(AuthMode::Strict)
+}
+
+async fn respond(auth_layer: OidcAuthLayer<serde_json::Value>) -> (StatusCode, String) {
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|error: Option<Extension<AuthError>>| async move {
+                format!("unauthenticated: {:?}", error.map(|Extension(error)| error))
+            }),
+        )
+        .layer(auth_layer);
+    let request = Request::builder()
+        .uri("/test")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("alice")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_unavailable_keys_follow_the_configured_policy() {
+    let (status, _) = respond(layer().await).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let auth_layer = layer()
+        .await
+        .with_keys_unavailable(KeysUnavailable::Unauthorized);
+    let (status, body) = respond(auth_layer).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        body,
+        "The bearer token is invalid: signing keys unavailable"
+    );
+
+    let auth_layer = layer()
+        .await
+        .with_keys_unavailable(KeysUnavailable::PassThrough);
+    let (status, body) = respond(auth_layer).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.starts_with("unauthenticated: Some(JwksUnavailable("),
+        "{body}"
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_tokens_are_rejected_when_failing_open() {
+    let auth_layer = layer()
+        .await
+        .with_keys_unavailable(KeysUnavailable::PassThrough);
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", "Bearer not-a-token")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_policy_requires_strict_mode() {
+    let error = layer()
+        .await
+        .with_mode(AuthMode::Optional)
+        .with_keys_unavailable(KeysUnavailable::Unauthorized)
+        .validate()
+        .err()
+        .unwrap();
+    assert_eq!(error, ConfigError::RejectionsWithoutStrictMode);
+}
+
+#[tokio::test]
+async fn test_unavailable_keys_do_not_redirect_browsers_to_login() {
+    let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+        layer()
+            .await
+            .with_login_redirect(LoginRedirect::new("/login")),
+    );
+    let request = Request::builder()
+        .uri("/test")
+        .header("Accept", "text/html")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("alice")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
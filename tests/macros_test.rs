@@ -0,0 +1,87 @@
+mod common;
+
+use axum::{body::Body, extract::Path, http::Request, routing::get, Router};
+use axum_jwt_oidc::{require_roles, require_scopes, OidcAuthLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+#[require_scopes("read:orders")]
+async fn order(Path(id): Path<u32>) -> String {
+    format!("order {id}")
+}
+
+#[require_scopes("read:orders", "write:orders")]
+async fn update_order() -> Result<&'static str, axum::http::StatusCode> {
+    Ok("updated")
+}
+
+#[require_roles("admin")]
+async fn admin() -> impl axum::response::IntoResponse {
+    "admin"
+}
+
+#[require_roles("deployer", client = "ops-console")]
+async fn deploy() -> &'static str {
+    "deployed"
+}
+
+async fn send(uri: &str, token: Option<String>) -> axum::response::Response {
+    let app = Router::new()
+        .route("/orders/{id}", get(order).put(update_order))
+        .route("/admin", get(admin))
+        .route("/deploy", get(deploy))
+        .layer(OidcAuthLayer::<TestClaims>::new(
+            common::validator().await,
+            common::validation(),
+        ));
+
+    let method = if uri == "/orders/7/update" {
+        "PUT"
+    } else {
+        "GET"
+    };
+    let uri = uri.trim_end_matches("/update");
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+fn token() -> String {
+    common::sign(&serde_json::json!({
+        "sub": "ivan",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "scope": "openid read:orders",
+        "realm_access": { "roles": ["user"] },
+        "resource_access": { "ops-console": { "roles": ["deployer"] } },
+    }))
+}
+
+#[tokio::test]
+async fn test_require_scopes_attribute() {
+    let response = send("/orders/7", Some(token())).await;
+    assert_eq!(response.status(), 200);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"order 7");
+
+    assert_eq!(send("/orders/7/update", Some(token())).await.status(), 403);
+    assert_eq!(send("/orders/7", None).await.status(), 401);
+}
+
+#[tokio::test]
+async fn test_require_roles_attribute() {
+    assert_eq!(send("/admin", Some(token())).await.status(), 403);
+    assert_eq!(send("/deploy", Some(token())).await.status(), 200);
+}
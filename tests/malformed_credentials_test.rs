@@ -0,0 +1,169 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, Request, StatusCode},
+    routing::get,
+    Extension, Router,
+};
+use axum_jwt_oidc::{AuthMode, MalformedCredentials, OidcAuthLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app(malformed: MalformedCredentials) -> Router {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_cookie("access_token")
+            .with_query_param("access_token")
+            .with_malformed_credentials(malformed);
+    Router::new()
+        .route(
+            "/test",
+            get(|Extension(claims): Extension<TestClaims>| async move { claims.sub }),
+        )
+        .layer(auth_layer)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Option<HeaderValue>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let challenge = response.headers().get(header::WWW_AUTHENTICATE).cloned();
+    (response.status(), challenge)
+}
+
+#[tokio::test]
+async fn test_repeated_authorization_header() {
+    let token = common::token_for("frank");
+    let request = || {
+        Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {token}"))
+            .header("Authorization", "Bearer other")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (status, challenge) = send(&app(MalformedCredentials::Reject).await, request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(challenge.unwrap(), "Bearer error=\"invalid_request\"",);
+
+    let (status, _) = send(&app(MalformedCredentials::Ignore).await, request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_non_ascii_header_falls_back_to_cookie_when_ignored() {
+    let token = common::token_for("frank");
+    let request = || {
+        Request::builder()
+            .uri("/test")
+            .header(
+                "Authorization",
+                HeaderValue::from_bytes(b"Bearer \xff\xfe").unwrap(),
+            )
+            .header("Cookie", format!("access_token={token}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (status, _) = send(&app(MalformedCredentials::Ignore).await, request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app(MalformedCredentials::Reject).await, request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_duplicated_cookies() {
+    let token = common::token_for("frank");
+    let app = app(MalformedCredentials::Reject).await;
+    let request = |cookies: String| {
+        Request::builder()
+            .uri("/test")
+            .header("Cookie", cookies)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let same = format!("access_token={token}; theme=dark; access_token={token}");
+    assert_eq!(send(&app, request(same)).await.0, StatusCode::OK);
+
+    let different = format!("access_token={token}; access_token=stale");
+    assert_eq!(
+        send(&app, request(different)).await.0,
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn test_percent_encoded_query_tokens() {
+    let token = common::token_for("frank");
+    let app = app(MalformedCredentials::Reject).await;
+    let request = |query: String| {
+        Request::builder()
+            .uri(format!("/test?{query}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // Percent-encoded tokens are decoded.
+    let encoded = token.replace('.', "%2E");
+    assert_eq!(
+        send(&app, request(format!("access_token={encoded}")))
+            .await
+            .0,
+        StatusCode::OK
+    );
+
+    for query in [
+        "access_token=%FF%FE".to_string(),
+        "access_token=abc%0Adef".to_string(),
+        format!("access_token={token}&access_token=other"),
+    ] {
+        assert_eq!(
+            send(&app, request(query.clone())).await.0,
+            StatusCode::BAD_REQUEST,
+            "{query}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fuzzed_credentials_are_never_accepted() {
+    let mut rng = fastrand::Rng::with_seed(0x5eed);
+    let apps = [
+        app(MalformedCredentials::Ignore).await,
+        app(MalformedCredentials::Reject).await,
+    ];
+
+    for _ in 0..500 {
+        let len = rng.usize(0..64);
+        let bytes: Vec<u8> = std::iter::repeat_with(|| rng.u8(..)).take(len).collect();
+        let mut request = Request::builder().uri(format!(
+            "/test?access_token={}",
+            form_urlencoded::byte_serialize(&bytes).collect::<String>()
+        ));
+        if let Ok(value) = HeaderValue::from_bytes(&[b"Bearer ".as_slice(), &bytes].concat()) {
+            request = request.header("Authorization", value);
+        }
+        let mut cookie = b"access_token=".to_vec();
+        cookie.extend(&bytes);
+        if let Ok(value) = HeaderValue::from_bytes(&cookie) {
+            request = request.header("Cookie", value);
+        }
+        let request = request.body(Body::empty()).unwrap();
+
+        let app = &apps[rng.usize(..apps.len())];
+        let (status, challenge) = send(app, request).await;
+        assert!(
+            status == StatusCode::UNAUTHORIZED || status == StatusCode::BAD_REQUEST,
+            "{bytes:?} was answered with {status}"
+        );
+        assert!(challenge.is_some());
+    }
+}
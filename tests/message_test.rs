@@ -0,0 +1,42 @@
+mod common;
+
+use axum_jwt_oidc::{AuthError, MessageAuthenticator};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+struct TestClaims {
+    sub: String,
+}
+
+#[tokio::test]
+async fn test_tokens_are_read_from_envelopes() {
+    let authenticator = MessageAuthenticator::<TestClaims>::new(
+        common::validator().await,
+        common::validation(),
+        "meta.auth.token",
+    );
+    let envelope = serde_json::json!({
+        "meta": { "auth": { "token": common::token_for("kate") } },
+        "data": { "order": 42 },
+    });
+
+    let (claims, metadata) = authenticator
+        .authenticate(&serde_json::to_vec(&envelope).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(claims.sub, "kate");
+    assert_eq!(metadata.issuer.as_deref(), Some(common::ISSUER));
+
+    let result = authenticator
+        .authenticate_value(&serde_json::json!({ "meta": {} }))
+        .await;
+    assert_eq!(result.unwrap_err(), AuthError::MissingToken);
+
+    let result = authenticator
+        .authenticate_value(&serde_json::json!({ "meta": { "auth": { "token": 7 } } }))
+        .await;
+    assert!(matches!(result, Err(AuthError::MalformedCredentials(_))));
+
+    let result = authenticator.authenticate(b"not json").await;
+    assert!(matches!(result, Err(AuthError::MalformedCredentials(_))));
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{OidcAuthLayer, UsageRecord};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+#[tokio::test]
+async fn test_metering_records_authenticated_requests_only() {
+    let records = Arc::new(Mutex::new(Vec::<UsageRecord>::new()));
+    let sink = {
+        let records = records.clone();
+        move |record| records.lock().unwrap().push(record)
+    };
+
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_metering(sink);
+    let app = Router::new()
+        .route("/orders/{id}", get(|| async { "order" }))
+        .layer(auth_layer);
+
+    let token = common::sign(&serde_json::json!({
+        "sub": "alice",
+        "azp": "billing-client",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    }));
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .uri("/orders/42")
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    app.oneshot(
+        Request::builder()
+            .uri("/orders/43")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].subject.as_deref(), Some("alice"));
+    assert_eq!(records[0].client_id.as_deref(), Some("billing-client"));
+    assert_eq!(records[0].route, "/orders/{id}");
+    assert_eq!(records[0].status, 200);
+    assert_eq!(records[0].response_bytes, Some(5));
+}
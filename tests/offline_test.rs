@@ -0,0 +1,70 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Json, Router};
+use axum_jwt_oidc::{AuthError, AuthMode, ConfigError, OidcAuthLayer, OidcConfig, OidcValidator};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tower::ServiceExt;
+
+async fn status(auth_layer: OidcAuthLayer<serde_json::Value>, token: &str) -> u16 {
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer.with_mode(AuthMode::Strict));
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.oneshot(request).await.unwrap().status().as_u16()
+}
+
+#[tokio::test]
+async fn test_offline_mode_never_fetches_keys() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Json(common::jwks())
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        format!("http://{addr}/jwks"),
+    ));
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_offline_mode();
+
+    assert_eq!(
+        auth_layer.clone().validate().err().unwrap(),
+        ConfigError::NetworkInOfflineMode
+    );
+    assert!(matches!(
+        auth_layer.warm_up().await.unwrap_err(),
+        AuthError::ConfigUnavailable(_)
+    ));
+    assert_eq!(status(auth_layer, &common::token_for("alice")).await, 503);
+    assert_eq!(fetches.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_offline_mode_accepts_static_keys() {
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::with_static_jwks(
+        &common::jwks().to_string(),
+        common::validation(),
+    )
+    .unwrap()
+    .with_offline_mode()
+    .validate()
+    .unwrap();
+
+    auth_layer.warm_up().await.unwrap();
+    assert_eq!(status(auth_layer, &common::token_for("alice")).await, 200);
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{
+    AuthorizationPolicy, LoginRedirect, OidcAuthLayer, PolicyDecision, PolicyError, PolicyInput,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+/// Lets everyone read, but only alice delete.
+struct OwnerCanDelete;
+
+impl AuthorizationPolicy for OwnerCanDelete {
+    fn evaluate<'a>(
+        &'a self,
+        input: &'a PolicyInput,
+    ) -> BoxFuture<'a, Result<PolicyDecision, PolicyError>> {
+        Box::pin(async move {
+            if input.path == "/broken" {
+                return Err("policy engine unreachable".into());
+            }
+            if input.method != "DELETE" || input.claims["sub"] == "alice" {
+                Ok(PolicyDecision::Allow)
+            } else {
+                Ok(PolicyDecision::Deny(Some(
+                    "only owners may delete".to_string(),
+                )))
+            }
+        })
+    }
+}
+
+async fn app(policy: impl AuthorizationPolicy) -> Router {
+    Router::new()
+        .route(
+            "/docs",
+            get(|| async { "docs" }).delete(|| async { "deleted" }),
+        )
+        .route("/broken", get(|| async { "broken" }))
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_policy(policy),
+        )
+}
+
+async fn send(app: Router, method: &str, uri: &str, sub: Option<&str>) -> axum::response::Response {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(sub) = sub {
+        request = request.header(
+            "Authorization",
+            format!("Bearer {}", common::token_for(sub)),
+        );
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_policy_authorizes_authenticated_requests() {
+    let app = app(OwnerCanDelete).await;
+
+    assert_eq!(
+        send(app.clone(), "GET", "/docs", Some("bob"))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        send(app.clone(), "DELETE", "/docs", Some("alice"))
+            .await
+            .status(),
+        200
+    );
+
+    let response = send(app.clone(), "DELETE", "/docs", Some("bob")).await;
+    assert_eq!(response.status(), 403);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"Access denied: only owners may delete");
+
+    // Unauthenticated requests in optional mode are left to the handlers.
+    assert_eq!(send(app, "DELETE", "/docs", None).await.status(), 200);
+}
+
+#[tokio::test]
+async fn test_policy_failure_is_service_unavailable() {
+    let app = app(OwnerCanDelete).await;
+    assert_eq!(
+        send(app, "GET", "/broken", Some("alice")).await.status(),
+        503
+    );
+}
+
+#[tokio::test]
+async fn test_denied_browsers_are_not_redirected_to_login() {
+    let app = Router::new()
+        .route(
+            "/docs",
+            get(|| async { "docs" }).delete(|| async { "deleted" }),
+        )
+        .route("/broken", get(|| async { "broken" }))
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_policy(OwnerCanDelete)
+                .with_login_redirect(LoginRedirect::new("/login")),
+        );
+    let send = |method: &str, uri: &str| {
+        app.clone().oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("Accept", "text/html")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", common::token_for("bob")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    let response = send("DELETE", "/docs").await.unwrap();
+    assert_eq!(response.status(), 403);
+    assert!(response.headers().get("location").is_none());
+    assert_eq!(send("GET", "/broken").await.unwrap().status(), 503);
+}
+
+#[cfg(feature = "opa")]
+#[tokio::test]
+async fn test_opa_policy() {
+    use axum::{routing::post, Json};
+    use axum_jwt_oidc::OpaPolicy;
+    use serde_json::{json, Value};
+
+    // Stands in for an OPA sidecar evaluating `allow` for the posted input.
+    let opa = Router::new().route(
+        "/v1/data/httpapi/authz/allow",
+        post(|Json(query): Json<Value>| async move {
+            let input = &query["input"];
+            match (input["method"].as_str(), input["claims"]["sub"].as_str()) {
+                (Some("GET"), _) => Json(json!({ "result": true })),
+                (_, Some("alice")) => Json(json!({ "result": { "allow": true } })),
+                _ => Json(json!({ "result": { "allow": false, "reason": "not the owner" } })),
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, opa).await.unwrap() });
+
+    let authorized = app(OpaPolicy::new(format!(
+        "http://{addr}/v1/data/httpapi/authz/allow"
+    )))
+    .await;
+    let status = |method, sub| {
+        let authorized = authorized.clone();
+        async move { send(authorized, method, "/docs", Some(sub)).await.status() }
+    };
+    assert_eq!(status("GET", "bob").await, 200);
+    assert_eq!(status("DELETE", "alice").await, 200);
+    assert_eq!(status("DELETE", "bob").await, 403);
+
+    let unreachable = app(OpaPolicy::new(format!("http://{addr}/v1/data/missing"))).await;
+    assert_eq!(
+        send(unreachable, "GET", "/docs", Some("bob"))
+            .await
+            .status(),
+        503
+    );
+}
+
+#[cfg(feature = "cedar")]
+#[tokio::test]
+async fn test_cedar_policy() {
+    use axum_jwt_oidc::CedarPolicy;
+
+    let policy = CedarPolicy::new(
+        r#"
+        permit(principal, action == Action::"GET", resource);
+        permit(principal == User::"alice", action == Action::"DELETE", resource == Resource::"7")
+            when { context.route == "/docs/{id}" && context.claims.iss == "https://issuer.example.com" };
+        "#,
+    )
+    .unwrap()
+    .resource_param("id");
+    let app = Router::new()
+        .route(
+            "/docs/{id}",
+            get(|| async { "doc" }).delete(|| async { "deleted" }),
+        )
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_policy(policy),
+        );
+
+    assert_eq!(
+        send(app.clone(), "GET", "/docs/8", Some("bob"))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        send(app.clone(), "DELETE", "/docs/7", Some("alice"))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        send(app.clone(), "DELETE", "/docs/8", Some("alice"))
+            .await
+            .status(),
+        403
+    );
+    assert_eq!(
+        send(app, "DELETE", "/docs/7", Some("bob")).await.status(),
+        403
+    );
+}
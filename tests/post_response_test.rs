@@ -0,0 +1,101 @@
+mod common;
+
+use axum::{body::Body, http::Request, http::StatusCode, routing::get, Router};
+use axum_jwt_oidc::{AuthError, AuthMode, AuthOutcome, OidcAuthLayer, ResponseEvent, Sampling};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn events(mode: AuthMode, requests: Vec<Option<String>>) -> Vec<ResponseEvent> {
+    sampled_events(mode, Sampling::new(1.0), requests).await
+}
+
+async fn sampled_events(
+    mode: AuthMode,
+    sampling: Sampling,
+    requests: Vec<Option<String>>,
+) -> Vec<ResponseEvent> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let events = events.clone();
+        move |event| {
+            events.lock().unwrap().push(event);
+            async {}
+        }
+    };
+    let app = Router::new()
+        .route("/reports/{id}", get(|| async { StatusCode::FORBIDDEN }))
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_mode(mode)
+                .with_post_response(hook)
+                .with_sampling(sampling),
+        );
+
+    for token in requests {
+        let mut request = Request::builder().uri("/reports/1");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+    let events = events.lock().unwrap().clone();
+    events
+}
+
+#[tokio::test]
+async fn test_post_response_hook_correlates_outcome_and_status() {
+    let events = events(
+        AuthMode::Optional,
+        vec![Some(common::token_for("kim")), None],
+    )
+    .await;
+
+    assert_eq!(events[0].outcome, AuthOutcome::Authenticated);
+    assert_eq!(events[0].subject.as_deref(), Some("kim"));
+    assert_eq!(events[0].route, "/reports/{id}");
+    assert_eq!(events[0].status, 403);
+
+    assert_eq!(
+        events[1].outcome,
+        AuthOutcome::Unauthenticated(AuthError::MissingToken)
+    );
+    assert_eq!(events[1].subject, None);
+    assert_eq!(events[1].status, 403);
+}
+
+#[tokio::test]
+async fn test_post_response_hook_sees_rejections() {
+    let events = events(AuthMode::Strict, vec![None]).await;
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].outcome,
+        AuthOutcome::Rejected(AuthError::MissingToken)
+    );
+    assert_eq!(events[0].status, 401);
+}
+
+#[tokio::test]
+async fn test_sampling_skips_unsampled_successes() {
+    let requests = vec![Some(common::token_for("kim")), None];
+
+    let events = sampled_events(AuthMode::Strict, Sampling::new(0.0), requests.clone()).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].outcome,
+        AuthOutcome::Rejected(AuthError::MissingToken)
+    );
+
+    let sampling = Sampling::new(0.0).always_sample_failures(false);
+    let events = sampled_events(AuthMode::Strict, sampling, requests).await;
+    assert!(events.is_empty());
+}
@@ -0,0 +1,59 @@
+use axum_jwt_oidc::{LoginRedirect, RedirectPolicy};
+
+#[test]
+fn test_return_to_restores_same_origin_paths() {
+    let redirect = LoginRedirect::new("/login");
+
+    assert_eq!(
+        redirect.return_to(Some("/orders/42?tab=2")),
+        "/orders/42?tab=2"
+    );
+    assert_eq!(redirect.return_to(None), "/");
+}
+
+#[test]
+fn test_return_to_rejects_open_redirects() {
+    let redirect = LoginRedirect::new("/login")
+        .allow_origin("https://app.example.com")
+        .default_return("/home");
+
+    assert_eq!(
+        redirect.return_to(Some("https://app.example.com/reports")),
+        "https://app.example.com/reports"
+    );
+    for target in [
+        "https://evil.example.com/",
+        "//evil.example.com/",
+        "/\\evil.example.com",
+        "https://app.example.com@evil.example.com/",
+        "javascript:alert(1)",
+    ] {
+        assert_eq!(redirect.return_to(Some(target)), "/home", "{target}");
+    }
+}
+
+#[test]
+fn test_redirect_policy_is_relative_only_by_default() {
+    let policy = RedirectPolicy::relative_only();
+
+    assert!(policy.is_allowed("/"));
+    assert!(policy.is_allowed("/logout/done?bye=1"));
+    assert!(!policy.is_allowed(""));
+    assert!(!policy.is_allowed("orders"));
+    assert!(!policy.is_allowed("https://app.example.com/"));
+    assert!(!policy.is_allowed("/\t/evil.example.com"));
+}
+
+#[test]
+fn test_redirect_policy_allowlists_origins() {
+    let policy = RedirectPolicy::relative_only()
+        .allow_origin("https://app.example.com/")
+        .allow_origin("http://localhost:3000");
+
+    assert!(policy.is_allowed("https://APP.example.com/x"));
+    assert!(policy.is_allowed("http://localhost:3000/"));
+    assert!(!policy.is_allowed("http://app.example.com/x"));
+    assert!(!policy.is_allowed("http://localhost:3001/"));
+    assert!(!policy.is_allowed("ftp://app.example.com/"));
+    assert_eq!(policy.resolve(Some("https://evil.example.com"), "/"), "/");
+}
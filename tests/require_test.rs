@@ -0,0 +1,45 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{OidcAuthLayer, RequireLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn send(app: Router, token: Option<String>) -> axum::response::Response {
+    let mut request = Request::builder().uri("/billing");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_async_predicate_authorizes_requests() {
+    let app = Router::new()
+        .route("/billing", get(|| async { "invoices" }))
+        .layer(RequireLayer::new(|claims: TestClaims| async move {
+            tokio::task::yield_now().await;
+            claims.sub == "alice"
+        }))
+        .layer(OidcAuthLayer::<TestClaims>::new(
+            common::validator().await,
+            common::validation(),
+        ));
+
+    let response = send(app.clone(), Some(common::token_for("alice"))).await;
+    assert_eq!(response.status(), 200);
+
+    let response = send(app.clone(), Some(common::token_for("mallory"))).await;
+    assert_eq!(response.status(), 403);
+    assert!(response.headers().get("www-authenticate").is_none());
+
+    let response = send(app, None).await;
+    assert_eq!(response.status(), 401);
+}
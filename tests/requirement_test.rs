@@ -0,0 +1,139 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthRequirement, ErrorFormat, LoginRedirect, OidcAuthLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app() -> Router {
+    let admin = Router::new()
+        .route("/users", get(|| async { "users" }))
+        .route_layer(AuthRequirement::realm_roles(["admin"]));
+    // Several requirements on the same routes must all be met.
+    let orders = Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .route_layer(AuthRequirement::scopes(["read:orders"]))
+        .route_layer(AuthRequirement::claim("email_verified", true));
+
+    Router::new()
+        .route("/public", get(|| async { "public" }))
+        .route(
+            "/profile",
+            get(|| async { "profile" }).layer(AuthRequirement::authenticated()),
+        )
+        .merge(orders)
+        .nest("/admin", admin)
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_error_format(ErrorFormat::ProblemJson),
+        )
+}
+
+fn token(extra: serde_json::Value) -> String {
+    let mut claims = serde_json::json!({
+        "sub": "judy",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+    });
+    claims
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    common::sign(&claims)
+}
+
+async fn send(app: Router, uri: &str, token: Option<String>) -> axum::response::Response {
+    let mut request = Request::builder().uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_route_requirements_are_enforced() {
+    let app = app().await;
+    let reader = token(serde_json::json!({ "scope": "read:orders", "email_verified": true }));
+    let unverified = token(serde_json::json!({ "scope": "read:orders" }));
+    let admin = token(serde_json::json!({ "realm_access": { "roles": ["admin"] } }));
+
+    assert_eq!(send(app.clone(), "/public", None).await.status(), 200);
+    assert_eq!(send(app.clone(), "/profile", None).await.status(), 401);
+    assert_eq!(
+        send(app.clone(), "/profile", Some(admin.clone()))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        send(app.clone(), "/orders", Some(reader.clone()))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        send(app.clone(), "/orders", Some(unverified))
+            .await
+            .status(),
+        403
+    );
+    assert_eq!(
+        send(app.clone(), "/admin/users", Some(admin))
+            .await
+            .status(),
+        200
+    );
+
+    // Rejections use the top-level layer's error format.
+    let response = send(app, "/admin/users", Some(reader)).await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn test_requirement_without_auth_layer_is_a_server_error() {
+    let app = Router::new().route(
+        "/profile",
+        get(|| async { "profile" }).layer(AuthRequirement::authenticated()),
+    );
+    assert_eq!(send(app, "/profile", None).await.status(), 500);
+}
+
+#[tokio::test]
+async fn test_browsers_missing_a_scope_are_forbidden_not_redirected() {
+    let app = Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .route_layer(AuthRequirement::scopes(["read:orders"]))
+        .layer(
+            OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+                .with_login_redirect(LoginRedirect::new("/login")),
+        );
+
+    let send = |token: Option<String>| {
+        let mut request = Request::builder()
+            .uri("/orders")
+            .header("Accept", "text/html");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    assert_eq!(send(None).await.unwrap().status(), 302);
+    let response = send(Some(token(serde_json::json!({ "scope": "profile" }))))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    assert!(response.headers().get("location").is_none());
+}
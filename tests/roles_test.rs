@@ -0,0 +1,70 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{Claims, KeycloakRoles, OidcAuthLayer, RequireRolesLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app() -> Router {
+    Router::new()
+        .route(
+            "/admin",
+            get(|| async { "admin" }).layer(RequireRolesLayer::realm(["admin"])),
+        )
+        .route(
+            "/deploy",
+            get(|Claims(roles): Claims<KeycloakRoles>| async move {
+                roles.client_roles("ops-console").join(",")
+            })
+            .layer(RequireRolesLayer::client("ops-console", ["deployer"])),
+        )
+        .layer(OidcAuthLayer::<TestClaims>::new(
+            common::validator().await,
+            common::validation(),
+        ))
+}
+
+fn keycloak_token() -> String {
+    common::sign(&serde_json::json!({
+        "sub": "grace",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "realm_access": { "roles": ["offline_access", "user"] },
+        "resource_access": {
+            "ops-console": { "roles": ["deployer", "viewer"] },
+        },
+    }))
+}
+
+async fn send(app: Router, uri: &str) -> axum::response::Response {
+    app.oneshot(
+        Request::builder()
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", keycloak_token()))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_keycloak_roles_are_enforced() {
+    let app = app().await;
+
+    let response = send(app.clone(), "/deploy").await;
+    assert_eq!(response.status(), 200);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"deployer,viewer");
+
+    let response = send(app, "/admin").await;
+    assert_eq!(response.status(), 403);
+}
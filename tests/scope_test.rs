@@ -0,0 +1,101 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{GrantedScopes, OidcAuthLayer, RequireScopesLayer};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app() -> Router {
+    Router::new()
+        .route("/orders", get(|| async { "orders" }))
+        .layer(RequireScopesLayer::new(["read:orders", "write:orders"]))
+        .layer(OidcAuthLayer::<TestClaims>::new(
+            common::validator().await,
+            common::validation(),
+        ))
+}
+
+fn token(scopes: serde_json::Value) -> String {
+    common::sign(&serde_json::json!({
+        "sub": "frank",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "scope": scopes,
+    }))
+}
+
+async fn send(app: Router, token: Option<String>) -> axum::response::Response {
+    let mut request = Request::builder().uri("/orders");
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    app.oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_required_scopes_are_enforced() {
+    let app = app().await;
+
+    let response = send(
+        app.clone(),
+        Some(token("openid read:orders write:orders".into())),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let response = send(app.clone(), Some(token("openid read:orders".into()))).await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.headers()["www-authenticate"],
+        "Bearer error=\"insufficient_scope\", scope=\"read:orders write:orders\""
+    );
+
+    let response = send(app, None).await;
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_scp_array_is_accepted() {
+    let token = common::sign(&serde_json::json!({
+        "sub": "frank",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "scp": ["read:orders", "write:orders"],
+    }));
+
+    let response = send(app().await, Some(token)).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[test]
+fn test_granted_scopes_flatten_into_custom_claims() {
+    #[derive(Deserialize)]
+    struct AppClaims {
+        sub: String,
+        #[serde(flatten)]
+        scopes: GrantedScopes,
+    }
+
+    let claims: AppClaims = serde_json::from_value(serde_json::json!({
+        "sub": "frank",
+        "scope": "openid read:orders",
+        "scp": ["write:orders"],
+    }))
+    .unwrap();
+
+    assert_eq!(claims.sub, "frank");
+    assert!(claims.scopes.has_scope("read:orders"));
+    assert!(claims.scopes.has_all(["openid", "write:orders"]));
+    assert!(claims.scopes.has_any(["admin", "openid"]));
+    assert!(!claims.scopes.has_any(["admin"]));
+    assert_eq!(claims.scopes.as_set().len(), 3);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{
+    auth_stack, AuthMode, OidcAuthLayer, PolicyDecision, PolicyError, PolicyInput,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+struct DenyMallory;
+
+impl axum_jwt_oidc::AuthorizationPolicy for DenyMallory {
+    fn evaluate<'a>(
+        &'a self,
+        input: &'a PolicyInput,
+    ) -> BoxFuture<'a, Result<PolicyDecision, PolicyError>> {
+        let decision = if input.claims["sub"] == "mallory" {
+            PolicyDecision::Deny(None)
+        } else {
+            PolicyDecision::Allow
+        };
+        Box::pin(async move { Ok(decision) })
+    }
+}
+
+async fn app() -> Router {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict);
+    Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(
+            auth_stack(auth_layer)
+                .with_policy(DenyMallory)
+                .with_rate_limit(3, Duration::from_secs(60))
+                .into_builder(),
+        )
+        // Build the routes once, as `axum::serve` does, so requests share the rate limiter.
+        .with_state(())
+}
+
+fn request(sub: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder().uri("/test");
+    if let Some(sub) = sub {
+        request = request.header(
+            "Authorization",
+            format!("Bearer {}", common::token_for(sub)),
+        );
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_auth_stack_authenticates_authorizes_and_limits() {
+    let app = app().await;
+
+    let response = app.clone().oneshot(request(Some("frank"))).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app.clone().oneshot(request(Some("mallory"))).await.unwrap();
+    assert_eq!(response.status(), 403);
+    let response = app.clone().oneshot(request(None)).await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // The fourth request within the period waits for the next one.
+    let limited = tokio::time::timeout(
+        Duration::from_millis(200),
+        app.oneshot(request(Some("frank"))),
+    )
+    .await;
+    assert!(limited.is_err());
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{Claims, OidcAuthLayer, StandardClaims};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_standard_claims_from_token() {
+    let auth_layer =
+        OidcAuthLayer::<StandardClaims>::new(common::validator().await, common::validation());
+    let app = Router::new()
+        .route(
+            "/me",
+            get(|Claims(claims): Claims<StandardClaims>| async move {
+                assert_eq!(claims.iss.as_deref(), Some(common::ISSUER));
+                assert_eq!(claims.aud, [common::AUDIENCE]);
+                assert!(claims.exp.is_some());
+                assert!(claims.scopes().has_all(["openid", "profile"]));
+                format!(
+                    "{} {}",
+                    claims.sub.unwrap_or_default(),
+                    claims.preferred_username.unwrap_or_default()
+                )
+            }),
+        )
+        .layer(auth_layer);
+    let token = common::sign(&serde_json::json!({
+        "sub": "frank",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() + 3600,
+        "preferred_username": "frank.s",
+        "scope": "openid profile",
+    }));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/me")
+                .header("Authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, "frank frank.s");
+}
+
+#[test]
+fn test_standard_claims_accept_audience_array() {
+    let claims: StandardClaims = serde_json::from_value(serde_json::json!({
+        "aud": ["api", "admin"],
+        "jti": "abc",
+    }))
+    .unwrap();
+
+    assert_eq!(claims.aud, ["api", "admin"]);
+    assert_eq!(claims.jti.as_deref(), Some("abc"));
+    assert_eq!(claims.sub, None);
+}
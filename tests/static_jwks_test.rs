@@ -0,0 +1,137 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{AuthMode, ConfigError, ManualClock, OidcAuthLayer};
+use serde_json::json;
+use std::time::{Duration, SystemTime};
+use tower::ServiceExt;
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+fn app(auth_layer: OidcAuthLayer<serde_json::Value>) -> Router {
+    Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer.with_mode(AuthMode::Strict))
+}
+
+#[tokio::test]
+async fn test_tokens_are_validated_with_static_keys() {
+    let jwks = common::jwks().to_string();
+    let auth_layer = OidcAuthLayer::with_static_jwks(&jwks, common::validation()).unwrap();
+    let readiness = auth_layer.readiness();
+    let app = app(auth_layer);
+
+    assert!(readiness.is_ready());
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+
+    let expired = common::sign(&json!({
+        "sub": "alice",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() - 3600,
+    }));
+    assert_eq!(status(&app, &expired).await, 401);
+    assert_eq!(status(&app, "not-a-token").await, 401);
+}
+
+#[tokio::test]
+async fn test_tokens_must_name_a_static_key() {
+    let mut jwks = common::jwks();
+    jwks["keys"][0]["kid"] = json!("other-key");
+    let auth_layer =
+        OidcAuthLayer::with_static_jwks(&jwks.to_string(), common::validation()).unwrap();
+    assert_eq!(
+        status(&app(auth_layer), &common::token_for("alice")).await,
+        401
+    );
+
+    // A single key without `kid` verifies tokens naming any key.
+    jwks["keys"][0].as_object_mut().unwrap().remove("kid");
+    let auth_layer =
+        OidcAuthLayer::with_static_jwks(&jwks.to_string(), common::validation()).unwrap();
+    assert_eq!(
+        status(&app(auth_layer), &common::token_for("alice")).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn test_static_keys_follow_the_clock() {
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer =
+        OidcAuthLayer::with_static_jwks(&common::jwks().to_string(), common::validation())
+            .unwrap()
+            .with_clock(clock.clone());
+    let app = app(auth_layer);
+    let token = common::token_for("alice");
+
+    assert_eq!(status(&app, &token).await, 200);
+    clock.advance(Duration::from_secs(7200));
+    assert_eq!(status(&app, &token).await, 401);
+}
+
+#[tokio::test]
+async fn test_encryption_keys_are_not_used_for_verification() {
+    for (field, value) in [("use", json!("enc")), ("key_ops", json!(["encrypt"]))] {
+        let mut jwks = common::jwks();
+        let mut encryption_key = jwks["keys"][0].clone();
+        encryption_key[field] = value;
+        encryption_key["kid"] = json!("encryption-key");
+        jwks["keys"].as_array_mut().unwrap().push(encryption_key);
+        let auth_layer =
+            OidcAuthLayer::with_static_jwks(&jwks.to_string(), common::validation()).unwrap();
+        let app = app(auth_layer);
+
+        assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+        let token = common::sign_with_kid(
+            "encryption-key",
+            &json!({
+                "sub": "alice",
+                "iss": common::ISSUER,
+                "aud": common::AUDIENCE,
+                "exp": common::now() + 3600,
+            }),
+        );
+        assert_eq!(status(&app, &token).await, 401, "{field}");
+
+        // A document holding only encryption keys is rejected.
+        jwks["keys"].as_array_mut().unwrap().remove(0);
+        let error = OidcAuthLayer::<serde_json::Value>::with_static_jwks(
+            &jwks.to_string(),
+            common::validation(),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, ConfigError::InvalidJwks(_)), "{error:?}");
+    }
+}
+
+#[test]
+fn test_invalid_static_jwks_are_rejected() {
+    for jwks in [
+        "not json",
+        r#"{"keys": []}"#,
+        r#"{"keys": [{"kty": "RSA"}]}"#,
+    ] {
+        let error =
+            OidcAuthLayer::<serde_json::Value>::with_static_jwks(jwks, common::validation())
+                .err()
+                .unwrap();
+        assert!(
+            matches!(error, ConfigError::InvalidJwks(_)),
+            "{jwks}: {error:?}"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use axum_jwt_oidc::{LoginRedirect, OidcAuthLayer, SubjectList, SubjectMatch, SubjectOverrides};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn app(overrides: Arc<SubjectOverrides>) -> Router {
+    Router::new().route("/test", get(|| async { "ok" })).layer(
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_subject_overrides(overrides),
+    )
+}
+
+async fn status(app: &Router, sub: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for(sub)),
+        )
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_subject_overrides_apply_at_runtime_and_report_matches() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let overrides = Arc::new(
+        SubjectOverrides::new().on_match(move |event| recorded.lock().unwrap().push(event.clone())),
+    );
+    let app = app(overrides.clone()).await;
+
+    assert_eq!(status(&app, "svc-billing-1").await, 200);
+
+    overrides.deny("svc-billing-*");
+    assert_eq!(status(&app, "svc-billing-1").await, 403);
+    assert_eq!(status(&app, "alice").await, 200);
+
+    overrides.allow("alice");
+    assert_eq!(status(&app, "alice").await, 200);
+    assert_eq!(status(&app, "bob").await, 403);
+
+    assert!(overrides.remove("svc-billing-*"));
+    assert!(overrides.remove("alice"));
+    assert_eq!(status(&app, "svc-billing-1").await, 200);
+
+    let events: Vec<_> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|event: &SubjectMatch| (event.subject.clone(), event.pattern.clone(), event.list))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (
+                "svc-billing-1".into(),
+                "svc-billing-*".into(),
+                SubjectList::Deny
+            ),
+            ("alice".into(), "alice".into(), SubjectList::Allow),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_subject_patterns_match_whole_subjects() {
+    let overrides = Arc::new(SubjectOverrides::new());
+    overrides.allow("svc-*-prod");
+    overrides.allow("admin");
+    overrides.deny("svc-legacy-*");
+    let app = app(overrides.clone()).await;
+
+    assert_eq!(status(&app, "svc-billing-prod").await, 200);
+    assert_eq!(status(&app, "svc--prod").await, 200);
+    assert_eq!(status(&app, "admin").await, 200);
+    assert_eq!(status(&app, "svc-billing-prod2").await, 403);
+    assert_eq!(status(&app, "administrator").await, 403);
+    // The denylist wins over the allowlist.
+    assert_eq!(status(&app, "svc-legacy-prod").await, 403);
+
+    assert_eq!(overrides.patterns(SubjectList::Deny), ["svc-legacy-*"]);
+}
+
+#[tokio::test]
+async fn test_match_hook_may_change_the_lists() {
+    // Lets each temporary subject in once.
+    let overrides = Arc::new_cyclic(|overrides: &std::sync::Weak<SubjectOverrides>| {
+        let overrides = overrides.clone();
+        SubjectOverrides::new().on_match(move |event| {
+            if let (SubjectList::Allow, Some(overrides)) = (event.list, overrides.upgrade()) {
+                overrides.deny(event.subject.clone());
+            }
+        })
+    });
+    overrides.allow("temp-*");
+    let app = app(overrides.clone()).await;
+
+    assert_eq!(status(&app, "temp-1").await, 200);
+    assert_eq!(status(&app, "temp-1").await, 403);
+    assert_eq!(overrides.patterns(SubjectList::Deny), ["temp-1"]);
+}
+
+#[tokio::test]
+async fn test_denied_browsers_are_not_redirected_to_login() {
+    let overrides = Arc::new(SubjectOverrides::new());
+    overrides.deny("svc-compromised");
+    let app = Router::new().route("/test", get(|| async { "ok" })).layer(
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_subject_overrides(overrides)
+            .with_login_redirect(LoginRedirect::new("/login")),
+    );
+
+    let request = Request::builder()
+        .uri("/test")
+        .header("Accept", "text/html")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("svc-compromised")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 403);
+    assert!(response.headers().get("location").is_none());
+}
@@ -0,0 +1,353 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{request::Parts, HeaderMap, HeaderName, Request, StatusCode},
+    routing::get,
+    Extension, Router,
+};
+use axum_jwt_oidc::{
+    AuthMode, CookieExtractor, HeaderExtractor, OidcAuthLayer, QueryExtractor, TokenExtractorChain,
+    TokenSource, WebSocketProtocolExtractor,
+};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct TestClaims {
+    sub: String,
+}
+
+async fn handler(claims: Option<Extension<TestClaims>>) -> String {
+    match claims {
+        Some(Extension(claims)) => claims.sub,
+        None => "Not authenticated".to_string(),
+    }
+}
+
+async fn body_string(response: axum::response::Response) -> String {
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(body_bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_token_from_cookie() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_cookie("access_token");
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    let cookie = format!("theme=dark; access_token={}", common::token_for("alice"));
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("Cookie", cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "alice");
+}
+
+#[tokio::test]
+async fn test_authorization_header_takes_precedence_over_cookie() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_cookie("access_token");
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", common::token_for("bob")),
+                )
+                .header(
+                    "Cookie",
+                    format!("access_token={}", common::token_for("alice")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "bob");
+}
+
+#[tokio::test]
+async fn test_cookie_is_ignored_unless_configured() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict);
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header(
+                    "Cookie",
+                    format!("access_token={}", common::token_for("alice")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_token_from_query_param_is_stripped() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_query_param("access_token")
+            .strip_query_param();
+    let app = Router::new()
+        .route(
+            "/events",
+            get(
+                |claims: Option<Extension<TestClaims>>, uri: axum::http::Uri| async move {
+                    format!("{} {uri}", handler(claims).await)
+                },
+            ),
+        )
+        .layer(auth_layer);
+
+    let uri = format!(
+        "/events?channel=a&access_token={}&since=1",
+        common::token_for("carol")
+    );
+    let response = app
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        body_string(response).await,
+        "carol /events?channel=a&since=1"
+    );
+}
+
+#[tokio::test]
+async fn test_token_from_custom_header_without_prefix() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_header(HeaderName::from_static("x-auth-token"), None);
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("X-Auth-Token", common::token_for("dave"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "dave");
+}
+
+#[tokio::test]
+async fn test_custom_token_extractor() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_token_extractor(|parts: &Parts| {
+                let value = parts.headers.get("x-envelope")?.to_str().ok()?;
+                value.strip_prefix("jwt:").map(str::to_string)
+            });
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("X-Envelope", format!("jwt:{}", common::token_for("erin")))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "erin");
+}
+
+#[tokio::test]
+async fn test_pre_auth_hooks_rewrite_requests_in_order() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_pre_auth(|parts: &mut Parts| {
+                if let Some(token) = parts.headers.remove("x-legacy-token") {
+                    parts.headers.insert("x-moved-token", token);
+                }
+            })
+            .with_pre_auth(|parts: &mut Parts| {
+                if let Some(token) = parts.headers.remove("x-moved-token") {
+                    let bearer = format!("Bearer {}", token.to_str().unwrap());
+                    parts
+                        .headers
+                        .insert("authorization", bearer.parse().unwrap());
+                }
+            });
+    let app = Router::new()
+        .route(
+            "/test",
+            get(
+                |claims: Option<Extension<TestClaims>>, headers: HeaderMap| async move {
+                    assert!(headers.get("x-legacy-token").is_none());
+                    handler(claims).await
+                },
+            ),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header("X-Legacy-Token", common::token_for("frank"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "frank");
+}
+
+#[tokio::test]
+async fn test_extractor_chain_records_matched_source() {
+    let chain = TokenExtractorChain::new()
+        .then(HeaderExtractor::bearer())
+        .then(CookieExtractor::new("access_token"))
+        .then(QueryExtractor::new("access_token"));
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_token_extractors(chain);
+    let app = Router::new()
+        .route(
+            "/test",
+            get(|Extension(source): Extension<TokenSource>| async move { source.to_string() }),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/test?access_token={}", common::token_for("frank")))
+                .header("Cookie", "other=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "query:access_token");
+}
+
+#[tokio::test]
+async fn test_websocket_protocol_token_is_scrubbed_and_echoed() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_mode(AuthMode::Strict)
+            .with_token_extractor(WebSocketProtocolExtractor::new());
+    let app = Router::new()
+        .route(
+            "/ws",
+            get(|headers: HeaderMap| async move {
+                // Stand-in for a WebSocket upgrade that did not pick a subprotocol.
+                assert_eq!(headers["sec-websocket-protocol"], "bearer, chat");
+                StatusCode::SWITCHING_PROTOCOLS
+            }),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/ws")
+                .header(
+                    "Sec-WebSocket-Protocol",
+                    format!("bearer, {}, chat", common::token_for("grace")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(response.headers()["sec-websocket-protocol"], "bearer");
+}
+
+#[tokio::test]
+async fn test_oauth2_proxy_forwarded_headers() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_token_extractors(TokenExtractorChain::oauth2_proxy());
+    let app = Router::new().route("/test", get(handler)).layer(auth_layer);
+
+    for header in ["X-Forwarded-Access-Token", "X-Auth-Request-Access-Token"] {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/test")
+                    .header(header, common::token_for("heidi"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(body_string(response).await, "heidi", "{header}");
+    }
+}
+
+#[cfg(feature = "typed-header")]
+#[tokio::test]
+async fn test_typed_bearer_extractor() {
+    let auth_layer =
+        OidcAuthLayer::<TestClaims>::new(common::validator().await, common::validation())
+            .with_token_extractors(
+                TokenExtractorChain::new().then(axum_jwt_oidc::TypedBearerExtractor),
+            );
+    let app = Router::new()
+        .route(
+            "/test",
+            get(
+                |Extension(claims): Extension<TestClaims>,
+                 Extension(source): Extension<TokenSource>| async move {
+                    format!("{} {source}", claims.sub)
+                },
+            ),
+        )
+        .layer(auth_layer);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/test")
+                .header(
+                    "Authorization",
+                    format!("bearer {}", common::token_for("grace")),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(body_string(response).await, "grace header:authorization");
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use axum::{body::Body, http::Request, routing::get, Json, Router};
+use axum_jwt_oidc::{AuthMode, ManualClock, OidcAuthLayer, OidcConfig, OidcValidator};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+/// Serves the test JWKS, counting fetches.
+async fn jwks_server(fetches: Arc<AtomicUsize>) -> String {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Json(common::jwks())
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}/jwks")
+}
+
+async fn status(app: &Router, token: &str) -> u16 {
+    let request = Request::builder()
+        .uri("/test")
+        .header("Authorization", format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    app.clone()
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+async fn app(fetches: Arc<AtomicUsize>, clock: &ManualClock, ttl: Option<Duration>) -> Router {
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_server(fetches).await,
+    ));
+    let mut auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_clock(clock.clone());
+    if let Some(ttl) = ttl {
+        auth_layer = auth_layer.with_unknown_kid_ttl(ttl);
+    }
+    Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer)
+}
+
+#[tokio::test]
+async fn test_unknown_kids_are_not_fetched_again_until_the_ttl_passes() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let clock = ManualClock::new(SystemTime::now());
+    let app = app(fetches.clone(), &clock, Some(Duration::from_secs(30))).await;
+    let bogus = common::token_with_kid("mallory", "bogus");
+
+    assert_eq!(status(&app, &common::token_for("alice")).await, 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // The first token with an unknown kid fetches the keys again, later ones do not.
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    for _ in 0..5 {
+        assert_eq!(status(&app, &bogus).await, 401);
+    }
+    assert_eq!(
+        status(&app, &common::token_with_kid("eve", "bogus")).await,
+        401
+    );
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    // Tokens signed with known keys are unaffected.
+    assert_eq!(status(&app, &common::token_for("bob")).await, 200);
+
+    clock.advance(Duration::from_secs(31));
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert_eq!(fetches.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_unknown_kids_are_fetched_every_time_by_default() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let clock = ManualClock::new(SystemTime::now());
+    let app = app(fetches.clone(), &clock, None).await;
+    let bogus = common::token_with_kid("mallory", "bogus");
+
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert!(fetches.load(Ordering::SeqCst) >= 2);
+}
+
+#[tokio::test]
+async fn test_unknown_kids_are_forgotten_when_the_clock_goes_backwards() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let clock = ManualClock::new(SystemTime::now());
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        jwks_server(fetches.clone()).await,
+    ));
+    let auth_layer = OidcAuthLayer::<serde_json::Value>::new(validator, common::validation())
+        .with_mode(AuthMode::Strict)
+        .with_clock(clock.clone())
+        .with_unknown_kid_ttl(Duration::from_secs(30));
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer.clone());
+    let bogus = common::token_with_kid("mallory", "bogus");
+
+    assert_eq!(status(&app, &bogus).await, 401);
+    let fetched = fetches.load(Ordering::SeqCst);
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert_eq!(fetches.load(Ordering::SeqCst), fetched);
+
+    // Set back by an hour, the clock would otherwise keep the kid remembered for an hour.
+    clock.set(SystemTime::now() - Duration::from_secs(3600));
+    assert_eq!(status(&app, &bogus).await, 401);
+    assert!(fetches.load(Ordering::SeqCst) > fetched);
+    assert_eq!(auth_layer.time_anomalies(), 1);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use axum_jwt_oidc::{validate_token, AuthError};
+use serde::Deserialize;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Deserialize)]
+struct TestClaims {
+    sub: String,
+}
+
+#[tokio::test]
+async fn test_validate_token_outside_http() {
+    let validator = common::validator().await;
+    let validation = common::validation();
+    let exp = common::now() + 3600;
+    let token = common::sign(&serde_json::json!({
+        "sub": "ivan",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": exp,
+    }));
+
+    let (claims, metadata) = validate_token::<TestClaims>(&validator, &validation, &token)
+        .await
+        .unwrap();
+
+    assert_eq!(claims.sub, "ivan");
+    assert_eq!(metadata.subject.as_deref(), Some("ivan"));
+    assert_eq!(metadata.issuer.as_deref(), Some(common::ISSUER));
+    assert_eq!(metadata.header.kid.as_deref(), Some(common::KID));
+    assert_eq!(
+        metadata.expires_at,
+        Some(UNIX_EPOCH + Duration::from_secs(exp as u64))
+    );
+}
+
+#[tokio::test]
+async fn test_validate_token_rejects_like_the_middleware() {
+    let validator = common::validator().await;
+    let validation = common::validation();
+    let expired = common::sign(&serde_json::json!({
+        "sub": "ivan",
+        "iss": common::ISSUER,
+        "aud": common::AUDIENCE,
+        "exp": common::now() - 3600,
+    }));
+
+    let result = validate_token::<TestClaims>(&validator, &validation, &expired).await;
+    assert_eq!(result.unwrap_err(), AuthError::Expired);
+
+    let result = validate_token::<TestClaims>(&validator, &validation, "not-a-jwt").await;
+    assert!(matches!(result, Err(AuthError::MalformedHeader(_))));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use axum_jwt_oidc::{AuthError, ManualClock, OidcAuthLayer, OidcConfig, OidcValidator};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+/// Serves the test JWKS with `status`, counting fetches, and returns a layer using it.
+async fn layer(status: StatusCode, fetches: Arc<AtomicUsize>) -> OidcAuthLayer<serde_json::Value> {
+    let app = Router::new().route(
+        "/jwks",
+        get(move || async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            (status, Json(common::jwks())).into_response()
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let validator = OidcValidator::new(OidcConfig::new(
+        common::ISSUER.to_string(),
+        common::AUDIENCE.to_string(),
+        format!("http://{addr}/jwks"),
+    ));
+    OidcAuthLayer::new(validator, common::validation())
+}
+
+#[tokio::test]
+async fn test_warm_up_fetches_keys_before_the_first_request() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let auth_layer = layer(StatusCode::OK, fetches.clone()).await;
+
+    auth_layer.warm_up().await.unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    let app = Router::new()
+        .route("/test", get(|| async { "ok" }))
+        .layer(auth_layer);
+    let request = Request::builder()
+        .uri("/test")
+        .header(
+            "Authorization",
+            format!("Bearer {}", common::token_for("alice")),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_warm_up_reports_an_unavailable_jwks() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let auth_layer = layer(StatusCode::NOT_FOUND, fetches).await;
+
+    let error = auth_layer.warm_up().await.unwrap_err();
+    assert!(matches!(error, AuthError::JwksUnavailable(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn test_readiness_follows_the_loaded_keys() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let clock = ManualClock::new(SystemTime::now());
+    let auth_layer = layer(StatusCode::OK, fetches)
+        .await
+        .with_clock(clock.clone());
+    let readiness = auth_layer.readiness().max_age(Duration::from_secs(600));
+    let probe = Router::new().route(
+        "/readyz",
+        get({
+            let readiness = readiness.clone();
+            move || async move { readiness }
+        }),
+    );
+    let status = || async {
+        let request = Request::builder()
+            .uri("/readyz")
+            .body(Body::empty())
+            .unwrap();
+        probe.clone().oneshot(request).await.unwrap().status()
+    };
+
+    assert_eq!(status().await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        readiness.check().unwrap_err(),
+        "signing keys not loaded yet"
+    );
+
+    auth_layer.warm_up().await.unwrap();
+    assert_eq!(status().await, StatusCode::OK);
+    assert!(readiness.is_ready());
+
+    clock.advance(Duration::from_secs(601));
+    assert_eq!(status().await, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!readiness.is_ready());
+}
+
+#[tokio::test]
+async fn test_readiness_reports_the_last_error() {
+    let auth_layer = layer(StatusCode::NOT_FOUND, Arc::new(AtomicUsize::new(0))).await;
+    let readiness = auth_layer.readiness();
+
+    auth_layer.warm_up().await.unwrap_err();
+    assert!(!readiness.is_ready());
+    let error = readiness.last_error().unwrap();
+    assert!(error.contains("404"), "{error}");
+    assert!(readiness
+        .check()
+        .unwrap_err()
+        .starts_with("signing keys not loaded: "));
+}